@@ -16,6 +16,17 @@ bind("ctrl-d", "delete_right")
 bind("ctrl-h", "delete")
 bind("ctrl-k", "delete_to_end")
 bind("ctrl-u", "delete_from_start")
+bind("ctrl-y", "yank")
+bind("alt-y", "yank_pop")
+bind("ctrl-t", "transpose_chars")
+bind("alt-t", "transpose_words")
+-- ctrl-_ and ctrl-/ are both read as ctrl-7
+bind("ctrl-7", "undo")
+bind("alt-_", "redo")
+bind("alt-c", "compose")
+bind("alt-s", "compose_send")
+bind("alt-x", "compose_cancel")
+bind("alt-e", "compose_editor")
 
 -- ctrl + up/down
 blight.bind("\x1b[1;5a", function () search.find_up() end)
@@ -31,3 +42,11 @@ blight.bind("up", history.previous_command)
 blight.bind("down", history.next_command)
 blight.bind("ctrl-p", history.previous_command)
 blight.bind("ctrl-n", history.next_command)
+
+-- Sessions
+for i=1,9 do
+	blight.bind("alt-" .. i, function () sessions.switch(i) end)
+end
+-- alt + left/right
+blight.bind("\x1b[1;3d", sessions.prev)
+blight.bind("\x1b[1;3c", sessions.next)
@@ -8,13 +8,38 @@ local command_set = {}
 local search_index = nil
 local search_commands = nil
 
-if settings.get("save_history") then
-    commands = json.decode(store.disk_read("__command_history") or "[]")
+-- The history file of the connected world, nil for the shared history
+local history_file = nil
+
+local function profile_history_file()
+    local profile = mud.get_profile()
+    return profile and profile.history_file
+end
+
+local function read_from_disk()
+    history_file = profile_history_file()
+    local data = nil
+    if history_file then
+        local file = io.open(history_file, "r")
+        if file then
+            data = file:read("a")
+            file:close()
+        end
+    elseif settings.get("save_history") then
+        data = store.disk_read("__command_history")
+    end
+    if not data or data == "" then
+        data = "[]"
+    end
+    commands = json.decode(data)
+    command_set = {}
     for _,c in ipairs(commands) do
         command_set[c] = true
     end
 end
 
+read_from_disk()
+
 local function reset()
     index = nil
     orig_cmd = nil
@@ -113,13 +138,29 @@ function mod.next_command()
 end
 
 local function write_to_disk()
-    if settings.get("save_history") then
+    if history_file then
+        local file = io.open(history_file, "w")
+        if file then
+            file:write(json.encode(commands))
+            file:close()
+        end
+    elseif settings.get("save_history") then
         store.disk_write("__command_history", json.encode(commands))
     end
 end
 
+-- Swaps in the history of the world connected to, or the shared one
+local function switch_history()
+    write_to_disk()
+    if profile_history_file() ~= history_file then
+        reset()
+        read_from_disk()
+    end
+end
+
 blight.on_quit(write_to_disk)
-mud.on_disconnect(write_to_disk)
+mud.on_connect(switch_history)
+mud.on_disconnect(switch_history)
 script.on_reset(write_to_disk)
 
 local function shift_commands(new_cmd)
@@ -0,0 +1,74 @@
+local mod = {}
+
+local Response = {}
+Response.__index = Response
+
+function Response:json()
+    return json.decode(self.body)
+end
+
+local function request(method, url, options, callback)
+    if type(options) == "function" then
+        callback = options
+        options = nil
+    end
+    options = options or {}
+
+    if options.json ~= nil then
+        local headers = {}
+        local has_content_type = false
+        for name, value in pairs(options.headers or {}) do
+            headers[name] = value
+            has_content_type = has_content_type or name:lower() == "content-type"
+        end
+        if not has_content_type then
+            headers["Content-Type"] = "application/json"
+        end
+        options = {
+            headers = headers,
+            body = json.encode(options.json),
+            timeout = options.timeout,
+        }
+    end
+
+    if callback then
+        core.http_request(method, url, options, function (response)
+            callback(setmetatable(response, Response))
+        end)
+        return
+    end
+
+    if tasks.get_current() == nil then
+        error("A callback is required outside of tasks", 2)
+    end
+    local result = nil
+    core.http_request(method, url, options, function (response)
+        result = setmetatable(response, Response)
+    end)
+    while result == nil do
+        tasks.yield()
+    end
+    return result
+end
+
+function mod.request(method, url, options, callback)
+    return request(method, url, options, callback)
+end
+
+function mod.get(url, options, callback)
+    return request("GET", url, options, callback)
+end
+
+function mod.post(url, options, callback)
+    return request("POST", url, options, callback)
+end
+
+function mod.put(url, options, callback)
+    return request("PUT", url, options, callback)
+end
+
+function mod.delete(url, options, callback)
+    return request("DELETE", url, options, callback)
+end
+
+return mod
@@ -10,12 +10,19 @@ local function Info(msg)
     print("[MSSP]: " .. msg)
 end
 
-local function print_info()
-    for k,v in pairs(mssp_values) do
+local function print_values(values)
+    for k,v in pairs(values) do
+        if type(v) == "table" then
+            v = table.concat(v, ", ")
+        end
         Info(k .. " = " .. v)
     end
 end
 
+local function print_info()
+    print_values(mssp_values)
+end
+
 local function decode(data)
     local parse_key, parse_val
 
@@ -88,5 +95,15 @@ mod.get = function ()
     return mssp_values
 end
 mod.print = print_info
+mod.query = function (host, port, callback)
+    core.mssp_query(host, port, callback or function (values, err)
+        if values == nil then
+            Info(err)
+        else
+            Info(host .. ":" .. port)
+            print_values(values)
+        end
+    end)
+end
 
 return mod
@@ -3,6 +3,7 @@ local REQUEST = 1
 local ACCEPTED = 2
 local REJECTED = 3
 
+-- Encodings that need no conversion, preferred over anything else offered
 local ACCEPTED_ENCODINGS = {
     "UTF-8",
     "ASCII",
@@ -56,14 +57,31 @@ core.subneg_recv(function (proto, recv)
     local options = utf8.char(unpack(recv))
     blight.debug("TELCHR[received]: " .. options)
 
-    for _,opt in ipairs(split(options, sep)) do
+    local offered = split(options, sep)
+    -- Prefer the encoding configured for the server, then UTF-8
+    local current = lower(mud.get_encoding())
+    for _,opt in ipairs(offered) do
+        if lower(opt) == current then
+            send_accept(opt)
+            return
+        end
+    end
+    for _,opt in ipairs(offered) do
         for _,accepted in ipairs(ACCEPTED_ENCODINGS) do
             if lower(opt) == lower(accepted) then
+                mud.set_encoding("UTF-8")
                 send_accept(opt)
                 return
             end
         end
     end
+    -- Fall back to anything Blightmud is able to convert
+    for _,opt in ipairs(offered) do
+        if pcall(mud.set_encoding, opt) then
+            send_accept(opt)
+            return
+        end
+    end
     send_reject()
 end)
 
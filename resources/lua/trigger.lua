@@ -18,6 +18,7 @@ function Trigger.new(re, options, callback)
     ret.regex = regex.new(re)
     ret.callback = callback
     ret.gag = options.gag or false
+    ret.redirect = options.redirect
     ret.raw = options.raw or false
     ret.prompt = options.prompt or false
     ret.count = options.count or nil
@@ -68,6 +69,9 @@ function Trigger:check_line(line)
         if self.gag then
             line:gag(true)
         end
+        if self.redirect then
+            line:redirect(self.redirect)
+        end
         line:matched(true)
         if self.count and self.count > 0 then
             self.count = self.count - 1
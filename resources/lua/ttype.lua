@@ -27,6 +27,7 @@ mtts = mtts | mod.MTTS_ANSI
 mtts = mtts | mod.MTTS_UTF8
 mtts = mtts | mod.MTTS_256_COLOR
 mtts = mtts | mod.MTTS_TRUE_COLOR
+mtts = mtts | mod.MTTS_MNES
 
 local NEGOTIATION_STACK = {}
 
@@ -48,6 +49,15 @@ local function init()
     }
 end
 
+-- The screen reader bit is added by mnes while it follows reader mode and TTS
+local function update_mnes()
+    if auto_reader_mode then
+        mnes.set_mtts(mtts & ~mod.MTTS_SCREEN_READER)
+    else
+        mnes.set_mtts(mtts)
+    end
+end
+
 local function Info(msg)
     print("[TTYPE]: " .. msg)
 end
@@ -89,11 +99,13 @@ end)
 
 function mod.set_term(new_term)
     term = new_term
+    mnes.set("TERMINAL_TYPE", term:upper())
     Info(string.format("Set TERM: %s", term))
 end
 
 function mod.set_mtts(new_mtts)
     mtts = new_mtts
+    update_mnes()
     Info(string.format("Set MTTS: '0x%X'", mtts))
 end
 
@@ -103,6 +115,7 @@ function mod.add_option(mtts_opt)
     if mtts_opt & mod.MTTS_SCREEN_READER then
         auto_reader_mode = false
     end
+    update_mnes()
     Info(string.format("Updated MTTS 0x%X | 0x%X = 0x%X", old_mtts, mtts_opt, mtts))
 end
 
@@ -112,6 +125,7 @@ function mod.rem_option(mtts_opt)
     if mtts_opt & mod.MTTS_SCREEN_READER then
         auto_reader_mode = false
     end
+    update_mnes()
     Info(string.format("Updated MTTS 0x%X & ~0x%X = 0x%X", old_mtts, mtts_opt, mtts))
 end
 
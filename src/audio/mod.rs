@@ -1,3 +1,9 @@
-pub use self::{handler::handle_audio_event, player::Player, player::SourceOptions};
+pub use self::{
+    handler::handle_audio_event,
+    msp::{Msp, MSP},
+    player::Player,
+    player::SourceOptions,
+};
 mod handler;
+mod msp;
 mod player;
@@ -0,0 +1,340 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use lazy_static::lazy_static;
+use log::debug;
+use regex::Regex;
+
+use crate::{
+    event::Event,
+    io::SaveData,
+    model::{Connection, Line, Servers},
+    tools::util::expand_tilde,
+};
+
+use super::SourceOptions;
+
+/// Telnet option code for MSP
+pub const MSP: u8 = 90;
+
+const EXTENSIONS: [&str; 5] = ["wav", "mp3", "ogg", "flac", "oga"];
+
+lazy_static! {
+    static ref MSP_TRIGGER: Regex = Regex::new(r"!!(SOUND|MUSIC)\(([^)]*)\)").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Directive {
+    Sound(String, SourceOptions),
+    Music(String, SourceOptions),
+    SoundOff,
+    MusicOff,
+}
+
+/// Handles in-band MUD Sound Protocol triggers (`!!SOUND(...)` and
+/// `!!MUSIC(...)`) found in mud output.
+pub struct Msp {
+    sound_dir: PathBuf,
+    /// Triggers are left alone until the server negotiates MSP or the user
+    /// configures a sound directory, so other players can't play sounds
+    enabled: bool,
+}
+
+impl Msp {
+    pub fn new(sound_dir: PathBuf) -> Self {
+        Self {
+            sound_dir,
+            enabled: false,
+        }
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    /// Builds an MSP handler for a host. Saved servers use their configured
+    /// `sound_dir` or `$DATA_DIR/sounds/<name>`, anything else uses
+    /// `$DATA_DIR/sounds/<host>`.
+    pub fn for_host(host: &str, port: u16) -> Self {
+        let servers = Servers::load();
+        let world = servers
+            .iter()
+            .find(|(_, conn)| conn.host == host && conn.port == port);
+        let (sound_dir, configured) = match world {
+            Some((
+                _,
+                Connection {
+                    sound_dir: Some(dir),
+                    ..
+                },
+            )) => (PathBuf::from(expand_tilde(dir).as_ref()), true),
+            Some((name, _)) => (crate::DATA_DIR.join("sounds").join(name), false),
+            None => (crate::DATA_DIR.join("sounds").join(host), false),
+        };
+        debug!("MSP sound directory: {:?}", sound_dir);
+        let mut msp = Self::new(sound_dir);
+        msp.enabled = configured;
+        msp
+    }
+
+    /// Strips MSP triggers from the line and returns the audio events they
+    /// translate to. Lines that only contained triggers are gagged.
+    pub fn parse_line(&self, line: &mut Line) -> Vec<Event> {
+        if !self.enabled || !line.line().contains("!!") || !MSP_TRIGGER.is_match(line.line()) {
+            return vec![];
+        }
+
+        let events = MSP_TRIGGER
+            .captures_iter(line.line())
+            .filter_map(|cap| parse_directive(&cap[1], &cap[2]))
+            .filter_map(|directive| self.to_event(directive))
+            .collect();
+
+        let remaining = MSP_TRIGGER.replace_all(line.line(), "").to_string();
+        line.set_content(&remaining);
+        if line.clean_line().trim().is_empty() {
+            line.flags.gag = true;
+            line.flags.skip_log = true;
+            line.flags.tts_gag = true;
+        }
+        events
+    }
+
+    fn to_event(&self, directive: Directive) -> Option<Event> {
+        match directive {
+            Directive::SoundOff => Some(Event::StopSFX),
+            Directive::MusicOff => Some(Event::StopMusic),
+            Directive::Sound(file, options) => self
+                .resolve(&file)
+                .map(|path| Event::PlaySFX(path.to_string_lossy().to_string(), options)),
+            Directive::Music(file, options) => self
+                .resolve(&file)
+                .map(|path| Event::PlayMusic(path.to_string_lossy().to_string(), options)),
+        }
+    }
+
+    /// Finds a file matching the MSP file name in the sound directory. The
+    /// name may contain subdirectories and `*`/`?` wildcards and may omit the
+    /// extension. If several files match one is picked at random.
+    fn resolve(&self, file: &str) -> Option<PathBuf> {
+        let path = Path::new(file);
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            debug!("MSP refusing file outside sound directory: {}", file);
+            return None;
+        }
+        let dir = match path.parent() {
+            Some(parent) => self.sound_dir.join(parent),
+            None => self.sound_dir.clone(),
+        };
+        let name = path.file_name()?.to_string_lossy().to_string();
+        let mut pattern = regex::escape(&name)
+            .replace(r"\*", ".*")
+            .replace(r"\?", ".");
+        if Path::new(&name).extension().is_none() {
+            pattern = format!("{pattern}\\.({})", EXTENSIONS.join("|"));
+        }
+        let re = Regex::new(&format!("(?i)^{pattern}$")).ok()?;
+
+        let mut matches: Vec<PathBuf> = fs::read_dir(&dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .is_some_and(|name| re.is_match(&name.to_string_lossy()))
+            })
+            .collect();
+        matches.sort();
+
+        if matches.is_empty() {
+            debug!("MSP no file matching {} in {:?}", file, dir);
+            None
+        } else {
+            let seed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as usize)
+                .unwrap_or_default();
+            Some(matches.swap_remove(seed % matches.len()))
+        }
+    }
+}
+
+fn parse_directive(kind: &str, args: &str) -> Option<Directive> {
+    let mut parts = args.split_whitespace();
+    let file = parts.next()?.to_string();
+    let music = kind == "MUSIC";
+    if file.eq_ignore_ascii_case("off") {
+        return Some(if music {
+            Directive::MusicOff
+        } else {
+            Directive::SoundOff
+        });
+    }
+
+    let mut options = SourceOptions::default();
+    if music {
+        options.continue_playing = Some(true);
+    } else {
+        options.priority = Some(50);
+    }
+    for part in parts {
+        if let Some((key, value)) = part.split_once('=') {
+            match key.to_ascii_uppercase().as_str() {
+                "V" => {
+                    if let Ok(volume) = value.parse::<u8>() {
+                        options.amplify = volume.min(100) as f32 / 100.0;
+                    }
+                }
+                "L" => match value.parse::<i32>() {
+                    Ok(-1) => options.repeat = true,
+                    Ok(loops) if loops > 0 => options.loops = loops as u32,
+                    _ => {}
+                },
+                "P" if !music => {
+                    if let Ok(priority) = value.parse::<u8>() {
+                        options.priority = Some(priority.min(100));
+                    }
+                }
+                "C" if music => options.continue_playing = Some(value != "0"),
+                _ => {}
+            }
+        }
+    }
+
+    Some(if music {
+        Directive::Music(file, options)
+    } else {
+        Directive::Sound(file, options)
+    })
+}
+
+#[cfg(test)]
+mod msp_tests {
+    use std::fs::{self, File};
+
+    use super::*;
+
+    fn sound_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blightmud_msp_{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("weather")).unwrap();
+        File::create(dir.join("bell.wav")).unwrap();
+        File::create(dir.join("theme.mp3")).unwrap();
+        File::create(dir.join("weather").join("rain1.wav")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_sound() {
+        let directive = parse_directive("SOUND", "bell.wav V=50 L=2 P=80 T=misc").unwrap();
+        assert_eq!(
+            directive,
+            Directive::Sound(
+                "bell.wav".to_string(),
+                SourceOptions {
+                    amplify: 0.5,
+                    loops: 2,
+                    priority: Some(80),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(parse_directive("SOUND", "Off"), Some(Directive::SoundOff));
+        assert_eq!(parse_directive("SOUND", ""), None);
+    }
+
+    #[test]
+    fn test_parse_music() {
+        let directive = parse_directive("MUSIC", "theme L=-1 C=0").unwrap();
+        assert_eq!(
+            directive,
+            Directive::Music(
+                "theme".to_string(),
+                SourceOptions {
+                    repeat: true,
+                    continue_playing: Some(false),
+                    ..Default::default()
+                }
+            )
+        );
+        let directive = parse_directive("MUSIC", "theme").unwrap();
+        assert_eq!(
+            directive,
+            Directive::Music(
+                "theme".to_string(),
+                SourceOptions {
+                    continue_playing: Some(true),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            parse_directive("MUSIC", "OFF U=http://x"),
+            Some(Directive::MusicOff)
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = sound_dir("resolve");
+        let msp = Msp::new(dir.clone());
+        assert_eq!(msp.resolve("bell.wav"), Some(dir.join("bell.wav")));
+        assert_eq!(msp.resolve("BELL"), Some(dir.join("bell.wav")));
+        assert_eq!(msp.resolve("the*"), Some(dir.join("theme.mp3")));
+        assert_eq!(
+            msp.resolve("weather/rain?.wav"),
+            Some(dir.join("weather").join("rain1.wav"))
+        );
+        assert_eq!(msp.resolve("missing.wav"), None);
+        assert_eq!(msp.resolve("../bell.wav"), None);
+        assert_eq!(msp.resolve("/etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let dir = sound_dir("parse_line");
+        let mut msp = Msp::new(dir.clone());
+
+        let mut line = Line::from("!!SOUND(bell.wav V=100)");
+        assert!(msp.parse_line(&mut line).is_empty());
+        assert!(!line.flags.gag);
+
+        msp.enable();
+        let events = msp.parse_line(&mut line);
+        assert!(line.flags.gag);
+        assert_eq!(
+            events,
+            vec![Event::PlaySFX(
+                dir.join("bell.wav").to_string_lossy().to_string(),
+                SourceOptions {
+                    priority: Some(50),
+                    ..Default::default()
+                }
+            )]
+        );
+
+        let mut line = Line::from("The bell rings. !!SOUND(bell) !!MUSIC(Off)");
+        let events = msp.parse_line(&mut line);
+        assert!(!line.flags.gag);
+        assert_eq!(line.line(), "The bell rings.");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], Event::StopMusic);
+
+        let mut line = Line::from("!!SOUND(nothing.wav)");
+        assert!(msp.parse_line(&mut line).is_empty());
+        assert!(line.flags.gag);
+
+        let mut line = Line::from("Nothing to see here!!");
+        assert!(msp.parse_line(&mut line).is_empty());
+        assert!(!line.flags.gag);
+        assert_eq!(line.line(), "Nothing to see here!!");
+    }
+}
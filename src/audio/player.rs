@@ -8,12 +8,22 @@ pub struct Player {
     handle: Option<rodio::OutputStreamHandle>,
     music: Option<Sink>,
     sfx: Option<Sink>,
+    music_file: Option<String>,
+    sfx_priority: Option<u8>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceOptions {
     pub repeat: bool,
     pub amplify: f32,
+    /// Number of times to play the file, ignored when `repeat` is set
+    pub loops: u32,
+    /// When set the sfx replaces any playing sfx of equal or lower priority
+    /// instead of being queued.
+    pub priority: Option<u8>,
+    /// When set the music replaces the current music instead of being
+    /// queued. If true the same file already playing will keep playing.
+    pub continue_playing: Option<bool>,
 }
 
 impl Default for SourceOptions {
@@ -21,10 +31,28 @@ impl Default for SourceOptions {
         Self {
             repeat: false,
             amplify: 1.0,
+            loops: 1,
+            priority: None,
+            continue_playing: None,
         }
     }
 }
 
+fn append_source(sink: &Sink, fpath: &str, options: &SourceOptions) -> Result<()> {
+    let loops = if options.repeat { 1 } else { options.loops };
+    for _ in 0..loops {
+        let file = File::open(fpath)?;
+        let source = rodio::Decoder::new(BufReader::new(file))?;
+        let source = source.amplify(options.amplify);
+        if options.repeat {
+            sink.append(source.repeat_infinite());
+        } else {
+            sink.append(source);
+        }
+    }
+    Ok(())
+}
+
 impl Player {
     pub fn new() -> Self {
         let mut music = None;
@@ -43,6 +71,8 @@ impl Player {
             handle,
             music,
             sfx,
+            music_file: None,
+            sfx_priority: None,
         }
     }
 
@@ -52,51 +82,61 @@ impl Player {
             handle: None,
             music: None,
             sfx: None,
+            music_file: None,
+            sfx_priority: None,
         }
     }
 
     pub fn play_music(&mut self, fpath: &str, options: SourceOptions) -> Result<()> {
+        if let Some(continue_playing) = options.continue_playing {
+            let playing = self.music.as_ref().is_some_and(|music| !music.empty());
+            if continue_playing && playing && self.music_file.as_deref() == Some(fpath) {
+                return Ok(());
+            }
+            self.stop_music()?;
+        }
         if self.music.is_none() {
             if let Some(handle) = &self.handle {
                 self.music = rodio::Sink::try_new(handle).ok();
             }
         }
         if let Some(music) = &self.music {
-            let file = File::open(fpath)?;
-            let source = rodio::Decoder::new(BufReader::new(file))?;
-            let source = source.amplify(options.amplify);
-            if options.repeat {
-                music.append(source.repeat_infinite());
-            } else {
-                music.append(source);
-            }
+            append_source(music, fpath, &options)?;
             music.play();
+            self.music_file = Some(fpath.to_string());
         }
         Ok(())
     }
 
     pub fn stop_music(&mut self) -> Result<()> {
         self.music = None;
+        self.music_file = None;
         Ok(())
     }
 
     pub fn play_sfx(&mut self, fpath: &str, options: SourceOptions) -> Result<()> {
+        if let Some(priority) = options.priority {
+            let playing = self.sfx.as_ref().is_some_and(|sfx| !sfx.empty());
+            if playing && self.sfx_priority.is_some_and(|current| current > priority) {
+                return Ok(());
+            }
+            self.stop_sfx()?;
+        }
         if self.sfx.is_none() {
             if let Some(handle) = &self.handle {
                 self.sfx = rodio::Sink::try_new(handle).ok();
             }
         }
         if let Some(sfx) = &self.sfx {
-            let file = File::open(fpath)?;
-            let source = rodio::Decoder::new(BufReader::new(file))?;
-            let source = source.amplify(options.amplify);
-            sfx.append(source);
+            append_source(sfx, fpath, &options)?;
+            self.sfx_priority = options.priority;
         }
         Ok(())
     }
 
     pub fn stop_sfx(&mut self) -> Result<()> {
         self.sfx = None;
+        self.sfx_priority = None;
         Ok(())
     }
 }
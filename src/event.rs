@@ -1,15 +1,20 @@
 use crate::io::FSEvent;
-use crate::net::spawn_connect_thread;
+use crate::net::{
+    spawn_connect_thread, spawn_reconnect_thread, spawn_replay_thread, spawn_send_queue_thread,
+    Encoding, GmcpMessage, HttpResponse, Keepalive, MsspInfo, RateLimit, ReconnectPolicy,
+    ShareOptions,
+};
 use crate::{audio::SourceOptions, model::Regex};
 use crate::{
-    model::{Connection, Line, PromptMask},
+    model::{Connection, Line, PromptMask, StatusLayout, StatusWidget, WindowOptions},
     net::{spawn_receive_thread, spawn_transmit_thread},
     session::Session,
+    timer::TimerEvent,
     tts::TTSEvent,
-    ui::UserInterface,
+    ui::{InputMode, UserInterface},
     TelnetData,
 };
-use libtelnet_rs::{bytes::Bytes, events::TelnetEvents};
+use libtelnet_rs::{bytes::Bytes, Parser};
 use log::debug;
 use std::sync::atomic::Ordering;
 use std::thread::JoinHandle;
@@ -32,24 +37,50 @@ pub enum QuitMethod {
 pub enum Event {
     AddTag(String),
     AddTimedEvent(chrono::Duration, Option<u32>, u32, bool),
+    AddWindow(String, WindowOptions),
     ClearTags,
     ClearTimers,
+    ClearWindow(String),
+    /// Composing several lines at the prompt started or ended
+    Composing(bool),
+    CloseSession(Option<String>),
     Connect(Connection),
     Connected(u16),
+    ConnectionLost(u16),
+    CancelReconnect,
     DisableProto(u8),
     Disconnect,
     DropTimedEvent(u32),
     EnableProto(u8),
     Error(String),
+    /// Opens the input in `$EDITOR`, sending it once saved
+    ExternalEditor(String),
     FindBackward(Regex),
     FindForward(Regex),
+    /// An event sent by the session with the given id
+    FromSession(usize, Box<Event>),
+    GmcpReceive(GmcpMessage),
+    HttpResponse(u32, HttpResponse),
     Info(String),
+    /// The input line was switched to another editing mode
+    InputMode(InputMode),
+    Keepalive(Option<Keepalive>),
+    /// The round trip time of a TIMING-MARK request
+    Latency(time::Duration),
     LoadScript(String),
     EvalScript(String),
+    /// The left mouse button moved while held at a column and row
+    MouseHold(u16, u16),
+    MousePress(u16, u16),
+    MouseRelease(u16, u16),
+    MsspResponse(u32, std::result::Result<MsspInfo, String>),
     MudOutput(Line),
+    NextSession,
+    OpenSession(String),
     Output(Line),
     PlayMusic(String, SourceOptions),
     PlaySFX(String, SourceOptions),
+    PrevSession,
     Prompt(Line),
     ProtoEnabled(u8),
     ProtoSubnegRecv(u8, Bytes),
@@ -57,30 +88,56 @@ pub enum Event {
     Quit(QuitMethod),
     QuitConfirmTimeout,
     Reconnect,
+    ReconnectAttempt(u32, u32, time::Duration),
+    ReconnectFailed(u32),
+    ReconnectPolicy(Option<ReconnectPolicy>),
     Redraw,
     RemoveTimer(u32),
+    RemoveWindow(String),
+    /// Play back a recording at the given speed
+    Replay(String, f64),
     ResetScript,
     ScrollBottom,
     ScrollDown,
     ScrollLock(bool),
     ScrollTop,
     ScrollUp,
+    /// Scroll a window back by some lines, or to its newest line
+    ScrollWindow(String, Option<i32>),
+    SendRate(Option<RateLimit>),
     ServerInput(Line),
     ServerSend(Bytes),
+    SetEncoding(Encoding),
     SettingChanged(String, bool),
+    Share(Option<ShareOptions>),
     ShowHelp(String, bool),
+    /// A listener with the first id accepted a client with the second id
+    SocketAccepted(u32, u32),
+    SocketClosed(u32, Option<String>),
+    SocketConnected(u32),
+    SocketData(u32, String),
     Speak(String, bool),
     SpeakStop,
+    /// Starts composing, with a text and a line to send after the text
+    StartCompose(Option<String>, Option<String>),
     StartLogging(String, bool),
+    StartRecording(Option<String>),
     StatusAreaHeight(u16),
+    StatusLayout(usize, Option<StatusLayout>),
     StatusLine(usize, String),
+    /// Add or update a status widget by name, None removes it
+    StatusWidget(String, Option<StatusWidget>),
     StopLogging,
     StopMusic,
+    StopRecording,
     StopSFX,
+    SwitchSession(String),
     TTSEnabled(bool),
     TTSEvent(TTSEvent),
     TimedEvent(u32),
     TimerTick(u128),
+    /// A line printed by a script in the window named by its flags
+    WindowOutput(Line),
     SetPromptInput(String),
     SetPromptCursorPos(usize),
     SetPromptMask(PromptMask),
@@ -155,9 +212,24 @@ impl EventHandler {
                         logger.log_line("> ", &line)?;
                     }
                     if !line.flags.matched {
-                        if let Ok(mut parser) = self.session.telnet_parser.lock() {
-                            if let TelnetEvents::DataSend(buffer) = parser.send_text(line.line()) {
-                                self.session.main_writer.send(Event::ServerSend(buffer))?;
+                        let data = output_buffer
+                            .encoding()
+                            .encode(&format!("{}\r\n", line.line()))
+                            .into_owned();
+                        let data = Parser::escape_iac(data);
+                        let data = if line.flags.priority {
+                            Some(data)
+                        } else {
+                            self.session
+                                .send_queue
+                                .lock()
+                                .unwrap()
+                                .push(line.line(), data)
+                        };
+                        match data {
+                            Some(data) => self.session.main_writer.send(Event::ServerSend(data))?,
+                            None => {
+                                spawn_send_queue_thread(self.session.clone());
                             }
                         }
                     }
@@ -168,10 +240,21 @@ impl EventHandler {
                 Ok(())
             }
             Event::Connect(connection) => {
+                self.session.cancel_reconnect();
                 self.session.disconnect();
                 spawn_connect_thread(self.session.clone(), connection);
                 Ok(())
             }
+            Event::Replay(path, speed) => {
+                self.session.cancel_reconnect();
+                if self.session.connected() {
+                    self.close_connection(screen, transmit_writer)?;
+                }
+                let (writer, reader): (Sender<TelnetData>, Receiver<TelnetData>) = channel();
+                spawn_replay_thread(self.session.clone(), path, speed, reader);
+                transmit_writer.replace(writer);
+                Ok(())
+            }
             Event::Connected(id) => {
                 let (writer, reader): (Sender<TelnetData>, Receiver<TelnetData>) = channel();
                 spawn_receive_thread(self.session.clone());
@@ -181,7 +264,21 @@ impl EventHandler {
                 let port = self.session.port();
                 debug!("Connected to {}:{}", host, port);
                 screen.set_host(&host, port)?;
+                self.session.apply_profile_settings();
                 if let Ok(mut script) = self.session.lua_script.lock() {
+                    let profile =
+                        Some(self.session.profile()).filter(|profile| !profile.is_empty());
+                    if script.has_other_profile_scripts(&profile) {
+                        // Start from a clean state so nothing of the last world lingers
+                        script.on_reset();
+                        script.set_profile(None);
+                        script.reset((screen.width(), screen.height()))?;
+                        self.session.timer_writer.send(TimerEvent::Clear(true))?;
+                        self.session.reconnect_policy.lock().unwrap().take();
+                    }
+                    let encoding = self.session.output_buffer.lock().unwrap().encoding();
+                    script.set_encoding(encoding.name());
+                    script.set_profile(profile);
                     script.on_connect(&host, port, id);
                     script.get_output_lines().iter().for_each(|l| {
                         screen.print_output(l);
@@ -190,38 +287,92 @@ impl EventHandler {
                 Ok(())
             }
             Event::Disconnect => {
+                self.session.cancel_reconnect();
                 if self.session.connected() {
-                    self.session.disconnect();
                     screen.print_info(&format!(
                         "Disconnecting from: {}:{}",
                         self.session.host(),
                         self.session.port()
                     ));
-                    if let Some(transmit_writer) = &transmit_writer {
-                        transmit_writer.send(None)?;
-                    }
-                    if let Ok(mut script) = self.session.lua_script.lock() {
-                        script.on_disconnect();
-                        script.get_output_lines().iter().for_each(|l| {
-                            screen.print_output(l);
-                        });
+                    self.close_connection(screen, transmit_writer)?;
+                }
+                Ok(())
+            }
+            Event::ConnectionLost(id) => {
+                if self.session.connected() && self.session.connection_id() == id {
+                    let connection = self.current_connection();
+                    self.close_connection(screen, transmit_writer)?;
+                    let policy = self.session.reconnect_policy.lock().unwrap().clone();
+                    if let Some(policy) = policy {
+                        spawn_reconnect_thread(self.session.clone(), policy, connection);
                     }
-                    transmit_writer.take();
-                    screen.set_host("", 0)?;
-                    screen.clear_tags()?;
-                    screen.print_prompt(&Line::from(""));
                 }
                 Ok(())
             }
+            Event::ReconnectAttempt(attempt, attempts, delay) => {
+                screen.print_info(&format!(
+                    "Reconnecting to {}:{} in {:.1}s (attempt {}/{}), press Esc to cancel",
+                    self.session.host(),
+                    self.session.port(),
+                    delay.as_secs_f64(),
+                    attempt,
+                    attempts,
+                ));
+                if let Ok(mut script) = self.session.lua_script.lock() {
+                    script.on_reconnect_attempt(attempt, delay.as_secs_f64());
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+                Ok(())
+            }
+            Event::ReconnectFailed(attempts) => {
+                screen.print_error(&format!(
+                    "Failed to reconnect to {}:{} after {} attempts",
+                    self.session.host(),
+                    self.session.port(),
+                    attempts
+                ));
+                if let Ok(mut script) = self.session.lua_script.lock() {
+                    script.on_reconnect_failed();
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+                Ok(())
+            }
+            Event::CancelReconnect => {
+                if self.session.cancel_reconnect() {
+                    screen.print_info("Reconnect cancelled");
+                }
+                Ok(())
+            }
+            Event::Keepalive(keepalive) => {
+                *self.session.keepalive.lock().unwrap() = keepalive;
+                // Wake up the transmit thread so the change applies right away
+                if let Some(transmit_writer) = &transmit_writer {
+                    transmit_writer.send(Some(Bytes::new()))?;
+                }
+                Ok(())
+            }
+            Event::SendRate(limit) => {
+                self.session.send_queue.lock().unwrap().set_limit(limit);
+                // Anything still queued is sent at the new rate
+                spawn_send_queue_thread(self.session.clone());
+                Ok(())
+            }
+            Event::ReconnectPolicy(policy) => {
+                if policy.is_none() {
+                    self.session.cancel_reconnect();
+                }
+                *self.session.reconnect_policy.lock().unwrap() = policy;
+                Ok(())
+            }
             Event::Reconnect => {
-                let host = self.session.host();
-                let port = self.session.port();
-                let tls = self.session.tls();
-                let verify = self.session.verify_cert();
-                if !host.is_empty() && !port > 0 {
-                    self.session
-                        .main_writer
-                        .send(Event::Connect(Connection::new(&host, port, tls, verify)))?;
+                self.session.cancel_reconnect();
+                let connection = self.current_connection();
+                if !connection.host.is_empty() && !connection.port > 0 {
+                    self.session.main_writer.send(Event::Connect(connection))?;
                 } else {
                     screen.print_error("Reconnect to what?");
                 }
@@ -231,6 +382,50 @@ impl EventHandler {
         }
     }
 
+    /// The settings of the current (or last) connection
+    fn current_connection(&self) -> Connection {
+        let client_cert = self.session.client_cert();
+        Connection {
+            pin_cert: self.session.pin_cert(),
+            encoding: self.session.encoding(),
+            profile: Box::new(self.session.profile()),
+            proxy: self.session.proxy(),
+            client_key: client_cert.as_ref().and_then(|cert| cert.key.clone()),
+            client_cert: client_cert.map(|cert| cert.cert),
+            ..Connection::new(
+                &self.session.host(),
+                self.session.port(),
+                self.session.tls(),
+                self.session.verify_cert(),
+            )
+        }
+    }
+
+    fn close_connection(
+        &mut self,
+        screen: &mut Box<dyn UserInterface>,
+        transmit_writer: &mut Option<Sender<TelnetData>>,
+    ) -> Result {
+        self.session.disconnect();
+        self.session.apply_profile_settings();
+        if let Some(transmit_writer) = &transmit_writer {
+            transmit_writer.send(None)?;
+        }
+        if let Ok(mut script) = self.session.lua_script.lock() {
+            script.set_profile(None);
+            script.on_disconnect();
+            script.get_output_lines().iter().for_each(|l| {
+                screen.print_output(l);
+            });
+        }
+        transmit_writer.take();
+        screen.set_host("", 0)?;
+        screen.set_latency(None)?;
+        screen.clear_tags()?;
+        screen.print_prompt(&Line::from(""));
+        Ok(())
+    }
+
     fn log_line(&self, prefix: &str, line: &Line) -> Result {
         if let Ok(mut logger) = self.session.logger.lock() {
             logger.log_line(prefix, line)?;
@@ -608,4 +803,68 @@ mod event_test {
         session.echo_input.store(false, Ordering::Relaxed);
         send_event();
     }
+
+    #[test]
+    fn test_throttled_input() {
+        let (session, reader, _) = build_session();
+        session
+            .send_queue
+            .lock()
+            .unwrap()
+            .set_limit(Some(RateLimit {
+                lines_per_second: 20.0,
+                burst: 1,
+            }));
+
+        let mut screen = MockUserInterface::new();
+        screen.expect_print_send().return_const(());
+        let mut screen: Box<dyn UserInterface> = Box::new(screen);
+        let mut handler = EventHandler::from(&session);
+        let mut send = |line: Line| {
+            handler
+                .handle_server_events(Event::ServerInput(line), &mut screen, &mut None)
+                .unwrap();
+        };
+        send(Line::from("north"));
+        send(Line::from("south"));
+        let mut flee = Line::from("flee");
+        flee.flags.priority = true;
+        send(flee);
+
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::ServerSend(Bytes::from_static(b"north\r\n")))
+        );
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::ServerSend(Bytes::from_static(b"flee\r\n")))
+        );
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::ServerSend(Bytes::from_static(b"south\r\n")))
+        );
+        assert!(session.send_queue.lock().unwrap().pending().is_empty());
+    }
+
+    #[test]
+    fn test_encoded_input() {
+        let (session, reader, _) = build_session();
+        session.set_encoding(Encoding::for_label("latin1").unwrap());
+
+        let mut screen = MockUserInterface::new();
+        screen.expect_print_send().return_const(());
+        let mut screen: Box<dyn UserInterface> = Box::new(screen);
+        let mut handler = EventHandler::from(&session);
+        handler
+            .handle_server_events(
+                Event::ServerInput(Line::from("café")),
+                &mut screen,
+                &mut None,
+            )
+            .unwrap();
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::ServerSend(Bytes::from_static(b"caf\xe9\r\n")))
+        );
+    }
 }
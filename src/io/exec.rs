@@ -1,7 +1,23 @@
-use std::process::{Command, Output};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
 
 use anyhow::bail;
 use anyhow::Result;
+use lazy_static::lazy_static;
+use ring::rand::{SecureRandom, SystemRandom};
+
+#[cfg(target_os = "macos")]
+const DEFAULT_URL_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_URL_OPENER: &str = "xdg-open";
+
+lazy_static! {
+    static ref URL_OPENER: Mutex<Option<String>> = Mutex::new(None);
+}
 
 pub fn exec(cmd: &str) -> Result<Output> {
     match Command::new("sh").arg("-c").arg(cmd).output() {
@@ -10,13 +26,175 @@ pub fn exec(cmd: &str) -> Result<Output> {
     }
 }
 
+/// The command clicked urls are opened with
+pub fn url_opener() -> String {
+    URL_OPENER
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_URL_OPENER.to_string())
+}
+
+/// Sets the command clicked urls are opened with, None restores the default
+pub fn set_url_opener(cmd: Option<String>) {
+    *URL_OPENER.lock().unwrap() = cmd;
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Splits the opener into arguments with the url in place of `%s`, or after
+/// them. No shell is involved so the url is never interpreted.
+fn url_command(opener: &str, url: &str) -> Vec<String> {
+    let mut args: Vec<String> = opener
+        .split_whitespace()
+        .map(|arg| arg.replace("%s", url))
+        .collect();
+    if !opener.contains("%s") {
+        args.push(url.to_string());
+    }
+    args
+}
+
+/// Opens the url with the url opener without waiting for it to finish
+pub fn open_url(url: &str) -> Result<()> {
+    // Links may come from the server, which must not reach other handlers
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    if url.starts_with('-') || !matches!(scheme.as_deref(), Some("http" | "https")) {
+        bail!(
+            "Refusing to open {}, only http and https urls are opened",
+            url
+        );
+    }
+    let args = url_command(&url_opener(), url);
+    let (program, args) = match args.split_first() {
+        Some(split) => split,
+        None => bail!("No url opener set"),
+    };
+    match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(_) => Ok(()),
+        Err(err) => bail!("Failed to open {}: {}", url, err),
+    }
+}
+
+/// The editor from `$VISUAL` or `$EDITOR`, falling back to vi
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens the text in `$EDITOR` and returns it once the editor exits. This
+/// takes over the terminal, so the screen has to be released first.
+pub fn edit_text(text: &str) -> Result<String> {
+    edit_text_with(&editor(), text)
+}
+
+/// Creates a new file only the user can read, with a name that can't be
+/// guessed in advance
+fn create_temp_file() -> Result<(PathBuf, fs::File)> {
+    let rng = SystemRandom::new();
+    let mut attempt = 0;
+    loop {
+        let mut name = [0u8; 8];
+        if rng.fill(&mut name).is_err() {
+            bail!("Failed to pick a name for a temporary file");
+        }
+        let name: String = name.iter().map(|byte| format!("{byte:02x}")).collect();
+        let path = std::env::temp_dir().join(format!("blightmud-{name}.txt"));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(err) => bail!("Failed to create {}: {}", path.display(), err),
+        }
+    }
+}
+
+fn edit_text_with(editor: &str, text: &str) -> Result<String> {
+    let (path, mut file) = create_temp_file()?;
+    let written = file.write_all(text.as_bytes());
+    drop(file);
+    let status = written.map(|_| {
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "{} {}",
+                editor,
+                shell_quote(&path.to_string_lossy())
+            ))
+            .status()
+    });
+    let edited = fs::read_to_string(&path);
+    fs::remove_file(&path).ok();
+    match status? {
+        Ok(status) if status.success() => Ok(edited?),
+        Ok(status) => bail!("{} exited with {}", editor, status),
+        Err(err) => bail!("Failed to run {}: {}", editor, err),
+    }
+}
+
 #[cfg(test)]
 mod test_exec {
 
+    use std::os::unix::fs::PermissionsExt;
+
     use super::*;
 
     #[test]
     fn test() {
         assert_eq!(b"test\n".to_vec(), exec("echo 'test'").unwrap().stdout);
     }
+
+    #[test]
+    fn test_edit_text() {
+        assert_eq!(
+            edit_text_with("sed -i 's/north/south/'", "go north\nsay hi\n").unwrap(),
+            "go south\nsay hi\n"
+        );
+        assert!(edit_text_with("false", "text").is_err());
+
+        let (path, _) = create_temp_file().unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_open_url_schemes() {
+        assert!(open_url("file:///etc/passwd").is_err());
+        assert!(open_url("-x").is_err());
+        assert!(open_url("ssh://host").is_err());
+    }
+
+    #[test]
+    fn test_url_command() {
+        assert_eq!(
+            url_command("xdg-open", "https://example.com/?a=1&b=2"),
+            vec!["xdg-open", "https://example.com/?a=1&b=2"]
+        );
+        assert_eq!(
+            url_command("firefox --new-tab %s", "http://a.b/it's"),
+            vec!["firefox", "--new-tab", "http://a.b/it's"]
+        );
+        assert_eq!(
+            url_command("  open --url=%s  -g", "http://x/$(id);`id`"),
+            vec!["open", "--url=http://x/$(id);`id`", "-g"]
+        );
+    }
 }
@@ -3,6 +3,7 @@ use chrono::{self, Local};
 use std::{
     fs::File,
     io::{BufWriter, Write},
+    path::PathBuf,
 };
 use strip_ansi_escapes::Writer as StripWriter;
 
@@ -13,7 +14,8 @@ use crate::model::Line;
 
 #[cfg_attr(test, automock)]
 pub trait LogWriter {
-    fn start_logging(&mut self, host: &str) -> Result<()>;
+    /// Starts a log for a host, in `dir` if given
+    fn start_logging(&mut self, host: &str, dir: Option<PathBuf>) -> Result<()>;
 
     fn log_str(&mut self, line: &str) -> Result<()>;
 
@@ -29,16 +31,16 @@ pub struct Logger {
     file: Option<BufWriter<StripWriter<File>>>,
 }
 
-fn get_and_ensure_log_dir(host: &str) -> std::path::PathBuf {
-    let path = crate::DATA_DIR.clone().join("logs").join(host);
+fn get_and_ensure_log_dir(host: &str, dir: Option<PathBuf>) -> PathBuf {
+    let path = dir.unwrap_or_else(|| crate::DATA_DIR.clone().join("logs").join(host));
     std::fs::create_dir_all(&path).ok();
     path
 }
 
 impl LogWriter for Logger {
-    fn start_logging(&mut self, host: &str) -> Result<()> {
+    fn start_logging(&mut self, host: &str, dir: Option<PathBuf>) -> Result<()> {
         if self.file.is_none() {
-            let path = get_and_ensure_log_dir(host);
+            let path = get_and_ensure_log_dir(host, dir);
 
             let logfile = path.join(format!("{}.log", Local::now().format("%Y%m%d.%H:%M:%S")));
             self.file = Some(BufWriter::new(StripWriter::new(File::create(logfile)?)));
@@ -87,7 +89,7 @@ mod logger_tests {
     fn test_logger() {
         let mut logger = Logger::default();
         assert!(!logger.is_logging());
-        logger.start_logging("hostname").unwrap();
+        logger.start_logging("hostname", None).unwrap();
         assert!(logger.is_logging());
         logger.stop_logging().unwrap();
         assert!(!logger.is_logging());
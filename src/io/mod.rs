@@ -1,11 +1,13 @@
 mod exec;
 mod fs_monitor;
 pub mod logger;
+mod recording;
 mod save;
 
-pub use exec::exec;
+pub use exec::{edit_text, exec, open_url, set_url_opener, url_opener};
 pub use fs_monitor::{FSEvent, FSMonitor};
 pub use logger::{LogWriter, Logger};
+pub use recording::{Recorder, RecordingReader};
 pub use save::SaveData;
 
 #[cfg(test)]
@@ -0,0 +1,165 @@
+use anyhow::{bail, Result};
+use chrono::Local;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const MAGIC: &[u8] = b"BLIGHTREC1\n";
+
+/// Writes the raw bytes received from a server to a file along with the time
+/// they arrived.
+///
+/// The file starts with a short magic header followed by one record per read:
+/// milliseconds since the start of the recording (u64), the length of the
+/// data (u32), both little endian, and then the data itself.
+pub struct Recorder {
+    file: BufWriter<File>,
+    path: PathBuf,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.flush()?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            start: Instant::now(),
+        })
+    }
+
+    /// Where recordings end up unless told otherwise
+    pub fn default_path(host: &str) -> PathBuf {
+        let host = if host.is_empty() { "unknown" } else { host };
+        crate::DATA_DIR
+            .join("recordings")
+            .join(host)
+            .join(format!("{}.rec", Local::now().format("%Y%m%d.%H:%M:%S")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, data: &[u8]) -> Result<()> {
+        let offset = self.start.elapsed().as_millis() as u64;
+        self.file.write_all(&offset.to_le_bytes())?;
+        self.file.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.file.write_all(data)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// A chunk of data read from a recording
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecordedChunk {
+    /// Time since the start of the recording
+    pub offset: Duration,
+    pub data: Vec<u8>,
+}
+
+/// Reads back the chunks written by a `Recorder`
+pub struct RecordingReader<R: Read> {
+    reader: R,
+}
+
+impl RecordingReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; MAGIC.len()];
+        if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+            bail!("Not a Blightmud recording");
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<RecordedChunk>> {
+        let mut offset = [0u8; 8];
+        match self.reader.read_exact(&mut offset) {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Some(RecordedChunk {
+            offset: Duration::from_millis(u64::from_le_bytes(offset)),
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = Result<RecordedChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test_recording {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_record_and_read() {
+        let path = crate::DATA_DIR.join("recordings").join("test.rec");
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder.record(b"Welcome\r\n").unwrap();
+        recorder.record(&[255, 251, 201]).unwrap();
+        assert_eq!(recorder.path(), path);
+
+        let chunks = RecordingReader::open(&path)
+            .unwrap()
+            .map(|chunk| chunk.unwrap().data)
+            .collect::<Vec<Vec<u8>>>();
+        assert_eq!(chunks, vec![b"Welcome\r\n".to_vec(), vec![255, 251, 201]]);
+    }
+
+    #[test]
+    fn test_read_offsets() {
+        let mut data = MAGIC.to_vec();
+        data.extend(1500u64.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend(b"hi");
+        let mut reader = RecordingReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            RecordedChunk {
+                offset: Duration::from_millis(1500),
+                data: b"hi".to_vec(),
+            }
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_recordings() {
+        assert!(RecordingReader::new(Cursor::new(b"not a recording".to_vec())).is_err());
+
+        let mut data = MAGIC.to_vec();
+        data.extend(0u64.to_le_bytes());
+        data.extend(10u32.to_le_bytes());
+        data.extend(b"short");
+        let mut reader = RecordingReader::new(Cursor::new(data)).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
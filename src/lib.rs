@@ -18,6 +18,7 @@ mod lua;
 mod model;
 mod net;
 mod session;
+mod session_manager;
 mod timer;
 mod tools;
 mod tts;
@@ -25,16 +26,18 @@ mod ui;
 
 use crate::event::{spawn_quit_confirm_timeout_thread, Event, QuitMethod};
 use crate::io::{FSMonitor, SaveData};
-use crate::model::{Servers, ECHO_INPUT, HIDE_TOPBAR, READER_MODE, SCROLL_SPLIT};
-use crate::session::{Session, SessionBuilder};
-use crate::timer::{spawn_timer_thread, TimerEvent};
+use crate::model::{
+    Servers, ECHO_INPUT, HIDE_TOPBAR, READER_MODE, SCROLL_SPLIT, SHOW_LATENCY, VI_MODE,
+};
+use crate::session::SessionBuilder;
+use crate::session_manager::{SessionManager, SessionSlot};
+use crate::timer::TimerEvent;
 use crate::tools::patch::migrate_v2_settings_and_servers;
 use crate::tools::util::expand_tilde;
-use crate::ui::{spawn_input_thread, UiWrapper, UserInterface};
-use event::EventHandler;
+use crate::ui::{send_input, spawn_input_thread, suspend_screen};
 use getopts::Matches;
-use model::{Connection, Settings, CONFIRM_QUIT, LOGGING_ENABLED, SAVE_HISTORY};
-use net::check_latest_version;
+use model::{Connection, Line, Settings, CONFIRM_QUIT, LOGGING_ENABLED, SAVE_HISTORY};
+use net::{check_latest_version, query_mssp, ShareOptions};
 
 pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), env!("GIT_DESCRIBE"));
 pub const PROJECT_NAME: &str = env!("CARGO_PKG_NAME");
@@ -94,9 +97,8 @@ lazy_static! {
         PathBuf::from(expand_tilde("~/Library/Application Support/blightmud").as_ref());
 }
 
-fn register_terminal_resize_listener(session: Session) -> thread::JoinHandle<()> {
+fn register_terminal_resize_listener(main_thread_writer: Sender<Event>) -> thread::JoinHandle<()> {
     let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGWINCH]).unwrap();
-    let main_thread_writer = session.main_writer;
     thread::Builder::new()
         .name("signal-thread".to_string())
         .spawn(move || {
@@ -138,13 +140,32 @@ pub struct RuntimeConfig {
     pub connect: Option<String>,
     pub script: Option<String>,
     pub eval: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub replay_speed: Option<f64>,
+    pub share: Option<u16>,
+    pub share_password: Option<String>,
     pub integration_test: bool,
 }
 
+impl RuntimeConfig {
+    /// Checks for options that can't be used as given
+    pub fn validate(&self) -> Result<()> {
+        if self.share.is_some() && self.share_password.is_none() {
+            bail!("--share requires a password in $BLIGHTMUD_SHARE_PASSWORD");
+        }
+        Ok(())
+    }
+}
+
 impl From<Matches> for RuntimeConfig {
     fn from(matches: Matches) -> Self {
         let world = matches.opt_get::<String>("world").ok().unwrap();
         let connect = matches.opt_get::<String>("connect").ok().unwrap();
+        let record = matches.opt_get::<String>("record").ok().unwrap();
+        let replay = matches.opt_get::<String>("replay").ok().unwrap();
+        let replay_speed = matches.opt_get::<f64>("replay-speed").ok().flatten();
+        let share = matches.opt_get::<u16>("share").ok().flatten();
         Self {
             reader_mode: matches.opt_present("reader-mode"),
             headless_mode: false,
@@ -156,6 +177,11 @@ impl From<Matches> for RuntimeConfig {
             connect,
             script: None,
             eval: None,
+            record,
+            replay,
+            replay_speed,
+            share,
+            share_password: None,
             integration_test: false,
         }
     }
@@ -175,7 +201,6 @@ pub fn start(rt: RuntimeConfig) -> Result<()> {
     info!("Starting application");
 
     let (main_writer, main_thread_read): (Sender<Event>, Receiver<Event>) = channel();
-    let timer_writer = spawn_timer_thread(main_writer.clone());
 
     let mut settings = Settings::try_load().expect("Error loading settings.ron");
     if rt.reader_mode {
@@ -185,18 +210,15 @@ pub fn start(rt: RuntimeConfig) -> Result<()> {
     let reader_mode = settings.get(READER_MODE).unwrap_or(rt.reader_mode);
 
     let dimensions = termion::terminal_size().unwrap_or((100, 100));
-    let session = SessionBuilder::new()
-        .main_writer(main_writer)
-        .timer_writer(timer_writer)
+    let builder = SessionBuilder::new()
         .screen_dimensions(dimensions)
         .tts_enabled(rt.use_tts)
         .reader_mode(reader_mode)
         .headless(rt.headless_mode)
         .save_history(settings.get(SAVE_HISTORY).unwrap())
-        .echo_input(settings.get(ECHO_INPUT).unwrap())
-        .build();
+        .echo_input(settings.get(ECHO_INPUT).unwrap());
 
-    if let Err(error) = run(main_thread_read, session, rt) {
+    if let Err(error) = run(main_thread_read, main_writer, builder, rt) {
         error!("Panic: {}", error);
         Err(error)
     } else {
@@ -205,6 +227,20 @@ pub fn start(rt: RuntimeConfig) -> Result<()> {
     }
 }
 
+/// Prints the MSSP information of a server without starting a session
+pub fn print_mssp(address: &str) -> Result<()> {
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>()?),
+        None => bail!("Expected HOST:PORT, got: {}", address),
+    };
+    let info = query_mssp(host, port)?;
+    let width = info.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, values) in info {
+        println!("{name:width$}  {}", values.join(", "));
+    }
+    Ok(())
+}
+
 fn handle_config(main_writer: &Sender<Event>, rt: &RuntimeConfig) {
     if let Some(path) = &rt.script {
         main_writer.send(Event::LoadScript(path.clone())).ok();
@@ -212,7 +248,24 @@ fn handle_config(main_writer: &Sender<Event>, rt: &RuntimeConfig) {
     if let Some(script) = &rt.eval {
         main_writer.send(Event::EvalScript(script.clone())).ok();
     }
-    if let Some(connect) = &rt.connect {
+    if let Some(path) = &rt.record {
+        main_writer
+            .send(Event::StartRecording(Some(path.clone())))
+            .ok();
+    }
+    if let Some(port) = rt.share {
+        let options = ShareOptions {
+            password: rt.share_password.clone(),
+            ..ShareOptions::new(port)
+        };
+        main_writer.send(Event::Share(Some(options))).ok();
+    }
+    if let Some(replay) = &rt.replay {
+        let speed = rt.replay_speed.unwrap_or(1.0);
+        main_writer
+            .send(Event::Replay(replay.clone(), speed))
+            .unwrap();
+    } else if let Some(connect) = &rt.connect {
         let split: Vec<&str> = connect.split(':').collect();
         let host = split[0];
         let port: u16 = split[1].parse().unwrap();
@@ -233,10 +286,36 @@ fn handle_config(main_writer: &Sender<Event>, rt: &RuntimeConfig) {
     }
 }
 
-fn run(main_thread_read: Receiver<Event>, mut session: Session, rt: RuntimeConfig) -> Result<()> {
-    let mut transmit_writer: Option<Sender<TelnetData>> = None;
-    let help_handler = HelpHandler::new(session.main_writer.clone());
-    let mut event_handler = EventHandler::from(&session);
+/// The Lua scripts in the config dir that are loaded into every session
+fn user_scripts(rt: &RuntimeConfig) -> Result<Vec<PathBuf>> {
+    if rt.integration_test {
+        return Ok(vec![]);
+    }
+    Ok(fs::read_dir(CONFIG_DIR.as_path())?
+        .filter_map(|entry| match entry {
+            Ok(file) => {
+                if let Ok(filename) = file.file_name().into_string() {
+                    if filename.ends_with(".lua") {
+                        Some(file.path())
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect::<Vec<PathBuf>>())
+}
+
+fn run(
+    main_thread_read: Receiver<Event>,
+    main_writer: Sender<Event>,
+    builder: SessionBuilder,
+    rt: RuntimeConfig,
+) -> Result<()> {
+    let help_handler = HelpHandler::new(main_writer.clone());
 
     let mut player = if !rt.integration_test {
         Player::new()
@@ -244,49 +323,24 @@ fn run(main_thread_read: Receiver<Event>, mut session: Session, rt: RuntimeConfi
         Player::disabled()
     };
 
-    let mut screen: Box<dyn UserInterface> = if !rt.headless_mode {
-        Box::new(UiWrapper::new(&session)?)
-    } else {
-        Box::new(UiWrapper::headless(&session)?)
-    };
+    let mut sessions = SessionManager::new(
+        main_writer.clone(),
+        builder,
+        rt.headless_mode,
+        user_scripts(&rt)?,
+    )?;
+    let main_session = sessions.active_slot_mut().session.clone();
 
-    let mut fs_monitor = FSMonitor::new(session.main_writer.clone())?;
+    let mut fs_monitor = FSMonitor::new(main_writer.clone())?;
 
-    screen.setup()?;
-
-    let _ = spawn_input_thread(session.clone());
-    let _ = register_terminal_resize_listener(session.clone());
-
-    let lua_scripts = if !rt.integration_test {
-        fs::read_dir(CONFIG_DIR.as_path())?
-            .filter_map(|entry| match entry {
-                Ok(file) => {
-                    if let Ok(filename) = file.file_name().into_string() {
-                        if filename.ends_with(".lua") {
-                            Some(file.path())
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            })
-            .collect::<Vec<PathBuf>>()
-    } else {
-        vec![]
-    };
+    sessions.active_slot_mut().screen.setup()?;
 
-    for script in lua_scripts {
-        session
-            .main_writer
-            .send(Event::LoadScript(script.to_str().unwrap().to_string()))?;
-    }
+    let _ = spawn_input_thread(sessions.active_session());
+    let _ = register_terminal_resize_listener(main_writer.clone());
 
-    check_latest_version(session.main_writer.clone());
+    check_latest_version(main_session.main_writer.clone());
     if cfg!(not(debug_assertions)) {
-        migrate_v2_settings_and_servers(session.main_writer.clone());
+        migrate_v2_settings_and_servers(main_session.main_writer.clone());
     }
 
     #[cfg(all(not(debug_assertions), target_os = "macos"))]
@@ -297,7 +351,7 @@ Please move your Lua scripts to ~/.config/blightmud
 Please move your data/config/log dirs to ~/.local/share/blightmud
 For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
             for line in msg.lines() {
-                session
+                main_session
                     .main_writer
                     .send(Event::Error(line.to_string()))
                     .unwrap();
@@ -305,11 +359,52 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
         }
     }
 
-    handle_config(&session.main_writer, &rt);
+    handle_config(&main_session.main_writer, &rt);
 
     let mut quit_pending = false;
     let mut quit_error: Option<String> = None;
+    let mut show_latency = Settings::load().get(SHOW_LATENCY)?;
     while let Ok(event) = main_thread_read.recv() {
+        // Events without a session are global and handled by the one on screen
+        let (index, event) = match event {
+            Event::FromSession(id, event) => match sessions.index_of(id) {
+                Some(index) => (index, *event),
+                None => continue,
+            },
+            event => (sessions.active_index(), event),
+        };
+        let index = match event {
+            Event::Redraw
+            | Event::SettingChanged(_, _)
+            | Event::Quit(_)
+            | Event::QuitConfirmTimeout => sessions.active_index(),
+            _ => index,
+        };
+        match event {
+            Event::OpenSession(_)
+            | Event::SwitchSession(_)
+            | Event::NextSession
+            | Event::PrevSession
+            | Event::CloseSession(_)
+            | Event::FSEvent(_) => {
+                sessions.handle_events(event)?;
+                sessions.active_slot_mut().screen.flush();
+                continue;
+            }
+            Event::SettingChanged(ref name, value) if name == READER_MODE => {
+                sessions.set_reader_mode(value)?;
+                continue;
+            }
+            _ => sessions.note_activity(index, &event)?,
+        }
+
+        let SessionSlot {
+            session,
+            screen,
+            event_handler,
+            transmit_writer,
+            ..
+        } = sessions.slot_mut(index);
         match event {
             Event::SetPromptInput(line) => {
                 if let Ok(mut buffer) = session.command_buffer.lock() {
@@ -336,17 +431,62 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                         .unwrap();
                 }
             }
+            Event::InputMode(mode) => screen.set_input_mode(mode)?,
+            Event::Composing(composing) => screen.set_composing(composing)?,
+            Event::StartCompose(text, terminator) => {
+                if let Ok(mut buffer) = session.command_buffer.lock() {
+                    buffer.start_compose(text, terminator);
+                    if let Ok(mut script) = session.lua_script.lock() {
+                        script.set_prompt_content(buffer.get_buffer(), buffer.get_pos());
+                    }
+                    session
+                        .main_writer
+                        .send(Event::UserInputBuffer(
+                            buffer.get_buffer(),
+                            buffer.get_pos(),
+                        ))
+                        .unwrap();
+                }
+                screen.set_composing(true)?;
+            }
+            Event::ExternalEditor(text) => {
+                let edited = suspend_screen(screen.as_mut(), || io::edit_text(&text));
+                session.external_editor.store(false, Ordering::Relaxed);
+                match edited {
+                    Ok(text) => {
+                        if let Ok(mut buffer) = session.command_buffer.lock() {
+                            send_input(buffer.finish_editor(&text), &session.main_writer);
+                            screen.set_composing(buffer.is_composing())?;
+                            if let Ok(mut script) = session.lua_script.lock() {
+                                script.set_prompt_content(buffer.get_buffer(), buffer.get_pos());
+                            }
+                            session
+                                .main_writer
+                                .send(Event::UserInputBuffer(
+                                    buffer.get_buffer(),
+                                    buffer.get_pos(),
+                                ))
+                                .unwrap();
+                        }
+                    }
+                    Err(err) => screen.print_error(&err.to_string()),
+                }
+            }
             Event::ServerSend(_)
             | Event::ServerInput(_)
             | Event::Connect(_)
             | Event::Connected(_)
+            | Event::ConnectionLost(_)
+            | Event::CancelReconnect
             | Event::Reconnect
+            | Event::ReconnectAttempt(_, _, _)
+            | Event::ReconnectFailed(_)
+            | Event::ReconnectPolicy(_)
+            | Event::Keepalive(_)
+            | Event::SendRate(_)
+            | Event::Replay(_, _)
             | Event::Disconnect => {
-                event_handler.handle_server_events(
-                    event.clone(),
-                    &mut screen,
-                    &mut transmit_writer,
-                )?;
+                event_handler.handle_server_events(event.clone(), screen, transmit_writer)?;
             }
             Event::MudOutput(_)
             | Event::Output(_)
@@ -360,7 +500,7 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
             | Event::SetPromptMask(_)
             | Event::ClearPromptMask => {
                 //tts_ctrl.handle_events(event.clone());
-                event_handler.handle_output_events(event, &mut screen)?;
+                event_handler.handle_output_events(event, screen)?;
             }
             Event::PlayMusic(_, _) | Event::StopMusic | Event::PlaySFX(_, _) | Event::StopSFX => {
                 if let Err(err) = audio::handle_audio_event(event, &mut player) {
@@ -372,21 +512,28 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                     lua.set_tts_enabled(enabled);
                 }
                 session.tts_ctrl.lock().unwrap().enabled(enabled);
+                let info = session.new_environ.lock().unwrap().set_tts_enabled(enabled);
+                session.send_new_environ_info(info);
             }
             Event::Speak(msg, interupt) => session.tts_ctrl.lock().unwrap().speak(&msg, interupt),
             Event::SpeakStop => session.tts_ctrl.lock().unwrap().flush(),
             Event::TTSEvent(event) => session.tts_ctrl.lock().unwrap().handle(event),
             Event::SettingChanged(name, value) => match name.as_str() {
-                READER_MODE => {
-                    if let Ok(mut lua) = session.lua_script.lock() {
-                        lua.set_reader_mode(value);
-                    }
-                    screen = Box::new(UiWrapper::new_from(screen, &session, value)?);
-                }
                 HIDE_TOPBAR | SCROLL_SPLIT => {
                     screen.setup()?;
                 }
                 ECHO_INPUT => session.echo_input.store(value, Ordering::Relaxed),
+                VI_MODE => {
+                    let mut buffer = session.command_buffer.lock().unwrap();
+                    buffer.set_vi_mode(value);
+                    screen.set_input_mode(buffer.mode())?;
+                }
+                SHOW_LATENCY => {
+                    show_latency = value;
+                    if !value {
+                        screen.set_latency(None)?;
+                    }
+                }
                 _ => {}
             },
             Event::StartLogging(world, force) => {
@@ -397,6 +544,18 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
             Event::StopLogging => {
                 session.stop_logging();
             }
+            Event::Latency(latency) => {
+                if let Ok(mut script) = session.lua_script.lock() {
+                    script.set_latency(latency);
+                }
+                if show_latency {
+                    screen.set_latency(Some(latency))?;
+                }
+            }
+            Event::SetEncoding(encoding) => session.set_encoding(encoding),
+            Event::StartRecording(path) => session.start_recording(path),
+            Event::StopRecording => session.stop_recording(),
+            Event::Share(options) => session.share(options),
             Event::EnableProto(proto) => {
                 if let Ok(mut parser) = session.telnet_parser.lock() {
                     parser.options.support(proto);
@@ -436,6 +595,17 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                     });
                 }
             }
+            Event::GmcpReceive(message) => {
+                if session.gmcp.lock().unwrap().echo() {
+                    screen.print_output(&Line::from(format!("[GMCP]: {message}")));
+                }
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_gmcp(&message);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
             Event::ProtoSubnegSend(proto, data) => {
                 if let Ok(mut parser) = session.telnet_parser.lock() {
                     if let Some(TelnetEvents::DataSend(data)) = parser.subnegotiation(proto, data) {
@@ -450,10 +620,29 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
             | Event::ScrollBottom
             | Event::FindForward(_)
             | Event::FindBackward(_) => {
-                event_handler.handle_scroll_events(event, &mut screen)?;
+                event_handler.handle_scroll_events(event, screen)?;
             }
             Event::StatusAreaHeight(height) => screen.set_status_area_height(height)?,
             Event::StatusLine(index, info) => screen.set_status_line(index, info)?,
+            Event::StatusLayout(index, layout) => screen.set_status_layout(index, layout)?,
+            Event::StatusWidget(name, widget) => screen.set_status_widget(&name, widget)?,
+            Event::AddWindow(name, options) => screen.add_window(&name, options)?,
+            Event::RemoveWindow(name) => screen.remove_window(&name)?,
+            Event::ClearWindow(name) => screen.clear_window(&name)?,
+            Event::ScrollWindow(name, lines) => screen.scroll_window(&name, lines)?,
+            Event::WindowOutput(line) => screen.print_output(&line),
+            Event::MousePress(x, y) => screen.mouse_press(x, y)?,
+            Event::MouseHold(x, y) => screen.mouse_hold(x, y)?,
+            Event::MouseRelease(x, y) => {
+                if let Some(link) = screen.mouse_release(x, y)? {
+                    if let Ok(script) = session.lua_script.lock() {
+                        script.on_link_click(&link);
+                        script.get_output_lines().iter().for_each(|l| {
+                            screen.print_output(l);
+                        });
+                    }
+                }
+            }
             Event::LoadScript(path) => {
                 info!("Loading script: {}", path);
                 let mut lua = session.lua_script.lock().unwrap();
@@ -497,6 +686,7 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                     screen.print_info("Done");
                 }
                 session.timer_writer.send(TimerEvent::Clear(true))?;
+                session.reconnect_policy.lock().unwrap().take();
             }
             Event::ShowHelp(hfile, lock) => {
                 help_handler.show_help(&hfile, lock)?;
@@ -514,6 +704,54 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                     });
                 }
             }
+            Event::HttpResponse(id, response) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_http_response(id, response);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
+            Event::MsspResponse(id, result) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_mssp_response(id, result);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
+            Event::SocketAccepted(listener_id, id) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_socket_accept(listener_id, id);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
+            Event::SocketConnected(id) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_socket_connect(id);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
+            Event::SocketData(id, data) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_socket_data(id, &data);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
+            Event::SocketClosed(id, reason) => {
+                if let Ok(script) = session.lua_script.lock() {
+                    script.on_socket_close(id, reason);
+                    script.get_output_lines().iter().for_each(|l| {
+                        screen.print_output(l);
+                    });
+                }
+            }
             Event::TimerTick(millis) => {
                 if let Ok(mut script) = session.lua_script.lock() {
                     script.tick(millis);
@@ -540,14 +778,6 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                     screen.print_error(&format!("Failed to monitor `{path}`: {err}"));
                 }
             }
-            Event::FSEvent(e) => {
-                if let Ok(script) = session.lua_script.lock() {
-                    script.handle_fs_event(e)?;
-                    script.get_output_lines().iter().for_each(|l| {
-                        screen.print_output(l);
-                    });
-                }
-            }
             Event::Redraw => {
                 screen.setup()?;
                 if let Ok(mut script) = session.lua_script.lock() {
@@ -579,18 +809,17 @@ For more info: https://github.com/LiquidityC/Blightmud/issues/173"#;
                 info!("ctrl-c quit confirmation timed out");
                 quit_pending = false;
             }
+            Event::OpenSession(_)
+            | Event::SwitchSession(_)
+            | Event::NextSession
+            | Event::PrevSession
+            | Event::CloseSession(_)
+            | Event::FSEvent(_)
+            | Event::FromSession(_, _) => {}
         };
         screen.flush();
     }
-    if let Ok(lua) = session.lua_script.lock() {
-        lua.on_quit();
-        lua.get_output_lines().iter().for_each(|l| {
-            screen.print_output(l);
-        });
-        screen.flush();
-    }
-    screen.reset()?;
-    session.close()?;
+    sessions.close_all()?;
     match quit_error {
         Some(error) => {
             bail!("{}", error)
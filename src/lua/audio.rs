@@ -9,6 +9,9 @@ fn parse_audio_options(opts: &Option<Table>) -> SourceOptions {
     if let Some(opts) = &opts {
         options.repeat = opts.get("loop").unwrap_or(options.repeat);
         options.amplify = opts.get("amplify").unwrap_or(options.amplify);
+        options.loops = opts.get("loops").unwrap_or(options.loops);
+        options.priority = opts.get("priority").unwrap_or(options.priority);
+        options.continue_playing = opts.get("continue").unwrap_or(options.continue_playing);
     }
     options
 }
@@ -95,6 +98,7 @@ mod test_player {
                 SourceOptions {
                     repeat: false,
                     amplify: 0.5,
+                    ..Default::default()
                 },
             ),
         );
@@ -105,6 +109,32 @@ mod test_player {
                 SourceOptions {
                     repeat: true,
                     amplify: 2.5,
+                    ..Default::default()
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_msp_options() {
+        assert_event(
+            r#"audio.play_sfx("test", { loops=3, priority=80 })"#,
+            Event::PlaySFX(
+                "test".to_string(),
+                SourceOptions {
+                    loops: 3,
+                    priority: Some(80),
+                    ..Default::default()
+                },
+            ),
+        );
+        assert_event(
+            r#"audio.play_music("test", { continue=false })"#,
+            Event::PlayMusic(
+                "test".to_string(),
+                SourceOptions {
+                    continue_playing: Some(false),
+                    ..Default::default()
                 },
             ),
         );
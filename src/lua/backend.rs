@@ -1,17 +1,21 @@
-use std::sync::mpsc::Sender;
+use std::sync::{mpsc::Sender, Arc, Mutex};
 
 use mlua::UserData;
 
-use crate::event::Event;
+use crate::{event::Event, net::SendQueue};
 
 #[derive(Clone)]
 pub struct Backend {
     pub writer: Sender<Event>,
+    pub send_queue: Arc<Mutex<SendQueue>>,
 }
 
 impl Backend {
     pub fn new(writer: Sender<Event>) -> Self {
-        Self { writer }
+        Self {
+            writer,
+            send_queue: Arc::default(),
+        }
     }
 }
 
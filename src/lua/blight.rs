@@ -1,6 +1,6 @@
 use super::{constants::*, regex::Regex, ui_event::UiEvent};
 use crate::event::{Event, QuitMethod};
-use crate::{model::Line, PROJECT_NAME, VERSION};
+use crate::{io, model::Line, PROJECT_NAME, VERSION};
 use log::debug;
 use mlua::{
     AnyUserData, Function, Result as LuaResult, Table, UserData, UserDataMethods, Variadic,
@@ -142,6 +142,20 @@ impl UserData for Blight {
             table.set(table.raw_len() + 1, func)?;
             Ok(())
         });
+        methods.add_function("on_link_click", |ctx, func: Function| -> mlua::Result<()> {
+            let table: Table = ctx.named_registry_value(LINK_CLICK_LISTENER_TABLE)?;
+            table.set(table.raw_len() + 1, func)?;
+            Ok(())
+        });
+        methods.add_function(
+            "url_opener",
+            |_, cmd: Option<String>| -> mlua::Result<String> {
+                if let Some(cmd) = cmd {
+                    io::set_url_opener((!cmd.trim().is_empty()).then_some(cmd));
+                }
+                Ok(io::url_opener())
+            },
+        );
         methods.add_function(
             "on_complete",
             |ctx, func: mlua::Function| -> mlua::Result<()> {
@@ -204,7 +218,8 @@ mod test_blight {
     use super::Blight;
     use crate::lua::constants::{
         BLIGHT_ON_DIMENSIONS_CHANGE_LISTENER_TABLE, BLIGHT_ON_QUIT_LISTENER_TABLE,
-        COMMAND_BINDING_TABLE, COMPLETION_CALLBACK_TABLE, STATUS_AREA_HEIGHT,
+        COMMAND_BINDING_TABLE, COMPLETION_CALLBACK_TABLE, LINK_CLICK_LISTENER_TABLE,
+        STATUS_AREA_HEIGHT,
     };
     use crate::{PROJECT_NAME, VERSION};
 
@@ -226,6 +241,8 @@ mod test_blight {
             .unwrap();
         lua.set_named_registry_value(COMMAND_BINDING_TABLE, lua.create_table().unwrap())
             .unwrap();
+        lua.set_named_registry_value(LINK_CLICK_LISTENER_TABLE, lua.create_table().unwrap())
+            .unwrap();
         lua.set_named_registry_value(STATUS_AREA_HEIGHT, 1u16)
             .unwrap();
         (lua, reader)
@@ -288,6 +305,28 @@ mod test_blight {
         assert_eq!(table.raw_len(), 1);
     }
 
+    #[test]
+    fn on_link_click() {
+        let (lua, _reader) = get_lua_state();
+        lua.load("blight.on_link_click(function () end)")
+            .exec()
+            .unwrap();
+        let table: mlua::Table = lua.named_registry_value(LINK_CLICK_LISTENER_TABLE).unwrap();
+        assert_eq!(table.raw_len(), 1);
+    }
+
+    #[test]
+    fn test_url_opener() {
+        let (lua, _reader) = get_lua_state();
+        let opener = |script: &str| lua.load(script).call::<_, String>(()).unwrap();
+        assert_eq!(
+            opener("return blight.url_opener(\"firefox %s\")"),
+            "firefox %s"
+        );
+        assert_eq!(opener("return blight.url_opener()"), "firefox %s");
+        assert_ne!(opener("return blight.url_opener(\"\")"), "");
+    }
+
     #[test]
     fn on_quit_function() {
         let (lua, _reader) = get_lua_state();
@@ -1,5 +1,7 @@
 pub const ON_CONNECTION_CALLBACK_TABLE: &str = "__connection_callback_table";
 pub const ON_DISCONNECT_CALLBACK_TABLE: &str = "__disconnect_callback_table";
+pub const ON_RECONNECT_ATTEMPT_CALLBACK_TABLE: &str = "__reconnect_attempt_callback_table";
+pub const ON_RECONNECT_FAILED_CALLBACK_TABLE: &str = "__reconnect_failed_callback_table";
 pub const IS_CONNECTED: &str = "__is_connected_bool";
 pub const TIMED_CALLBACK_TABLE: &str = "__timed_callback_table";
 pub const TIMED_CALLBACK_TABLE_CORE: &str = "__timed_callback_table_core";
@@ -13,6 +15,9 @@ pub const BLIGHT_ON_QUIT_LISTENER_TABLE: &str = "__on_quit_listeners";
 pub const BLIGHT_ON_DIMENSIONS_CHANGE_LISTENER_TABLE: &str = "__on_dimensions_change_listeners";
 pub const BACKEND: &str = "__blight_backend_wrapper";
 pub const CONNECTION_ID: &str = "__blight_connection_id";
+pub const ENCODING: &str = "__blight_encoding";
+pub const PROFILE: &str = "__blight_profile";
+pub const LATENCY: &str = "__blight_latency";
 pub const COMPLETION_CALLBACK_TABLE: &str = "__completion_callback_table";
 pub const PROMPT_CONTENT: &str = "__prompt_content";
 pub const PROMPT_CURSOR_INDEX: &str = "__prompt_cursor_index";
@@ -20,6 +25,13 @@ pub const PROMPT_MASK_CONTENT: &str = "__prompt_mask_content";
 pub const PROMPT_INPUT_LISTENER_TABLE: &str = "__prompt_listeners";
 pub const FS_LISTENERS: &str = "__fs_listeners";
 pub const SCRIPT_RESET_LISTENERS: &str = "__script_reset_listeners";
+pub const SOCKET_CALLBACK_TABLE: &str = "__socket_callback_table";
+pub const HTTP_CALLBACK_TABLE: &str = "__http_callback_table";
+pub const MSSP_CALLBACK_TABLE: &str = "__mssp_callback_table";
+pub const GMCP_LISTENER_TABLE: &str = "__gmcp_listener_table";
+pub const GMCP_READY_LISTENER_TABLE: &str = "__gmcp_ready_listener_table";
+pub const LINK_CALLBACK_TABLE: &str = "__link_callback_table";
+pub const LINK_CLICK_LISTENER_TABLE: &str = "__link_click_listener_table";
 pub const STATUS_AREA_HEIGHT: &str = "__status_area_height";
 
 // Core tables
@@ -1,16 +1,59 @@
-use std::sync::mpsc::Sender;
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::Sender,
+    },
+    time::Duration,
+};
 
 use libtelnet_rs::bytes::Bytes;
 use log::debug;
 use mlua::{AnyUserData, Table, UserData, UserDataMethods};
 
-use crate::{event::Event, io::exec};
+use crate::{
+    event::Event,
+    io::exec,
+    net::{spawn_http_request, spawn_mssp_query, HttpRequest},
+};
 
 use super::{
-    constants::{PROTO_ENABLED_LISTENERS_TABLE, PROTO_SUBNEG_LISTENERS_TABLE},
+    constants::{
+        HTTP_CALLBACK_TABLE, MSSP_CALLBACK_TABLE, PROTO_ENABLED_LISTENERS_TABLE,
+        PROTO_SUBNEG_LISTENERS_TABLE,
+    },
     exec_response::ExecResponse,
 };
 
+/// Shared by all scripts so a response to a request made before a script
+/// reset can't reach a callback registered after it.
+static NEXT_HTTP_ID: AtomicU32 = AtomicU32::new(1);
+static NEXT_MSSP_ID: AtomicU32 = AtomicU32::new(1);
+
+fn parse_http_request(
+    method: &str,
+    url: &str,
+    options: Option<Table>,
+) -> mlua::Result<HttpRequest> {
+    let mut request = HttpRequest::new(method, url);
+    if let Some(options) = options {
+        if let Some(headers) = options.get::<_, Option<Table>>("headers")? {
+            for pair in headers.pairs::<String, String>() {
+                request.headers.push(pair?);
+            }
+        }
+        request.body = options.get("body")?;
+        if let Some(timeout) = options.get::<_, Option<f64>>("timeout")? {
+            if timeout <= 0.0 {
+                return Err(mlua::Error::external(
+                    "Timeout must be positive".to_string(),
+                ));
+            }
+            request.timeout = Duration::from_secs_f64(timeout);
+        }
+    }
+    Ok(request)
+}
+
 #[derive(Debug, Clone)]
 pub struct Core {
     main_writer: Sender<Event>,
@@ -84,6 +127,37 @@ impl UserData for Core {
                 }
             },
         );
+        methods.add_function(
+            "http_request",
+            |ctx,
+             (method, url, options, callback): (
+                String,
+                String,
+                Option<Table>,
+                mlua::Function,
+            )| {
+                let request = parse_http_request(&method, &url, options)?;
+                let id = NEXT_HTTP_ID.fetch_add(1, Ordering::Relaxed);
+                let table: Table = ctx.named_registry_value(HTTP_CALLBACK_TABLE)?;
+                table.raw_set(id, callback)?;
+                let this_aux = ctx.globals().get::<_, AnyUserData>("core")?;
+                let this = this_aux.borrow::<Core>()?;
+                spawn_http_request(id, request, this.main_writer.clone());
+                Ok(id)
+            },
+        );
+        methods.add_function(
+            "mssp_query",
+            |ctx, (host, port, callback): (String, u16, mlua::Function)| {
+                let id = NEXT_MSSP_ID.fetch_add(1, Ordering::Relaxed);
+                let table: Table = ctx.named_registry_value(MSSP_CALLBACK_TABLE)?;
+                table.raw_set(id, callback)?;
+                let this_aux = ctx.globals().get::<_, AnyUserData>("core")?;
+                let this = this_aux.borrow::<Core>()?;
+                spawn_mssp_query(id, host, port, this.main_writer.clone());
+                Ok(id)
+            },
+        );
         methods.add_function("time", |_, ()| -> Result<i64, mlua::Error> {
             Ok(chrono::Local::now().timestamp_millis())
         });
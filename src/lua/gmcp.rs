@@ -0,0 +1,318 @@
+use std::sync::{mpsc::Sender, Arc, Mutex};
+
+use libtelnet_rs::{bytes::Bytes, telnet::op_option as opt};
+use log::debug;
+use mlua::{AnyUserData, Function, Lua, Table, UserData, UserDataMethods};
+
+use crate::{
+    event::Event,
+    net::{gmcp_matches, Gmcp, GmcpMessage},
+};
+
+use super::{
+    constants::{GMCP_LISTENER_TABLE, GMCP_READY_LISTENER_TABLE},
+    util::{json_to_lua, lua_to_json},
+};
+
+/// The `gmcp` global, backed by the GMCP state of the session
+pub struct GmcpLib {
+    state: Arc<Mutex<Gmcp>>,
+    writer: Sender<Event>,
+}
+
+impl GmcpLib {
+    pub const LUA_GLOBAL_NAME: &'static str = "gmcp";
+
+    pub fn new(state: Arc<Mutex<Gmcp>>, writer: Sender<Event>) -> Self {
+        Self { state, writer }
+    }
+
+    fn send(&self, message: String) {
+        debug!("GMCP send: {}", message);
+        self.writer
+            .send(Event::ProtoSubnegSend(opt::GMCP, Bytes::from(message)))
+            .unwrap();
+    }
+}
+
+/// Calls a listener with either the decoded or the raw data of a message
+fn call_gmcp_listener(
+    lua: &Lua,
+    callback: &Function,
+    decode: bool,
+    message: &GmcpMessage,
+) -> mlua::Result<()> {
+    if decode {
+        let data = json_to_lua(lua, &message.data)?;
+        callback.call((data, message.package.as_str()))
+    } else {
+        callback.call((message.raw.as_str(), message.package.as_str()))
+    }
+}
+
+/// Adds a listener and runs it for the cached messages it matches
+pub fn add_listener(
+    ctx: &Lua,
+    pattern: String,
+    callback: Function,
+    decode: bool,
+) -> mlua::Result<()> {
+    let listener = ctx.create_table()?;
+    listener.set("pattern", pattern.as_str())?;
+    listener.set("callback", callback.clone())?;
+    listener.set("decode", decode)?;
+    let table: Table = ctx.named_registry_value(GMCP_LISTENER_TABLE)?;
+    table.raw_set(table.raw_len() + 1, listener)?;
+
+    let cached = {
+        let this_aux = ctx
+            .globals()
+            .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+        let this = this_aux.borrow::<GmcpLib>()?;
+        let cached = this.state.lock().unwrap().cached(&pattern);
+        cached
+    };
+    for message in &cached {
+        call_gmcp_listener(ctx, &callback, decode, message)?;
+    }
+    Ok(())
+}
+
+/// Runs the listeners matching a received message
+pub fn dispatch_gmcp(lua: &Lua, message: &GmcpMessage) -> mlua::Result<()> {
+    let table: Table = lua.named_registry_value(GMCP_LISTENER_TABLE)?;
+    for listener in table.sequence_values::<Table>() {
+        let listener = listener?;
+        let pattern: String = listener.get("pattern")?;
+        if gmcp_matches(&pattern, &message.package) {
+            let callback: Function = listener.get("callback")?;
+            call_gmcp_listener(lua, &callback, listener.get("decode")?, message)?;
+        }
+    }
+    Ok(())
+}
+
+impl UserData for GmcpLib {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("on_ready", |ctx, callback: Function| {
+            let table: Table = ctx.named_registry_value(GMCP_READY_LISTENER_TABLE)?;
+            table.raw_set(table.raw_len() + 1, callback.clone())?;
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let ready = this_aux
+                .borrow::<GmcpLib>()?
+                .state
+                .lock()
+                .unwrap()
+                .is_ready();
+            if ready {
+                callback.call::<_, ()>(())?;
+            }
+            Ok(())
+        });
+        methods.add_function("is_ready", |ctx, ()| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<GmcpLib>()?;
+            let ready = this.state.lock().unwrap().is_ready();
+            Ok(ready)
+        });
+        methods.add_function(
+            "register",
+            |ctx, (module, version): (String, Option<u32>)| {
+                let this_aux = ctx
+                    .globals()
+                    .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+                let this = this_aux.borrow::<GmcpLib>()?;
+                let message = this
+                    .state
+                    .lock()
+                    .unwrap()
+                    .register(&module, version.unwrap_or(1));
+                if let Some(message) = message {
+                    this.send(message);
+                }
+                Ok(())
+            },
+        );
+        methods.add_function("unregister", |ctx, module: String| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<GmcpLib>()?;
+            let message = this.state.lock().unwrap().unregister(&module);
+            if let Some(message) = message {
+                this.send(message);
+            }
+            Ok(())
+        });
+        methods.add_function("supports", |ctx, ()| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<GmcpLib>()?;
+            let supports = this.state.lock().unwrap().supports().clone();
+            ctx.create_table_from(supports)
+        });
+        methods.add_function("receive", |ctx, (pattern, callback): (String, Function)| {
+            add_listener(ctx, pattern, callback, false)
+        });
+        methods.add_function("listen", |ctx, (pattern, callback): (String, Function)| {
+            add_listener(ctx, pattern, callback, true)
+        });
+        methods.add_function("get", |ctx, package: String| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<GmcpLib>()?;
+            let data = this
+                .state
+                .lock()
+                .unwrap()
+                .get(&package)
+                .map(|message| message.data.clone());
+            match data {
+                Some(data) => json_to_lua(ctx, &data),
+                None => Ok(mlua::Value::Nil),
+            }
+        });
+        methods.add_function(
+            "send",
+            |ctx, (package, data): (String, Option<mlua::Value>)| {
+                let this_aux = ctx
+                    .globals()
+                    .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+                let this = this_aux.borrow::<GmcpLib>()?;
+                let message = match data {
+                    Some(data) => format!("{package} {}", lua_to_json(data)?),
+                    None => package,
+                };
+                this.send(message);
+                Ok(())
+            },
+        );
+        methods.add_function("echo", |ctx, enabled: bool| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(GmcpLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<GmcpLib>()?;
+            this.state.lock().unwrap().set_echo(enabled);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_gmcp {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+
+    fn get_lua() -> (Lua, Arc<Mutex<Gmcp>>, Receiver<Event>) {
+        let (writer, reader) = channel();
+        let state = Arc::new(Mutex::new(Gmcp::default()));
+        let lua = Lua::new();
+        lua.set_named_registry_value(GMCP_LISTENER_TABLE, lua.create_table().unwrap())
+            .unwrap();
+        lua.set_named_registry_value(GMCP_READY_LISTENER_TABLE, lua.create_table().unwrap())
+            .unwrap();
+        lua.globals()
+            .set(
+                GmcpLib::LUA_GLOBAL_NAME,
+                GmcpLib::new(state.clone(), writer),
+            )
+            .unwrap();
+        (lua, state, reader)
+    }
+
+    fn sent(reader: &Receiver<Event>) -> String {
+        match reader.try_recv().unwrap() {
+            Event::ProtoSubnegSend(opt::GMCP, data) => String::from_utf8(data.to_vec()).unwrap(),
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let (lua, state, _reader) = get_lua();
+        let message =
+            GmcpMessage::parse(b"Char.Vitals {\"hp\":10,\"tags\":[\"a\",\"b\"]}").unwrap();
+        state.lock().unwrap().receive(message.clone());
+        lua.load(
+            r#"
+            raw = {}
+            hp = {}
+            gmcp.receive("Char.Vitals", function (data, package) raw[package] = data end)
+            gmcp.listen("char.*", function (data) table.insert(hp, data.hp) end)
+            gmcp.listen("Room.*", function () error("Should not be called") end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let message = GmcpMessage::parse(b"Char.Vitals {\"hp\":20}").unwrap();
+        dispatch_gmcp(&lua, &message).unwrap();
+
+        let raw: String = lua.load("return raw['Char.Vitals']").eval().unwrap();
+        assert_eq!(raw, "{\"hp\":20}");
+        let hp: Vec<i64> = lua.load("return hp").eval().unwrap();
+        assert_eq!(hp, vec![10, 20]);
+        let tag: String = lua
+            .load("return gmcp.get('Char.Vitals').tags[2]")
+            .eval()
+            .unwrap();
+        assert_eq!(tag, "b");
+        assert!(lua
+            .load("return gmcp.get('Room.Info') == nil")
+            .eval::<bool>()
+            .unwrap());
+    }
+
+    #[test]
+    fn test_register() {
+        let (lua, state, reader) = get_lua();
+        lua.load(
+            r#"
+            ready = false
+            gmcp.on_ready(function () ready = true end)
+            gmcp.register("Char")
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(reader.try_recv().is_err());
+        assert!(!lua.globals().get::<_, bool>("ready").unwrap());
+
+        state.lock().unwrap().enable();
+        lua.load(r#"gmcp.register("Room.Info", 2) gmcp.unregister("Char")"#)
+            .exec()
+            .unwrap();
+        assert_eq!(sent(&reader), "Core.Supports.Add [\"Room.Info 2\"]");
+        assert_eq!(sent(&reader), "Core.Supports.Remove [\"Char\"]");
+        let version: u32 = lua
+            .load("return gmcp.supports()['Room.Info']")
+            .eval()
+            .unwrap();
+        assert_eq!(version, 2);
+        assert!(lua.load("return gmcp.is_ready()").eval::<bool>().unwrap());
+    }
+
+    #[test]
+    fn test_send() {
+        let (lua, _state, reader) = get_lua();
+        lua.load(
+            r#"
+            gmcp.send("Core.Ping")
+            gmcp.send("Char.Login", { name = "hero", tags = { 1, 2.5 }, options = {} })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert_eq!(sent(&reader), "Core.Ping");
+        assert_eq!(
+            sent(&reader),
+            "Char.Login {\"name\":\"hero\",\"options\":[],\"tags\":[1,2.5]}"
+        );
+    }
+}
@@ -1,6 +1,17 @@
-use mlua::{UserData, UserDataMethods};
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use crate::model::Line as mLine;
+use mlua::{Table, UserData, UserDataMethods};
+
+use crate::model::{Line as mLine, Link, LinkAction};
+
+use super::{constants::LINK_CALLBACK_TABLE, util::link_to_lua};
+
+/// Shared by all scripts so a link made before a script reset can't reach a
+/// callback registered after it.
+static NEXT_LINK_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Callbacks of older links are dropped, those lines have long scrolled away
+const MAX_LINK_CALLBACKS: u32 = 1000;
 
 #[derive(Clone)]
 pub struct Line {
@@ -58,6 +69,15 @@ impl UserData for Line {
                 Ok(this.inner.flags.skip_log)
             },
         );
+        methods.add_method_mut(
+            "redirect",
+            |_, this, window: Option<String>| -> mlua::Result<Option<String>> {
+                if window.is_some() {
+                    this.inner.flags.window = window;
+                }
+                Ok(this.inner.flags.window.clone())
+            },
+        );
         methods.add_method("prompt", |_, this, _: ()| -> mlua::Result<bool> {
             Ok(this.inner.flags.prompt)
         });
@@ -79,13 +99,63 @@ impl UserData for Line {
             "replacement",
             |_, this, _: ()| -> mlua::Result<Option<String>> { Ok(this.replacement.clone()) },
         );
+        methods.add_method("links", |ctx, this, ()| -> mlua::Result<mlua::Table> {
+            let links = ctx.create_table()?;
+            for (i, link) in this.inner.links.iter().enumerate() {
+                links.set(i + 1, link_to_lua(ctx, link)?)?;
+            }
+            Ok(links)
+        });
+        methods.add_method_mut(
+            "link",
+            |ctx,
+             this,
+             (start, stop, action, hint): (usize, usize, mlua::Value, Option<String>)| {
+                let clean: Vec<char> = this.inner.clean_line().chars().collect();
+                if start < 1 || start > stop || stop > clean.len() {
+                    return Err(mlua::Error::external(format!(
+                        "Invalid link position: {start}-{stop}"
+                    )));
+                }
+                let action = match action {
+                    mlua::Value::String(command) => {
+                        LinkAction::Send(vec![command.to_str()?.to_string()])
+                    }
+                    mlua::Value::Function(callback) => {
+                        let id = NEXT_LINK_ID.fetch_add(1, Ordering::Relaxed);
+                        let table: Table = ctx.named_registry_value(LINK_CALLBACK_TABLE)?;
+                        table.raw_set(id, callback)?;
+                        if id > MAX_LINK_CALLBACKS {
+                            table.raw_set(id - MAX_LINK_CALLBACKS, mlua::Value::Nil)?;
+                        }
+                        LinkAction::Callback(id)
+                    }
+                    _ => {
+                        return Err(mlua::Error::external(
+                            "Link action must be a command or a function",
+                        ))
+                    }
+                };
+                this.inner.links.push(Link {
+                    text: clean[start - 1..stop].iter().collect(),
+                    action,
+                    hint,
+                    prompt: false,
+                    start: start - 1,
+                    end: stop,
+                });
+                Ok(())
+            },
+        );
     }
 }
 
 #[cfg(test)]
 mod test_lua_line {
-    use super::Line;
-    use crate::model::Line as mLine;
+    use mlua::Table;
+
+    use super::{Line, LINK_CALLBACK_TABLE};
+    use crate::model::{Line as mLine, Link, LinkAction};
 
     fn test_line() -> Line {
         Line::from(mLine::from("\x1b[31mA testing line\x1b[0m"))
@@ -153,6 +223,20 @@ mod test_lua_line {
         assert!(line.inner.flags.skip_log);
     }
 
+    #[test]
+    fn test_redirect() {
+        test_lua!("test_line" => test_line());
+
+        assert_lua!(Option<String>, "test_line:redirect()", None);
+        assert_lua!(
+            Option<String>,
+            "test_line:redirect(\"chat\")",
+            Some("chat".to_string())
+        );
+        let line: Line = global!("test_line");
+        assert_eq!(line.inner.flags.window, Some("chat".to_string()));
+    }
+
     #[test]
     fn test_matched() {
         test_lua!("test_line" => test_line());
@@ -187,4 +271,69 @@ mod test_lua_line {
         let line: Line = global!("test_line");
         assert_eq!(line.replacement, Some("test test".to_string()));
     }
+
+    #[test]
+    fn test_links() {
+        let mut line = mLine::from("Exits: north");
+        line.links.push(Link {
+            text: "north".to_string(),
+            action: LinkAction::Send(vec!["north".to_string(), "look north".to_string()]),
+            hint: Some("Go north".to_string()),
+            prompt: false,
+            start: 7,
+            end: 12,
+        });
+        test_lua!("test_line" => Line::from(line));
+        assert_lua!(usize, "#test_line:links()", 1);
+        assert_lua_string!("test_line:links()[1].text", "north");
+        assert_lua_string!("test_line:links()[1].send[2]", "look north");
+        assert_lua_string!("test_line:links()[1].hint", "Go north");
+        assert_lua_bool!("test_line:links()[1].url == nil", true);
+        assert_lua_string!(
+            "test_line:line():sub(test_line:links()[1].start, test_line:links()[1].stop)",
+            "north"
+        );
+        set_global!("plain", test_line());
+        assert_lua!(usize, "#plain:links()", 0);
+    }
+
+    #[test]
+    fn test_add_link() {
+        let lua = mlua::Lua::new();
+        lua.set_named_registry_value(LINK_CALLBACK_TABLE, lua.create_table().unwrap())
+            .unwrap();
+        lua.globals()
+            .set("line", Line::from(mLine::from("Exits: north south")))
+            .unwrap();
+        lua.load(
+            r#"
+            line:link(8, 12, "go north", "Go north")
+            line:link(14, 18, function () end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(lua.load("line:link(14, 19, 'x')").exec().is_err());
+        assert!(lua.load("line:link(1, 2, {})").exec().is_err());
+
+        let line: Line = lua.globals().get("line").unwrap();
+        let links = &line.inner.links;
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].text, "north");
+        assert_eq!(
+            links[0].action,
+            LinkAction::Send(vec!["go north".to_string()])
+        );
+        assert_eq!(links[0].hint, Some("Go north".to_string()));
+        assert_eq!((links[1].start, links[1].end), (13, 18));
+        let id = match links[1].action {
+            LinkAction::Callback(id) => id,
+            ref action => panic!("Unexpected action: {action:?}"),
+        };
+        let table: Table = lua.named_registry_value(LINK_CALLBACK_TABLE).unwrap();
+        assert!(table
+            .raw_get::<_, Option<mlua::Function>>(id)
+            .unwrap()
+            .is_some());
+    }
 }
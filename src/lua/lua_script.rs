@@ -1,11 +1,22 @@
 use super::fs_event::FSEvent;
 use super::{
-    audio::Audio, backend::Backend, blight::*, line::Line as LuaLine, plugin, script::Script,
-    socket::SocketLib, tts::Tts,
+    audio::Audio,
+    backend::Backend,
+    blight::*,
+    gmcp::{dispatch_gmcp, GmcpLib},
+    line::Line as LuaLine,
+    mnes::MnesLib,
+    plugin,
+    script::Script,
+    socket::{register_socket, Listener, SocketLib},
+    status::StatusLib,
+    tts::Tts,
+    ui::UiLib,
 };
 use super::{constants::*, core::Core, ui_event::UiEvent};
 use super::{
-    log::Log, mud::Mud, regex::RegexLib, settings::Settings, store::Store, timer::Timer, util::*,
+    log::Log, mud::Mud, regex::RegexLib, sessions::Sessions, settings::Settings, store::Store,
+    timer::Timer, util::*,
 };
 use crate::lua::fs::Fs;
 use crate::lua::prompt::Prompt;
@@ -14,12 +25,21 @@ use crate::lua::prompt_mask::PromptMask;
 use crate::lua::spellcheck::{self, Spellchecker};
 use crate::model::Completions;
 use crate::tools::util::expand_tilde;
-use crate::{event::Event, lua::servers::Servers, model, model::Line};
+use crate::{
+    event::Event,
+    lua::servers::Servers,
+    model,
+    model::Line,
+    net::{Gmcp, GmcpMessage, HttpResponse, MsspInfo, NewEnviron, SendQueue},
+};
 use anyhow::Result;
+use libtelnet_rs::telnet::op_option::GMCP;
 use log::{debug, info};
 use mlua::{AnyUserData, FromLua, Lua, Result as LuaResult, Value};
 use std::io::prelude::*;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{fs::File, sync::mpsc::Sender};
 
 pub struct LuaScriptBuilder {
@@ -27,6 +47,11 @@ pub struct LuaScriptBuilder {
     dimensions: (u16, u16),
     reader_mode: bool,
     tts_enabled: bool,
+    sessions: Sessions,
+    send_queue: Arc<Mutex<SendQueue>>,
+    gmcp: Arc<Mutex<Gmcp>>,
+    new_environ: Arc<Mutex<NewEnviron>>,
+    profile: Option<model::Profile>,
 }
 
 impl LuaScriptBuilder {
@@ -36,6 +61,11 @@ impl LuaScriptBuilder {
             dimensions: (0, 0),
             reader_mode: false,
             tts_enabled: false,
+            sessions: Sessions::default(),
+            send_queue: Arc::default(),
+            gmcp: Arc::default(),
+            new_environ: Arc::default(),
+            profile: None,
         }
     }
 
@@ -54,15 +84,40 @@ impl LuaScriptBuilder {
         self
     }
 
+    pub fn send_queue(mut self, send_queue: Arc<Mutex<SendQueue>>) -> Self {
+        self.send_queue = send_queue;
+        self
+    }
+
+    pub fn gmcp(mut self, gmcp: Arc<Mutex<Gmcp>>) -> Self {
+        self.gmcp = gmcp;
+        self
+    }
+
+    pub fn new_environ(mut self, new_environ: Arc<Mutex<NewEnviron>>) -> Self {
+        self.new_environ = new_environ;
+        self
+    }
+
     pub fn build(self) -> LuaScript {
         let main_writer = self.writer.clone();
         let reader_mode = self.reader_mode;
         let tts_enabled = self.tts_enabled;
+        let sessions = self.sessions.clone();
+        let send_queue = self.send_queue.clone();
+        let gmcp = self.gmcp.clone();
+        let new_environ = self.new_environ.clone();
         LuaScript {
             state: create_default_lua_state(self, None),
             writer: main_writer,
             tts_enabled,
             reader_mode,
+            sessions,
+            send_queue,
+            gmcp,
+            new_environ,
+            profile: None,
+            profile_scripts: vec![],
         }
     }
 }
@@ -72,6 +127,14 @@ pub struct LuaScript {
     writer: Sender<Event>,
     tts_enabled: bool,
     reader_mode: bool,
+    sessions: Sessions,
+    send_queue: Arc<Mutex<SendQueue>>,
+    gmcp: Arc<Mutex<Gmcp>>,
+    new_environ: Arc<Mutex<NewEnviron>>,
+    /// The world profile of the current connection
+    profile: Option<model::Profile>,
+    /// The profile scripts loaded into this state
+    profile_scripts: Vec<String>,
 }
 
 /// load the provided filenames in the lua resource directory as named chunks that get called,
@@ -106,7 +169,8 @@ fn create_default_lua_state(builder: LuaScriptBuilder, store: Option<Store>) ->
     let state = unsafe { Lua::unsafe_new() };
     let writer = builder.writer;
 
-    let backend = Backend::new(writer.clone());
+    let mut backend = Backend::new(writer.clone());
+    backend.send_queue = builder.send_queue;
     let mut blight = Blight::new(writer.clone());
     let store = match store {
         Some(store) => store,
@@ -137,13 +201,27 @@ fn create_default_lua_state(builder: LuaScriptBuilder, store: Option<Store>) ->
         state.set_named_registry_value(PROTO_SUBNEG_LISTENERS_TABLE, state.create_table()?)?;
         state.set_named_registry_value(ON_CONNECTION_CALLBACK_TABLE, state.create_table()?)?;
         state.set_named_registry_value(ON_DISCONNECT_CALLBACK_TABLE, state.create_table()?)?;
+        state
+            .set_named_registry_value(ON_RECONNECT_ATTEMPT_CALLBACK_TABLE, state.create_table()?)?;
+        state
+            .set_named_registry_value(ON_RECONNECT_FAILED_CALLBACK_TABLE, state.create_table()?)?;
         state.set_named_registry_value(COMPLETION_CALLBACK_TABLE, state.create_table()?)?;
         state.set_named_registry_value(FS_LISTENERS, state.create_table()?)?;
         state.set_named_registry_value(SCRIPT_RESET_LISTENERS, state.create_table()?)?;
+        state.set_named_registry_value(SOCKET_CALLBACK_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(HTTP_CALLBACK_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(MSSP_CALLBACK_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(GMCP_LISTENER_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(GMCP_READY_LISTENER_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(LINK_CALLBACK_TABLE, state.create_table()?)?;
+        state.set_named_registry_value(LINK_CLICK_LISTENER_TABLE, state.create_table()?)?;
         state.set_named_registry_value(PROMPT_CONTENT, String::new())?;
         state.set_named_registry_value(PROMPT_CURSOR_INDEX, 0)?;
         state.set_named_registry_value(PROMPT_INPUT_LISTENER_TABLE, state.create_table()?)?;
         state.set_named_registry_value(STATUS_AREA_HEIGHT, 1)?;
+        if let Some(profile) = &builder.profile {
+            state.set_named_registry_value(PROFILE, profile_to_lua(&state, profile)?)?;
+        }
 
         globals.set("blight", blight)?;
         globals.set("core", Core::new(writer.clone()))?;
@@ -159,9 +237,20 @@ fn create_default_lua_state(builder: LuaScriptBuilder, store: Option<Store>) ->
         globals.set("plugin", plugin::Handler::new())?;
         globals.set("audio", Audio {})?;
         globals.set("socket", SocketLib {})?;
+        globals.set(
+            GmcpLib::LUA_GLOBAL_NAME,
+            GmcpLib::new(builder.gmcp, writer.clone()),
+        )?;
+        globals.set(
+            MnesLib::LUA_GLOBAL_NAME,
+            MnesLib::new(builder.new_environ, writer.clone()),
+        )?;
         globals.set("servers", Servers {})?;
         globals.set("prompt", Prompt {})?;
         globals.set("prompt_mask", PromptMask {})?;
+        globals.set(Sessions::LUA_GLOBAL_NAME, builder.sessions)?;
+        globals.set(UiLib::LUA_GLOBAL_NAME, UiLib::new(writer.clone()))?;
+        globals.set(StatusLib::LUA_GLOBAL_NAME, StatusLib::new(writer.clone()))?;
         #[cfg(feature = "spellcheck")]
         globals.set(spellcheck::LUA_GLOBAL_NAME, Spellchecker::new())?;
 
@@ -173,9 +262,9 @@ fn create_default_lua_state(builder: LuaScriptBuilder, store: Option<Store>) ->
             "alias.lua",
             "search.lua",
             "history.lua",
-            "gmcp.lua",
             "msdp.lua",
             "tasks.lua",
+            "http.lua",
             "ttype.lua",
             "mssp.lua"
         );
@@ -229,11 +318,60 @@ impl LuaScript {
             dimensions,
             tts_enabled: self.tts_enabled,
             reader_mode: self.reader_mode,
+            sessions: self.sessions.clone(),
+            send_queue: self.send_queue.clone(),
+            gmcp: self.gmcp.clone(),
+            new_environ: self.new_environ.clone(),
+            profile: self.profile.clone(),
         };
         self.state = create_default_lua_state(builder, store);
+        self.profile_scripts.clear();
+        self.load_profile_scripts();
         Ok(())
     }
 
+    /// Sets the world profile of the current connection, None once
+    /// disconnected. Its scripts are loaded unless they already were.
+    pub fn set_profile(&mut self, profile: Option<model::Profile>) {
+        self.profile = profile;
+        self.exec_lua(&mut || -> LuaResult<()> {
+            match &self.profile {
+                Some(profile) => self
+                    .state
+                    .set_named_registry_value(PROFILE, profile_to_lua(&self.state, profile)?),
+                None => self.state.unset_named_registry_value(PROFILE),
+            }
+        });
+        if let Some(history_file) = self.profile.as_ref().and_then(|p| p.history_file.as_ref()) {
+            if let Some(dir) = Path::new(expand_tilde(history_file).as_ref()).parent() {
+                std::fs::create_dir_all(dir).ok();
+            }
+        }
+        self.load_profile_scripts();
+    }
+
+    /// If scripts of a profile other than this one are loaded. They can only
+    /// be unloaded by a reset.
+    pub fn has_other_profile_scripts(&self, profile: &Option<model::Profile>) -> bool {
+        let scripts = profile.as_ref().map(|profile| &profile.scripts[..]);
+        !self.profile_scripts.is_empty() && scripts.unwrap_or_default() != self.profile_scripts
+    }
+
+    fn load_profile_scripts(&mut self) {
+        let scripts = match &self.profile {
+            Some(profile) if profile.scripts != self.profile_scripts => profile.scripts.clone(),
+            _ => return,
+        };
+        for script in &scripts {
+            if let Err(err) = self.load_script(script) {
+                self.writer
+                    .send(Event::Error(format!("Failed to load file: {err}")))
+                    .unwrap();
+            }
+        }
+        self.profile_scripts = scripts;
+    }
+
     pub fn handle_fs_event(&self, event: crate::io::FSEvent) -> Result<()> {
         self.exec_lua(&mut || -> LuaResult<()> {
             let table: mlua::Table = self.state.named_registry_value(FS_LISTENERS)?;
@@ -352,6 +490,52 @@ impl LuaScript {
         });
     }
 
+    /// Lets the link click listeners handle a clicked link, running its
+    /// default action unless one of them returns true
+    pub fn on_link_click(&self, link: &model::Link) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self.state.named_registry_value(LINK_CLICK_LISTENER_TABLE)?;
+            let entry = link_to_lua(&self.state, link)?;
+            for pair in table.pairs::<mlua::Value, mlua::Function>() {
+                let (_, cb) = pair?;
+                if cb.call::<_, Option<bool>>(entry.clone())? == Some(true) {
+                    return Ok(());
+                }
+            }
+            match &link.action {
+                model::LinkAction::Send(commands) if link.prompt => {
+                    if let Some(command) = commands.first() {
+                        self.writer
+                            .send(Event::SetPromptInput(command.clone()))
+                            .unwrap();
+                    }
+                }
+                model::LinkAction::Send(commands) => {
+                    for command in commands {
+                        // Links may come from the server so they must not reach aliases
+                        let mut line = Line::from(command.as_str());
+                        line.flags.source = Some("user".to_string());
+                        line.flags.bypass_script = true;
+                        self.writer.send(Event::ServerInput(line)).unwrap();
+                    }
+                }
+                model::LinkAction::Url(url) => {
+                    if let Err(err) = crate::io::open_url(url) {
+                        self.writer.send(Event::Error(err.to_string())).unwrap();
+                    }
+                }
+                model::LinkAction::Callback(id) => {
+                    let table: mlua::Table =
+                        self.state.named_registry_value(LINK_CALLBACK_TABLE)?;
+                    if let Some(callback) = table.raw_get::<_, Option<mlua::Function>>(*id)? {
+                        callback.call::<_, ()>(entry)?;
+                    }
+                }
+            }
+            Ok(())
+        });
+    }
+
     pub fn run_timed_function(&mut self, id: u32) {
         self.exec_lua(&mut || -> LuaResult<()> {
             let core_table: mlua::Table =
@@ -370,6 +554,106 @@ impl LuaScript {
         });
     }
 
+    fn run_socket_callback<'lua, A: mlua::ToLuaMulti<'lua>>(
+        &'lua self,
+        id: u32,
+        name: &str,
+        args: A,
+    ) -> LuaResult<()> {
+        let table: mlua::Table = self.state.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+        if let Some(callbacks) = table.raw_get::<_, Option<mlua::Table>>(id)? {
+            if let Some(callback) = callbacks.raw_get::<_, Option<mlua::Function>>(name)? {
+                callback.call::<_, ()>(args)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands a client accepted by a listener to its accept callback
+    pub fn on_socket_accept(&self, listener_id: u32, id: u32) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self.state.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+            if let Some(callbacks) = table.raw_get::<_, Option<mlua::Table>>(listener_id)? {
+                let listener: AnyUserData = callbacks.raw_get("listener")?;
+                let client = listener.borrow::<Listener>()?.take_client(id);
+                if let Some(client) = client {
+                    let client = register_socket(&self.state, client)?;
+                    let callback: mlua::Function = callbacks.raw_get("accept")?;
+                    callback.call::<_, ()>(client)?;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    pub fn on_socket_connect(&self, id: u32) {
+        self.exec_lua(&mut || self.run_socket_callback(id, "connect", ()));
+    }
+
+    pub fn on_socket_data(&self, id: u32, data: &str) {
+        self.exec_lua(&mut || self.run_socket_callback(id, "data", data));
+    }
+
+    /// Runs the close callback of a socket and forgets its callbacks
+    pub fn on_socket_close(&self, id: u32, reason: Option<String>) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let result = self.run_socket_callback(id, "close", reason.clone());
+            let table: mlua::Table = self.state.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+            table.raw_set(id, mlua::Value::Nil)?;
+            result
+        });
+    }
+
+    /// Runs the callback waiting for the response of an HTTP request
+    pub fn on_http_response(&self, id: u32, response: HttpResponse) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self.state.named_registry_value(HTTP_CALLBACK_TABLE)?;
+            if let Some(callback) = table.raw_get::<_, Option<mlua::Function>>(id)? {
+                table.raw_set(id, mlua::Value::Nil)?;
+                let result = self.state.create_table()?;
+                if let Some(error) = &response.error {
+                    result.set("error", error.as_str())?;
+                } else {
+                    let headers = self.state.create_table()?;
+                    for (name, value) in &response.headers {
+                        headers.set(name.as_str(), value.as_str())?;
+                    }
+                    result.set("status", response.status)?;
+                    result.set("headers", headers)?;
+                    result.set("body", response.body.as_str())?;
+                }
+                callback.call::<_, ()>(result)?;
+            }
+            Ok(())
+        });
+    }
+
+    /// Runs the callback waiting for the result of an MSSP query. Variables
+    /// with a single value map to a string, others to a list of strings.
+    pub fn on_mssp_response(&self, id: u32, result: Result<MsspInfo, String>) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self.state.named_registry_value(MSSP_CALLBACK_TABLE)?;
+            if let Some(callback) = table.raw_get::<_, Option<mlua::Function>>(id)? {
+                table.raw_set(id, mlua::Value::Nil)?;
+                match &result {
+                    Ok(info) => {
+                        let values = self.state.create_table()?;
+                        for (name, value) in info {
+                            match value.as_slice() {
+                                [] => values.set(name.as_str(), "")?,
+                                [value] => values.set(name.as_str(), value.as_str())?,
+                                value => values.set(name.as_str(), value.to_vec())?,
+                            }
+                        }
+                        callback.call::<_, ()>(values)?;
+                    }
+                    Err(error) => callback.call::<_, ()>((mlua::Value::Nil, error.as_str()))?,
+                }
+            }
+            Ok(())
+        });
+    }
+
     pub fn tick(&mut self, millis: u128) {
         self.exec_lua(&mut || -> LuaResult<()> {
             let core_tick_table: mlua::Table = self
@@ -424,6 +708,21 @@ impl LuaScript {
         Ok(())
     }
 
+    /// Updates the encoding reported by `mud.get_encoding()`
+    pub fn set_encoding(&mut self, name: &str) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            self.state.set_named_registry_value(ENCODING, name)
+        });
+    }
+
+    /// Updates the latency reported by `mud.latency()`
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            self.state
+                .set_named_registry_value(LATENCY, latency.as_secs_f64())
+        });
+    }
+
     pub fn on_connect(&mut self, host: &str, port: u16, id: u16) {
         self.exec_lua(&mut || -> LuaResult<()> {
             self.state.set_named_registry_value(IS_CONNECTED, true)?;
@@ -442,6 +741,7 @@ impl LuaScript {
     pub fn on_disconnect(&mut self) {
         self.exec_lua(&mut || -> LuaResult<()> {
             self.state.set_named_registry_value(IS_CONNECTED, false)?;
+            self.state.unset_named_registry_value(LATENCY)?;
             let table: mlua::Table = self
                 .state
                 .named_registry_value(ON_DISCONNECT_CALLBACK_TABLE)?;
@@ -453,6 +753,32 @@ impl LuaScript {
         });
     }
 
+    pub fn on_reconnect_attempt(&mut self, attempt: u32, delay: f64) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self
+                .state
+                .named_registry_value(ON_RECONNECT_ATTEMPT_CALLBACK_TABLE)?;
+            for pair in table.pairs::<mlua::Value, mlua::Function>() {
+                let (_, cb) = pair?;
+                cb.call::<_, ()>((attempt, delay))?;
+            }
+            Ok(())
+        });
+    }
+
+    pub fn on_reconnect_failed(&mut self) {
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let table: mlua::Table = self
+                .state
+                .named_registry_value(ON_RECONNECT_FAILED_CALLBACK_TABLE)?;
+            for pair in table.pairs::<mlua::Value, mlua::Function>() {
+                let (_, cb) = pair?;
+                cb.call::<_, ()>(())?;
+            }
+            Ok(())
+        });
+    }
+
     pub fn set_dimensions(&mut self, dim: (u16, u16)) {
         self.exec_lua(&mut || -> LuaResult<()> {
             let blight_aud: AnyUserData = self.state.globals().get("blight")?;
@@ -481,6 +807,15 @@ impl LuaScript {
         });
     }
 
+    pub fn set_sessions(&mut self, sessions: Sessions) {
+        self.sessions = sessions.clone();
+        self.exec_lua(&mut || -> LuaResult<()> {
+            let sessions_aud: AnyUserData = self.state.globals().get(Sessions::LUA_GLOBAL_NAME)?;
+            *sessions_aud.borrow_mut::<Sessions>()? = sessions.clone();
+            Ok(())
+        });
+    }
+
     pub fn set_tts_enabled(&mut self, tts_enabled: bool) {
         {
             self.tts_enabled = tts_enabled;
@@ -502,6 +837,13 @@ impl LuaScript {
                 let (_, cb) = pair.unwrap();
                 cb.call::<_, ()>(proto)?;
             }
+            if proto == GMCP {
+                let table: mlua::Table =
+                    self.state.named_registry_value(GMCP_READY_LISTENER_TABLE)?;
+                for cb in table.sequence_values::<mlua::Function>() {
+                    cb?.call::<_, ()>(())?;
+                }
+            }
             Ok(())
         });
     }
@@ -519,6 +861,10 @@ impl LuaScript {
         });
     }
 
+    pub fn on_gmcp(&self, message: &GmcpMessage) {
+        self.exec_lua(&mut || dispatch_gmcp(&self.state, message));
+    }
+
     pub fn tab_complete(&mut self, input: &str) -> Completions {
         self.exec_lua(&mut || -> LuaResult<Completions> {
             let mut completions = Completions::default();
@@ -583,15 +929,20 @@ mod lua_script_tests {
     use super::LuaScriptBuilder;
     use super::CONNECTION_ID;
     use crate::event::QuitMethod;
-    use crate::lua::constants::TIMED_CALLBACK_TABLE;
+    use crate::lua::constants::{LINK_CALLBACK_TABLE, TIMED_CALLBACK_TABLE};
+    use crate::lua::line::Line as LuaLine;
     use crate::model::Completions;
-    use crate::model::{Connection, PromptMask, Regex};
+    use crate::model::{Connection, Link, LinkAction, Profile, PromptMask, Regex, StatusWidget};
+    use crate::net::{GmcpMessage, NewEnviron};
     use crate::{event::Event, lua::regex::Regex as LReg, model::Line, PROJECT_NAME, VERSION};
     use libtelnet_rs::{bytes::Bytes, vbytes};
     use mlua::Table;
     use std::{
         collections::BTreeMap,
-        sync::mpsc::{channel, Receiver, Sender},
+        sync::{
+            mpsc::{channel, Receiver, Sender},
+            Arc, Mutex,
+        },
     };
 
     fn test_trigger(line: &str, lua: &LuaScript) -> bool {
@@ -856,6 +1207,23 @@ mod lua_script_tests {
         }
     }
 
+    #[test]
+    fn test_ttype_mtts_follows_reader_mode() {
+        let (writer, _reader): (Sender<Event>, Receiver<Event>) = channel();
+        let new_environ = Arc::new(Mutex::new(NewEnviron::default()));
+        let lua = LuaScriptBuilder::new(writer)
+            .new_environ(new_environ.clone())
+            .dimensions((80, 80))
+            .build();
+        lua.state
+            .load("ttype.add_option(ttype.MTTS_PROXY)")
+            .exec()
+            .unwrap();
+        assert_eq!(new_environ.lock().unwrap().variables()["MTTS"], "911");
+        new_environ.lock().unwrap().set_reader_mode(true);
+        assert_eq!(new_environ.lock().unwrap().variables()["MTTS"], "975");
+    }
+
     #[test]
     fn test_output() {
         let (lua, _) = get_lua();
@@ -874,6 +1242,22 @@ mod lua_script_tests {
         );
     }
 
+    #[test]
+    fn test_reset_closes_listeners() {
+        let path = std::env::temp_dir().join(format!("blightmud-{}.sock", std::process::id()));
+        let (mut lua, _reader) = get_lua();
+        lua.state
+            .load(&format!(
+                "assert(socket.listen_unix({:?}, function () end))",
+                path.to_str().unwrap()
+            ))
+            .exec()
+            .unwrap();
+        assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+        lua.reset((100, 100)).unwrap();
+        assert!(!path.exists());
+    }
+
     #[test]
     fn test_reset() {
         assert_event("script.reset()", Event::ResetScript);
@@ -909,6 +1293,23 @@ mod lua_script_tests {
         assert!(!line.flags.gag);
     }
 
+    #[test]
+    fn test_trigger_redirect() {
+        let (lua, _reader) = get_lua();
+        lua.state
+            .load(r#"trigger.add("^\\[Gossip\\]", { redirect = "chat" }, function () end)"#)
+            .exec()
+            .unwrap();
+
+        let mut line = Line::from("[Gossip] Hello");
+        lua.on_mud_output(&mut line);
+        assert_eq!(line.flags.window, Some("chat".to_string()));
+
+        let mut line = Line::from("You are hungry");
+        lua.on_mud_output(&mut line);
+        assert_eq!(line.flags.window, None);
+    }
+
     fn check_color(lua: &LuaScript, output: &str, result: &str) {
         lua.state
             .load(&format!("blight.output({})", output))
@@ -1271,6 +1672,100 @@ mod lua_script_tests {
         assert!(lua.state.globals().get::<_, bool>("quit").unwrap());
     }
 
+    #[test]
+    fn confirm_link_click() {
+        let (lua, reader) = get_lua();
+        let link = |action| Link {
+            text: "north".to_string(),
+            action,
+            hint: None,
+            prompt: false,
+            start: 0,
+            end: 5,
+        };
+        let send = link(LinkAction::Send(vec!["north".to_string()]));
+        lua.on_link_click(&send);
+        let mut line = Line::from("north");
+        line.flags.source = Some("user".to_string());
+        line.flags.bypass_script = true;
+        assert_eq!(reader.try_recv(), Ok(Event::ServerInput(line)));
+
+        lua.on_link_click(&Link {
+            prompt: true,
+            ..send.clone()
+        });
+        assert_eq!(
+            reader.try_recv(),
+            Ok(Event::SetPromptInput("north".to_string()))
+        );
+
+        lua.state
+            .globals()
+            .set("line", LuaLine::from(Line::from("north")))
+            .unwrap();
+        lua.state
+            .load(
+                r#"
+        line:link(1, 5, function (link) clicked = link.text end)
+        blight.on_link_click(function (link) return link.send ~= nil end)
+        "#,
+            )
+            .exec()
+            .unwrap();
+        lua.on_link_click(&send);
+        assert!(reader.try_recv().is_err());
+
+        let id = {
+            let callbacks: mlua::Table =
+                lua.state.named_registry_value(LINK_CALLBACK_TABLE).unwrap();
+            callbacks
+                .pairs::<u32, mlua::Function>()
+                .next()
+                .unwrap()
+                .unwrap()
+                .0
+        };
+        lua.on_link_click(&link(LinkAction::Callback(id)));
+        assert_eq!(
+            lua.state.globals().get::<_, String>("clicked").unwrap(),
+            "north"
+        );
+    }
+
+    #[test]
+    fn confirm_status_bindings() {
+        let (lua, reader) = get_lua();
+        lua.state
+            .load(
+                r#"
+        local hp = status.gauge("hp")
+        hp:bind_gmcp("Char.Vitals", "hp", "stats.maxhp")
+        local mana = status.gauge("mana")
+        mana:bind_msdp("MANA", "MANA_MAX")
+        "#,
+            )
+            .exec()
+            .unwrap();
+        let gauge = |reader: &Receiver<Event>| match reader.try_recv() {
+            Ok(Event::StatusWidget(_, Some(StatusWidget::Gauge(gauge)))) => {
+                (gauge.value, gauge.max)
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        };
+        assert_eq!(gauge(&reader), (0.0, 100.0));
+        assert_eq!(gauge(&reader), (0.0, 100.0));
+
+        let message = GmcpMessage::parse(b"Char.Vitals {\"hp\":\"20\",\"stats\":{\"maxhp\":40}}");
+        lua.on_gmcp(&message.unwrap());
+        assert_eq!(gauge(&reader), (20.0, 40.0));
+
+        lua.state
+            .load(r#"msdp._subneg_recv({ 1, 77, 65, 78, 65, 2, 53 })"#)
+            .exec()
+            .unwrap();
+        assert_eq!(gauge(&reader), (5.0, 100.0));
+    }
+
     #[test]
     fn confirm_timed_function() {
         let (mut lua, _reader) = get_lua();
@@ -1431,6 +1926,28 @@ mod lua_script_tests {
         assert_eq!(lua.state.globals().get::<_, String>("buf").unwrap(), "test");
     }
 
+    #[test]
+    fn confirm_prompt_compose() {
+        let (lua, reader) = get_lua();
+        lua.state
+            .load(
+                r#"
+        prompt.compose()
+        prompt.compose({ text = "Dear Bob,\n", terminator = "." })
+        "#,
+            )
+            .exec()
+            .unwrap();
+        assert_eq!(reader.recv(), Ok(Event::StartCompose(None, None)));
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::StartCompose(
+                Some("Dear Bob,\n".to_string()),
+                Some(".".to_string())
+            ))
+        );
+    }
+
     #[test]
     fn set_prompt_mask_content() {
         let (mut lua, _reader) = get_lua();
@@ -1446,4 +1963,42 @@ mod lua_script_tests {
         assert_eq!(result.get::<i32, String>(11).unwrap(), "hi");
         assert_eq!(result.get::<i32, String>(21).unwrap(), "bye");
     }
+
+    #[test]
+    fn test_profile() {
+        let (mut lua, _reader) = get_lua();
+        let script = std::env::temp_dir().join("blightmud_test_profile.lua");
+        std::fs::write(&script, "loaded = (loaded or 0) + 1").unwrap();
+        let profile = Profile {
+            scripts: vec![script.to_str().unwrap().to_string()],
+            history_file: Some("/tmp/history.json".to_string()),
+            ..Default::default()
+        };
+        let loaded = |lua: &LuaScript| -> u32 { lua.state.load("return loaded").eval().unwrap() };
+
+        lua.set_profile(Some(profile.clone()));
+        lua.set_profile(None);
+        assert!(!lua.has_other_profile_scripts(&Some(profile.clone())));
+        assert!(lua.has_other_profile_scripts(&None));
+        assert!(lua.has_other_profile_scripts(&Some(Profile::default())));
+        lua.set_profile(Some(profile));
+        assert_eq!(loaded(&lua), 1);
+        let history_file: String = lua
+            .state
+            .load("return mud.get_profile().history_file")
+            .eval()
+            .unwrap();
+        assert_eq!(history_file, "/tmp/history.json");
+
+        lua.reset((80, 80)).unwrap();
+        assert_eq!(loaded(&lua), 1);
+
+        lua.set_profile(None);
+        assert!(lua
+            .state
+            .load("return mud.get_profile() == nil")
+            .eval::<bool>()
+            .unwrap());
+        std::fs::remove_file(script).ok();
+    }
 }
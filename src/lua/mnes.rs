@@ -0,0 +1,132 @@
+use std::sync::{mpsc::Sender, Arc, Mutex};
+
+use libtelnet_rs::{bytes::Bytes, telnet::op_option as opt};
+use mlua::{AnyUserData, UserData, UserDataMethods};
+
+use crate::{event::Event, net::NewEnviron};
+
+/// The `mnes` global, for the variables sent with NEW-ENVIRON
+pub struct MnesLib {
+    state: Arc<Mutex<NewEnviron>>,
+    writer: Sender<Event>,
+}
+
+impl MnesLib {
+    pub const LUA_GLOBAL_NAME: &'static str = "mnes";
+
+    pub fn new(state: Arc<Mutex<NewEnviron>>, writer: Sender<Event>) -> Self {
+        Self { state, writer }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut NewEnviron) -> Option<Bytes>) {
+        let info = change(&mut self.state.lock().unwrap());
+        if let Some(info) = info {
+            self.writer
+                .send(Event::ProtoSubnegSend(opt::NEWENVIRON, info))
+                .unwrap();
+        }
+    }
+}
+
+impl UserData for MnesLib {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("set", |ctx, (name, value): (String, String)| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            this.update(|env| env.set(&name, Some(value)));
+            Ok(())
+        });
+        methods.add_function("remove", |ctx, name: String| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            this.update(|env| env.set(&name, None));
+            Ok(())
+        });
+        methods.add_function("restore", |ctx, name: String| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            this.update(|env| env.restore(&name));
+            Ok(())
+        });
+        methods.add_function("set_mtts", |ctx, mtts: u32| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            this.update(|env| env.set_mtts(mtts));
+            Ok(())
+        });
+        methods.add_function("get", |ctx, name: String| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            let value = this.state.lock().unwrap().variables().remove(&name);
+            Ok(value)
+        });
+        methods.add_function("variables", |ctx, ()| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(MnesLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<MnesLib>()?;
+            let variables = this.state.lock().unwrap().variables();
+            ctx.create_table_from(variables)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_mnes {
+    use std::sync::mpsc::channel;
+
+    use mlua::Lua;
+
+    use super::*;
+
+    #[test]
+    fn test_variables() {
+        let (writer, reader) = channel();
+        let state = Arc::new(Mutex::new(NewEnviron::default()));
+        let lua = Lua::new();
+        lua.globals()
+            .set(
+                MnesLib::LUA_GLOBAL_NAME,
+                MnesLib::new(state.clone(), writer),
+            )
+            .unwrap();
+        lua.load(
+            r#"
+            mnes.set("CLIENT_NAME", "TEST")
+            mnes.set("IPADDRESS", "10.0.0.1")
+            mnes.remove("TERMINAL_TYPE")
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert!(reader.try_recv().is_err());
+
+        let (name, ip, term): (String, String, Option<String>) = lua
+            .load(r#"return mnes.get("CLIENT_NAME"), mnes.variables().IPADDRESS, mnes.get("TERMINAL_TYPE")"#)
+            .eval()
+            .unwrap();
+        assert_eq!(name, "TEST");
+        assert_eq!(ip, "10.0.0.1");
+        assert_eq!(term, None);
+
+        state.lock().unwrap().reply(&[1]);
+        lua.load(r#"mnes.restore("CLIENT_NAME")"#).exec().unwrap();
+        match reader.try_recv().unwrap() {
+            Event::ProtoSubnegSend(opt::NEWENVIRON, data) => {
+                assert_eq!(data[..2], [2, 0]);
+                assert!(data.ends_with(b"BLIGHTMUD"));
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub use self::lua_script::{LuaScript, LuaScriptBuilder};
+pub use self::sessions::Sessions;
 pub use self::ui_event::UiEvent;
 
 #[cfg(test)]
@@ -12,9 +13,11 @@ mod core;
 mod exec_response;
 mod fs;
 mod fs_event;
+mod gmcp;
 mod line;
 mod log;
 mod lua_script;
+mod mnes;
 mod mud;
 mod plugin;
 mod prompt;
@@ -22,12 +25,15 @@ mod prompt_mask;
 mod regex;
 mod script;
 mod servers;
+mod sessions;
 mod settings;
 mod socket;
 #[cfg(feature = "spellcheck")]
 mod spellcheck;
+mod status;
 mod store;
 mod timer;
 mod tts;
+mod ui;
 mod ui_event;
 pub mod util;
@@ -1,17 +1,23 @@
+use std::time::Duration;
+
 use libtelnet_rs::bytes::Bytes;
-use mlua::{Function, Table, UserData, UserDataMethods};
+use mlua::{Function, Table, UserData, UserDataMethods, Value};
 
 use crate::{
     event::Event,
-    model::{Connection, Line},
+    io::SaveData,
+    model::{CertPins, Connection, GlobalProxy, Line, Proxy},
+    net::{Encoding, Keepalive, KeepaliveMode, RateLimit, ReconnectPolicy, ShareOptions},
 };
 
 use super::{
     backend::Backend,
     constants::{
-        BACKEND, IS_CONNECTED, MUD_INPUT_LISTENER_TABLE, MUD_OUTPUT_LISTENER_TABLE,
-        ON_CONNECTION_CALLBACK_TABLE, ON_DISCONNECT_CALLBACK_TABLE,
+        BACKEND, ENCODING, IS_CONNECTED, LATENCY, MUD_INPUT_LISTENER_TABLE,
+        MUD_OUTPUT_LISTENER_TABLE, ON_CONNECTION_CALLBACK_TABLE, ON_DISCONNECT_CALLBACK_TABLE,
+        ON_RECONNECT_ATTEMPT_CALLBACK_TABLE, ON_RECONNECT_FAILED_CALLBACK_TABLE, PROFILE,
     },
+    util::{get_option, parse_encoding_option, parse_profile_option, parse_proxy_option},
 };
 
 pub struct Mud {}
@@ -22,6 +28,66 @@ impl Mud {
     }
 }
 
+fn parse_reconnect_policy(table: &Table) -> mlua::Result<ReconnectPolicy> {
+    let mut policy = ReconnectPolicy::default();
+    policy.attempts = table
+        .get::<_, Option<u32>>("attempts")?
+        .unwrap_or(policy.attempts);
+    if let Some(delay) = table.get::<_, Option<f64>>("delay")? {
+        policy.delay = Duration::from_secs_f64(delay.max(0.0));
+    }
+    if let Some(max_delay) = table.get::<_, Option<f64>>("max_delay")? {
+        policy.max_delay = Duration::from_secs_f64(max_delay.max(0.0));
+    }
+    policy.jitter = table
+        .get::<_, Option<f64>>("jitter")?
+        .unwrap_or(policy.jitter);
+    Ok(policy)
+}
+
+fn parse_keepalive(table: &Table) -> mlua::Result<Keepalive> {
+    let mut keepalive = Keepalive::default();
+    if let Some(interval) = table.get::<_, Option<f64>>("interval")? {
+        if interval <= 0.0 {
+            return Err(mlua::Error::external(
+                "Keepalive interval must be positive".to_string(),
+            ));
+        }
+        keepalive.interval = Duration::from_secs_f64(interval);
+    }
+    keepalive.mode = match table.get::<_, Option<String>>("mode")?.as_deref() {
+        None | Some("timing_mark") => KeepaliveMode::TimingMark,
+        Some("nop") => KeepaliveMode::Nop,
+        Some(mode) => {
+            return Err(mlua::Error::external(format!(
+                "Unknown keepalive mode: {mode}"
+            )))
+        }
+    };
+    Ok(keepalive)
+}
+
+fn parse_rate_limit(table: &Table) -> mlua::Result<RateLimit> {
+    let mut limit = RateLimit::default();
+    if let Some(lines_per_second) = table.get::<_, Option<f64>>("lines_per_second")? {
+        if lines_per_second <= 0.0 {
+            return Err(mlua::Error::external(
+                "Lines per second must be positive".to_string(),
+            ));
+        }
+        limit.lines_per_second = lines_per_second;
+    }
+    if let Some(burst) = table.get::<_, Option<u32>>("burst")? {
+        if burst == 0 {
+            return Err(mlua::Error::external(
+                "Burst must be at least 1".to_string(),
+            ));
+        }
+        limit.burst = burst;
+    }
+    Ok(limit)
+}
+
 impl UserData for Mud {
     fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
         methods.add_function(
@@ -50,7 +116,14 @@ impl UserData for Mud {
         });
         methods.add_function(
             "connect",
-            |ctx, (host, port, tls, verify): (String, u16, bool, Option<bool>)| {
+            |ctx,
+             (host, port, tls, verify, options): (
+                String,
+                u16,
+                bool,
+                Option<bool>,
+                Option<Table>,
+            )| {
                 let backend: Backend = ctx.named_registry_value(BACKEND)?;
                 let verify_cert = if tls { verify.unwrap_or(true) } else { false };
                 backend
@@ -60,11 +133,38 @@ impl UserData for Mud {
                         port,
                         tls,
                         verify_cert,
+                        pin_cert: get_option(&options, "pin_cert")?.unwrap_or(false),
+                        proxy: parse_proxy_option(&options)?,
+                        client_cert: get_option(&options, "client_cert")?,
+                        client_key: get_option(&options, "client_key")?,
+                        encoding: parse_encoding_option(&options)?,
+                        profile: Box::new(parse_profile_option(&options)?),
+                        ..Default::default()
                     }))
                     .unwrap();
                 Ok(())
             },
         );
+        methods.add_function("set_proxy", |_, url: Option<String>| {
+            let proxy = url
+                .map(|url| url.parse::<Proxy>())
+                .transpose()
+                .map_err(mlua::Error::external)?;
+            GlobalProxy(proxy).save();
+            Ok(())
+        });
+        methods.add_function("get_proxy", |_, ()| {
+            Ok(GlobalProxy::load().0.map(|proxy| proxy.to_string()))
+        });
+        methods.add_function("cert_pins", |_, ()| Ok(CertPins::load().0));
+        methods.add_function("forget_cert_pin", |_, (host, port): (String, u16)| {
+            let mut pins = CertPins::load();
+            let removed = pins.remove(&host, port);
+            if removed {
+                pins.save();
+            }
+            Ok(removed)
+        });
         methods.add_function("disconnect", |ctx, ()| {
             let backend: Backend = ctx.named_registry_value(BACKEND)?;
             backend.writer.send(Event::Disconnect).unwrap();
@@ -75,6 +175,96 @@ impl UserData for Mud {
             backend.writer.send(Event::Reconnect).unwrap();
             Ok(())
         });
+        methods.add_function("set_encoding", |ctx, label: String| {
+            let encoding = Encoding::for_label(&label)
+                .ok_or_else(|| mlua::Error::external(format!("Unknown encoding: {label}")))?;
+            ctx.set_named_registry_value(ENCODING, encoding.name())?;
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::SetEncoding(encoding)).unwrap();
+            Ok(())
+        });
+        methods.add_function("get_profile", |ctx, ()| {
+            let profile: Option<Table> = ctx.named_registry_value(PROFILE)?;
+            Ok(profile)
+        });
+        methods.add_function("get_encoding", |ctx, ()| {
+            let name: Option<String> = ctx.named_registry_value(ENCODING)?;
+            Ok(name.unwrap_or_else(|| Encoding::default().name().to_string()))
+        });
+        methods.add_function("start_recording", |ctx, path: Option<String>| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::StartRecording(path)).unwrap();
+            Ok(())
+        });
+        methods.add_function("stop_recording", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::StopRecording).unwrap();
+            Ok(())
+        });
+        methods.add_function("share", |ctx, (port, options): (u16, Option<Table>)| {
+            let mut share = ShareOptions::new(port);
+            if let Some(options) = options {
+                if let Some(addr) = options.get::<_, Option<String>>("addr")? {
+                    share.addr = addr;
+                }
+                share.password = options.get("password")?;
+            }
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::Share(Some(share))).unwrap();
+            Ok(())
+        });
+        methods.add_function("stop_sharing", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::Share(None)).unwrap();
+            Ok(())
+        });
+        methods.add_function("replay", |ctx, (path, speed): (String, Option<f64>)| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend
+                .writer
+                .send(Event::Replay(path, speed.unwrap_or(1.0)))
+                .unwrap();
+            Ok(())
+        });
+        methods.add_function("auto_reconnect", |ctx, options: Value| {
+            let policy = match options {
+                Value::Boolean(false) => None,
+                Value::Nil | Value::Boolean(true) => Some(ReconnectPolicy::default()),
+                Value::Table(table) => Some(parse_reconnect_policy(&table)?),
+                _ => {
+                    return Err(mlua::Error::external(
+                        "Expected a table or a boolean".to_string(),
+                    ))
+                }
+            };
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::ReconnectPolicy(policy)).unwrap();
+            Ok(())
+        });
+        methods.add_function("keepalive", |ctx, options: Value| {
+            let keepalive = match options {
+                Value::Boolean(false) => None,
+                Value::Nil | Value::Boolean(true) => Some(Keepalive::default()),
+                Value::Table(table) => Some(parse_keepalive(&table)?),
+                _ => {
+                    return Err(mlua::Error::external(
+                        "Expected a table or a boolean".to_string(),
+                    ))
+                }
+            };
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::Keepalive(keepalive)).unwrap();
+            Ok(())
+        });
+        methods.add_function("latency", |ctx, ()| {
+            let latency: Option<f64> = ctx.named_registry_value(LATENCY)?;
+            Ok(latency)
+        });
+        methods.add_function("cancel_reconnect", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::CancelReconnect).unwrap();
+            Ok(())
+        });
         methods.add_function(
             "send",
             |ctx, (msg, options): (String, Option<mlua::Table>)| {
@@ -85,6 +275,7 @@ impl UserData for Mud {
                 if let Some(table) = options {
                     line.flags.gag = table.get("gag")?;
                     line.flags.skip_log = table.get("skip_log")?;
+                    line.flags.priority = table.get("priority")?;
                 }
 
                 let backend: Backend = ctx.named_registry_value(BACKEND)?;
@@ -92,6 +283,44 @@ impl UserData for Mud {
                 Ok(())
             },
         );
+        methods.add_function("send_rate", |ctx, options: Value| {
+            let limit = match options {
+                Value::Boolean(false) => None,
+                Value::Nil | Value::Boolean(true) => Some(RateLimit::default()),
+                Value::Table(table) => Some(parse_rate_limit(&table)?),
+                _ => {
+                    return Err(mlua::Error::external(
+                        "Expected a table or a boolean".to_string(),
+                    ))
+                }
+            };
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend.writer.send(Event::SendRate(limit)).unwrap();
+            Ok(())
+        });
+        methods.add_function("get_send_rate", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            let limit = backend.send_queue.lock().unwrap().limit();
+            match limit {
+                Some(limit) => {
+                    let table = ctx.create_table()?;
+                    table.set("lines_per_second", limit.lines_per_second)?;
+                    table.set("burst", limit.burst)?;
+                    Ok(Value::Table(table))
+                }
+                None => Ok(Value::Nil),
+            }
+        });
+        methods.add_function("send_queue", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            let pending = backend.send_queue.lock().unwrap().pending();
+            Ok(pending)
+        });
+        methods.add_function("clear_send_queue", |ctx, ()| {
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            let count = backend.send_queue.lock().unwrap().clear();
+            Ok(count)
+        });
         methods.add_function("send_bytes", |ctx, bytes: Vec<u8>| {
             let backend: Backend = ctx.named_registry_value(BACKEND)?;
             backend
@@ -117,6 +346,18 @@ impl UserData for Mud {
             table.set(table.raw_len() + 1, callback)?;
             Ok(())
         });
+        methods.add_function("on_reconnect_attempt", |ctx, callback: mlua::Function| {
+            let table: mlua::Table =
+                ctx.named_registry_value(ON_RECONNECT_ATTEMPT_CALLBACK_TABLE)?;
+            table.set(table.raw_len() + 1, callback)?;
+            Ok(())
+        });
+        methods.add_function("on_reconnect_failed", |ctx, callback: mlua::Function| {
+            let table: mlua::Table =
+                ctx.named_registry_value(ON_RECONNECT_FAILED_CALLBACK_TABLE)?;
+            table.set(table.raw_len() + 1, callback)?;
+            Ok(())
+        });
         methods.add_function("is_connected", |ctx, ()| {
             let value: bool = ctx.named_registry_value(IS_CONNECTED)?;
             Ok(value)
@@ -150,7 +391,10 @@ mod test_mud {
         model::Line,
     };
 
-    use super::Mud;
+    use super::{
+        Duration, Encoding, Keepalive, KeepaliveMode, Mud, RateLimit, ReconnectPolicy,
+        ShareOptions, LATENCY,
+    };
 
     #[test]
     fn test_output_register() {
@@ -201,6 +445,7 @@ mod test_mud {
                 port: 99,
                 tls: false,
                 verify_cert: false,
+                ..Default::default()
             }),
         );
         assert_event(
@@ -210,6 +455,7 @@ mod test_mud {
                 port: 99,
                 tls: false,
                 verify_cert: false,
+                ..Default::default()
             }),
         );
         assert_event(
@@ -219,6 +465,7 @@ mod test_mud {
                 port: 99,
                 tls: true,
                 verify_cert: true,
+                ..Default::default()
             }),
         );
         assert_event(
@@ -228,6 +475,7 @@ mod test_mud {
                 port: 99,
                 tls: true,
                 verify_cert: true,
+                ..Default::default()
             }),
         );
         assert_event(
@@ -237,6 +485,38 @@ mod test_mud {
                 port: 99,
                 tls: true,
                 verify_cert: false,
+                ..Default::default()
+            }),
+        );
+        assert_event(
+            "mud.connect(\"hostname\", 99, false, false, { proxy = \"socks5://proxy:1080\" })",
+            Event::Connect(Connection {
+                host: "hostname".to_string(),
+                port: 99,
+                proxy: Some("socks5://proxy:1080".parse().unwrap()),
+                ..Default::default()
+            }),
+        );
+        assert_event(
+            "mud.connect(\"hostname\", 99, true, false, { pin_cert = true })",
+            Event::Connect(Connection {
+                host: "hostname".to_string(),
+                port: 99,
+                tls: true,
+                pin_cert: true,
+                ..Default::default()
+            }),
+        );
+        assert_event(
+            "mud.connect(\"hostname\", 99, true, true, { client_cert = \"me.pem\", client_key = \"me.key\" })",
+            Event::Connect(Connection {
+                host: "hostname".to_string(),
+                port: 99,
+                tls: true,
+                verify_cert: true,
+                client_cert: Some("me.pem".to_string()),
+                client_key: Some("me.key".to_string()),
+                ..Default::default()
             }),
         );
     }
@@ -258,6 +538,209 @@ mod test_mud {
         assert_eq!(reader.recv().unwrap(), Event::Disconnect);
     }
 
+    #[test]
+    fn test_auto_reconnect() {
+        assert_event(
+            "mud.auto_reconnect()",
+            Event::ReconnectPolicy(Some(ReconnectPolicy::default())),
+        );
+        assert_event(
+            "mud.auto_reconnect({ attempts = 3, delay = 0.5, max_delay = 10, jitter = 0 })",
+            Event::ReconnectPolicy(Some(ReconnectPolicy {
+                attempts: 3,
+                delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(10),
+                jitter: 0.0,
+            })),
+        );
+        assert_event("mud.auto_reconnect(false)", Event::ReconnectPolicy(None));
+        assert_event("mud.cancel_reconnect()", Event::CancelReconnect);
+    }
+
+    #[test]
+    fn test_keepalive() {
+        assert_event(
+            "mud.keepalive()",
+            Event::Keepalive(Some(Keepalive::default())),
+        );
+        assert_event(
+            "mud.keepalive({ interval = 30, mode = 'nop' })",
+            Event::Keepalive(Some(Keepalive {
+                interval: Duration::from_secs(30),
+                mode: KeepaliveMode::Nop,
+            })),
+        );
+        assert_event("mud.keepalive(false)", Event::Keepalive(None));
+
+        let lua = Lua::new();
+        lua.globals().set("mud", Mud::new()).unwrap();
+        assert!(lua.load("mud.keepalive({ mode = 'ping' })").exec().is_err());
+        assert!(lua.load("mud.keepalive({ interval = 0 })").exec().is_err());
+        assert_eq!(
+            lua.load("return mud.latency()")
+                .call::<_, Option<f64>>(())
+                .unwrap(),
+            None
+        );
+        lua.set_named_registry_value(LATENCY, 0.25).unwrap();
+        assert_eq!(
+            lua.load("return mud.latency()")
+                .call::<_, Option<f64>>(())
+                .unwrap(),
+            Some(0.25)
+        );
+    }
+
+    #[test]
+    fn test_encoding() {
+        let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
+        let lua = Lua::new();
+        lua.set_named_registry_value(BACKEND, Backend::new(writer))
+            .unwrap();
+        lua.globals().set("mud", Mud::new()).unwrap();
+        let get_encoding = || {
+            lua.load("return mud.get_encoding()")
+                .call::<_, String>(())
+                .unwrap()
+        };
+        assert_eq!(get_encoding(), "UTF-8");
+        lua.load("mud.set_encoding('cp437')").exec().unwrap();
+        assert_eq!(reader.recv(), Ok(Event::SetEncoding(Encoding::Cp437)));
+        assert_eq!(get_encoding(), "CP437");
+        assert!(lua.load("mud.set_encoding('klingon')").exec().is_err());
+        assert!(lua
+            .load("mud.connect('host', 4000, false, false, { encoding = 'klingon' })")
+            .exec()
+            .is_err());
+        assert_eq!(get_encoding(), "CP437");
+
+        assert_event(
+            "mud.connect('host', 4000, false, false, { encoding = 'latin1' })",
+            Event::Connect(Connection {
+                host: "host".to_string(),
+                port: 4000,
+                encoding: Some("latin1".to_string()),
+                ..Default::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn test_recording() {
+        assert_event("mud.start_recording()", Event::StartRecording(None));
+        assert_event(
+            "mud.start_recording('dump.rec')",
+            Event::StartRecording(Some("dump.rec".to_string())),
+        );
+        assert_event("mud.stop_recording()", Event::StopRecording);
+        assert_event(
+            "mud.replay('dump.rec')",
+            Event::Replay("dump.rec".to_string(), 1.0),
+        );
+        assert_event(
+            "mud.replay('dump.rec', 0)",
+            Event::Replay("dump.rec".to_string(), 0.0),
+        );
+    }
+
+    #[test]
+    fn test_share() {
+        assert_event(
+            "mud.share(4000)",
+            Event::Share(Some(ShareOptions::new(4000))),
+        );
+        assert_event(
+            "mud.share(4000, { addr = '0.0.0.0', password = 'secret' })",
+            Event::Share(Some(ShareOptions {
+                addr: "0.0.0.0".to_string(),
+                port: 4000,
+                password: Some("secret".to_string()),
+            })),
+        );
+        assert_event("mud.stop_sharing()", Event::Share(None));
+    }
+
+    #[test]
+    fn test_send_priority() {
+        let mut line = Line::from("flee");
+        line.flags.bypass_script = true;
+        line.flags.priority = true;
+        line.flags.source = Some("script".to_string());
+        assert_event(
+            "mud.send('flee', { priority = true })",
+            Event::ServerInput(line),
+        );
+    }
+
+    #[test]
+    fn test_send_rate() {
+        assert_event(
+            "mud.send_rate()",
+            Event::SendRate(Some(RateLimit::default())),
+        );
+        assert_event(
+            "mud.send_rate({ lines_per_second = 2.5, burst = 4 })",
+            Event::SendRate(Some(RateLimit {
+                lines_per_second: 2.5,
+                burst: 4,
+            })),
+        );
+        assert_event("mud.send_rate(false)", Event::SendRate(None));
+
+        let lua = Lua::new();
+        lua.globals().set("mud", Mud::new()).unwrap();
+        assert!(lua
+            .load("mud.send_rate({ lines_per_second = 0 })")
+            .exec()
+            .is_err());
+        assert!(lua.load("mud.send_rate({ burst = 0 })").exec().is_err());
+    }
+
+    #[test]
+    fn test_send_queue() {
+        let (writer, _reader): (Sender<Event>, Receiver<Event>) = channel();
+        let backend = Backend::new(writer);
+        let send_queue = backend.send_queue.clone();
+        let lua = Lua::new();
+        lua.set_named_registry_value(BACKEND, backend).unwrap();
+        lua.globals().set("mud", Mud::new()).unwrap();
+
+        assert!(lua
+            .load("return mud.get_send_rate()")
+            .call::<_, Option<mlua::Table>>(())
+            .unwrap()
+            .is_none());
+        {
+            let mut queue = send_queue.lock().unwrap();
+            queue.set_limit(Some(RateLimit {
+                lines_per_second: 1.0,
+                burst: 1,
+            }));
+            for line in ["north", "east", "south"] {
+                queue.push(line, Bytes::from(format!("{line}\r\n")));
+            }
+        }
+        assert_eq!(
+            lua.load("return mud.get_send_rate().burst")
+                .call::<_, u32>(())
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            lua.load("return mud.send_queue()")
+                .call::<_, Vec<String>>(())
+                .unwrap(),
+            vec!["east", "south"]
+        );
+        assert_eq!(
+            lua.load("return mud.clear_send_queue()")
+                .call::<_, usize>(())
+                .unwrap(),
+            2
+        );
+        assert!(send_queue.lock().unwrap().pending().is_empty());
+    }
+
     #[test]
     fn test_send_bytes() {
         assert_event(
@@ -5,6 +5,7 @@ use crate::event::Event;
 use super::{
     backend::Backend,
     constants::{BACKEND, PROMPT_CONTENT, PROMPT_CURSOR_INDEX, PROMPT_INPUT_LISTENER_TABLE},
+    util::get_option,
 };
 
 #[derive(Debug, Clone)]
@@ -38,6 +39,16 @@ impl UserData for Prompt {
             backend.writer.send(Event::SetPromptCursorPos(pos)).unwrap();
             Ok(())
         });
+        methods.add_function("compose", |ctx, options: Option<Table>| {
+            let text: Option<String> = get_option(&options, "text")?;
+            let terminator: Option<String> = get_option(&options, "terminator")?;
+            let backend: Backend = ctx.named_registry_value(BACKEND)?;
+            backend
+                .writer
+                .send(Event::StartCompose(text, terminator))
+                .unwrap();
+            Ok(())
+        });
         methods.add_function(
             "add_prompt_listener",
             |ctx, func: Function| -> mlua::Result<()> {
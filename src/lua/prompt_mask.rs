@@ -63,7 +63,7 @@ mod test_prompt_mask {
     fn get_lua_state(prompt_content: &str) -> (Lua, Receiver<Event>) {
         let lua = Lua::new();
         let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
-        let backend = Backend { writer };
+        let backend = Backend::new(writer);
         let prompt_mask = PromptMask {};
         lua.globals().set("prompt_mask", prompt_mask).unwrap();
         lua.set_named_registry_value(PROMPT_CONTENT, prompt_content)
@@ -1,6 +1,10 @@
 use crate::io::SaveData;
+use crate::lua::util::{
+    get_option, parse_encoding_option, parse_profile_option, parse_proxy_option,
+};
 use crate::model::{Connection, Servers as MServers};
-use mlua::{UserData, UserDataMethods};
+use crate::net::Encoding;
+use mlua::{Table, UserData, UserDataMethods};
 
 use mlua::prelude::ToLua;
 
@@ -23,6 +27,23 @@ impl UserData for Server {
                     "port" => Ok(this.connection.port.to_lua(ctx)?),
                     "tls" => Ok(this.connection.tls.to_lua(ctx)?),
                     "verify_cert" => Ok(this.connection.verify_cert.to_lua(ctx)?),
+                    "pin_cert" => Ok(this.connection.pin_cert.to_lua(ctx)?),
+                    "sound_dir" => Ok(this.connection.sound_dir.clone().to_lua(ctx)?),
+                    "proxy" => Ok(this
+                        .connection
+                        .proxy
+                        .as_ref()
+                        .map(|proxy| proxy.url())
+                        .to_lua(ctx)?),
+                    "client_cert" => Ok(this.connection.client_cert.clone().to_lua(ctx)?),
+                    "client_key" => Ok(this.connection.client_key.clone().to_lua(ctx)?),
+                    "encoding" => Ok(this.connection.encoding.clone().to_lua(ctx)?),
+                    "scripts" => Ok(this.connection.profile.scripts.clone().to_lua(ctx)?),
+                    "settings" => Ok(this.connection.profile.settings.clone().to_lua(ctx)?),
+                    "log_dir" => Ok(this.connection.profile.log_dir.clone().to_lua(ctx)?),
+                    "history_file" => {
+                        Ok(this.connection.profile.history_file.clone().to_lua(ctx)?)
+                    }
                     _ => Err(mlua::Error::external(format!("Invalid index: {key}"))),
                 }
             },
@@ -52,7 +73,14 @@ impl UserData for Servers {
         methods.add_function(
             "add",
             |_,
-             (name, host, port, tls, verify): (String, String, u16, bool, Option<bool>)|
+             (name, host, port, tls, verify, options): (
+                String,
+                String,
+                u16,
+                bool,
+                Option<bool>,
+                Option<Table>,
+            )|
              -> mlua::Result<()> {
                 let mut servers = ServerLoader::get()?;
 
@@ -67,6 +95,13 @@ impl UserData for Servers {
                         port,
                         tls,
                         verify_cert: verify.unwrap_or(false),
+                        pin_cert: get_option(&options, "pin_cert")?.unwrap_or(false),
+                        sound_dir: get_option(&options, "sound_dir")?,
+                        proxy: parse_proxy_option(&options)?,
+                        client_cert: get_option(&options, "client_cert")?,
+                        client_key: get_option(&options, "client_key")?,
+                        encoding: parse_encoding_option(&options)?,
+                        profile: Box::new(parse_profile_option(&options)?),
                     };
                     servers.insert(name, connection);
                     servers.save();
@@ -85,6 +120,42 @@ impl UserData for Servers {
                 )))
             }
         });
+        methods.add_function(
+            "set_encoding",
+            |_, (name, encoding): (String, Option<String>)| -> mlua::Result<()> {
+                if let Some(label) = &encoding {
+                    if Encoding::for_label(label).is_none() {
+                        return Err(mlua::Error::external(format!("Unknown encoding: {label}")));
+                    }
+                }
+                let mut servers = ServerLoader::get()?;
+                if let Some(connection) = servers.get_mut(&name) {
+                    connection.encoding = encoding;
+                    servers.save();
+                    Ok(())
+                } else {
+                    Err(mlua::Error::external(format!(
+                        "Saved server does not exist: {name}"
+                    )))
+                }
+            },
+        );
+        methods.add_function(
+            "set_profile",
+            |_, (name, profile): (String, Option<Table>)| -> mlua::Result<()> {
+                let profile = parse_profile_option(&profile)?;
+                let mut servers = ServerLoader::get()?;
+                if let Some(connection) = servers.get_mut(&name) {
+                    *connection.profile = profile;
+                    servers.save();
+                    Ok(())
+                } else {
+                    Err(mlua::Error::external(format!(
+                        "Saved server does not exist: {name}"
+                    )))
+                }
+            },
+        );
         methods.add_function("get", |_, name: String| -> mlua::Result<Server> {
             let servers = ServerLoader::get()?;
             if servers.contains_key(&name) {
@@ -0,0 +1,143 @@
+use mlua::{AnyUserData, UserData, UserDataMethods, Value};
+
+use crate::event::Event;
+
+use super::{backend::Backend, constants::BACKEND};
+
+/// The sessions of the client as seen from the Lua state of one of them
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sessions {
+    /// The session owning this Lua state
+    pub name: String,
+    /// The session currently on screen
+    pub active: String,
+    /// All sessions in the order they were opened
+    pub names: Vec<String>,
+}
+
+impl Sessions {
+    pub const LUA_GLOBAL_NAME: &'static str = "sessions";
+}
+
+fn send_event(ctx: &mlua::Lua, event: Event) -> mlua::Result<()> {
+    let backend: Backend = ctx.named_registry_value(BACKEND)?;
+    backend.writer.send(event).unwrap();
+    Ok(())
+}
+
+impl UserData for Sessions {
+    fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
+        methods.add_function("open", |ctx, name: String| {
+            send_event(ctx, Event::OpenSession(name))
+        });
+        methods.add_function("switch", |ctx, target: Value| {
+            let target = match target {
+                Value::Integer(index) => index.to_string(),
+                Value::Number(index) => (index as i64).to_string(),
+                Value::String(name) => name.to_str()?.to_string(),
+                _ => {
+                    return Err(mlua::Error::external(
+                        "Expected a session name or index".to_string(),
+                    ))
+                }
+            };
+            send_event(ctx, Event::SwitchSession(target))
+        });
+        methods.add_function("next", |ctx, ()| send_event(ctx, Event::NextSession));
+        methods.add_function("prev", |ctx, ()| send_event(ctx, Event::PrevSession));
+        methods.add_function("close", |ctx, name: Option<String>| {
+            send_event(ctx, Event::CloseSession(name))
+        });
+        methods.add_function("name", |ctx, ()| {
+            let sessions_aud: AnyUserData = ctx.globals().get(Sessions::LUA_GLOBAL_NAME)?;
+            let sessions = sessions_aud.borrow::<Sessions>()?;
+            Ok(sessions.name.clone())
+        });
+        methods.add_function("active", |ctx, ()| {
+            let sessions_aud: AnyUserData = ctx.globals().get(Sessions::LUA_GLOBAL_NAME)?;
+            let sessions = sessions_aud.borrow::<Sessions>()?;
+            Ok(sessions.active.clone())
+        });
+        methods.add_function("list", |ctx, ()| {
+            let sessions_aud: AnyUserData = ctx.globals().get(Sessions::LUA_GLOBAL_NAME)?;
+            let sessions = sessions_aud.borrow::<Sessions>()?;
+            Ok(sessions.names.clone())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_sessions {
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    use mlua::Lua;
+
+    use super::*;
+
+    fn get_lua() -> (Lua, Receiver<Event>) {
+        let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
+        let lua = Lua::new();
+        lua.set_named_registry_value(BACKEND, Backend::new(writer))
+            .unwrap();
+        lua.globals()
+            .set(
+                Sessions::LUA_GLOBAL_NAME,
+                Sessions {
+                    name: "alt".to_string(),
+                    active: "main".to_string(),
+                    names: vec!["main".to_string(), "alt".to_string()],
+                },
+            )
+            .unwrap();
+        (lua, reader)
+    }
+
+    #[test]
+    fn test_events() {
+        let (lua, reader) = get_lua();
+        let assert_event = |code: &str, event: Event| {
+            lua.load(code).exec().unwrap();
+            assert_eq!(reader.recv(), Ok(event));
+        };
+        assert_event(
+            "sessions.open('bank')",
+            Event::OpenSession("bank".to_string()),
+        );
+        assert_event(
+            "sessions.switch('alt')",
+            Event::SwitchSession("alt".to_string()),
+        );
+        assert_event("sessions.switch(2)", Event::SwitchSession("2".to_string()));
+        assert_event("sessions.next()", Event::NextSession);
+        assert_event("sessions.prev()", Event::PrevSession);
+        assert_event("sessions.close()", Event::CloseSession(None));
+        assert_event(
+            "sessions.close('alt')",
+            Event::CloseSession(Some("alt".to_string())),
+        );
+        assert!(lua.load("sessions.switch({})").exec().is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        let (lua, _reader) = get_lua();
+        assert_eq!(
+            lua.load("return sessions.name()")
+                .call::<_, String>(())
+                .unwrap(),
+            "alt"
+        );
+        assert_eq!(
+            lua.load("return sessions.active()")
+                .call::<_, String>(())
+                .unwrap(),
+            "main"
+        );
+        assert_eq!(
+            lua.load("return sessions.list()")
+                .call::<_, Vec<String>>(())
+                .unwrap(),
+            vec!["main", "alt"]
+        );
+    }
+}
@@ -1,26 +1,422 @@
 use std::{
-    io::Write,
-    net::{Shutdown, TcpStream},
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
 };
 
-use mlua::{UserData, UserDataMethods};
+use log::debug;
+use mlua::{AnyUserData, Function, Lua, Table, UserData, UserDataMethods};
 
 use crate::{
     event::Event,
-    lua::{backend::Backend, constants::BACKEND},
-    net::open_tcp_stream,
+    lua::{
+        backend::Backend,
+        constants::{BACKEND, SOCKET_CALLBACK_TABLE},
+    },
+    net::open_tcp_stream_timeout,
 };
 
+/// Ids are shared by all scripts so events from sockets opened before a
+/// script reset can't reach callbacks registered after it.
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// How often listeners check if they have been closed
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+struct SocketOptions {
+    line_mode: bool,
+    timeout: Duration,
+    read_timeout: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            line_mode: false,
+            timeout: Duration::from_secs(3),
+            read_timeout: None,
+        }
+    }
+}
+
+fn parse_options(options: Option<Table>) -> mlua::Result<SocketOptions> {
+    let mut socket_options = SocketOptions::default();
+    if let Some(table) = options {
+        socket_options.line_mode = table.get::<_, Option<bool>>("line_mode")?.unwrap_or(false);
+        if let Some(timeout) = table.get::<_, Option<f64>>("timeout")? {
+            if timeout <= 0.0 {
+                return Err(mlua::Error::external(
+                    "Timeout must be positive".to_string(),
+                ));
+            }
+            socket_options.timeout = Duration::from_secs_f64(timeout);
+        }
+        if let Some(read_timeout) = table.get::<_, Option<f64>>("read_timeout")? {
+            if read_timeout <= 0.0 {
+                return Err(mlua::Error::external(
+                    "Read timeout must be positive".to_string(),
+                ));
+            }
+            socket_options.read_timeout = Some(Duration::from_secs_f64(read_timeout));
+        }
+    }
+    Ok(socket_options)
+}
+
+/// Removes and returns the complete lines in the buffer, without line endings
+fn split_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = vec![];
+    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+        let mut line: Vec<u8> = buffer.drain(..=pos).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        lines.push(String::from_utf8_lossy(&line).to_string());
+    }
+    lines
+}
+
+/// Removes and returns the decoded buffer, keeping a character that is cut
+/// off at the end for the next read
+fn take_text(buffer: &mut Vec<u8>) -> String {
+    let mut keep = 0;
+    for (back, byte) in buffer.iter().rev().take(3).enumerate() {
+        let len = match byte {
+            0x80..=0xbf => continue,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => break,
+        };
+        if len > back + 1 {
+            keep = back + 1;
+        }
+        break;
+    }
+    let text: Vec<u8> = buffer.drain(..buffer.len() - keep).collect();
+    String::from_utf8_lossy(&text).to_string()
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => Ok(Self::Tcp(stream.try_clone()?)),
+            Self::Unix(stream) => Ok(Self::Unix(stream.try_clone()?)),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            Self::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            Self::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            Self::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            Self::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+enum State {
+    Connecting,
+    Open(Stream),
+    Closed,
+}
+
+/// Reads from the socket until it's closed, posting the data to the main loop
+fn read_socket(
+    id: u32,
+    mut stream: Stream,
+    options: SocketOptions,
+    state: Arc<Mutex<State>>,
+    writer: Sender<Event>,
+) {
+    let send_data = |data: String| writer.send(Event::SocketData(id, data)).is_ok();
+    let mut pending = vec![];
+    let mut buffer = [0; BUFFER_SIZE];
+    stream.set_read_timeout(options.read_timeout).ok();
+    let reason = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break None,
+            Ok(n) if options.line_mode => {
+                pending.extend_from_slice(&buffer[..n]);
+                if !split_lines(&mut pending).into_iter().all(send_data) {
+                    break None;
+                }
+            }
+            Ok(n) => {
+                pending.extend_from_slice(&buffer[..n]);
+                let text = take_text(&mut pending);
+                if !text.is_empty() && !send_data(text) {
+                    break None;
+                }
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                break Some("Timed out".to_string())
+            }
+            Err(err) => break Some(err.to_string()),
+        }
+    };
+    if !pending.is_empty() {
+        send_data(String::from_utf8_lossy(&pending).to_string());
+    }
+    stream.shutdown().ok();
+    *state.lock().unwrap() = State::Closed;
+    debug!("Socket {} closed: {:?}", id, reason);
+    writer.send(Event::SocketClosed(id, reason)).ok();
+}
+
+fn spawn_reader(
+    id: u32,
+    stream: Stream,
+    options: SocketOptions,
+    state: Arc<Mutex<State>>,
+    writer: Sender<Event>,
+) {
+    thread::Builder::new()
+        .name(format!("socket-{id}-thread"))
+        .spawn(move || read_socket(id, stream, options, state, writer))
+        .unwrap();
+}
+
+fn spawn_connect(
+    id: u32,
+    host: String,
+    port: u16,
+    options: SocketOptions,
+    state: Arc<Mutex<State>>,
+    writer: Sender<Event>,
+) {
+    thread::Builder::new()
+        .name(format!("socket-{id}-thread"))
+        .spawn(move || {
+            let stream = open_tcp_stream_timeout(&host, port, options.timeout)
+                .and_then(|stream| Ok((Stream::Tcp(stream.try_clone()?), Stream::Tcp(stream))));
+            let stream = {
+                let mut state = state.lock().unwrap();
+                match stream {
+                    Ok((stream, reader)) if matches!(*state, State::Connecting) => {
+                        *state = State::Open(stream);
+                        Some(reader)
+                    }
+                    Ok((stream, _)) => {
+                        stream.shutdown().ok();
+                        None
+                    }
+                    Err(err) => {
+                        debug!("Socket {} failed to connect: {}", id, err);
+                        *state = State::Closed;
+                        writer
+                            .send(Event::SocketClosed(
+                                id,
+                                Some(format!("Unable to connect to {host}:{port}")),
+                            ))
+                            .ok();
+                        return;
+                    }
+                }
+            };
+            match stream {
+                Some(stream) => {
+                    writer.send(Event::SocketConnected(id)).ok();
+                    read_socket(id, stream, options, state, writer);
+                }
+                None => {
+                    writer.send(Event::SocketClosed(id, None)).ok();
+                }
+            }
+        })
+        .unwrap();
+}
+
+enum ServerSocket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl ServerSocket {
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok(Stream::Tcp(stream))
+            }
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok(Stream::Unix(stream))
+            }
+        }
+    }
+}
+
+/// Binds a Unix socket, replacing a socket file left behind by a listener
+/// that is gone
+fn bind_unix(path: &str) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(err) if err.kind() == ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+/// Accepts clients until the listener is closed. Accepted clients wait in
+/// `pending` until the main loop hands them to the accept callback.
+fn spawn_listener(
+    id: u32,
+    server: ServerSocket,
+    options: SocketOptions,
+    closed: Arc<AtomicBool>,
+    pending: Arc<Mutex<HashMap<u32, Socket>>>,
+    writer: Sender<Event>,
+) {
+    thread::Builder::new()
+        .name(format!("socket-{id}-listener-thread"))
+        .spawn(move || {
+            while !closed.load(Ordering::Relaxed) {
+                match server.accept() {
+                    Ok(stream) => {
+                        let Ok(reader) = stream.try_clone() else {
+                            continue;
+                        };
+                        let client = Socket::new(State::Open(stream));
+                        let (client_id, state) = (client.id, client.state.clone());
+                        pending.lock().unwrap().insert(client_id, client);
+                        if writer.send(Event::SocketAccepted(id, client_id)).is_err() {
+                            break;
+                        }
+                        spawn_reader(client_id, reader, options, state, writer.clone());
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL)
+                    }
+                    Err(err) => {
+                        debug!("Listener {} failed: {}", id, err);
+                        break;
+                    }
+                }
+            }
+            debug!("Listener {} closed", id);
+        })
+        .unwrap();
+}
+
+/// Creates the userdata for a socket and keeps it alive until the socket
+/// has closed, so callbacks keep working without holding on to it.
+pub(crate) fn register_socket(ctx: &Lua, socket: Socket) -> mlua::Result<AnyUserData<'_>> {
+    let id = socket.id;
+    let socket = ctx.create_userdata(socket)?;
+    let callbacks = ctx.create_table()?;
+    callbacks.raw_set("socket", socket.clone())?;
+    let table: Table = ctx.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+    table.raw_set(id, callbacks)?;
+    Ok(socket)
+}
+
+fn listen<'lua>(
+    ctx: &'lua Lua,
+    server: ServerSocket,
+    port: Option<u16>,
+    path: Option<PathBuf>,
+    callback: Function<'lua>,
+    options: SocketOptions,
+) -> mlua::Result<AnyUserData<'lua>> {
+    let backend: Backend = ctx.named_registry_value(BACKEND)?;
+    let listener = Listener {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        port,
+        path,
+        closed: Arc::new(AtomicBool::new(false)),
+        pending: Arc::default(),
+    };
+    spawn_listener(
+        listener.id,
+        server,
+        options,
+        listener.closed.clone(),
+        listener.pending.clone(),
+        backend.writer,
+    );
+    let id = listener.id;
+    let listener = ctx.create_userdata(listener)?;
+    let callbacks = ctx.create_table()?;
+    callbacks.raw_set("listener", listener.clone())?;
+    callbacks.raw_set("accept", callback)?;
+    let table: Table = ctx.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+    table.raw_set(id, callbacks)?;
+    Ok(listener)
+}
+
 pub struct SocketLib;
 
 impl UserData for SocketLib {
     fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
         methods.add_function(
             "connect",
-            |ctx, (host, port): (String, u16)| -> mlua::Result<Option<Socket>> {
+            |ctx, (host, port, options): (String, u16, Option<Table>)| {
+                let options = parse_options(options)?;
                 let backend: Backend = ctx.named_registry_value(BACKEND)?;
-                if let Ok(connection) = open_tcp_stream(&host, port) {
-                    Ok(Some(Socket { connection }))
+                let stream = open_tcp_stream_timeout(&host, port, options.timeout)
+                    .and_then(|stream| Ok((Stream::Tcp(stream.try_clone()?), Stream::Tcp(stream))));
+                if let Ok((stream, reader)) = stream {
+                    let socket = Socket::new(State::Open(stream));
+                    spawn_reader(
+                        socket.id,
+                        reader,
+                        options,
+                        socket.state.clone(),
+                        backend.writer,
+                    );
+                    Ok(Some(register_socket(ctx, socket)?))
                 } else {
                     backend
                         .writer
@@ -30,22 +426,243 @@ impl UserData for SocketLib {
                 }
             },
         );
+        methods.add_function(
+            "connect_async",
+            |ctx, (host, port, options): (String, u16, Option<Table>)| {
+                let options = parse_options(options)?;
+                let backend: Backend = ctx.named_registry_value(BACKEND)?;
+                let socket = Socket::new(State::Connecting);
+                spawn_connect(
+                    socket.id,
+                    host,
+                    port,
+                    options,
+                    socket.state.clone(),
+                    backend.writer,
+                );
+                register_socket(ctx, socket)
+            },
+        );
+        methods.add_function(
+            "listen",
+            |ctx, (addr, port, callback, options): (String, u16, Function, Option<Table>)| {
+                let options = parse_options(options)?;
+                let bound = TcpListener::bind((addr.as_str(), port)).and_then(|listener| {
+                    listener.set_nonblocking(true)?;
+                    let port = listener.local_addr()?.port();
+                    Ok((listener, port))
+                });
+                match bound {
+                    Ok((listener, port)) => Ok(Some(listen(
+                        ctx,
+                        ServerSocket::Tcp(listener),
+                        Some(port),
+                        None,
+                        callback,
+                        options,
+                    )?)),
+                    Err(err) => {
+                        let backend: Backend = ctx.named_registry_value(BACKEND)?;
+                        backend
+                            .writer
+                            .send(Event::Error(format!(
+                                "Unable to listen on {addr}:{port}: {err}"
+                            )))
+                            .unwrap();
+                        Ok(None)
+                    }
+                }
+            },
+        );
+        methods.add_function(
+            "listen_unix",
+            |ctx, (path, callback, options): (String, Function, Option<Table>)| {
+                let options = parse_options(options)?;
+                let bound = bind_unix(&path).and_then(|listener| {
+                    listener.set_nonblocking(true)?;
+                    Ok(listener)
+                });
+                match bound {
+                    Ok(listener) => Ok(Some(listen(
+                        ctx,
+                        ServerSocket::Unix(listener),
+                        None,
+                        Some(PathBuf::from(path)),
+                        callback,
+                        options,
+                    )?)),
+                    Err(err) => {
+                        let backend: Backend = ctx.named_registry_value(BACKEND)?;
+                        backend
+                            .writer
+                            .send(Event::Error(format!("Unable to listen on {path}: {err}")))
+                            .unwrap();
+                        Ok(None)
+                    }
+                }
+            },
+        );
     }
 }
 
 pub struct Socket {
-    connection: TcpStream,
+    id: u32,
+    state: Arc<Mutex<State>>,
+}
+
+impl Socket {
+    fn new(state: State) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        match &*state {
+            State::Open(stream) => {
+                let _ = stream.shutdown();
+            }
+            State::Connecting => *state = State::Closed,
+            State::Closed => {}
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn set_callback(ctx: &Lua, id: u32, name: &str, callback: Function) -> mlua::Result<()> {
+    let table: Table = ctx.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+    match table.raw_get::<_, Option<Table>>(id)? {
+        Some(callbacks) => callbacks.raw_set(name, callback),
+        // The socket has already closed
+        None => Ok(()),
+    }
 }
 
 impl UserData for Socket {
     fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
         methods.add_method_mut("send", |_, this, data: String| {
-            let _ = this.connection.write(data.as_bytes());
-            Ok(())
+            let mut state = this.state.lock().unwrap();
+            match &mut *state {
+                State::Open(stream) => Ok(stream.write_all(data.as_bytes()).is_ok()),
+                _ => Ok(false),
+            }
         });
-        methods.add_method_mut("close", |_, this, ()| {
-            let _ = this.connection.shutdown(Shutdown::Both);
+        methods.add_method("close", |_, this, ()| {
+            this.close();
             Ok(())
         });
+        methods.add_method("is_connected", |_, this, ()| {
+            Ok(matches!(*this.state.lock().unwrap(), State::Open(_)))
+        });
+        methods.add_method("on_connect", |ctx, this, callback: Function| {
+            set_callback(ctx, this.id, "connect", callback)
+        });
+        methods.add_method("on_data", |ctx, this, callback: Function| {
+            set_callback(ctx, this.id, "data", callback)
+        });
+        methods.add_method("on_close", |ctx, this, callback: Function| {
+            set_callback(ctx, this.id, "close", callback)
+        });
+    }
+}
+
+pub struct Listener {
+    id: u32,
+    port: Option<u16>,
+    path: Option<PathBuf>,
+    closed: Arc<AtomicBool>,
+    pending: Arc<Mutex<HashMap<u32, Socket>>>,
+}
+
+impl Listener {
+    /// Takes an accepted client that hasn't been handed to a script yet
+    pub(crate) fn take_client(&self, id: u32) -> Option<Socket> {
+        self.pending.lock().unwrap().remove(&id)
+    }
+
+    fn close(&self) {
+        if !self.closed.swap(true, Ordering::Relaxed) {
+            if let Some(path) = &self.path {
+                std::fs::remove_file(path).ok();
+            }
+        }
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl UserData for Listener {
+    fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
+        methods.add_method("close", |ctx, this, ()| {
+            this.close();
+            let table: Table = ctx.named_registry_value(SOCKET_CALLBACK_TABLE)?;
+            table.raw_set(this.id, mlua::Value::Nil)
+        });
+        methods.add_method("is_listening", |_, this, ()| {
+            Ok(!this.closed.load(Ordering::Relaxed))
+        });
+        methods.add_method("port", |_, this, ()| Ok(this.port));
+    }
+}
+
+#[cfg(test)]
+mod test_socket {
+    use super::*;
+
+    #[test]
+    fn test_split_lines() {
+        let mut buffer = b"first\r\nsecond\nthi".to_vec();
+        assert_eq!(split_lines(&mut buffer), vec!["first", "second"]);
+        assert_eq!(buffer, b"thi");
+        buffer.extend_from_slice(b"rd\n\n");
+        assert_eq!(split_lines(&mut buffer), vec!["third", ""]);
+        assert!(buffer.is_empty());
+        assert!(split_lines(&mut buffer).is_empty());
+    }
+
+    #[test]
+    fn test_take_text() {
+        let mut buffer = "añ€".as_bytes().to_vec();
+        buffer.extend_from_slice(&"😀".as_bytes()[..2]);
+        assert_eq!(take_text(&mut buffer), "añ€");
+        assert_eq!(buffer, &"😀".as_bytes()[..2]);
+        assert_eq!(take_text(&mut buffer), "");
+        buffer.extend_from_slice(&"😀".as_bytes()[2..]);
+        assert_eq!(take_text(&mut buffer), "😀");
+        assert!(buffer.is_empty());
+        buffer.extend_from_slice(b"bad \xff");
+        assert_eq!(take_text(&mut buffer), "bad \u{fffd}");
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let lua = mlua::Lua::new();
+        let options = parse_options(None).unwrap();
+        assert!(!options.line_mode);
+        assert_eq!(options.read_timeout, None);
+
+        let table = lua
+            .load("return { line_mode = true, timeout = 0.5, read_timeout = 10 }")
+            .eval::<Table>()
+            .unwrap();
+        let options = parse_options(Some(table)).unwrap();
+        assert!(options.line_mode);
+        assert_eq!(options.timeout, Duration::from_millis(500));
+        assert_eq!(options.read_timeout, Some(Duration::from_secs(10)));
+
+        let table = lua.load("return { timeout = 0 }").eval::<Table>().unwrap();
+        assert!(parse_options(Some(table)).is_err());
     }
 }
@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use mlua::{AnyUserData, Function, Table, UserData, UserDataMethods, Value};
+
+use crate::{
+    event::Event,
+    model::{Gauge, StatusItem, StatusLayout, StatusWidget},
+};
+
+use super::{gmcp::add_listener, util::get_option};
+
+type Widgets = Arc<Mutex<HashMap<String, StatusWidget>>>;
+
+fn to_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(value) => Some(*value as f64),
+        Value::Number(value) => Some(*value),
+        Value::String(value) => value.to_str().ok()?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn to_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::String(value) => Some(value.to_str().ok()?.to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Looks up a dotted path like `vitals.hp` in decoded data
+fn lookup<'lua>(data: &Value<'lua>, path: &str) -> mlua::Result<Value<'lua>> {
+    let mut value = data.clone();
+    for key in path.split('.') {
+        value = match value {
+            Value::Table(table) => table.get(key)?,
+            _ => return Ok(Value::Nil),
+        };
+    }
+    Ok(value)
+}
+
+/// Reads a segment of a status line, a single item or a list of them
+fn parse_items(value: Value) -> mlua::Result<Vec<StatusItem>> {
+    let item = |value: Value| -> mlua::Result<StatusItem> {
+        match value {
+            Value::UserData(widget) => {
+                Ok(StatusItem::Widget(widget.borrow::<Widget>()?.name.clone()))
+            }
+            value => match to_text(&value) {
+                Some(text) => Ok(StatusItem::Text(text)),
+                None => Err(mlua::Error::external(
+                    "Status items must be text or widgets",
+                )),
+            },
+        }
+    };
+    match value {
+        Value::Nil => Ok(vec![]),
+        Value::Table(items) => items.sequence_values::<Value>().map(|v| item(v?)).collect(),
+        value => Ok(vec![item(value)?]),
+    }
+}
+
+fn parse_gauge(gauge: &mut Gauge, options: &Option<Table>) -> mlua::Result<()> {
+    if let Some(label) = get_option(options, "label")? {
+        gauge.label = label;
+    }
+    if let Some(value) = get_option(options, "value")? {
+        gauge.value = value;
+    }
+    if let Some(max) = get_option(options, "max")? {
+        gauge.max = max;
+    }
+    if let Some(width) = get_option(options, "width")? {
+        gauge.width = width;
+    }
+    if let Some(color) = get_option(options, "color")? {
+        gauge.color = color;
+    }
+    if let Some(empty_color) = get_option(options, "empty_color")? {
+        gauge.empty_color = empty_color;
+    }
+    if let Some(show_value) = get_option(options, "show_value")? {
+        gauge.show_value = show_value;
+    }
+    Ok(())
+}
+
+/// A handle to a widget in the status area
+#[derive(Clone)]
+pub struct Widget {
+    name: String,
+    widgets: Widgets,
+    writer: Sender<Event>,
+}
+
+impl Widget {
+    /// Sets the text of a text widget, or the value and max of a gauge.
+    /// Values that can't be used are ignored.
+    fn set(&self, value: &Value, max: &Value) {
+        let mut widgets = self.widgets.lock().unwrap();
+        if let Some(widget) = widgets.get_mut(&self.name) {
+            match widget {
+                StatusWidget::Text(text) => {
+                    if let Some(value) = to_text(value) {
+                        *text = value;
+                    }
+                }
+                StatusWidget::Gauge(gauge) => {
+                    if let Some(value) = to_number(value) {
+                        gauge.value = value;
+                    }
+                    if let Some(max) = to_number(max) {
+                        gauge.max = max;
+                    }
+                }
+            }
+            self.writer
+                .send(Event::StatusWidget(self.name.clone(), Some(widget.clone())))
+                .unwrap();
+        }
+    }
+}
+
+impl UserData for Widget {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("name", |_, this, ()| Ok(this.name.clone()));
+        methods.add_method("set", |_, this, (value, max): (Value, Value)| {
+            this.set(&value, &max);
+            Ok(())
+        });
+        methods.add_method(
+            "bind_gmcp",
+            |ctx, this, (package, value, max): (String, String, Option<String>)| {
+                let widget = this.clone();
+                let callback = ctx.create_function(move |_, (data, _): (Value, Value)| {
+                    let max = match &max {
+                        Some(max) => lookup(&data, max)?,
+                        None => Value::Nil,
+                    };
+                    widget.set(&lookup(&data, &value)?, &max);
+                    Ok(())
+                })?;
+                add_listener(ctx, package, callback, true)
+            },
+        );
+        methods.add_method(
+            "bind_msdp",
+            |ctx, this, (value, max): (String, Option<String>)| {
+                let msdp: Table = ctx.globals().get("msdp")?;
+                let register: Function = msdp.get("register")?;
+                let widget = this.clone();
+                register.call::<_, ()>((
+                    value,
+                    ctx.create_function(move |_, value: Value| {
+                        widget.set(&value, &Value::Nil);
+                        Ok(())
+                    })?,
+                ))?;
+                if let Some(max) = max {
+                    let widget = this.clone();
+                    register.call::<_, ()>((
+                        max,
+                        ctx.create_function(move |_, max: Value| {
+                            widget.set(&Value::Nil, &max);
+                            Ok(())
+                        })?,
+                    ))?;
+                }
+                Ok(())
+            },
+        );
+        methods.add_method("remove", |_, this, ()| {
+            this.widgets.lock().unwrap().remove(&this.name);
+            this.writer
+                .send(Event::StatusWidget(this.name.clone(), None))
+                .unwrap();
+            Ok(())
+        });
+    }
+}
+
+/// The `status` global, for gauges and other widgets in the status area
+pub struct StatusLib {
+    widgets: Widgets,
+    writer: Sender<Event>,
+}
+
+impl StatusLib {
+    pub const LUA_GLOBAL_NAME: &'static str = "status";
+
+    pub fn new(writer: Sender<Event>) -> Self {
+        Self {
+            widgets: Arc::new(Mutex::new(HashMap::new())),
+            writer,
+        }
+    }
+
+    /// Adds or replaces a widget and returns a handle to it
+    fn add(&self, name: String, widget: StatusWidget) -> Widget {
+        self.widgets
+            .lock()
+            .unwrap()
+            .insert(name.clone(), widget.clone());
+        self.writer
+            .send(Event::StatusWidget(name.clone(), Some(widget)))
+            .unwrap();
+        Widget {
+            name,
+            widgets: self.widgets.clone(),
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl UserData for StatusLib {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("gauge", |ctx, (name, options): (String, Option<Table>)| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(StatusLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<StatusLib>()?;
+            // Options given again update the gauge, keeping its value
+            let mut gauge = match this.widgets.lock().unwrap().get(&name) {
+                Some(StatusWidget::Gauge(gauge)) => gauge.clone(),
+                _ => Gauge::default(),
+            };
+            parse_gauge(&mut gauge, &options)?;
+            Ok(this.add(name, StatusWidget::Gauge(gauge)))
+        });
+        methods.add_function("text", |ctx, (name, text): (String, Option<Value>)| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(StatusLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<StatusLib>()?;
+            let text = text.as_ref().and_then(to_text).unwrap_or_default();
+            Ok(this.add(name, StatusWidget::Text(text)))
+        });
+        methods.add_function("line", |ctx, (index, layout): (usize, Option<Table>)| {
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(StatusLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<StatusLib>()?;
+            let layout = match layout {
+                Some(layout) => Some(StatusLayout {
+                    left: parse_items(layout.get("left")?)?,
+                    center: parse_items(layout.get("center")?)?,
+                    right: parse_items(layout.get("right")?)?,
+                }),
+                None => None,
+            };
+            this.writer
+                .send(Event::StatusLayout(index, layout))
+                .unwrap();
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_status {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use mlua::Lua;
+
+    use super::*;
+
+    fn get_lua() -> (Lua, Receiver<Event>) {
+        let (writer, reader) = channel();
+        let lua = Lua::new();
+        lua.globals()
+            .set(StatusLib::LUA_GLOBAL_NAME, StatusLib::new(writer))
+            .unwrap();
+        (lua, reader)
+    }
+
+    fn widget(reader: &Receiver<Event>) -> StatusWidget {
+        match reader.try_recv().unwrap() {
+            Event::StatusWidget(_, Some(widget)) => widget,
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_gauge() {
+        let (lua, reader) = get_lua();
+        lua.load(
+            r#"
+            hp = status.gauge("hp", { label = "HP", max = 50, width = 20 })
+            hp:set(25)
+            hp:set("30", 60)
+            hp:set({})
+            status.gauge("hp", { color = "red" })
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let gauge = Gauge {
+            label: "HP".to_string(),
+            max: 50.0,
+            width: 20,
+            ..Default::default()
+        };
+        assert_eq!(widget(&reader), StatusWidget::Gauge(gauge.clone()));
+        let gauge = Gauge {
+            value: 25.0,
+            ..gauge
+        };
+        assert_eq!(widget(&reader), StatusWidget::Gauge(gauge.clone()));
+        let gauge = Gauge {
+            value: 30.0,
+            max: 60.0,
+            ..gauge
+        };
+        assert_eq!(widget(&reader), StatusWidget::Gauge(gauge.clone()));
+        assert_eq!(widget(&reader), StatusWidget::Gauge(gauge.clone()));
+        let gauge = Gauge {
+            color: "red".to_string(),
+            ..gauge
+        };
+        assert_eq!(widget(&reader), StatusWidget::Gauge(gauge));
+    }
+
+    #[test]
+    fn test_text_and_line() {
+        let (lua, reader) = get_lua();
+        lua.load(
+            r#"
+            local room = status.text("room")
+            room:set(42)
+            status.line(1, { left = { "Room: ", room }, right = "right" })
+            room:remove()
+            room:set("gone")
+            status.line(2)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert_eq!(widget(&reader), StatusWidget::Text(String::new()));
+        assert_eq!(widget(&reader), StatusWidget::Text("42".to_string()));
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::StatusLayout(
+                1,
+                Some(StatusLayout {
+                    left: vec![
+                        StatusItem::Text("Room: ".to_string()),
+                        StatusItem::Widget("room".to_string())
+                    ],
+                    center: vec![],
+                    right: vec![StatusItem::Text("right".to_string())],
+                })
+            )
+        );
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::StatusWidget("room".to_string(), None)
+        );
+        assert_eq!(reader.try_recv().unwrap(), Event::StatusLayout(2, None));
+        assert!(lua
+            .load(r#"status.line(0, { left = { {} } })"#)
+            .exec()
+            .is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let lua = Lua::new();
+        let data: Value = lua
+            .load("return { vitals = { hp = 10 }, name = 'x' }")
+            .eval()
+            .unwrap();
+        assert_eq!(to_number(&lookup(&data, "vitals.hp").unwrap()), Some(10.0));
+        assert_eq!(lookup(&data, "name.hp").unwrap(), Value::Nil);
+        assert_eq!(lookup(&data, "missing").unwrap(), Value::Nil);
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::mpsc::Sender;
+
+use mlua::{AnyUserData, Table, UserData, UserDataMethods};
+
+use crate::{
+    event::Event,
+    model::{Line, WindowOptions, WindowSize},
+};
+
+use super::util::get_option;
+
+/// Reads the `position`, `size`, `scrollback` and `log` of a window
+fn parse_window_options(options: &Option<Table>) -> mlua::Result<WindowOptions> {
+    let mut window = WindowOptions::default();
+    if let Some(position) = get_option::<String>(options, "position")? {
+        window.position = position.parse().map_err(mlua::Error::external)?;
+    }
+    match get_option::<mlua::Value>(options, "size")? {
+        Some(mlua::Value::Integer(size)) => window.size = WindowSize::Fixed(size.max(0) as u16),
+        Some(mlua::Value::Number(size)) => window.size = WindowSize::Fixed(size.max(0.0) as u16),
+        Some(mlua::Value::String(size)) => {
+            window.size = size.to_str()?.parse().map_err(mlua::Error::external)?;
+        }
+        Some(mlua::Value::Nil) | None => {}
+        Some(_) => {
+            return Err(mlua::Error::external(
+                "Window size must be a number or string",
+            ))
+        }
+    }
+    if let Some(scrollback) = get_option(options, "scrollback")? {
+        window.scrollback = scrollback;
+    }
+    window.log = get_option(options, "log")?.unwrap_or(window.log);
+    Ok(window)
+}
+
+/// A handle to a named output window
+#[derive(Clone)]
+pub struct Window {
+    name: String,
+    writer: Sender<Event>,
+}
+
+impl UserData for Window {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("name", |_, this, ()| Ok(this.name.clone()));
+        methods.add_method("print", |_, this, strings: mlua::Variadic<String>| {
+            let mut line = Line::from(strings.join(" "));
+            line.flags.window = Some(this.name.clone());
+            this.writer.send(Event::WindowOutput(line)).unwrap();
+            Ok(())
+        });
+        methods.add_method("clear", |_, this, ()| {
+            this.writer
+                .send(Event::ClearWindow(this.name.clone()))
+                .unwrap();
+            Ok(())
+        });
+        methods.add_method("scroll", |_, this, lines: i32| {
+            this.writer
+                .send(Event::ScrollWindow(this.name.clone(), Some(lines)))
+                .unwrap();
+            Ok(())
+        });
+        methods.add_method("scroll_bottom", |_, this, ()| {
+            this.writer
+                .send(Event::ScrollWindow(this.name.clone(), None))
+                .unwrap();
+            Ok(())
+        });
+        methods.add_method("close", |_, this, ()| {
+            this.writer
+                .send(Event::RemoveWindow(this.name.clone()))
+                .unwrap();
+            Ok(())
+        });
+    }
+}
+
+/// The `ui` global, for laying out named output windows
+pub struct UiLib {
+    writer: Sender<Event>,
+}
+
+impl UiLib {
+    pub const LUA_GLOBAL_NAME: &'static str = "ui";
+
+    pub fn new(writer: Sender<Event>) -> Self {
+        Self { writer }
+    }
+}
+
+impl UserData for UiLib {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("window", |ctx, (name, options): (String, Option<Table>)| {
+            if name.trim().is_empty() {
+                return Err(mlua::Error::external("Window name can't be empty"));
+            }
+            let options = parse_window_options(&options)?;
+            let this_aux = ctx
+                .globals()
+                .get::<_, AnyUserData>(UiLib::LUA_GLOBAL_NAME)?;
+            let this = this_aux.borrow::<UiLib>()?;
+            this.writer
+                .send(Event::AddWindow(name.clone(), options))
+                .unwrap();
+            Ok(Window {
+                name,
+                writer: this.writer.clone(),
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_ui {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use mlua::Lua;
+
+    use super::*;
+    use crate::model::WindowPosition;
+
+    fn get_lua() -> (Lua, Receiver<Event>) {
+        let (writer, reader) = channel();
+        let lua = Lua::new();
+        lua.globals()
+            .set(UiLib::LUA_GLOBAL_NAME, UiLib::new(writer))
+            .unwrap();
+        (lua, reader)
+    }
+
+    #[test]
+    fn test_window() {
+        let (lua, reader) = get_lua();
+        lua.load(
+            r#"
+            local chat = ui.window("chat", { position = "right", size = "30%", log = true })
+            chat:print("Hello", "there")
+            chat:scroll(5)
+            chat:scroll_bottom()
+            chat:clear()
+            chat:close()
+            ui.window("group")
+            "#,
+        )
+        .exec()
+        .unwrap();
+
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::AddWindow(
+                "chat".to_string(),
+                WindowOptions {
+                    position: WindowPosition::Right,
+                    size: WindowSize::Percent(30),
+                    scrollback: 1000,
+                    log: true,
+                }
+            )
+        );
+        match reader.try_recv().unwrap() {
+            Event::WindowOutput(line) => {
+                assert_eq!(line.line(), "Hello there");
+                assert_eq!(line.flags.window, Some("chat".to_string()));
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+        let chat = || "chat".to_string();
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::ScrollWindow(chat(), Some(5))
+        );
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::ScrollWindow(chat(), None)
+        );
+        assert_eq!(reader.try_recv().unwrap(), Event::ClearWindow(chat()));
+        assert_eq!(reader.try_recv().unwrap(), Event::RemoveWindow(chat()));
+        assert_eq!(
+            reader.try_recv().unwrap(),
+            Event::AddWindow("group".to_string(), WindowOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let (lua, _reader) = get_lua();
+        for script in [
+            r#"ui.window("")"#,
+            r#"ui.window("chat", { position = "middle" })"#,
+            r#"ui.window("chat", { size = "wide" })"#,
+            r#"ui.window("chat", { size = {} })"#,
+        ] {
+            assert!(lua.load(script).exec().is_err(), "{script}");
+        }
+    }
+}
@@ -17,6 +17,16 @@ pub enum UiEvent {
     ScrollTop,
     ScrollBottom,
     Complete,
+    Yank,
+    YankPop,
+    TransposeChars,
+    TransposeWords,
+    Undo,
+    Redo,
+    Compose,
+    ComposeSend,
+    ComposeCancel,
+    ComposeEditor,
     Unknown(String),
 }
 
@@ -40,6 +50,16 @@ impl From<&str> for UiEvent {
             "scroll_top" => UiEvent::ScrollTop,
             "scroll_bottom" => UiEvent::ScrollBottom,
             "complete" => UiEvent::Complete,
+            "yank" => UiEvent::Yank,
+            "yank_pop" => UiEvent::YankPop,
+            "transpose_chars" => UiEvent::TransposeChars,
+            "transpose_words" => UiEvent::TransposeWords,
+            "undo" => UiEvent::Undo,
+            "redo" => UiEvent::Redo,
+            "compose" => UiEvent::Compose,
+            "compose_send" => UiEvent::ComposeSend,
+            "compose_cancel" => UiEvent::ComposeCancel,
+            "compose_editor" => UiEvent::ComposeEditor,
             _ => UiEvent::Unknown(s.to_string()),
         }
     }
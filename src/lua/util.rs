@@ -1,5 +1,12 @@
-use crate::event::Event;
-use std::sync::mpsc::Sender;
+use crate::{
+    event::Event,
+    model::{Link, LinkAction, Profile, Proxy, SETTINGS},
+    net::Encoding,
+    tools::util::expand_tilde,
+};
+use mlua::{FromLua, Lua, Table};
+use serde_json::{Map, Number, Value};
+use std::{collections::HashMap, sync::mpsc::Sender};
 
 pub fn output_stack_trace(writer: &Sender<Event>, error: &str) {
     writer
@@ -12,3 +19,150 @@ pub fn output_stack_trace(writer: &Sender<Event>, error: &str) {
     }
     writer.send(Event::LuaError(error.to_string())).ok();
 }
+
+/// Reads an optional value from an options table
+pub fn get_option<'lua, T: FromLua<'lua>>(
+    options: &Option<Table<'lua>>,
+    key: &str,
+) -> mlua::Result<Option<T>> {
+    match options {
+        Some(options) => options.get(key),
+        None => Ok(None),
+    }
+}
+
+/// Reads the `proxy` url from an options table
+pub fn parse_proxy_option(options: &Option<Table>) -> mlua::Result<Option<Proxy>> {
+    let url: Option<String> = get_option(options, "proxy")?;
+    url.map(|url| url.parse::<Proxy>())
+        .transpose()
+        .map_err(mlua::Error::external)
+}
+
+/// Reads and validates the `encoding` from an options table
+pub fn parse_encoding_option(options: &Option<Table>) -> mlua::Result<Option<String>> {
+    let label: Option<String> = get_option(options, "encoding")?;
+    match label {
+        Some(label) if Encoding::for_label(&label).is_none() => {
+            Err(mlua::Error::external(format!("Unknown encoding: {label}")))
+        }
+        label => Ok(label),
+    }
+}
+
+/// Reads the world profile (`scripts`, `settings`, `log_dir` and
+/// `history_file`) from an options table
+pub fn parse_profile_option(options: &Option<Table>) -> mlua::Result<Profile> {
+    let settings: HashMap<String, bool> = get_option(options, "settings")?.unwrap_or_default();
+    if let Some(key) = settings
+        .keys()
+        .find(|key| !SETTINGS.contains(&key.as_str()))
+    {
+        return Err(mlua::Error::external(format!("Unknown setting: {key}")));
+    }
+    Ok(Profile {
+        scripts: get_option(options, "scripts")?.unwrap_or_default(),
+        settings,
+        log_dir: get_option(options, "log_dir")?,
+        history_file: get_option(options, "history_file")?,
+    })
+}
+
+/// Converts a world profile into a table, with `~` expanded in its paths
+pub fn profile_to_lua<'lua>(lua: &'lua Lua, profile: &Profile) -> mlua::Result<Table<'lua>> {
+    let expand = |path: &String| expand_tilde(path).to_string();
+    let table = lua.create_table()?;
+    table.set(
+        "scripts",
+        profile.scripts.iter().map(expand).collect::<Vec<_>>(),
+    )?;
+    table.set("settings", profile.settings.clone())?;
+    table.set("log_dir", profile.log_dir.as_ref().map(expand))?;
+    table.set("history_file", profile.history_file.as_ref().map(expand))?;
+    Ok(table)
+}
+
+/// Converts a link into a table, with 1-based positions in the clean line
+pub fn link_to_lua<'lua>(lua: &'lua Lua, link: &Link) -> mlua::Result<Table<'lua>> {
+    let entry = lua.create_table()?;
+    entry.set("text", link.text.clone())?;
+    match &link.action {
+        LinkAction::Send(commands) => entry.set("send", commands.clone())?,
+        LinkAction::Url(url) => entry.set("url", url.clone())?,
+        LinkAction::Callback(_) => {}
+    }
+    entry.set("hint", link.hint.clone())?;
+    entry.set("prompt", link.prompt)?;
+    entry.set("start", link.start + 1)?;
+    entry.set("stop", link.end)?;
+    Ok(entry)
+}
+
+/// Converts JSON into Lua values, null becomes nil
+pub fn json_to_lua<'lua>(lua: &'lua Lua, value: &Value) -> mlua::Result<mlua::Value<'lua>> {
+    Ok(match value {
+        Value::Null => mlua::Value::Nil,
+        Value::Bool(value) => mlua::Value::Boolean(*value),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => mlua::Value::Integer(value),
+            None => mlua::Value::Number(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(value) => mlua::Value::String(lua.create_string(value)?),
+        Value::Array(values) => {
+            let table = lua.create_table()?;
+            for (i, value) in values.iter().enumerate() {
+                table.raw_set(i + 1, json_to_lua(lua, value)?)?;
+            }
+            mlua::Value::Table(table)
+        }
+        Value::Object(values) => {
+            let table = lua.create_table()?;
+            for (key, value) in values {
+                table.raw_set(key.as_str(), json_to_lua(lua, value)?)?;
+            }
+            mlua::Value::Table(table)
+        }
+    })
+}
+
+/// Converts Lua values into JSON. Tables with a sequence are arrays, the same
+/// as `json.encode` does.
+pub fn lua_to_json(value: mlua::Value) -> mlua::Result<Value> {
+    Ok(match value {
+        mlua::Value::Nil => Value::Null,
+        mlua::Value::Boolean(value) => Value::Bool(value),
+        mlua::Value::Integer(value) => Value::from(value),
+        mlua::Value::Number(value) => Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| mlua::Error::external(format!("Can't encode {value} as JSON")))?,
+        mlua::Value::String(value) => Value::String(value.to_str()?.to_string()),
+        mlua::Value::Table(table) => {
+            if table.raw_len() > 0
+                || table
+                    .clone()
+                    .pairs::<mlua::Value, mlua::Value>()
+                    .next()
+                    .is_none()
+            {
+                let values = table
+                    .sequence_values::<mlua::Value>()
+                    .map(|value| lua_to_json(value?))
+                    .collect::<mlua::Result<Vec<Value>>>()?;
+                Value::Array(values)
+            } else {
+                let mut values = Map::new();
+                for pair in table.pairs::<String, mlua::Value>() {
+                    let (key, value) = pair?;
+                    values.insert(key, lua_to_json(value)?);
+                }
+                Value::Object(values)
+            }
+        }
+        value => {
+            return Err(mlua::Error::external(format!(
+                "Can't encode {} as JSON",
+                value.type_name()
+            )))
+        }
+    })
+}
@@ -1,8 +1,10 @@
-use std::env;
+use std::{env, process};
 
 use blightmud::{register_panic_hook, RuntimeConfig, PROJECT_NAME, VERSION};
 use getopts::Options;
 
+const SHARE_PASSWORD_VAR: &str = "BLIGHTMUD_SHARE_PASSWORD";
+
 fn print_help(program: &str, opts: Options) {
     let brief = format!("USAGE: {program} [options]\n\n{PROJECT_NAME} {VERSION}");
     print!("{}", opts.usage(&brief));
@@ -42,6 +44,31 @@ fn setup_options() -> Options {
         );
     }
     opts.optopt("w", "world", "Connect to a predefined world", "WORLD");
+    opts.optopt("", "record", "Record raw server data to a file", "FILE");
+    opts.optopt(
+        "",
+        "replay",
+        "Replay a recording instead of connecting to a server",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "replay-speed",
+        "Playback speed of --replay, 0 plays back instantly (default: 1)",
+        "SPEED",
+    );
+    opts.optopt(
+        "",
+        "share",
+        "Let other telnet clients attach on a local port (password from $BLIGHTMUD_SHARE_PASSWORD)",
+        "PORT",
+    );
+    opts.optopt(
+        "",
+        "mssp",
+        "Print the MSSP information of a server and exit",
+        "HOST:PORT",
+    );
     opts.optflag("h", "help", "Print help menu");
     opts.optflag("v", "version", "Print version information");
     opts.optflag("V", "verbose", "Enable verbose logging");
@@ -69,9 +96,26 @@ fn main() {
     } else if matches.opt_present("v") {
         print_version();
         return;
+    } else if let Some(address) = matches.opt_str("mssp") {
+        if let Err(err) = blightmud::print_mssp(&address) {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+        return;
     }
 
-    let rt = RuntimeConfig::from(matches);
+    let mut rt = RuntimeConfig::from(matches);
+    // Kept out of the arguments so other users can't read it, and out of the
+    // environment of anything started later
+    rt.share_password = env::var(SHARE_PASSWORD_VAR)
+        .ok()
+        .filter(|password| !password.is_empty());
+    env::remove_var(SHARE_PASSWORD_VAR);
+
+    if let Err(err) = rt.validate() {
+        eprintln!("{err}");
+        process::exit(1);
+    }
 
     if let Some(connect) = &rt.connect {
         if !connect.contains(':') {
@@ -106,4 +150,42 @@ mod tests {
         assert!(rt.verbose);
         assert_eq!(rt.connect, Some("localhost:8080".to_string()));
     }
+
+    #[test]
+    fn test_replay_parse() {
+        let args: Vec<String> = ["blightmud", "--replay", "dump.rec", "--replay-speed", "4"]
+            .iter()
+            .map(|s| String::from(*s))
+            .collect();
+        let opts = setup_options();
+        let matches = match opts.parse(&args[1..]) {
+            Ok(m) => m,
+            Err(f) => panic!("{}", f.to_string()),
+        };
+        let rt = RuntimeConfig::from(matches);
+        assert_eq!(rt.replay, Some("dump.rec".to_string()));
+        assert_eq!(rt.replay_speed, Some(4.0));
+        assert_eq!(rt.record, None);
+    }
+
+    #[test]
+    fn test_share_parse() {
+        let args: Vec<String> = ["blightmud", "--share", "4000"]
+            .iter()
+            .map(|s| String::from(*s))
+            .collect();
+        let matches = setup_options().parse(&args[1..]).unwrap();
+        let rt = RuntimeConfig::from(matches);
+        assert_eq!(rt.share, Some(4000));
+    }
+
+    #[test]
+    fn test_share_requires_password() {
+        let args: Vec<String> = ["blightmud", "--share", "4000"]
+            .iter()
+            .map(|s| String::from(*s))
+            .collect();
+        let matches = setup_options().parse(&args[1..]).unwrap();
+        assert!(RuntimeConfig::from(matches).validate().is_err());
+    }
 }
@@ -0,0 +1,61 @@
+use crate::io::SaveData;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Certificate fingerprints recorded on the first TLS connection to a server
+/// using trust-on-first-use validation, keyed by `host:port`.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct CertPins(pub BTreeMap<String, String>);
+
+impl CertPins {
+    pub fn key(host: &str, port: u16) -> String {
+        format!("{host}:{port}")
+    }
+
+    pub fn get(&self, host: &str, port: u16) -> Option<&String> {
+        self.0.get(&Self::key(host, port))
+    }
+
+    pub fn insert(&mut self, host: &str, port: u16, fingerprint: &str) {
+        self.0
+            .insert(Self::key(host, port), fingerprint.to_string());
+    }
+
+    pub fn remove(&mut self, host: &str, port: u16) -> bool {
+        self.0.remove(&Self::key(host, port)).is_some()
+    }
+}
+
+impl SaveData for CertPins {
+    fn relative_path() -> PathBuf {
+        crate::CONFIG_DIR.join("cert_pins.ron")
+    }
+
+    fn is_pretty() -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_cert_pins {
+    use super::*;
+
+    #[test]
+    fn test_pins() {
+        let mut pins = CertPins::default();
+        assert_eq!(pins.get("mud.org", 4000), None);
+        pins.insert("mud.org", 4000, "AB:CD");
+        pins.insert("mud.org", 4001, "EF:01");
+        assert_eq!(pins.get("mud.org", 4000), Some(&"AB:CD".to_string()));
+        assert_eq!(
+            pins.0.keys().collect::<Vec<_>>(),
+            vec!["mud.org:4000", "mud.org:4001"]
+        );
+        assert!(pins.remove("mud.org", 4000));
+        assert!(!pins.remove("mud.org", 4000));
+        assert_eq!(pins.get("mud.org", 4000), None);
+    }
+}
@@ -1,12 +1,37 @@
 use crate::io::SaveData;
 
+use super::Proxy;
+
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 use std::fmt;
 use std::path::PathBuf;
 
-#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+/// The environment set up for a saved world whenever it is connected to
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct Profile {
+    /// Scripts loaded on connect
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<String>,
+    /// Settings that replace the saved ones while connected
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub settings: HashMap<String, bool>,
+    /// Where logs are written instead of the default log dir
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_dir: Option<String>,
+    /// Where the command history is kept instead of the shared history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_file: Option<String>,
+}
+
+impl Profile {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize, Clone)]
 pub struct Connection {
     pub host: String,
     pub port: u16,
@@ -14,6 +39,20 @@ pub struct Connection {
     pub tls: bool,
     #[serde(default)]
     pub verify_cert: bool,
+    #[serde(default)]
+    pub pin_cert: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Proxy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Profile::is_empty")]
+    pub profile: Box<Profile>,
 }
 
 impl Connection {
@@ -23,6 +62,7 @@ impl Connection {
             port,
             tls,
             verify_cert,
+            ..Default::default()
         }
     }
 }
@@ -33,7 +73,23 @@ impl fmt::Display for Connection {
             f,
             "Host: {}, Port: {} TLS: {} Verify: {}",
             self.host, self.port, self.tls, self.verify_cert
-        )
+        )?;
+        if self.pin_cert {
+            write!(f, " Pinned: true")?;
+        }
+        if let Some(proxy) = &self.proxy {
+            write!(f, " Proxy: {proxy}")?;
+        }
+        if let Some(cert) = &self.client_cert {
+            write!(f, " Cert: {cert}")?;
+        }
+        if let Some(encoding) = &self.encoding {
+            write!(f, " Encoding: {encoding}")?;
+        }
+        if !self.profile.scripts.is_empty() {
+            write!(f, " Scripts: {}", self.profile.scripts.join(", "))?;
+        }
+        Ok(())
     }
 }
 
@@ -67,4 +123,35 @@ mod test_connection {
             "Host: host.com, Port: 4000 TLS: false Verify: false".to_string()
         );
     }
+
+    #[test]
+    fn test_profile() {
+        let servers: Servers = ron::from_str(
+            r#"{
+                "plain": (host: "plain.com", port: 4000),
+                "world": (
+                    host: "world.com",
+                    port: 4000,
+                    profile: (
+                        scripts: ["~/muds/world.lua"],
+                        settings: { "mouse_enabled": true },
+                        history_file: Some("~/muds/world_history.json"),
+                    ),
+                ),
+            }"#,
+        )
+        .unwrap();
+        assert!(servers["plain"].profile.is_empty());
+        let world = &servers["world"];
+        assert_eq!(world.profile.scripts, vec!["~/muds/world.lua"]);
+        assert!(world.profile.settings["mouse_enabled"]);
+        assert_eq!(world.profile.log_dir, None);
+        assert_eq!(
+            world.to_string(),
+            "Host: world.com, Port: 4000 TLS: false Verify: false Scripts: ~/muds/world.lua"
+        );
+        assert!(!ron::to_string(&servers["plain"])
+            .unwrap()
+            .contains("profile"));
+    }
 }
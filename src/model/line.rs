@@ -1,3 +1,4 @@
+use super::Link;
 use log::error;
 use std::fmt;
 use strip_ansi_escapes::strip as strip_ansi;
@@ -13,7 +14,11 @@ pub struct Flags {
     pub tts_gag: bool,
     pub tts_interrupt: bool,
     pub separate_receives: bool,
+    /// Sent right away, skipping the send queue
+    pub priority: bool,
     pub source: Option<String>,
+    /// The named window the line is printed in instead of the main output
+    pub window: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +27,7 @@ pub struct Line {
     clean_content: String,
     clean_utf8: bool,
     pub flags: Flags,
+    pub links: Vec<Link>,
 }
 
 impl Eq for Line {}
@@ -63,6 +69,7 @@ impl From<&Line> for Line {
             clean_content: line.clean_content.clone(),
             clean_utf8: line.clean_utf8,
             flags: line.flags.clone(),
+            links: line.links.clone(),
         }
     }
 }
@@ -75,6 +82,7 @@ impl From<&str> for Line {
             clean_content,
             clean_utf8,
             flags: Flags::default(),
+            links: vec![],
         }
     }
 }
@@ -87,6 +95,7 @@ impl From<String> for Line {
             clean_content,
             clean_utf8,
             flags: Flags::default(),
+            links: vec![],
         }
     }
 }
@@ -99,6 +108,7 @@ impl From<&String> for Line {
             clean_content,
             clean_utf8,
             flags: Flags::default(),
+            links: vec![],
         }
     }
 }
@@ -118,6 +128,7 @@ impl From<&[u8]> for Line {
             clean_content,
             clean_utf8,
             flags: Flags::default(),
+            links: vec![],
         }
     }
 }
@@ -138,6 +149,7 @@ impl From<&Vec<u8>> for Line {
             clean_content,
             clean_utf8,
             flags: Flags::default(),
+            links: vec![],
         }
     }
 }
@@ -194,6 +206,7 @@ impl Line {
 
     pub fn replace_with(&mut self, other: &Line) {
         self.flags = other.flags.clone();
+        self.links = other.links.clone();
     }
 }
 
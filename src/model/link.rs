@@ -0,0 +1,82 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// What happens when a link is activated.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkAction {
+    /// One or more commands to be sent to the mud. The first entry is the
+    /// default action, any following entries make up a menu.
+    Send(Vec<String>),
+    /// An url that should be opened outside of the client.
+    Url(String),
+    /// A Lua callback added by a script, by id.
+    Callback(u32),
+}
+
+/// An actionable region of a line, eg. an MXP `<SEND>` or `<A>` tag.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Link {
+    pub text: String,
+    pub action: LinkAction,
+    pub hint: Option<String>,
+    /// The command should be placed in the prompt rather than sent.
+    pub prompt: bool,
+    /// Char offset of the first character of the link in the clean line
+    pub start: usize,
+    /// Char offset of the first character after the link in the clean line
+    pub end: usize,
+}
+
+lazy_static! {
+    static ref URL: Regex = Regex::new(r#"https?://[^\s<>"']+"#).unwrap();
+}
+
+impl Link {
+    /// Finds the urls in a clean line, leaving out punctuation ending a
+    /// sentence or closing a parenthesis around them
+    pub fn find_urls(clean: &str) -> Vec<Link> {
+        URL.find_iter(clean)
+            .filter_map(|found| {
+                let url = found
+                    .as_str()
+                    .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']);
+                let start = clean[..found.start()].chars().count();
+                let end = start + url.chars().count();
+                (url.len() > "https://".len()).then(|| Link {
+                    text: url.to_string(),
+                    action: LinkAction::Url(url.to_string()),
+                    hint: None,
+                    prompt: false,
+                    start,
+                    end,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_link {
+    use super::*;
+
+    #[test]
+    fn test_find_urls() {
+        let urls = Link::find_urls("See (https://blightmud.dev/docs), or http://x.org/é?a=1.");
+        let found: Vec<(&str, usize, usize)> = urls
+            .iter()
+            .map(|link| (link.text.as_str(), link.start, link.end))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("https://blightmud.dev/docs", 5, 31),
+                ("http://x.org/é?a=1", 37, 55)
+            ]
+        );
+        assert_eq!(
+            urls[0].action,
+            LinkAction::Url("https://blightmud.dev/docs".to_string())
+        );
+        assert!(Link::find_urls("http:// nothing").is_empty());
+    }
+}
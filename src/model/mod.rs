@@ -1,13 +1,23 @@
+mod cert_pins;
 mod completions;
 mod connection;
 mod line;
+mod link;
 mod prompt_mask;
+mod proxy;
 mod regex;
 mod settings;
+mod status;
+mod window;
 
 pub use self::{regex::Regex, regex::RegexOptions};
+pub use cert_pins::CertPins;
 pub use completions::Completions;
-pub use connection::{Connection, Servers};
+pub use connection::{Connection, Profile, Servers};
 pub use line::Line;
+pub use link::{Link, LinkAction};
 pub use prompt_mask::PromptMask;
+pub use proxy::{GlobalProxy, Proxy, ProxyKind};
 pub use settings::*;
+pub use status::{Gauge, StatusItem, StatusLayout, StatusWidget};
+pub use window::{WindowOptions, WindowPosition, WindowSize};
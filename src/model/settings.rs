@@ -1,13 +1,23 @@
 use crate::io::SaveData;
 use anyhow::bail;
 use anyhow::Result;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// Settings replaced by the profile of the world on screen
+    static ref OVERRIDES: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct Settings {
     settings: HashMap<String, bool>,
+    /// Never saved, these only last while a world is connected
+    #[serde(skip)]
+    overrides: HashMap<String, bool>,
 }
 
 pub const LOGGING_ENABLED: &str = "logging_enabled";
@@ -22,7 +32,9 @@ pub const HIDE_TOPBAR: &str = "hide_topbar";
 pub const COMMAND_SEARCH: &str = "command_search";
 pub const SMART_HISTORY: &str = "smart_history";
 pub const ECHO_INPUT: &str = "echo_input";
-pub const SETTINGS: [&str; 12] = [
+pub const SHOW_LATENCY: &str = "show_latency";
+pub const VI_MODE: &str = "vi_mode";
+pub const SETTINGS: [&str; 14] = [
     LOGGING_ENABLED,
     TTS_ENABLED,
     MOUSE_ENABLED,
@@ -35,25 +47,51 @@ pub const SETTINGS: [&str; 12] = [
     COMMAND_SEARCH,
     SMART_HISTORY,
     ECHO_INPUT,
+    SHOW_LATENCY,
+    VI_MODE,
 ];
 
 impl Settings {
     pub fn get(&self, key: &str) -> Result<bool> {
         if SETTINGS.contains(&key) {
-            Ok(*self.settings.get(key).unwrap_or(&false))
+            let value = self.overrides.get(key).or_else(|| self.settings.get(key));
+            Ok(*value.unwrap_or(&false))
         } else {
             bail!("Unknown setting: {}", key)
         }
     }
 
+    /// Sets and stops overriding a setting
     pub fn set(&mut self, key: &str, value: bool) -> Result<()> {
         if SETTINGS.contains(&key) {
             self.settings.insert(key.to_string(), value);
+            if self.overrides.remove(key).is_some() {
+                OVERRIDES.lock().unwrap().remove(key);
+            }
             Ok(())
         } else {
             bail!("Unknown setting: {}", key)
         }
     }
+
+    /// Replaces the settings overridden by a world profile. Returns the
+    /// settings whose value changed along with their new value.
+    pub fn set_overrides(overrides: HashMap<String, bool>) -> Vec<(String, bool)> {
+        let before = Self::load();
+        let overrides = overrides
+            .into_iter()
+            .filter(|(key, _)| SETTINGS.contains(&key.as_str()))
+            .collect();
+        *OVERRIDES.lock().unwrap() = overrides;
+        let after = Self::load();
+        SETTINGS
+            .iter()
+            .filter_map(|key| {
+                let value = after.get(key).ok()?;
+                (before.get(key).ok()? != value).then(|| (key.to_string(), value))
+            })
+            .collect()
+    }
 }
 
 impl Default for Settings {
@@ -71,7 +109,12 @@ impl Default for Settings {
         settings.insert(COMMAND_SEARCH.to_string(), false);
         settings.insert(SMART_HISTORY.to_string(), false);
         settings.insert(ECHO_INPUT.to_string(), true);
-        Self { settings }
+        settings.insert(SHOW_LATENCY.to_string(), false);
+        settings.insert(VI_MODE.to_string(), false);
+        Self {
+            settings,
+            overrides: OVERRIDES.lock().unwrap().clone(),
+        }
     }
 }
 
@@ -81,6 +124,7 @@ impl SaveData for Settings {
     }
 
     fn on_load(&mut self) {
+        self.overrides = OVERRIDES.lock().unwrap().clone();
         let default = Self::default();
         if default.settings.len() != self.settings.len() {
             for (key, val) in default.settings {
@@ -96,7 +140,10 @@ impl SaveData for Settings {
 
 impl From<HashMap<String, bool>> for Settings {
     fn from(map: HashMap<String, bool>) -> Self {
-        Self { settings: map }
+        Self {
+            settings: map,
+            overrides: HashMap::new(),
+        }
     }
 }
 
@@ -119,7 +166,7 @@ mod settings_test {
     #[test]
     fn new_settings() {
         let map = HashMap::new();
-        let settings = Settings { settings: map };
+        let settings = Settings::from(map);
         assert_eq!(false, settings.get(TTS_ENABLED).unwrap());
         assert_eq!(
             "Unknown setting: SOMETHING_RANDOM",
@@ -144,4 +191,18 @@ mod settings_test {
                 .to_string()
         );
     }
+
+    #[test]
+    fn override_settings() {
+        let mut settings = Settings::from(Settings::default().settings);
+        settings.overrides.insert(MOUSE_ENABLED.to_string(), true);
+        assert!(settings.get(MOUSE_ENABLED).unwrap());
+
+        let saved = ron::to_string(&settings).unwrap();
+        assert!(saved.contains("\"mouse_enabled\":false"));
+
+        settings.set(MOUSE_ENABLED, false).unwrap();
+        assert!(!settings.get(MOUSE_ENABLED).unwrap());
+        assert!(settings.overrides.is_empty());
+    }
 }
@@ -0,0 +1,104 @@
+/// A value shown as a bar in the status area, eg. health or mana
+#[derive(Debug, PartialEq, Clone)]
+pub struct Gauge {
+    pub label: String,
+    pub value: f64,
+    pub max: f64,
+    /// Cells of the bar
+    pub width: u16,
+    /// Color of the filled part of the bar, as an ANSI sequence
+    pub color: String,
+    /// Color of the empty part of the bar, as an ANSI sequence
+    pub empty_color: String,
+    /// Show `value/max` after the bar
+    pub show_value: bool,
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self {
+            label: String::new(),
+            value: 0.0,
+            max: 100.0,
+            width: 10,
+            color: "\x1b[32m".to_string(),
+            empty_color: "\x1b[90m".to_string(),
+            show_value: true,
+        }
+    }
+}
+
+impl Gauge {
+    /// The filled cells of the bar
+    pub fn filled(&self) -> u16 {
+        if self.max <= 0.0 {
+            return 0;
+        }
+        let ratio = (self.value / self.max).clamp(0.0, 1.0);
+        (ratio * self.width as f64).round() as u16
+    }
+}
+
+/// A named piece of the status area that scripts update by name
+#[derive(Debug, PartialEq, Clone)]
+pub enum StatusWidget {
+    Text(String),
+    Gauge(Gauge),
+}
+
+/// A part of a status line, either fixed text or a widget by name
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StatusItem {
+    Text(String),
+    Widget(String),
+}
+
+/// A status line made of left, centered and right aligned segments
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct StatusLayout {
+    pub left: Vec<StatusItem>,
+    pub center: Vec<StatusItem>,
+    pub right: Vec<StatusItem>,
+}
+
+impl StatusLayout {
+    pub fn uses(&self, widget: &str) -> bool {
+        self.left
+            .iter()
+            .chain(&self.center)
+            .chain(&self.right)
+            .any(|item| matches!(item, StatusItem::Widget(name) if name == widget))
+    }
+}
+
+#[cfg(test)]
+mod test_status {
+    use super::*;
+
+    #[test]
+    fn test_filled() {
+        let gauge = |value: f64, max: f64| Gauge {
+            value,
+            max,
+            ..Default::default()
+        };
+        assert_eq!(gauge(50.0, 100.0).filled(), 5);
+        assert_eq!(gauge(14.0, 100.0).filled(), 1);
+        assert_eq!(gauge(150.0, 100.0).filled(), 10);
+        assert_eq!(gauge(-5.0, 100.0).filled(), 0);
+        assert_eq!(gauge(5.0, 0.0).filled(), 0);
+    }
+
+    #[test]
+    fn test_uses() {
+        let layout = StatusLayout {
+            right: vec![
+                StatusItem::Text("hp".to_string()),
+                StatusItem::Widget("mana".to_string()),
+            ],
+            ..Default::default()
+        };
+        assert!(layout.uses("mana"));
+        assert!(!layout.uses("hp"));
+    }
+}
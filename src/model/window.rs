@@ -0,0 +1,114 @@
+use anyhow::{bail, Result};
+
+use std::str::FromStr;
+
+/// The edge of the output area a window is docked to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WindowPosition {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl FromStr for WindowPosition {
+    type Err = anyhow::Error;
+
+    fn from_str(position: &str) -> Result<Self> {
+        Ok(match position.to_ascii_lowercase().as_str() {
+            "top" => WindowPosition::Top,
+            "bottom" => WindowPosition::Bottom,
+            "left" => WindowPosition::Left,
+            "right" => WindowPosition::Right,
+            _ => bail!(
+                "Invalid window position '{}', use top, bottom, left or right",
+                position
+            ),
+        })
+    }
+}
+
+/// Rows of a top or bottom window, columns of a side window
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WindowSize {
+    Fixed(u16),
+    /// Percent of the space left when the window is placed
+    Percent(u16),
+}
+
+impl WindowSize {
+    pub fn resolve(&self, available: u16) -> u16 {
+        match self {
+            WindowSize::Fixed(size) => *size,
+            WindowSize::Percent(percent) => {
+                (available as u32 * (*percent).min(100) as u32 / 100) as u16
+            }
+        }
+    }
+}
+
+impl FromStr for WindowSize {
+    type Err = anyhow::Error;
+
+    /// Parses `10` or `30%`
+    fn from_str(size: &str) -> Result<Self> {
+        let size = size.trim();
+        let parsed = match size.strip_suffix('%') {
+            Some(percent) => percent.trim().parse().map(WindowSize::Percent),
+            None => size.parse().map(WindowSize::Fixed),
+        };
+        match parsed {
+            Ok(size) => Ok(size),
+            Err(_) => bail!("Invalid window size '{}', use eg. 10 or 30%", size),
+        }
+    }
+}
+
+/// How a named output window is laid out and kept
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WindowOptions {
+    pub position: WindowPosition,
+    pub size: WindowSize,
+    /// Lines kept for scrolling back
+    pub scrollback: usize,
+    /// Log the lines of the window to its own file
+    pub log: bool,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            position: WindowPosition::Top,
+            size: WindowSize::Fixed(10),
+            scrollback: 1000,
+            log: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_window {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "Left".parse::<WindowPosition>().unwrap(),
+            WindowPosition::Left
+        );
+        assert!("middle".parse::<WindowPosition>().is_err());
+        assert_eq!("12".parse::<WindowSize>().unwrap(), WindowSize::Fixed(12));
+        assert_eq!(
+            "30 %".parse::<WindowSize>().unwrap(),
+            WindowSize::Percent(30)
+        );
+        assert!("wide".parse::<WindowSize>().is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(WindowSize::Fixed(5).resolve(3), 5);
+        assert_eq!(WindowSize::Percent(30).resolve(40), 12);
+        assert_eq!(WindowSize::Percent(150).resolve(40), 40);
+    }
+}
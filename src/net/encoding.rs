@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+use encoding_rs::EncoderResult;
+
+/// The upper half of code page 437, the lower half is plain ASCII. The
+/// graphical glyphs of the control characters aren't used since MUDs rely on
+/// them being control characters (eg. ESC).
+#[rustfmt::skip]
+const CP437: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{00A0}',
+];
+
+/// The character encoding used by a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// The IBM PC code page, used for line drawing by ANSI-art MUDs
+    Cp437,
+    Other(&'static encoding_rs::Encoding),
+}
+
+impl Encoding {
+    /// Looks up an encoding by any of its common names, eg. `latin1`,
+    /// `ISO-8859-1`, `CP437` or `Big5`.
+    pub fn for_label(label: &str) -> Option<Self> {
+        let label = label.trim();
+        match label.to_ascii_lowercase().as_str() {
+            "cp437" | "ibm437" | "ibm-437" | "437" | "cspc8codepage437" => Some(Self::Cp437),
+            _ => match encoding_rs::Encoding::for_label_no_replacement(label.as_bytes()) {
+                Some(encoding) if encoding == encoding_rs::UTF_8 => Some(Self::Utf8),
+                Some(encoding) => Some(Self::Other(encoding)),
+                None => None,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Cp437 => "CP437",
+            Self::Other(encoding) => encoding.name(),
+        }
+    }
+
+    /// Converts received bytes to UTF-8
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Self::Utf8 => Cow::Borrowed(bytes),
+            Self::Cp437 if bytes.is_ascii() => Cow::Borrowed(bytes),
+            Self::Cp437 => {
+                let text: String = bytes
+                    .iter()
+                    .map(|b| match b {
+                        0x80.. => CP437[(b - 0x80) as usize],
+                        _ => *b as char,
+                    })
+                    .collect();
+                Cow::Owned(text.into_bytes())
+            }
+            Self::Other(encoding) => match encoding.decode_without_bom_handling(bytes).0 {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            },
+        }
+    }
+
+    /// Converts text to be sent to the encoding of the server. Characters
+    /// that can't be represented are replaced by `?`.
+    pub fn encode<'a>(&self, text: &'a str) -> Cow<'a, [u8]> {
+        match self {
+            Self::Utf8 => Cow::Borrowed(text.as_bytes()),
+            _ if text.is_ascii() => Cow::Borrowed(text.as_bytes()),
+            Self::Cp437 => Cow::Owned(
+                text.chars()
+                    .map(|c| match c {
+                        '\0'..='\x7f' => c as u8,
+                        _ => CP437
+                            .iter()
+                            .position(|glyph| *glyph == c)
+                            .map_or(b'?', |pos| pos as u8 + 0x80),
+                    })
+                    .collect(),
+            ),
+            Self::Other(encoding) => {
+                let mut encoder = encoding.new_encoder();
+                let mut out = Vec::with_capacity(text.len() * 2);
+                let mut rest = text;
+                loop {
+                    let (result, read) =
+                        encoder.encode_from_utf8_to_vec_without_replacement(rest, &mut out, true);
+                    rest = &rest[read..];
+                    match result {
+                        EncoderResult::InputEmpty => break,
+                        EncoderResult::OutputFull => out.reserve(rest.len() * 2 + 8),
+                        EncoderResult::Unmappable(_) => out.push(b'?'),
+                    }
+                }
+                Cow::Owned(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_encoding {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(Encoding::for_label("UTF-8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::for_label("utf8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::for_label("CP437"), Some(Encoding::Cp437));
+        assert_eq!(Encoding::for_label("IBM437"), Some(Encoding::Cp437));
+        assert_eq!(
+            Encoding::for_label("latin1").unwrap().name(),
+            "windows-1252"
+        );
+        assert_eq!(
+            Encoding::for_label("ISO-8859-1").unwrap().name(),
+            "windows-1252"
+        );
+        assert_eq!(Encoding::for_label("big5").unwrap().name(), "Big5");
+        assert_eq!(Encoding::for_label("klingon"), None);
+    }
+
+    #[test]
+    fn test_latin1() {
+        let encoding = Encoding::for_label("latin1").unwrap();
+        assert_eq!(
+            encoding.decode(b"caf\xe9\r\n").as_ref(),
+            "café\r\n".as_bytes()
+        );
+        assert_eq!(encoding.encode("café").as_ref(), b"caf\xe9");
+        assert_eq!(encoding.encode("日本").as_ref(), b"??");
+    }
+
+    #[test]
+    fn test_big5() {
+        let encoding = Encoding::for_label("big5").unwrap();
+        assert_eq!(
+            encoding.decode(b"\xa4\xa4\xa4\xe5").as_ref(),
+            "中文".as_bytes()
+        );
+        assert_eq!(encoding.encode("中文").as_ref(), b"\xa4\xa4\xa4\xe5");
+    }
+
+    #[test]
+    fn test_cp437() {
+        let encoding = Encoding::Cp437;
+        assert_eq!(
+            encoding.decode(b"\x1b[31m\xc9\xcd\xbb\x1b[0m").as_ref(),
+            "\x1b[31m╔═╗\x1b[0m".as_bytes()
+        );
+        assert_eq!(
+            encoding.decode(b"\xb0\xb1\xb2\xdb").as_ref(),
+            "░▒▓█".as_bytes()
+        );
+        assert_eq!(encoding.encode("╔═╗ é€").as_ref(), b"\xc9\xcd\xbb \x82?");
+    }
+
+    #[test]
+    fn test_utf8_passthrough() {
+        let encoding = Encoding::default();
+        assert!(matches!(encoding.decode(b"abc"), Cow::Borrowed(_)));
+        assert_eq!(encoding.encode("日本").as_ref(), "日本".as_bytes());
+    }
+}
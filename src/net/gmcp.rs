@@ -0,0 +1,234 @@
+use std::{collections::BTreeMap, fmt};
+
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::{PROJECT_NAME, VERSION};
+
+/// A GMCP message received from the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct GmcpMessage {
+    pub package: String,
+    /// The data as it was sent
+    pub raw: String,
+    /// The decoded data, null if it was missing or not valid JSON
+    pub data: Value,
+}
+
+impl GmcpMessage {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let msg = String::from_utf8_lossy(bytes);
+        let (package, raw) = match msg.split_once(' ') {
+            Some((package, raw)) => (package, raw.trim()),
+            None => (msg.trim(), ""),
+        };
+        if package.is_empty() {
+            return None;
+        }
+        let data = if raw.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(raw).unwrap_or_else(|err| {
+                debug!("Invalid GMCP data for {}: {}", package, err);
+                Value::Null
+            })
+        };
+        Some(Self {
+            package: package.to_string(),
+            raw: raw.to_string(),
+            data,
+        })
+    }
+}
+
+impl fmt::Display for GmcpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.raw.is_empty() {
+            write!(f, "{}", self.package)
+        } else {
+            write!(f, "{} {}", self.package, self.raw)
+        }
+    }
+}
+
+/// Checks a package name against a pattern. `Char.Vitals` only matches
+/// itself, `Char.*` matches everything below `Char` and `*` matches all.
+/// Package names are case insensitive.
+pub fn gmcp_matches(pattern: &str, package: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let package = package.to_lowercase();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => package.starts_with(prefix),
+        None => pattern == package,
+    }
+}
+
+/// GMCP state of a session that outlives script resets
+#[derive(Debug, Default)]
+pub struct Gmcp {
+    ready: bool,
+    echo: bool,
+    /// The last message received for each package
+    cache: BTreeMap<String, GmcpMessage>,
+    /// The modules and versions announced with Core.Supports
+    supports: BTreeMap<String, u32>,
+}
+
+impl Gmcp {
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Marks GMCP as negotiated. Returns the messages that introduce us to
+    /// the server.
+    pub fn enable(&mut self) -> Vec<String> {
+        self.ready = true;
+        let hello = json!({ "client": PROJECT_NAME, "version": VERSION });
+        let mut messages = vec![format!("Core.Hello {hello}")];
+        if !self.supports.is_empty() {
+            messages.push(format!("Core.Supports.Set {}", self.supports_list()));
+        }
+        messages
+    }
+
+    /// Forgets what was received on a connection that has ended. Registered
+    /// modules are kept and announced again on the next connection.
+    pub fn reset(&mut self) {
+        self.ready = false;
+        self.cache.clear();
+    }
+
+    pub fn receive(&mut self, message: GmcpMessage) {
+        self.cache.insert(message.package.to_lowercase(), message);
+    }
+
+    pub fn get(&self, package: &str) -> Option<&GmcpMessage> {
+        self.cache.get(&package.to_lowercase())
+    }
+
+    /// The cached messages of the packages matching a pattern
+    pub fn cached(&self, pattern: &str) -> Vec<GmcpMessage> {
+        self.cache
+            .values()
+            .filter(|message| gmcp_matches(pattern, &message.package))
+            .cloned()
+            .collect()
+    }
+
+    pub fn supports(&self) -> &BTreeMap<String, u32> {
+        &self.supports
+    }
+
+    fn supports_list(&self) -> Value {
+        self.supports
+            .iter()
+            .map(|(module, version)| format!("{module} {version}"))
+            .collect()
+    }
+
+    /// Adds a module to Core.Supports. Returns the message to send if the
+    /// server needs to be told.
+    pub fn register(&mut self, module: &str, version: u32) -> Option<String> {
+        if self.supports.insert(module.to_string(), version) == Some(version) || !self.ready {
+            return None;
+        }
+        Some(format!(
+            "Core.Supports.Add {}",
+            json!([format!("{module} {version}")])
+        ))
+    }
+
+    /// Removes a module from Core.Supports. Returns the message to send if
+    /// the server needs to be told.
+    pub fn unregister(&mut self, module: &str) -> Option<String> {
+        if self.supports.remove(module).is_none() || !self.ready {
+            return None;
+        }
+        Some(format!("Core.Supports.Remove {}", json!([module])))
+    }
+}
+
+#[cfg(test)]
+mod test_gmcp {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let message = GmcpMessage::parse(b"Char.Vitals { \"hp\": 100 }").unwrap();
+        assert_eq!(message.package, "Char.Vitals");
+        assert_eq!(message.raw, "{ \"hp\": 100 }");
+        assert_eq!(message.data, json!({ "hp": 100 }));
+        assert_eq!(message.to_string(), "Char.Vitals { \"hp\": 100 }");
+
+        let message = GmcpMessage::parse(b"Core.Goodbye").unwrap();
+        assert_eq!(message.raw, "");
+        assert_eq!(message.data, Value::Null);
+
+        let message = GmcpMessage::parse(b"Room.Name Not json").unwrap();
+        assert_eq!(message.raw, "Not json");
+        assert_eq!(message.data, Value::Null);
+
+        assert_eq!(GmcpMessage::parse(b""), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(gmcp_matches("Char.Vitals", "Char.Vitals"));
+        assert!(gmcp_matches("char.vitals", "Char.Vitals"));
+        assert!(!gmcp_matches("Char.Vitals", "Char.Vitals.Extra"));
+        assert!(gmcp_matches("Char.*", "Char.Vitals"));
+        assert!(gmcp_matches("Char.*", "Char.Items.List"));
+        assert!(!gmcp_matches("Char.*", "Char"));
+        assert!(!gmcp_matches("Char.*", "Room.Info"));
+        assert!(gmcp_matches("*", "Room.Info"));
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut gmcp = Gmcp::default();
+        gmcp.receive(GmcpMessage::parse(b"Char.Vitals {\"hp\":1}").unwrap());
+        gmcp.receive(GmcpMessage::parse(b"char.vitals {\"hp\":2}").unwrap());
+        gmcp.receive(GmcpMessage::parse(b"Room.Info {}").unwrap());
+        assert_eq!(gmcp.get("Char.Vitals").unwrap().data, json!({ "hp": 2 }));
+        assert_eq!(gmcp.cached("Char.*").len(), 1);
+        assert_eq!(gmcp.cached("*").len(), 2);
+        gmcp.reset();
+        assert!(gmcp.get("Room.Info").is_none());
+    }
+
+    #[test]
+    fn test_supports() {
+        let mut gmcp = Gmcp::default();
+        assert_eq!(gmcp.register("Char", 1), None);
+        assert_eq!(gmcp.register("Room", 1), None);
+        assert_eq!(
+            gmcp.enable(),
+            vec![
+                format!("Core.Hello {{\"client\":\"{PROJECT_NAME}\",\"version\":\"{VERSION}\"}}"),
+                "Core.Supports.Set [\"Char 1\",\"Room 1\"]".to_string(),
+            ]
+        );
+        assert_eq!(gmcp.register("Room", 1), None);
+        assert_eq!(
+            gmcp.register("Comm.Channel", 2),
+            Some("Core.Supports.Add [\"Comm.Channel 2\"]".to_string())
+        );
+        assert_eq!(
+            gmcp.unregister("Char"),
+            Some("Core.Supports.Remove [\"Char\"]".to_string())
+        );
+        assert_eq!(gmcp.unregister("Char"), None);
+
+        gmcp.reset();
+        assert!(!gmcp.is_ready());
+        assert_eq!(gmcp.supports().len(), 2);
+    }
+}
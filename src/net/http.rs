@@ -0,0 +1,177 @@
+use std::{sync::mpsc::Sender, thread, time::Duration};
+
+use anyhow::Result;
+use log::debug;
+use reqwest::{
+    blocking::ClientBuilder,
+    header::{HeaderName, HeaderValue, USER_AGENT},
+    Method,
+};
+
+use crate::{event::Event, VERSION};
+
+/// A request made from a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub timeout: Duration,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str) -> Self {
+        Self {
+            method: method.to_uppercase(),
+            url: url.to_string(),
+            headers: vec![],
+            body: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The outcome of a request, `error` is set if no response was received
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub error: Option<String>,
+}
+
+impl HttpResponse {
+    fn from_error(err: impl ToString) -> Self {
+        Self {
+            error: Some(err.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+fn send_request(request: &HttpRequest) -> Result<HttpResponse> {
+    let client = ClientBuilder::new().timeout(request.timeout).build()?;
+    let method = Method::from_bytes(request.method.as_bytes())?;
+    let mut builder = client
+        .request(method, &request.url)
+        .header(USER_AGENT, format!("Blightmud/{VERSION}"));
+    for (name, value) in &request.headers {
+        builder = builder.header(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    let response = builder.send()?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+    let body = response.text()?;
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+        error: None,
+    })
+}
+
+/// Performs the request in the background and posts the response as an
+/// `Event::HttpResponse` tagged with `id`
+pub fn spawn_http_request(id: u32, request: HttpRequest, writer: Sender<Event>) {
+    thread::Builder::new()
+        .name("http-thread".to_string())
+        .spawn(move || {
+            debug!("HTTP {} {}", request.method, request.url);
+            let response = send_request(&request).unwrap_or_else(HttpResponse::from_error);
+            writer.send(Event::HttpResponse(id, response)).ok();
+        })
+        .unwrap();
+}
+
+#[cfg(test)]
+mod test_http {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc::channel,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/items?id=1", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = vec![];
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                request.push(line.trim_end().to_string());
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 201 Created\r\nX-Test: yes\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+
+        let (writer, reader) = channel();
+        let mut request = HttpRequest::new("post", &url);
+        request
+            .headers
+            .push(("X-Token".to_string(), "secret".to_string()));
+        request.body = Some("{\"a\":1}".to_string());
+        spawn_http_request(7, request, writer);
+
+        let (request, body) = server.join().unwrap();
+        assert_eq!(request[0], "POST /items?id=1 HTTP/1.1");
+        assert!(request.contains(&"x-token: secret".to_string()));
+        assert_eq!(body, "{\"a\":1}");
+
+        match reader.recv().unwrap() {
+            Event::HttpResponse(id, response) => {
+                assert_eq!(id, 7);
+                assert_eq!(response.status, 201);
+                assert_eq!(response.body, "ok");
+                assert!(response
+                    .headers
+                    .contains(&("x-test".to_string(), "yes".to_string())));
+                assert_eq!(response.error, None);
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_failed_request() {
+        let (writer, reader) = channel();
+        spawn_http_request(1, HttpRequest::new("GET", "not a url"), writer);
+        match reader.recv().unwrap() {
+            Event::HttpResponse(1, response) => assert!(response.error.is_some()),
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use libtelnet_rs::telnet::{op_command as cmd, op_option as opt};
+
+const NOP: [u8; 2] = [cmd::IAC, cmd::NOP];
+/// Asks the server to answer with WILL/WONT TIMING-MARK (RFC 860)
+const TIMING_MARK_REQUEST: [u8; 3] = [cmd::IAC, cmd::DO, opt::TM];
+
+/// Marks older than this are considered lost
+const MAX_PENDING: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveMode {
+    /// Sends a telnet NOP which the server doesn't answer
+    Nop,
+    /// Sends a TIMING-MARK request, the reply is used to measure latency
+    TimingMark,
+}
+
+/// Periodically sends data to keep idle connections from being dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub mode: KeepaliveMode,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            mode: KeepaliveMode::TimingMark,
+        }
+    }
+}
+
+impl Keepalive {
+    pub fn data(&self) -> &'static [u8] {
+        match self.mode {
+            KeepaliveMode::Nop => &NOP,
+            KeepaliveMode::TimingMark => &TIMING_MARK_REQUEST,
+        }
+    }
+}
+
+/// Measures the round trip time of TIMING-MARK requests
+#[derive(Debug, Default)]
+pub struct LatencyMeter {
+    pending: VecDeque<Instant>,
+}
+
+impl LatencyMeter {
+    /// Call when a TIMING-MARK request has been sent
+    pub fn sent(&mut self) {
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(Instant::now());
+    }
+
+    /// Call when a TIMING-MARK reply has been received. Returns the round trip
+    /// time if the reply answers one of our requests.
+    pub fn received(&mut self) -> Option<Duration> {
+        self.pending.pop_front().map(|sent| sent.elapsed())
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_keepalive {
+    use super::*;
+
+    #[test]
+    fn test_keepalive_data() {
+        let mut keepalive = Keepalive::default();
+        assert_eq!(keepalive.data(), &[cmd::IAC, cmd::DO, opt::TM]);
+        keepalive.mode = KeepaliveMode::Nop;
+        assert_eq!(keepalive.data(), &[cmd::IAC, cmd::NOP]);
+    }
+
+    #[test]
+    fn test_latency_meter() {
+        let mut meter = LatencyMeter::default();
+        assert!(!meter.is_pending());
+        assert_eq!(meter.received(), None);
+
+        meter.sent();
+        assert!(meter.is_pending());
+        std::thread::sleep(Duration::from_millis(5));
+        let latency = meter.received().unwrap();
+        assert!(latency >= Duration::from_millis(5));
+        assert!(!meter.is_pending());
+
+        for _ in 0..MAX_PENDING + 2 {
+            meter.sent();
+        }
+        assert_eq!(meter.pending.len(), MAX_PENDING);
+        meter.reset();
+        assert!(!meter.is_pending());
+    }
+}
@@ -1,19 +1,43 @@
 pub use self::{
     check_version::check_latest_version,
+    encoding::Encoding,
+    gmcp::{gmcp_matches, Gmcp, GmcpMessage},
+    http::{spawn_http_request, HttpRequest, HttpResponse},
+    keepalive::{Keepalive, KeepaliveMode, LatencyMeter},
+    mssp::{query_mssp, spawn_mssp_query, MsspInfo},
     mud_connection::MudConnection,
+    mxp::MXP,
+    new_environ::NewEnviron,
     output_buffer::OutputBuffer,
+    proxy::open_proxy_stream,
+    reconnect::{spawn_reconnect_thread, ReconnectPolicy},
+    replay::spawn_replay_thread,
     rw_stream::RwStream,
+    send_queue::{spawn_send_queue_thread, RateLimit, SendQueue},
     tcp_stream::{spawn_connect_thread, spawn_receive_thread, spawn_transmit_thread, BUFFER_SIZE},
     telnet::{TelnetHandler, TelnetMode},
-    tls::CertificateValidation,
-    util::open_tcp_stream,
+    telnet_proxy::{start_sharing, ShareOptions, TelnetProxy},
+    tls::{describe_io_error, CertificateValidation, ClientCertificate},
+    util::{open_tcp_stream, open_tcp_stream_timeout},
 };
 
 mod check_version;
+mod encoding;
+mod gmcp;
+mod http;
+mod keepalive;
+mod mssp;
 mod mud_connection;
+mod mxp;
+mod new_environ;
 mod output_buffer;
+mod proxy;
+mod reconnect;
+mod replay;
 mod rw_stream;
+mod send_queue;
 mod tcp_stream;
 mod telnet;
+mod telnet_proxy;
 mod tls;
 mod util;
@@ -0,0 +1,259 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use libtelnet_rs::{
+    compatibility::CompatibilityTable,
+    events::TelnetEvents,
+    telnet::{op_command as cmd, op_option as opt},
+    Parser,
+};
+use log::debug;
+
+use crate::{event::Event, net::open_tcp_stream_timeout};
+
+const MSSP_VAR: u8 = 1;
+const MSSP_VAL: u8 = 2;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the server to offer MSSP before asking in plain text
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(2);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_INTERVAL: Duration = Duration::from_millis(100);
+
+const REQUEST: &[u8] = b"MSSP-REQUEST\r\n";
+const REPLY_START: &str = "MSSP-REPLY-START";
+const REPLY_END: &str = "MSSP-REPLY-END";
+
+/// MSSP variables in the order the server sent them, each with its values
+pub type MsspInfo = Vec<(String, Vec<String>)>;
+
+/// Decodes the variables of an MSSP subnegotiation
+pub fn parse_mssp(data: &[u8]) -> MsspInfo {
+    let mut info: MsspInfo = vec![];
+    let mut marker = None;
+    let mut field = vec![];
+    // The trailing VAR flushes the last field
+    for byte in data.iter().chain(&[MSSP_VAR]) {
+        if *byte == MSSP_VAR || *byte == MSSP_VAL {
+            let value = String::from_utf8_lossy(&field).to_string();
+            match marker {
+                Some(MSSP_VAR) => info.push((value, vec![])),
+                Some(_) => {
+                    if let Some((_, values)) = info.last_mut() {
+                        values.push(value);
+                    }
+                }
+                None => {}
+            }
+            field.clear();
+            marker = Some(*byte);
+        } else {
+            field.push(*byte);
+        }
+    }
+    info
+}
+
+/// Decodes the reply to a plain text `MSSP-REQUEST`, None until all of it has
+/// been received
+pub fn parse_mssp_reply(text: &str) -> Option<MsspInfo> {
+    let start = text.find(REPLY_START)? + REPLY_START.len();
+    let end = start + text[start..].find(REPLY_END)?;
+    let info = text[start..end]
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim_end_matches('\r').split('\t');
+            let name = fields.next()?.trim();
+            if name.is_empty() {
+                None
+            } else {
+                Some((name.to_string(), fields.map(str::to_string).collect()))
+            }
+        })
+        .collect();
+    Some(info)
+}
+
+/// Connects to a server only to ask for its MSSP information. MSSP is
+/// negotiated if the server offers it, otherwise the information is requested
+/// in plain text.
+pub fn query_mssp(host: &str, port: u16) -> Result<MsspInfo> {
+    let mut stream = open_tcp_stream_timeout(host, port, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(READ_INTERVAL))?;
+    let mut compat = CompatibilityTable::default();
+    compat.support(opt::MSSP);
+    let mut parser = Parser::with_support(compat);
+
+    let started = Instant::now();
+    let mut requested = false;
+    let mut text = String::new();
+    let mut buffer = [0; 4096];
+    while started.elapsed() < QUERY_TIMEOUT {
+        if !requested && started.elapsed() >= NEGOTIATION_TIMEOUT {
+            debug!("Requesting MSSP in plain text");
+            stream.write_all(REQUEST)?;
+            requested = true;
+        }
+        let n = match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        };
+        for event in parser.receive(&buffer[..n]) {
+            match event {
+                TelnetEvents::DataSend(data) => stream.write_all(&data)?,
+                TelnetEvents::Negotiation(neg) if neg.option == opt::MSSP => {
+                    if neg.command == cmd::WILL {
+                        // Subnegotiations are only accepted for enabled options
+                        parser._will(opt::MSSP);
+                    } else if !requested {
+                        stream.write_all(REQUEST)?;
+                        requested = true;
+                    }
+                }
+                TelnetEvents::Subnegotiation(sub) if sub.option == opt::MSSP => {
+                    return Ok(parse_mssp(&sub.buffer));
+                }
+                TelnetEvents::DataReceive(data) => {
+                    text.push_str(&String::from_utf8_lossy(&data));
+                    if let Some(info) = parse_mssp_reply(&text) {
+                        return Ok(info);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    bail!("No MSSP information received from {host}:{port}")
+}
+
+/// Queries a server in the background and posts the result as an
+/// `Event::MsspResponse` tagged with `id`
+pub fn spawn_mssp_query(id: u32, host: String, port: u16, writer: Sender<Event>) {
+    thread::Builder::new()
+        .name("mssp-thread".to_string())
+        .spawn(move || {
+            let result = query_mssp(&host, port).map_err(|err| err.to_string());
+            writer.send(Event::MsspResponse(id, result)).ok();
+        })
+        .unwrap();
+}
+
+#[cfg(test)]
+mod test_mssp {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn info(values: &[(&str, &[&str])]) -> MsspInfo {
+        values
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.to_string(),
+                    values.iter().map(|value| value.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = b"\x01NAME\x02Blight\x01PLAYERS\x0242\x01CODEBASE\x02Diku\x02Merc";
+        assert_eq!(
+            parse_mssp(data),
+            info(&[
+                ("NAME", &["Blight"]),
+                ("PLAYERS", &["42"]),
+                ("CODEBASE", &["Diku", "Merc"]),
+            ])
+        );
+        assert_eq!(parse_mssp(b""), vec![]);
+        assert_eq!(parse_mssp(b"\x02stray\x01EMPTY"), info(&[("EMPTY", &[])]));
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let text = "Welcome\r\nMSSP-REPLY-START\r\nNAME\tBlight\r\nCODEBASE\tDiku\tMerc\r\n";
+        assert_eq!(parse_mssp_reply(text), None);
+        let text = format!("{text}MSSP-REPLY-END\r\n");
+        assert_eq!(
+            parse_mssp_reply(&text),
+            Some(info(&[
+                ("NAME", &["Blight"]),
+                ("CODEBASE", &["Diku", "Merc"])
+            ]))
+        );
+    }
+
+    fn serve(respond: fn(&mut std::net::TcpStream)) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            respond(&mut stream);
+        });
+        port
+    }
+
+    #[test]
+    fn test_query_negotiated() {
+        let port = serve(|stream| {
+            stream
+                .write_all(&[
+                    cmd::IAC,
+                    cmd::WILL,
+                    opt::GMCP,
+                    cmd::IAC,
+                    cmd::WILL,
+                    opt::MSSP,
+                ])
+                .unwrap();
+            let mut buffer = [0; 6];
+            stream.read_exact(&mut buffer).unwrap();
+            assert_eq!(
+                buffer,
+                [cmd::IAC, cmd::DONT, opt::GMCP, cmd::IAC, cmd::DO, opt::MSSP]
+            );
+            let mut reply = vec![cmd::IAC, cmd::SB, opt::MSSP];
+            reply.extend(b"\x01NAME\x02Blight");
+            reply.extend([cmd::IAC, cmd::SE]);
+            stream.write_all(&reply).unwrap();
+        });
+        assert_eq!(
+            query_mssp("127.0.0.1", port).unwrap(),
+            info(&[("NAME", &["Blight"])])
+        );
+    }
+
+    #[test]
+    fn test_query_plain_text() {
+        let port = serve(|stream| {
+            stream.write_all(&[cmd::IAC, cmd::WONT, opt::MSSP]).unwrap();
+            let mut buffer = [0; REQUEST.len()];
+            stream.read_exact(&mut buffer).unwrap();
+            assert_eq!(buffer, REQUEST);
+            stream
+                .write_all(b"\r\nMSSP-REPLY-START\r\nPLAYERS\t7\r\nMSSP-REPLY-END\r\n")
+                .unwrap();
+        });
+        assert_eq!(
+            query_mssp("127.0.0.1", port).unwrap(),
+            info(&[("PLAYERS", &["7"])])
+        );
+    }
+
+    #[test]
+    fn test_query_closed() {
+        let port = serve(|_| {});
+        assert!(query_mssp("127.0.0.1", port).is_err());
+    }
+}
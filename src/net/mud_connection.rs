@@ -9,8 +9,9 @@ use std::{
     sync::{atomic::AtomicU16, atomic::Ordering, Arc, Mutex},
 };
 
-use crate::net::open_tcp_stream;
-use crate::net::tls::{CertificateValidation, TlsStream};
+use crate::model::{Connection, Profile, Proxy};
+use crate::net::tls::{CertificateValidation, ClientCertificate, TlsStream};
+use crate::net::{open_proxy_stream, open_tcp_stream};
 
 use super::RwStream;
 
@@ -23,6 +24,10 @@ pub struct MudConnection {
     pub port: u16,
     pub tls: bool,
     pub tls_validation: CertificateValidation,
+    pub proxy: Option<Proxy>,
+    pub client_cert: Option<ClientCertificate>,
+    pub encoding: Option<String>,
+    pub profile: Profile,
 }
 
 lazy_static! {
@@ -43,6 +48,10 @@ impl MudConnection {
             port: 4000,
             tls: false,
             tls_validation: CertificateValidation::DangerousDisabled,
+            proxy: None,
+            client_cert: None,
+            encoding: None,
+            profile: Profile::default(),
         }
     }
 
@@ -62,26 +71,43 @@ impl MudConnection {
         }
     }
 
-    pub fn connect(
-        &mut self,
-        host: &str,
-        port: u16,
-        tls: bool,
-        tls_validation: CertificateValidation,
-    ) -> Result<()> {
-        self.host = host.to_string();
-        self.port = port;
-        self.tls = tls;
-        self.tls_validation = tls_validation;
+    pub fn connect(&mut self, connection: &Connection) -> Result<()> {
+        self.host = connection.host.clone();
+        self.port = connection.port;
+        self.tls = connection.tls;
+        self.tls_validation = if connection.pin_cert {
+            CertificateValidation::TrustOnFirstUse
+        } else {
+            connection.verify_cert.into()
+        };
+        self.proxy = connection.proxy.clone();
+        self.client_cert = connection
+            .client_cert
+            .as_ref()
+            .map(|cert| ClientCertificate {
+                cert: cert.clone(),
+                key: connection.client_key.clone(),
+            });
+        self.encoding = connection.encoding.clone();
+        self.profile = *connection.profile.clone();
 
         debug!(
             "Connecting to {}:{} tls: {} verify: {}",
-            host, port, tls, tls_validation
+            self.host, self.port, self.tls, self.tls_validation
         );
 
-        let stream = open_tcp_stream(&self.host, self.port)?;
-        if tls {
-            self.tls_stream = Some(TlsStream::tls_init(stream, host, tls_validation)?);
+        let stream = match &self.proxy {
+            Some(proxy) => open_proxy_stream(proxy, &self.host, self.port)?,
+            None => open_tcp_stream(&self.host, self.port)?,
+        };
+        if self.tls {
+            self.tls_stream = Some(TlsStream::tls_init(
+                stream,
+                &self.host,
+                self.port,
+                self.tls_validation,
+                self.client_cert.as_ref(),
+            )?);
         } else {
             self.stream = Some(RwStream::new(stream));
         }
@@ -0,0 +1,934 @@
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::model::{Link, LinkAction};
+use crate::{PROJECT_NAME, VERSION};
+
+/// Telnet option code for MXP
+pub const MXP: u8 = 91;
+
+/// Tags that are allowed in open mode
+const OPEN_TAGS: [&str; 18] = [
+    "b",
+    "bold",
+    "strong",
+    "i",
+    "italic",
+    "em",
+    "u",
+    "underline",
+    "s",
+    "strike",
+    "strikeout",
+    "c",
+    "color",
+    "font",
+    "h",
+    "high",
+    "nobr",
+    "p",
+];
+
+/// Limit for elements expanding into other elements
+const MAX_ELEMENT_DEPTH: usize = 8;
+
+/// Limit for element expansions on a single line
+const MAX_EXPANSIONS: usize = 256;
+
+/// Limit for tags that are open at the same time
+const MAX_OPEN_TAGS: usize = 64;
+
+const SUPPORTED_TAGS: [&str; 8] = ["b", "i", "u", "s", "color", "send", "a", "version"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    #[default]
+    Open,
+    Secure,
+    Locked,
+}
+
+/// A server defined element created with `<!ELEMENT>`
+#[derive(Debug, Clone, Default)]
+struct Element {
+    definition: String,
+    attributes: Vec<(String, String)>,
+    tag: Option<u8>,
+    open: bool,
+    empty: bool,
+}
+
+/// Text collected for an open `<SEND>` or `<A>` tag
+#[derive(Debug, Clone, Default)]
+struct Capture {
+    href: Option<String>,
+    hint: Option<String>,
+    url: bool,
+    prompt: bool,
+    text: Vec<u8>,
+    start: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OpenTag {
+    name: String,
+    secure: bool,
+    style: Option<String>,
+    capture: Option<Capture>,
+    line_tag: bool,
+}
+
+#[derive(Debug, Default)]
+struct LineState {
+    out: Vec<u8>,
+    links: Vec<Link>,
+    col: usize,
+}
+
+type Args = Vec<(Option<String>, String)>;
+
+/// MXP state machine. Feed it complete lines of output with `parse_line` and
+/// it returns the line with tags converted to ansi styling along with any
+/// links found on the line.
+#[derive(Debug, Clone, Default)]
+pub struct Mxp {
+    default_mode: Mode,
+    line_mode: Option<Mode>,
+    temp_secure: bool,
+    elements: HashMap<String, Element>,
+    stack: Vec<OpenTag>,
+    responses: Vec<String>,
+    depth: usize,
+    expansions: usize,
+}
+
+impl Mxp {
+    pub fn parse_line(&mut self, line: &[u8]) -> (Vec<u8>, Vec<Link>) {
+        let mut state = LineState::default();
+        self.expansions = 0;
+        state.out.extend(self.style_codes().as_bytes());
+        self.process(line, &mut state, false);
+        self.end_line(&mut state);
+        (state.out, state.links)
+    }
+
+    /// Replies that should be sent to the server, eg. for `<VERSION>`
+    pub fn take_responses(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.responses)
+    }
+
+    fn mode(&self) -> Mode {
+        self.line_mode.unwrap_or(self.default_mode)
+    }
+
+    fn is_secure(&mut self) -> bool {
+        let secure = self.mode() == Mode::Secure || self.temp_secure;
+        self.temp_secure = false;
+        secure
+    }
+
+    fn process(&mut self, data: &[u8], state: &mut LineState, from_element: bool) {
+        let mut i = 0;
+        while i < data.len() {
+            let c = data[i];
+            if c == 0x1b && data.get(i + 1) == Some(&b'[') {
+                let mut j = i + 2;
+                while j < data.len() && !(0x40..=0x7e).contains(&data[j]) {
+                    j += 1;
+                }
+                if j < data.len() && data[j] == b'z' {
+                    if let Ok(code) = String::from_utf8_lossy(&data[i + 2..j]).parse::<u8>() {
+                        self.mode_change(code, state);
+                    }
+                } else {
+                    state.out.extend(&data[i..data.len().min(j + 1)]);
+                }
+                i = j + 1;
+                continue;
+            }
+
+            if from_element || self.mode() != Mode::Locked {
+                if data[i..].starts_with(b"<!--") {
+                    i = find_subslice(&data[i..], b"-->")
+                        .map(|end| i + end + 3)
+                        .unwrap_or(data.len());
+                    continue;
+                } else if c == b'<' {
+                    if let Some(end) = find_tag_end(data, i) {
+                        let tag = String::from_utf8_lossy(&data[i + 1..end]).to_string();
+                        self.handle_tag(&tag, state, from_element);
+                        i = end + 1;
+                        continue;
+                    }
+                } else if c == b'&' {
+                    if let Some((decoded, len)) = decode_entity(&data[i..]) {
+                        self.text(decoded.as_bytes(), state);
+                        i += len;
+                        continue;
+                    }
+                }
+            }
+            self.text(&data[i..i + 1], state);
+            i += 1;
+        }
+    }
+
+    fn text(&mut self, bytes: &[u8], state: &mut LineState) {
+        state.out.extend(bytes);
+        state.col += bytes
+            .iter()
+            .filter(|b| (**b & 0xC0) != 0x80 && **b != b'\r')
+            .count();
+        for tag in self.stack.iter_mut() {
+            if let Some(capture) = &mut tag.capture {
+                capture.text.extend(bytes);
+            }
+        }
+    }
+
+    fn mode_change(&mut self, code: u8, state: &mut LineState) {
+        debug!("MXP mode change: {}", code);
+        match code {
+            0 => self.line_mode = Some(Mode::Open),
+            1 => self.line_mode = Some(Mode::Secure),
+            2 => self.line_mode = Some(Mode::Locked),
+            3 => {
+                self.close_from(0, state);
+                self.default_mode = Mode::Open;
+                self.line_mode = None;
+            }
+            4 => self.temp_secure = true,
+            5 => self.set_default_mode(Mode::Open),
+            6 => self.set_default_mode(Mode::Secure),
+            7 => self.set_default_mode(Mode::Locked),
+            10..=99 => {
+                let element = self
+                    .elements
+                    .iter()
+                    .find(|(_, el)| el.tag == Some(code))
+                    .map(|(name, el)| (name.clone(), el.clone()));
+                if let Some((name, element)) = element {
+                    self.open_element(&name, &element, vec![], true, state);
+                    if let Some(tag) = self.stack.iter_mut().rev().find(|t| t.name == name) {
+                        tag.line_tag = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_default_mode(&mut self, mode: Mode) {
+        self.default_mode = mode;
+        self.line_mode = None;
+    }
+
+    fn handle_tag(&mut self, tag: &str, state: &mut LineState, from_element: bool) {
+        // Tags opened in temp secure mode are closed like open tags
+        let persistent = from_element || self.mode() == Mode::Secure;
+        let secure = persistent || self.is_secure();
+        let tag = tag.trim();
+        if let Some(name) = tag.strip_prefix('/') {
+            self.close_tag(&name.trim().to_lowercase(), secure, state);
+            return;
+        }
+        if let Some(definition) = tag.strip_prefix('!') {
+            if secure {
+                self.define(definition);
+            }
+            return;
+        }
+
+        let (name, args) = parse_tag(tag);
+        if !secure && !self.is_open_tag(&name) {
+            debug!("MXP ignoring secure tag in open mode: {}", name);
+            return;
+        }
+        match name.as_str() {
+            "b" | "bold" | "strong" | "h" | "high" => {
+                self.push_style(&name, persistent, "1", state)
+            }
+            "i" | "italic" | "em" => self.push_style(&name, persistent, "3", state),
+            "u" | "underline" => self.push_style(&name, persistent, "4", state),
+            "s" | "strike" | "strikeout" => self.push_style(&name, persistent, "9", state),
+            "c" | "color" => {
+                let style = color_style(arg(&args, "fore", 0), arg(&args, "back", 1));
+                self.push_style(&name, persistent, &style, state);
+            }
+            "font" => {
+                let style = color_style(arg(&args, "color", 2), arg(&args, "back", 3));
+                self.push_style(&name, persistent, &style, state);
+            }
+            "send" | "a" => {
+                if self.stack_full(&name) {
+                    return;
+                }
+                let prompt = args
+                    .iter()
+                    .any(|(key, val)| key.is_none() && val.eq_ignore_ascii_case("prompt"));
+                let args: Args = args
+                    .into_iter()
+                    .filter(|(key, val)| key.is_some() || !val.eq_ignore_ascii_case("prompt"))
+                    .collect();
+                let capture = Capture {
+                    href: arg(&args, "href", 0),
+                    hint: arg(&args, "hint", 1),
+                    url: name == "a",
+                    prompt,
+                    text: vec![],
+                    start: state.col,
+                };
+                self.stack.push(OpenTag {
+                    name,
+                    secure: persistent,
+                    style: Some("4".to_string()),
+                    capture: Some(capture),
+                    line_tag: false,
+                });
+                state.out.extend(b"\x1b[4m");
+            }
+            "version" => self.responses.push(format!(
+                "\x1b[1z<VERSION MXP=1.0 CLIENT={PROJECT_NAME} VERSION={VERSION}>"
+            )),
+            "support" => {
+                let tags = SUPPORTED_TAGS
+                    .iter()
+                    .map(|tag| format!("+{tag}"))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                self.responses.push(format!("\x1b[1z<SUPPORTS {tags}>"));
+            }
+            _ => {
+                if let Some(element) = self.elements.get(&name).cloned() {
+                    self.open_element(&name, &element, args, persistent, state);
+                }
+            }
+        }
+    }
+
+    fn is_open_tag(&self, name: &str) -> bool {
+        OPEN_TAGS.contains(&name) || self.elements.get(name).is_some_and(|el| el.open)
+    }
+
+    fn stack_full(&self, name: &str) -> bool {
+        let full = self.stack.len() >= MAX_OPEN_TAGS;
+        if full {
+            debug!("MXP too many open tags, dropping: {}", name);
+        }
+        full
+    }
+
+    fn push_style(&mut self, name: &str, secure: bool, style: &str, state: &mut LineState) {
+        if self.stack_full(name) {
+            return;
+        }
+        let style = if style.is_empty() {
+            None
+        } else {
+            state.out.extend(format!("\x1b[{style}m").as_bytes());
+            Some(style.to_string())
+        };
+        self.stack.push(OpenTag {
+            name: name.to_string(),
+            secure,
+            style,
+            ..Default::default()
+        });
+    }
+
+    fn open_element(
+        &mut self,
+        name: &str,
+        element: &Element,
+        args: Args,
+        secure: bool,
+        state: &mut LineState,
+    ) {
+        if self.stack_full(name) {
+            return;
+        }
+        self.stack.push(OpenTag {
+            name: name.to_string(),
+            secure,
+            ..Default::default()
+        });
+        if self.depth > MAX_ELEMENT_DEPTH {
+            debug!("MXP element nesting too deep: {}", name);
+            return;
+        }
+        if self.expansions >= MAX_EXPANSIONS {
+            debug!("MXP too many element expansions: {}", name);
+            return;
+        }
+        self.expansions += 1;
+        let mut definition = element.definition.clone();
+        for (index, (att, default)) in element.attributes.iter().enumerate() {
+            let value = arg(&args, att, index).unwrap_or_else(|| default.clone());
+            definition = definition.replace(&format!("&{att};"), &value);
+        }
+        self.depth += 1;
+        self.process(definition.as_bytes(), state, true);
+        self.depth -= 1;
+        if element.empty {
+            self.close_tag(name, true, state);
+        }
+    }
+
+    fn close_tag(&mut self, name: &str, secure: bool, state: &mut LineState) {
+        if let Some(index) = self.stack.iter().rposition(|tag| tag.name == name) {
+            if self.stack[index].secure && !secure && !self.is_open_tag(name) {
+                debug!("MXP refusing to close secure tag in open mode: {}", name);
+                return;
+            }
+            self.close_from(index, state);
+        }
+    }
+
+    /// Close all tags from `index` and up the stack
+    fn close_from(&mut self, index: usize, state: &mut LineState) {
+        if index >= self.stack.len() {
+            return;
+        }
+        let closed: Vec<OpenTag> = self.stack.drain(index..).collect();
+        let styled = closed.iter().any(|tag| tag.style.is_some());
+        for tag in closed.into_iter().rev() {
+            if let Some(capture) = tag.capture {
+                self.finish_link(capture, state);
+            }
+        }
+        if styled {
+            state.out.extend(b"\x1b[0m");
+            state.out.extend(self.style_codes().as_bytes());
+        }
+    }
+
+    fn finish_link(&self, capture: Capture, state: &mut LineState) {
+        let text = String::from_utf8_lossy(&capture.text).replace('\r', "");
+        if text.is_empty() && capture.href.is_none() {
+            return;
+        }
+        let action = if capture.url {
+            LinkAction::Url(capture.href.unwrap_or_else(|| text.clone()))
+        } else {
+            let commands = match capture.href {
+                Some(href) => href
+                    .split('|')
+                    .map(|cmd| cmd.replace("&text;", &text))
+                    .collect(),
+                None => vec![text.clone()],
+            };
+            LinkAction::Send(commands)
+        };
+        let hint = capture
+            .hint
+            .and_then(|hint| hint.split('|').next().map(|hint| hint.to_string()));
+        state.links.push(Link {
+            text,
+            action,
+            hint,
+            prompt: capture.prompt,
+            start: capture.start,
+            end: state.col,
+        });
+    }
+
+    fn style_codes(&self) -> String {
+        self.stack
+            .iter()
+            .filter_map(|tag| tag.style.as_ref())
+            .map(|style| format!("\x1b[{style}m"))
+            .collect()
+    }
+
+    fn end_line(&mut self, state: &mut LineState) {
+        // Tags opened in open mode and line tags only live until the end of the line.
+        if let Some(index) = self
+            .stack
+            .iter()
+            .position(|tag| !tag.secure || tag.line_tag)
+        {
+            self.close_from(index, state);
+        }
+        let mut open_links = vec![];
+        for tag in self.stack.iter_mut() {
+            if let Some(capture) = &mut tag.capture {
+                open_links.push(capture.clone());
+                capture.text.clear();
+                capture.start = 0;
+            }
+        }
+        for capture in open_links {
+            self.finish_link(capture, state);
+        }
+        if self.stack.iter().any(|tag| tag.style.is_some()) {
+            state.out.extend(b"\x1b[0m");
+        }
+        self.line_mode = None;
+        self.temp_secure = false;
+    }
+
+    fn define(&mut self, definition: &str) {
+        let (kind, args) = parse_tag(definition);
+        if kind != "element" && kind != "el" {
+            debug!("MXP unsupported definition: {}", kind);
+            return;
+        }
+        let mut positional = args.iter().filter(|(key, _)| key.is_none());
+        let name = match positional.next() {
+            Some((_, name)) => name.to_lowercase(),
+            None => return,
+        };
+        let mut element = Element::default();
+        for (_, value) in positional {
+            match value.to_lowercase().as_str() {
+                "open" => element.open = true,
+                "empty" => element.empty = true,
+                "delete" => {
+                    self.elements.remove(&name);
+                    return;
+                }
+                _ => element.definition = value.clone(),
+            }
+        }
+        if let Some(att) = arg_named(&args, "att") {
+            element.attributes = att
+                .split_whitespace()
+                .map(|att| match att.split_once('=') {
+                    Some((key, default)) => (key.to_lowercase(), default.to_string()),
+                    None => (att.to_lowercase(), String::new()),
+                })
+                .collect();
+        }
+        element.tag = arg_named(&args, "tag").and_then(|tag| tag.parse().ok());
+        debug!("MXP defined element: {} => {:?}", name, element);
+        self.elements.insert(name, element);
+    }
+}
+
+fn find_subslice(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+/// Find the closing '>' of a tag starting at `start`, skipping quoted values
+fn find_tag_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in data.iter().enumerate().skip(start + 1) {
+        match (quote, c) {
+            (None, b'\'' | b'"') => quote = Some(*c),
+            (Some(q), c) if q == *c => quote = None,
+            (None, b'>') => return Some(i),
+            (None, b'<') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn decode_entity(data: &[u8]) -> Option<(String, usize)> {
+    let end = data.iter().take(10).position(|c| *c == b';')?;
+    let name = std::str::from_utf8(&data[1..end]).ok()?;
+    let decoded = match name {
+        "lt" => "<".to_string(),
+        "gt" => ">".to_string(),
+        "amp" => "&".to_string(),
+        "quot" => "\"".to_string(),
+        "apos" => "'".to_string(),
+        "nbsp" => " ".to_string(),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix('x') {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)?.to_string()
+        }
+    };
+    Some((decoded, end + 1))
+}
+
+/// Splits a tag into its lowercased name and a list of arguments. Arguments
+/// are either named (`key=value`) or positional.
+fn parse_tag(tag: &str) -> (String, Args) {
+    let chars: Vec<char> = tag.chars().collect();
+    let mut i = 0;
+    let mut args = vec![];
+
+    let read_value = |i: &mut usize| -> String {
+        let mut value = String::new();
+        if let Some(q) = chars.get(*i).filter(|c| **c == '\'' || **c == '"') {
+            let q = *q;
+            *i += 1;
+            while *i < chars.len() && chars[*i] != q {
+                value.push(chars[*i]);
+                *i += 1;
+            }
+            *i += 1;
+        } else {
+            while *i < chars.len() && !chars[*i].is_whitespace() {
+                value.push(chars[*i]);
+                *i += 1;
+            }
+        }
+        value
+    };
+
+    let mut name = String::new();
+    while i < chars.len() && !chars[i].is_whitespace() {
+        name.push(chars[i]);
+        i += 1;
+    }
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if chars[i] == '\'' || chars[i] == '"' {
+            args.push((None, read_value(&mut i)));
+            continue;
+        }
+        let mut key = String::new();
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '=' {
+            key.push(chars[i]);
+            i += 1;
+        }
+        if chars.get(i) == Some(&'=') {
+            i += 1;
+            args.push((Some(key.to_lowercase()), read_value(&mut i)));
+        } else {
+            args.push((None, key));
+        }
+    }
+
+    (name.to_lowercase(), args)
+}
+
+fn arg_named(args: &Args, name: &str) -> Option<String> {
+    args.iter()
+        .find(|(key, _)| key.as_deref() == Some(name))
+        .map(|(_, val)| val.clone())
+}
+
+/// Lookup an argument by name, falling back to its position among the
+/// unnamed arguments.
+fn arg(args: &Args, name: &str, position: usize) -> Option<String> {
+    arg_named(args, name).or_else(|| {
+        args.iter()
+            .filter(|(key, _)| key.is_none())
+            .nth(position)
+            .map(|(_, val)| val.clone())
+    })
+}
+
+fn color_style(fore: Option<String>, back: Option<String>) -> String {
+    let mut codes = vec![];
+    if let Some((r, g, b)) = fore.as_deref().and_then(parse_color) {
+        codes.push(format!("38;2;{r};{g};{b}"));
+    }
+    if let Some((r, g, b)) = back.as_deref().and_then(parse_color) {
+        codes.push(format!("48;2;{r};{g};{b}"));
+    }
+    codes.join(";")
+}
+
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        return Some(((value >> 16) as u8, (value >> 8) as u8, value as u8));
+    }
+    let rgb = match color.to_lowercase().as_str() {
+        "black" => (0, 0, 0),
+        "white" => (255, 255, 255),
+        "red" => (255, 0, 0),
+        "lime" => (0, 255, 0),
+        "green" => (0, 128, 0),
+        "blue" => (0, 0, 255),
+        "yellow" => (255, 255, 0),
+        "cyan" | "aqua" => (0, 255, 255),
+        "magenta" | "fuchsia" => (255, 0, 255),
+        "silver" => (192, 192, 192),
+        "gray" | "grey" => (128, 128, 128),
+        "maroon" => (128, 0, 0),
+        "olive" => (128, 128, 0),
+        "purple" => (128, 0, 128),
+        "teal" => (0, 128, 128),
+        "navy" => (0, 0, 128),
+        "orange" => (255, 165, 0),
+        "brown" => (165, 42, 42),
+        "pink" => (255, 192, 203),
+        "gold" => (255, 215, 0),
+        "violet" => (238, 130, 238),
+        "darkred" => (139, 0, 0),
+        "darkgreen" => (0, 100, 0),
+        "darkblue" => (0, 0, 139),
+        "lightblue" => (173, 216, 230),
+        "lightgreen" => (144, 238, 144),
+        _ => return None,
+    };
+    Some(rgb)
+}
+
+#[cfg(test)]
+mod mxp_tests {
+    use super::*;
+
+    fn parse(mxp: &mut Mxp, line: &str) -> (String, Vec<Link>) {
+        let (out, links) = mxp.parse_line(line.as_bytes());
+        (String::from_utf8(out).unwrap(), links)
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let mut mxp = Mxp::default();
+        assert_eq!(parse(&mut mxp, "Just some text").0, "Just some text");
+        assert_eq!(
+            parse(&mut mxp, "\x1b[31mRed text\x1b[0m").0,
+            "\x1b[31mRed text\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_open_tags() {
+        let mut mxp = Mxp::default();
+        assert_eq!(
+            parse(&mut mxp, "<b>bold</b> and <i>italic</i>").0,
+            "\x1b[1mbold\x1b[0m and \x1b[3mitalic\x1b[0m"
+        );
+        assert_eq!(
+            parse(&mut mxp, "<COLOR fore=red>red</COLOR>").0,
+            "\x1b[38;2;255;0;0mred\x1b[0m"
+        );
+        assert_eq!(
+            parse(&mut mxp, "<c #00ff00 blue>x</c>").0,
+            "\x1b[38;2;0;255;0;48;2;0;0;255mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_nested_close_restyles() {
+        let mut mxp = Mxp::default();
+        assert_eq!(
+            parse(&mut mxp, "<b><u>a</u>b</b>").0,
+            "\x1b[1m\x1b[4ma\x1b[0m\x1b[1mb\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_open_tags_closed_at_newline() {
+        let mut mxp = Mxp::default();
+        assert_eq!(parse(&mut mxp, "<b>unclosed").0, "\x1b[1munclosed\x1b[0m");
+        assert_eq!(parse(&mut mxp, "next").0, "next");
+    }
+
+    #[test]
+    fn test_entities() {
+        let mut mxp = Mxp::default();
+        assert_eq!(
+            parse(&mut mxp, "&lt;grin&gt; &amp; &#65;&#x42; &unknown").0,
+            "<grin> & AB &unknown"
+        );
+    }
+
+    #[test]
+    fn test_secure_tags_ignored_in_open_mode() {
+        let mut mxp = Mxp::default();
+        let (out, links) = parse(&mut mxp, "<send>north</send>");
+        assert_eq!(out, "north");
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn test_send_secure_line() {
+        let mut mxp = Mxp::default();
+        let (out, links) = parse(&mut mxp, "\x1b[1zExits: <send>north</send> <send \"go south|look south\" hint=\"Go south|Go|Look\">south</send>");
+        assert_eq!(out, "Exits: \x1b[4mnorth\x1b[0m \x1b[4msouth\x1b[0m");
+        assert_eq!(
+            links,
+            vec![
+                Link {
+                    text: "north".to_string(),
+                    action: LinkAction::Send(vec!["north".to_string()]),
+                    hint: None,
+                    prompt: false,
+                    start: 7,
+                    end: 12,
+                },
+                Link {
+                    text: "south".to_string(),
+                    action: LinkAction::Send(vec![
+                        "go south".to_string(),
+                        "look south".to_string()
+                    ]),
+                    hint: Some("Go south".to_string()),
+                    prompt: false,
+                    start: 13,
+                    end: 18,
+                },
+            ]
+        );
+
+        // Secure line mode reverts at the end of the line
+        let (out, links) = parse(&mut mxp, "<send>north</send>");
+        assert_eq!(out, "north");
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn test_send_text_substitution_and_prompt() {
+        let mut mxp = Mxp::default();
+        let (_, links) = parse(
+            &mut mxp,
+            "\x1b[1z<SEND href=\"buy &text;\" PROMPT>sword</SEND>",
+        );
+        assert_eq!(links[0].action, LinkAction::Send(vec!["buy sword".into()]));
+        assert!(links[0].prompt);
+    }
+
+    #[test]
+    fn test_anchor() {
+        let mut mxp = Mxp::default();
+        let (out, links) = parse(&mut mxp, "\x1b[1z<a href='https://example.com'>site</a>");
+        assert_eq!(out, "\x1b[4msite\x1b[0m");
+        assert_eq!(
+            links[0].action,
+            LinkAction::Url("https://example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_locked_mode() {
+        let mut mxp = Mxp::default();
+        assert_eq!(parse(&mut mxp, "\x1b[2z<b>&lt;</b>").0, "<b>&lt;</b>");
+        assert_eq!(parse(&mut mxp, "\x1b[7z").0, "");
+        assert_eq!(parse(&mut mxp, "<b>locked</b>").0, "<b>locked</b>");
+        assert_eq!(
+            parse(&mut mxp, "\x1b[0z<b>open</b>").0,
+            "\x1b[1mopen\x1b[0m"
+        );
+        assert_eq!(parse(&mut mxp, "<b>locked</b>").0, "<b>locked</b>");
+        assert_eq!(
+            parse(&mut mxp, "\x1b[3z<b>reset</b>").0,
+            "\x1b[1mreset\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_temp_secure() {
+        let mut mxp = Mxp::default();
+        let (_, links) = parse(&mut mxp, "\x1b[4z<send>look</send>");
+        assert_eq!(links.len(), 1);
+        // Only the next tag is secure
+        let (out, links) = parse(&mut mxp, "\x1b[4z<b>x</b><send>look</send>");
+        assert_eq!(out, "\x1b[1mx\x1b[0mlook");
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn test_secure_tags_persist() {
+        let mut mxp = Mxp::default();
+        parse(&mut mxp, "\x1b[6z");
+        let (out, links) = parse(&mut mxp, "<send>first");
+        assert_eq!(out, "\x1b[4mfirst\x1b[0m");
+        assert_eq!(links[0].text, "first");
+        let (out, links) = parse(&mut mxp, "second</send>");
+        assert_eq!(out, "\x1b[4msecond\x1b[0m");
+        assert_eq!(links[0].text, "second");
+        assert_eq!(links[0].start, 0);
+    }
+
+    #[test]
+    fn test_element_definition() {
+        let mut mxp = Mxp::default();
+        let (out, _) = parse(
+            &mut mxp,
+            "\x1b[1z<!ELEMENT Ex '<send href=\"go &dir;\">' ATT='dir=north'>",
+        );
+        assert_eq!(out, "");
+        let (out, links) = parse(&mut mxp, "\x1b[1z<Ex>n</Ex> <ex dir=south>s</ex>");
+        assert_eq!(out, "\x1b[4mn\x1b[0m \x1b[4ms\x1b[0m");
+        assert_eq!(links[0].action, LinkAction::Send(vec!["go north".into()]));
+        assert_eq!(links[1].action, LinkAction::Send(vec!["go south".into()]));
+
+        parse(&mut mxp, "\x1b[1z<!ELEMENT ex DELETE>");
+        parse(&mut mxp, "\x1b[1z<!ELEMENT loop '<loop>'>");
+        assert_eq!(parse(&mut mxp, "\x1b[1z<loop>x</loop>").0, "x");
+        assert_eq!(parse(&mut mxp, "\x1b[1z<ex>n</ex>").0, "n");
+    }
+
+    #[test]
+    fn test_element_expansion_limit() {
+        let mut mxp = Mxp::default();
+        parse(&mut mxp, "\x1b[1z<!ELEMENT e0 'x'>");
+        for i in 1..=8 {
+            let inner = format!("<e{0}></e{0}>", i - 1).repeat(4);
+            parse(&mut mxp, &format!("\x1b[1z<!ELEMENT e{i} '{inner}'>"));
+        }
+        let (out, _) = parse(&mut mxp, "\x1b[1z<e8></e8>");
+        assert!(out.len() < MAX_EXPANSIONS);
+        assert_eq!(parse(&mut mxp, "\x1b[1z<e1></e1>").0, "xxxx");
+    }
+
+    #[test]
+    fn test_open_tag_limit() {
+        let mut mxp = Mxp::default();
+        parse(&mut mxp, "\x1b[6z");
+        for _ in 0..MAX_OPEN_TAGS * 2 {
+            parse(&mut mxp, "<send>x");
+        }
+        assert_eq!(mxp.stack.len(), MAX_OPEN_TAGS);
+        parse(&mut mxp, "</send>");
+        assert_eq!(mxp.stack.len(), MAX_OPEN_TAGS - 1);
+    }
+
+    #[test]
+    fn test_open_element() {
+        let mut mxp = Mxp::default();
+        parse(&mut mxp, "\x1b[1z<!EL hi '<b>' OPEN>");
+        parse(&mut mxp, "\x1b[1z<!EL lo '<u>'>");
+        assert_eq!(
+            parse(&mut mxp, "<hi>a</hi><lo>b</lo>").0,
+            "\x1b[1ma\x1b[0mb"
+        );
+    }
+
+    #[test]
+    fn test_line_tag() {
+        let mut mxp = Mxp::default();
+        parse(
+            &mut mxp,
+            "\x1b[1z<!ELEMENT RName '<b>' FLAG=\"RoomName\" TAG=20>",
+        );
+        assert_eq!(
+            parse(&mut mxp, "\x1b[20zThe Town Square").0,
+            "\x1b[1mThe Town Square\x1b[0m"
+        );
+        assert_eq!(parse(&mut mxp, "Plain").0, "Plain");
+    }
+
+    #[test]
+    fn test_comment() {
+        let mut mxp = Mxp::default();
+        assert_eq!(parse(&mut mxp, "a<!-- <b> -->b").0, "ab");
+    }
+
+    #[test]
+    fn test_version() {
+        let mut mxp = Mxp::default();
+        parse(&mut mxp, "\x1b[1z<VERSION>");
+        let responses = mxp.take_responses();
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].starts_with("\x1b[1z<VERSION MXP=1.0 CLIENT="));
+        assert!(mxp.take_responses().is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_tag_is_text() {
+        let mut mxp = Mxp::default();
+        assert_eq!(parse(&mut mxp, "1 < 2").0, "1 < 2");
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+};
+
+use libtelnet_rs::bytes::Bytes;
+
+use crate::{PROJECT_NAME, VERSION};
+
+const IS: u8 = 0;
+const SEND: u8 = 1;
+const INFO: u8 = 2;
+
+const VAR: u8 = 0;
+const VALUE: u8 = 1;
+const ESC: u8 = 2;
+const USERVAR: u8 = 3;
+
+/// ANSI, VT100, UTF-8, 256 colors, true color and MNES
+const MTTS_DEFAULT: u32 = 0x001 | 0x002 | 0x004 | 0x008 | 0x100 | 0x200;
+const MTTS_SCREEN_READER: u32 = 0x040;
+
+/// The variables sent to servers asking for them with NEW-ENVIRON, following
+/// the Mud New-Environ Standard (MNES)
+#[derive(Debug)]
+pub struct NewEnviron {
+    charset: String,
+    reader_mode: bool,
+    tts_enabled: bool,
+    /// MTTS without the screen reader bit, which follows reader mode and TTS
+    mtts: u32,
+    /// Variables set by scripts, None keeps a variable from being sent
+    overrides: BTreeMap<String, Option<String>>,
+    /// Once the server has asked, changes are sent to it as they happen
+    requested: bool,
+}
+
+impl Default for NewEnviron {
+    fn default() -> Self {
+        Self {
+            charset: "UTF-8".to_string(),
+            reader_mode: false,
+            tts_enabled: false,
+            mtts: MTTS_DEFAULT,
+            overrides: BTreeMap::new(),
+            requested: false,
+        }
+    }
+}
+
+fn push_escaped(data: &mut Vec<u8>, text: &str) {
+    for byte in text.bytes() {
+        if byte <= USERVAR {
+            data.push(ESC);
+        }
+        data.push(byte);
+    }
+}
+
+/// Appends a variable, leaving out the value marks it as undefined
+fn push_variable(data: &mut Vec<u8>, kind: u8, name: &str, value: Option<&String>) {
+    data.push(kind);
+    push_escaped(data, name);
+    if let Some(value) = value {
+        data.push(VALUE);
+        push_escaped(data, value);
+    }
+}
+
+/// Reads the variable names of a SEND request
+fn parse_request(data: &[u8]) -> Vec<(u8, String)> {
+    let mut names: Vec<(u8, Vec<u8>)> = vec![];
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match *byte {
+            VAR | USERVAR => names.push((*byte, vec![])),
+            ESC => {
+                if let (Some(byte), Some((_, name))) = (bytes.next(), names.last_mut()) {
+                    name.push(*byte);
+                }
+            }
+            byte => {
+                if let Some((_, name)) = names.last_mut() {
+                    name.push(byte);
+                }
+            }
+        }
+    }
+    names
+        .into_iter()
+        .map(|(kind, name)| (kind, String::from_utf8_lossy(&name).to_string()))
+        .collect()
+}
+
+impl NewEnviron {
+    pub fn new(reader_mode: bool, tts_enabled: bool) -> Self {
+        Self {
+            reader_mode,
+            tts_enabled,
+            ..Default::default()
+        }
+    }
+
+    fn defaults(&self) -> BTreeMap<String, String> {
+        let mut mtts = self.mtts;
+        if self.reader_mode || self.tts_enabled {
+            mtts |= MTTS_SCREEN_READER;
+        }
+        let term = env::var("TERM").unwrap_or_else(|_| "xterm-256color".to_string());
+        [
+            ("CHARSET", self.charset.clone()),
+            ("CLIENT_NAME", PROJECT_NAME.to_uppercase()),
+            ("CLIENT_VERSION", VERSION.to_string()),
+            ("MTTS", mtts.to_string()),
+            ("TERMINAL_TYPE", term.to_uppercase()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+    }
+
+    /// The variables as they are sent to the server
+    pub fn variables(&self) -> BTreeMap<String, String> {
+        let mut variables = self.defaults();
+        for (name, value) in &self.overrides {
+            match value {
+                Some(value) => variables.insert(name.clone(), value.clone()),
+                None => variables.remove(name),
+            };
+        }
+        variables
+    }
+
+    /// Answers a request from the server, None if it wasn't a SEND
+    pub fn reply(&mut self, request: &[u8]) -> Option<Bytes> {
+        if request.first() != Some(&SEND) {
+            return None;
+        }
+        self.requested = true;
+        let variables = self.variables();
+        let mut data = vec![IS];
+        let names = parse_request(&request[1..]);
+        let send_all = names.is_empty() || names.contains(&(VAR, String::new()));
+        if send_all {
+            for (name, value) in &variables {
+                push_variable(&mut data, VAR, name, Some(value));
+            }
+        }
+        for (kind, name) in names {
+            let sent = name.is_empty() || (send_all && kind == VAR);
+            if !sent {
+                push_variable(&mut data, kind, &name, variables.get(&name));
+            }
+        }
+        Some(Bytes::from(data))
+    }
+
+    /// Applies a change and returns the INFO telling the server about any
+    /// variables that changed, once it has asked for them
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> Option<Bytes> {
+        let before = self.variables();
+        change(self);
+        let after = self.variables();
+        if !self.requested {
+            return None;
+        }
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let mut data = vec![INFO];
+        for name in names {
+            let value = after.get(name);
+            if before.get(name) != value {
+                push_variable(&mut data, VAR, name, value);
+            }
+        }
+        (data.len() > 1).then(|| Bytes::from(data))
+    }
+
+    /// Sets a variable, None keeps it from being sent
+    pub fn set(&mut self, name: &str, value: Option<String>) -> Option<Bytes> {
+        self.update(|env| {
+            env.overrides.insert(name.to_string(), value);
+        })
+    }
+
+    /// Goes back to the default value of a variable
+    pub fn restore(&mut self, name: &str) -> Option<Bytes> {
+        self.update(|env| {
+            env.overrides.remove(name);
+        })
+    }
+
+    pub fn set_charset(&mut self, charset: &str) -> Option<Bytes> {
+        self.update(|env| env.charset = charset.to_string())
+    }
+
+    pub fn set_mtts(&mut self, mtts: u32) -> Option<Bytes> {
+        self.update(|env| env.mtts = mtts)
+    }
+
+    pub fn set_reader_mode(&mut self, reader_mode: bool) -> Option<Bytes> {
+        self.update(|env| env.reader_mode = reader_mode)
+    }
+
+    pub fn set_tts_enabled(&mut self, tts_enabled: bool) -> Option<Bytes> {
+        self.update(|env| env.tts_enabled = tts_enabled)
+    }
+
+    /// Forgets that the server of a connection that has ended asked
+    pub fn reset(&mut self) {
+        self.requested = false;
+    }
+}
+
+#[cfg(test)]
+mod test_new_environ {
+    use super::*;
+
+    fn encode(command: u8, variables: &[(&str, Option<&str>)]) -> Bytes {
+        let mut data = vec![command];
+        for (name, value) in variables {
+            push_variable(&mut data, VAR, name, value.map(str::to_string).as_ref());
+        }
+        Bytes::from(data)
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut data = vec![];
+        push_variable(&mut data, VAR, "A\x01B", Some(&"\x00".to_string()));
+        assert_eq!(data, [VAR, b'A', ESC, 1, b'B', VALUE, ESC, 0]);
+        assert_eq!(
+            parse_request(&[VAR, b'A', ESC, 1, USERVAR, b'X']),
+            vec![(VAR, "A\x01".to_string()), (USERVAR, "X".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_reply() {
+        let mut env = NewEnviron::new(true, false);
+        assert_eq!(env.reply(&[IS]), None);
+
+        let mut request = vec![SEND, VAR];
+        request.extend(b"CLIENT_NAME");
+        request.push(VAR);
+        request.extend(b"IPADDRESS");
+        assert_eq!(
+            env.reply(&request),
+            Some(encode(
+                IS,
+                &[("CLIENT_NAME", Some("BLIGHTMUD")), ("IPADDRESS", None)]
+            ))
+        );
+
+        let variables = env.variables();
+        assert_eq!(variables["MTTS"], "847");
+        assert_eq!(variables["CLIENT_VERSION"], VERSION);
+        assert_eq!(env.reply(&[SEND]).unwrap().len(), {
+            let mut all = vec![IS];
+            for (name, value) in &variables {
+                push_variable(&mut all, VAR, name, Some(value));
+            }
+            all.len()
+        });
+    }
+
+    #[test]
+    fn test_info() {
+        let mut env = NewEnviron::default();
+        assert_eq!(env.set("CLIENT_NAME", Some("MUDLET".to_string())), None);
+        assert_eq!(env.variables()["CLIENT_NAME"], "MUDLET");
+
+        env.reply(&[SEND]);
+        assert_eq!(
+            env.set_reader_mode(true),
+            Some(encode(INFO, &[("MTTS", Some("847"))]))
+        );
+        assert_eq!(env.set_tts_enabled(true), None);
+        assert_eq!(
+            env.set("TERMINAL_TYPE", None),
+            Some(encode(INFO, &[("TERMINAL_TYPE", None)]))
+        );
+        assert_eq!(
+            env.restore("CLIENT_NAME"),
+            Some(encode(INFO, &[("CLIENT_NAME", Some("BLIGHTMUD"))]))
+        );
+
+        assert_eq!(
+            env.set_mtts(MTTS_DEFAULT | 0x080),
+            Some(encode(INFO, &[("MTTS", Some("975"))]))
+        );
+
+        env.reset();
+        assert_eq!(env.set_charset("ISO-8859-1"), None);
+        assert_eq!(env.variables()["CHARSET"], "ISO-8859-1");
+    }
+}
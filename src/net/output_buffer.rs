@@ -2,12 +2,27 @@ use log::debug;
 
 use crate::model::Line;
 
-use super::{tcp_stream::BUFFER_SIZE, telnet::TelnetMode};
+use super::{encoding::Encoding, mxp::Mxp, tcp_stream::BUFFER_SIZE, telnet::TelnetMode};
 
 pub struct OutputBuffer {
     buffer: Vec<u8>,
     telnet_mode: TelnetMode,
     new_data: bool,
+    mxp: Option<Mxp>,
+    encoding: Encoding,
+}
+
+fn build_line(mxp: &mut Option<Mxp>, encoding: Encoding, bytes: &[u8]) -> Line {
+    let bytes = encoding.decode(bytes);
+    let bytes = bytes.as_ref();
+    if let Some(mxp) = mxp {
+        let (content, links) = mxp.parse_line(bytes);
+        let mut line = Line::from(content.as_slice());
+        line.links = links;
+        line
+    } else {
+        Line::from(bytes)
+    }
 }
 
 impl OutputBuffer {
@@ -16,16 +31,55 @@ impl OutputBuffer {
             buffer: Vec::with_capacity(BUFFER_SIZE),
             telnet_mode: telnet_mode.clone(),
             new_data: false,
+            mxp: None,
+            encoding: Encoding::default(),
         }
     }
 
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Start parsing MXP tags in received output. Returns true if MXP wasn't
+    /// already enabled.
+    pub fn enable_mxp(&mut self) -> bool {
+        if self.mxp.is_none() {
+            self.mxp = Some(Mxp::default());
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn disable_mxp(&mut self) {
+        self.mxp = None;
+    }
+
+    pub fn mxp_responses(&mut self) -> Vec<String> {
+        self.mxp
+            .as_mut()
+            .map(|mxp| mxp.take_responses())
+            .unwrap_or_default()
+    }
+
     pub fn telnet_mode(&mut self, mode: &TelnetMode) {
         self.telnet_mode = mode.clone();
     }
 
     pub fn buffer_to_prompt(&mut self, consume_buffer: bool) -> Line {
-        let mut prompt = if !self.buffer.is_empty() {
-            Line::from(&self.buffer)
+        let mut prompt = if self.mxp.is_some() {
+            if consume_buffer {
+                build_line(&mut self.mxp, self.encoding, &self.buffer)
+            } else {
+                // The buffer will be parsed again so leave the MXP state untouched
+                build_line(&mut self.mxp.clone(), self.encoding, &self.buffer)
+            }
+        } else if !self.buffer.is_empty() {
+            build_line(&mut None, self.encoding, &self.buffer)
         } else {
             Line::from("")
         };
@@ -54,13 +108,14 @@ impl OutputBuffer {
 
         self.buffer.append(&mut Vec::from(data));
 
-        let cut_line =
+        let encoding = self.encoding;
+        let mut cut_line =
             |lines: &mut Vec<Line>, i: usize, last_cut: usize, cut_len: usize| -> usize {
                 if i == 0 {
-                    lines.push(Line::from("".to_string()));
+                    lines.push(build_line(&mut self.mxp, encoding, &[]));
                     cut_len
                 } else {
-                    let mut line = Line::from(&self.buffer[last_cut..i]);
+                    let mut line = build_line(&mut self.mxp, encoding, &self.buffer[last_cut..i]);
                     if self.telnet_mode == TelnetMode::UnterminatedPrompt
                         && last_cut == 0
                         && existing_buffer_len > 0
@@ -95,6 +150,7 @@ impl OutputBuffer {
     pub fn clear(&mut self) {
         self.buffer.clear();
         self.telnet_mode = TelnetMode::default();
+        self.mxp = None;
     }
 
     #[cfg(test)]
@@ -111,7 +167,10 @@ impl OutputBuffer {
 mod output_buffer_tests {
 
     use super::OutputBuffer;
-    use crate::{model::Line, net::TelnetMode};
+    use crate::{
+        model::{Line, LinkAction},
+        net::{Encoding, TelnetMode},
+    };
 
     #[test]
     fn test_prompt_capture() {
@@ -224,4 +283,51 @@ mod output_buffer_tests {
         assert_eq!(iter.next(), Some(&Line::from("line 5")));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn test_mxp_lines() {
+        let mut buffer = OutputBuffer::new(&TelnetMode::default());
+        let lines = buffer.receive(b"<b>bold</b>\r\n");
+        assert_eq!(lines[0], Line::from("<b>bold</b>"));
+
+        assert!(buffer.enable_mxp());
+        assert!(!buffer.enable_mxp());
+        let lines = buffer.receive(b"<b>bold</b>\r\n\x1b[1z<send>north</send>\r\nprompt<send>");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], Line::from("\x1b[1mbold\x1b[0m"));
+        assert_eq!(lines[1].clean_line(), "north");
+        assert_eq!(
+            lines[1].links[0].action,
+            LinkAction::Send(vec!["north".to_string()])
+        );
+        assert_eq!(buffer.buffer_to_prompt(false).clean_line(), "prompt");
+        assert_eq!(buffer.buffer_to_prompt(true).clean_line(), "prompt");
+
+        buffer.clear();
+        let lines = buffer.receive(b"<b>bold</b>\r\n");
+        assert_eq!(lines[0], Line::from("<b>bold</b>"));
+    }
+
+    #[test]
+    fn test_mxp_version_response() {
+        let mut buffer = OutputBuffer::new(&TelnetMode::default());
+        buffer.receive(b"\x1b[1z<VERSION>\r\n");
+        assert!(buffer.mxp_responses().is_empty());
+        buffer.enable_mxp();
+        buffer.receive(b"\x1b[1z<VERSION>\r\n");
+        assert_eq!(buffer.mxp_responses().len(), 1);
+    }
+
+    #[test]
+    fn test_encoded_lines() {
+        let mut buffer = OutputBuffer::new(&TelnetMode::UnterminatedPrompt);
+        buffer.set_encoding(Encoding::for_label("latin1").unwrap());
+        let lines = buffer.receive(b"Caf\xe9\r\nPr\xeat> ");
+        assert_eq!(lines[0], Line::from("Café"));
+        assert_eq!(buffer.buffer_to_prompt(true), Line::from("Prêt> "));
+
+        buffer.set_encoding(Encoding::Cp437);
+        let lines = buffer.receive(b"\xc9\xcd\xbb\r\n");
+        assert_eq!(lines[0], Line::from("╔═╗"));
+    }
 }
@@ -0,0 +1,295 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use log::debug;
+
+use crate::model::{Proxy, ProxyKind};
+use crate::tools::util::base64;
+
+use super::open_tcp_stream;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0x00;
+const SOCKS_USER_PASS: u8 = 0x02;
+const SOCKS_NO_ACCEPTABLE: u8 = 0xff;
+const SOCKS_CONNECT: u8 = 0x01;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+
+/// Opens a connection to `host:port` tunneled through the provided proxy
+pub fn open_proxy_stream(proxy: &Proxy, host: &str, port: u16) -> Result<TcpStream> {
+    debug!("Connecting to {}:{} through {}", host, port, proxy);
+    let mut stream = match open_tcp_stream(&proxy.host, proxy.port) {
+        Ok(stream) => stream,
+        Err(err) => bail!(
+            "Unable to reach proxy {}:{}: {}",
+            proxy.host,
+            proxy.port,
+            err
+        ),
+    };
+
+    // Don't hang forever on a proxy that never answers
+    stream.set_read_timeout(Some(Duration::new(10, 0)))?;
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_handshake(&mut stream, proxy, host, port)?,
+        ProxyKind::Http => http_connect(&mut stream, proxy, host, port)?,
+    }
+    stream.set_read_timeout(None)?;
+    Ok(stream)
+}
+
+fn socks5_handshake<S: Read + Write>(
+    stream: &mut S,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    if proxy.username.is_some() {
+        stream.write_all(&[SOCKS_VERSION, 2, SOCKS_NO_AUTH, SOCKS_USER_PASS])?;
+    } else {
+        stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTH])?;
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != SOCKS_VERSION {
+        bail!("Proxy is not a SOCKS5 proxy");
+    }
+    match reply[1] {
+        SOCKS_NO_AUTH => {}
+        SOCKS_USER_PASS => socks5_authenticate(stream, proxy)?,
+        SOCKS_NO_ACCEPTABLE if proxy.username.is_none() => {
+            bail!("SOCKS5 proxy requires authentication")
+        }
+        method => bail!("SOCKS5 proxy selected unsupported auth method {}", method),
+    }
+
+    if host.len() > u8::MAX as usize {
+        bail!("Host name is too long for SOCKS5: {}", host);
+    }
+    let mut request = vec![
+        SOCKS_VERSION,
+        SOCKS_CONNECT,
+        0,
+        SOCKS_ATYP_DOMAIN,
+        host.len() as u8,
+    ];
+    request.extend(host.as_bytes());
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        bail!(
+            "SOCKS5 proxy refused connection: {}",
+            socks5_error(reply[1])
+        );
+    }
+    // Skip the bound address and port
+    let addr_len = match reply[3] {
+        SOCKS_ATYP_IPV4 => 4,
+        SOCKS_ATYP_IPV6 => 16,
+        SOCKS_ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => bail!("SOCKS5 proxy replied with unknown address type {}", atyp),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+fn socks5_authenticate<S: Read + Write>(stream: &mut S, proxy: &Proxy) -> Result<()> {
+    let username = proxy.username.as_deref().unwrap_or_default();
+    let password = proxy.password.as_deref().unwrap_or_default();
+    if username.len() > u8::MAX as usize || password.len() > u8::MAX as usize {
+        bail!("SOCKS5 credentials are too long");
+    }
+    let mut request = vec![1, username.len() as u8];
+    request.extend(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend(password.as_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        bail!("SOCKS5 proxy authentication failed");
+    }
+    Ok(())
+}
+
+fn socks5_error(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn http_connect<S: Read + Write>(
+    stream: &mut S,
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    let target = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(username) = &proxy.username {
+        let credentials = format!(
+            "{}:{}",
+            username,
+            proxy.password.as_deref().unwrap_or_default()
+        );
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64(credentials.as_bytes())
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    // Read byte by byte so nothing sent by the mud after the header is lost
+    let mut response = vec![];
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            bail!("HTTP proxy closed the connection");
+        }
+        response.push(byte[0]);
+        if response.len() > 8192 {
+            bail!("HTTP proxy response is too large");
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        Some(_) => bail!("HTTP proxy refused connection: {}", status),
+        None => bail!("Invalid response from HTTP proxy: {}", status),
+    }
+}
+
+#[cfg(test)]
+mod test_proxy {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Replays a canned server response and records what was written
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: Cursor::new(input.to_vec()),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_socks5_handshake() {
+        let proxy: Proxy = "socks5://proxy:1080".parse().unwrap();
+        let mut stream = MockStream::new(&[5, 0, 5, 0, 0, 1, 127, 0, 0, 1, 0x0f, 0xa0]);
+        socks5_handshake(&mut stream, &proxy, "mud.org", 4000).unwrap();
+        assert_eq!(
+            stream.output,
+            [&[5, 1, 0, 5, 1, 0, 3, 7][..], b"mud.org", &[0x0f, 0xa0]].concat()
+        );
+    }
+
+    #[test]
+    fn test_socks5_auth() {
+        let proxy: Proxy = "socks5://me:pw@proxy:1080".parse().unwrap();
+        let mut stream = MockStream::new(&[5, 2, 1, 0, 5, 0, 0, 1, 127, 0, 0, 1, 0, 1]);
+        socks5_handshake(&mut stream, &proxy, "a", 1).unwrap();
+        assert_eq!(
+            stream.output,
+            [
+                &[5, 2, 0, 2][..],
+                &[1, 2, b'm', b'e', 2, b'p', b'w'],
+                &[5, 1, 0, 3, 1, b'a', 0, 1],
+            ]
+            .concat()
+        );
+
+        let mut stream = MockStream::new(&[5, 2, 1, 1]);
+        let err = socks5_handshake(&mut stream, &proxy, "a", 1).unwrap_err();
+        assert_eq!(err.to_string(), "SOCKS5 proxy authentication failed");
+    }
+
+    #[test]
+    fn test_socks5_refused() {
+        let proxy: Proxy = "socks5://proxy:1080".parse().unwrap();
+        let mut stream = MockStream::new(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        let err = socks5_handshake(&mut stream, &proxy, "mud.org", 4000).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SOCKS5 proxy refused connection: connection refused"
+        );
+
+        let mut stream = MockStream::new(&[5, 0xff]);
+        let err = socks5_handshake(&mut stream, &proxy, "mud.org", 4000).unwrap_err();
+        assert_eq!(err.to_string(), "SOCKS5 proxy requires authentication");
+    }
+
+    #[test]
+    fn test_http_connect() {
+        let proxy: Proxy = "http://me:pw@proxy:3128".parse().unwrap();
+        let mut stream = MockStream::new(b"HTTP/1.1 200 Connection established\r\n\r\nWelcome");
+        http_connect(&mut stream, &proxy, "mud.org", 4000).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&stream.output),
+            "CONNECT mud.org:4000 HTTP/1.1\r\nHost: mud.org:4000\r\nProxy-Authorization: Basic bWU6cHc=\r\n\r\n"
+        );
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "Welcome");
+
+        let mut stream = MockStream::new(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n");
+        let err = http_connect(&mut stream, &proxy, "mud.org", 4000).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HTTP proxy refused connection: HTTP/1.1 407 Proxy Authentication Required"
+        );
+    }
+}
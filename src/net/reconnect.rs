@@ -0,0 +1,133 @@
+use std::{
+    sync::mpsc::{channel, RecvTimeoutError},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use log::debug;
+
+use crate::{event::Event, model::Connection, session::Session};
+
+/// Describes how Blightmud should try to restore a connection that was
+/// dropped by the network or the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Number of connection attempts before giving up
+    pub attempts: u32,
+    /// Delay before the first attempt. Doubled for every following attempt.
+    pub delay: Duration,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Fraction (0.0 - 1.0) of the delay to randomly add or subtract
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.25,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before the given (1-based) attempt without any jitter applied
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// The delay before the given (1-based) attempt with jitter applied
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.backoff(attempt);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter > 0.0 {
+            // Map the sub second clock to a value in the range -1.0..1.0
+            let nanos = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or_default();
+            let spread = nanos as f64 / 500_000_000.0 - 1.0;
+            backoff.mul_f64(1.0 + jitter * spread)
+        } else {
+            backoff
+        }
+    }
+}
+
+pub fn spawn_reconnect_thread(
+    mut session: Session,
+    policy: ReconnectPolicy,
+    connection: Connection,
+) -> thread::JoinHandle<()> {
+    let (cancel_writer, cancel_reader) = channel();
+    // Replacing a previous sender will cancel any reconnect already in progress
+    session
+        .reconnect_cancel
+        .lock()
+        .unwrap()
+        .replace(cancel_writer);
+    thread::Builder::new()
+        .name("reconnect-thread".to_string())
+        .spawn(move || {
+            let Connection { host, port, .. } = &connection;
+            for attempt in 1..=policy.attempts {
+                let delay = policy.delay(attempt);
+                session.send_event(Event::ReconnectAttempt(attempt, policy.attempts, delay));
+                match cancel_reader.recv_timeout(delay) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => {
+                        debug!("Reconnect to {}:{} cancelled", host, port);
+                        return;
+                    }
+                }
+                debug!("Reconnect attempt {} to {}:{}", attempt, host, port);
+                if session.connect(&connection) {
+                    return;
+                }
+            }
+            session.send_event(Event::ReconnectFailed(policy.attempts));
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test_reconnect {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = ReconnectPolicy {
+            attempts: 10,
+            delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.0,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(4), Duration::from_secs(4));
+        assert_eq!(policy.backoff(5), Duration::from_secs(5));
+        assert_eq!(policy.backoff(100), Duration::from_secs(5));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = ReconnectPolicy {
+            jitter: 0.5,
+            ..Default::default()
+        };
+        for attempt in 1..=policy.attempts {
+            let backoff = policy.backoff(attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= backoff.mul_f64(0.5));
+            assert!(delay <= backoff.mul_f64(1.5));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::{
+    event::Event, io::RecordingReader, session::Session, tools::util::expand_tilde, TelnetData,
+};
+
+use super::TelnetHandler;
+
+/// Feeds a recording through the telnet handler as if it was received from a
+/// server. `speed` scales the original timing, 0 plays it back instantly.
+///
+/// Nothing reaches a network. Data sent in the meantime arrives on
+/// `transmit_read` and is dropped. Playback stops early if the channel is
+/// closed, eg. when connecting to a real server.
+pub fn spawn_replay_thread(
+    session: Session,
+    path: String,
+    speed: f64,
+    transmit_read: Receiver<TelnetData>,
+) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name("replay-thread".to_string())
+        .spawn(move || {
+            let writer = session.main_writer.clone();
+            let file = PathBuf::from(expand_tilde(&path).as_ref());
+            let reader = match RecordingReader::open(&file) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    writer
+                        .send(Event::Error(format!(
+                            "Unable to open recording {path}: {err}"
+                        )))
+                        .unwrap();
+                    return;
+                }
+            };
+            writer
+                .send(Event::Info(format!("Replaying: {path}")))
+                .unwrap();
+
+            let mut telnet_handler = TelnetHandler::new(session.clone());
+            let start = Instant::now();
+            for chunk in reader {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        writer
+                            .send(Event::Error(format!("Broken recording {path}: {err}")))
+                            .unwrap();
+                        break;
+                    }
+                };
+                let due = if speed > 0.0 {
+                    chunk.offset.div_f64(speed)
+                } else {
+                    Duration::ZERO
+                };
+                if !discard_until(&transmit_read, start + due) {
+                    debug!("Replay of {} stopped", path);
+                    return;
+                }
+                // The compressed remainder of a chunk that started MCCP was
+                // recorded again, decompressed, in the following chunks.
+                telnet_handler.parse(&chunk.data);
+            }
+            writer
+                .send(Event::Info("Replay finished".to_string()))
+                .unwrap();
+            while let Ok(Some(_)) = transmit_read.recv() {}
+        })
+        .unwrap()
+}
+
+/// Drops outgoing data until the deadline passes. Returns false if the
+/// replay should stop.
+fn discard_until(transmit_read: &Receiver<TelnetData>, deadline: Instant) -> bool {
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match transmit_read.recv_timeout(timeout) {
+            Ok(Some(data)) => debug!("Replay discarded: {:?}", data),
+            Ok(None) | Err(RecvTimeoutError::Disconnected) => return false,
+            Err(RecvTimeoutError::Timeout) => return true,
+        }
+    }
+}
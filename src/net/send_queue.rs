@@ -0,0 +1,240 @@
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+use libtelnet_rs::bytes::Bytes;
+use log::debug;
+
+use crate::{event::Event, session::Session};
+
+/// Limits how fast lines are sent to the server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Lines sent per second once the burst is used up
+    pub lines_per_second: f64,
+    /// Lines that may be sent at once after being idle
+    pub burst: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            lines_per_second: 5.0,
+            burst: 10,
+        }
+    }
+}
+
+/// Holds back lines sent to the server when a rate limit is exceeded. Only
+/// lines are queued, protocol data is always sent right away.
+#[derive(Debug)]
+pub struct SendQueue {
+    limit: Option<RateLimit>,
+    tokens: f64,
+    refilled: Instant,
+    pending: VecDeque<(String, Bytes)>,
+    draining: bool,
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            tokens: 0.0,
+            refilled: Instant::now(),
+            pending: VecDeque::new(),
+            draining: false,
+        }
+    }
+}
+
+impl SendQueue {
+    pub fn limit(&self) -> Option<RateLimit> {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: Option<RateLimit>) {
+        self.limit = limit;
+        self.tokens = limit.map_or(0.0, |limit| limit.burst as f64);
+        self.refilled = Instant::now();
+    }
+
+    fn refill(&mut self) {
+        if let Some(limit) = self.limit {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * limit.lines_per_second).min(limit.burst as f64);
+            self.refilled = now;
+        }
+    }
+
+    /// Takes one token if one is available
+    fn take_token(&mut self) -> bool {
+        self.refill();
+        if self.limit.is_none() {
+            true
+        } else if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Queues a line. Returns the data if it may be sent right away.
+    pub fn push(&mut self, line: &str, data: Bytes) -> Option<Bytes> {
+        if self.pending.is_empty() && self.take_token() {
+            Some(data)
+        } else {
+            debug!("Queueing: {}", line);
+            self.pending.push_back((line.to_string(), data));
+            None
+        }
+    }
+
+    /// Returns the next pending line if it's due
+    pub fn pop(&mut self) -> Option<Bytes> {
+        if !self.pending.is_empty() && self.take_token() {
+            self.pending.pop_front().map(|(_, data)| data)
+        } else {
+            None
+        }
+    }
+
+    /// Time until the next pending line is due, None if nothing is pending
+    pub fn next_due(&mut self) -> Option<Duration> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        self.refill();
+        match self.limit {
+            Some(limit) if self.tokens < 1.0 => Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) / limit.lines_per_second,
+            )),
+            _ => Some(Duration::ZERO),
+        }
+    }
+
+    /// The lines waiting to be sent
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.iter().map(|(line, _)| line.clone()).collect()
+    }
+
+    /// Drops all pending lines, returns how many there were
+    pub fn clear(&mut self) -> usize {
+        let count = self.pending.len();
+        self.pending.clear();
+        count
+    }
+}
+
+/// Sends the pending lines of the session's queue as they become due. Does
+/// nothing if the queue is already being drained.
+pub fn spawn_send_queue_thread(session: Session) -> Option<thread::JoinHandle<()>> {
+    {
+        let mut queue = session.send_queue.lock().unwrap();
+        if queue.draining || queue.pending.is_empty() {
+            return None;
+        }
+        queue.draining = true;
+    }
+    let handle = thread::Builder::new()
+        .name("send-queue-thread".to_string())
+        .spawn(move || loop {
+            let wait = {
+                let mut queue = session.send_queue.lock().unwrap();
+                if let Some(data) = queue.pop() {
+                    session.main_writer.send(Event::ServerSend(data)).unwrap();
+                    continue;
+                }
+                match queue.next_due() {
+                    Some(wait) => wait,
+                    None => {
+                        queue.draining = false;
+                        break;
+                    }
+                }
+            };
+            thread::sleep(wait);
+        })
+        .unwrap();
+    Some(handle)
+}
+
+#[cfg(test)]
+mod test_send_queue {
+    use super::*;
+
+    fn limited(lines_per_second: f64, burst: u32) -> SendQueue {
+        let mut queue = SendQueue::default();
+        queue.set_limit(Some(RateLimit {
+            lines_per_second,
+            burst,
+        }));
+        queue
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut queue = SendQueue::default();
+        for _ in 0..100 {
+            assert!(queue
+                .push("look", Bytes::from_static(b"look\r\n"))
+                .is_some());
+        }
+        assert!(queue.pending().is_empty());
+        assert_eq!(queue.next_due(), None);
+    }
+
+    #[test]
+    fn test_burst() {
+        let mut queue = limited(1.0, 2);
+        assert!(queue.push("one", Bytes::from_static(b"one\r\n")).is_some());
+        assert!(queue.push("two", Bytes::from_static(b"two\r\n")).is_some());
+        assert!(queue
+            .push("three", Bytes::from_static(b"three\r\n"))
+            .is_none());
+        assert!(queue
+            .push("four", Bytes::from_static(b"four\r\n"))
+            .is_none());
+        assert_eq!(queue.pending(), vec!["three", "four"]);
+        assert!(queue.pop().is_none());
+
+        let due = queue.next_due().unwrap();
+        assert!(due > Duration::from_millis(900) && due <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_drain_in_order() {
+        let mut queue = limited(100.0, 1);
+        assert!(queue.push("one", Bytes::from_static(b"one\r\n")).is_some());
+        assert!(queue.push("two", Bytes::from_static(b"two\r\n")).is_none());
+        assert!(queue
+            .push("three", Bytes::from_static(b"three\r\n"))
+            .is_none());
+
+        let margin = Duration::from_millis(1);
+        thread::sleep(queue.next_due().unwrap() + margin);
+        assert_eq!(queue.pop(), Some(Bytes::from_static(b"two\r\n")));
+        thread::sleep(queue.next_due().unwrap() + margin);
+        assert_eq!(queue.pop(), Some(Bytes::from_static(b"three\r\n")));
+        assert_eq!(queue.next_due(), None);
+    }
+
+    #[test]
+    fn test_clear_and_unlimit() {
+        let mut queue = limited(1.0, 1);
+        assert!(queue.push("one", Bytes::from_static(b"one\r\n")).is_some());
+        assert!(queue.push("two", Bytes::from_static(b"two\r\n")).is_none());
+        assert!(queue
+            .push("three", Bytes::from_static(b"three\r\n"))
+            .is_none());
+        queue.set_limit(None);
+        assert_eq!(queue.next_due(), Some(Duration::ZERO));
+        assert_eq!(queue.pop(), Some(Bytes::from_static(b"two\r\n")));
+        assert_eq!(queue.clear(), 1);
+        assert!(queue.pending().is_empty());
+    }
+}
@@ -1,16 +1,24 @@
-use crate::{event::Event, model::Connection, net::TelnetHandler, session::Session};
-use flate2::read::ZlibDecoder;
+use crate::{
+    event::Event,
+    io::SaveData,
+    model::{Connection, GlobalProxy},
+    net::TelnetHandler,
+    session::Session,
+};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use libtelnet_rs::bytes::Bytes;
 use log::{debug, error};
 use std::{
     io::{Chain, Cursor, Read, Write},
-    sync::mpsc::Receiver,
+    sync::mpsc::{Receiver, RecvTimeoutError},
     thread,
+    time::Instant,
 };
 
-use super::MudConnection;
+use super::{describe_io_error, telnet::MCCP3_START, KeepaliveMode, MudConnection};
 
 type Decoder = ZlibDecoder<Chain<Cursor<Vec<u8>>, MudConnection>>;
+type Encoder = ZlibEncoder<MudConnection>;
 
 pub const BUFFER_SIZE: usize = 32 * 1024;
 
@@ -26,7 +34,7 @@ impl MudReceiver {
         self.decoder.replace(chain);
     }
 
-    fn read_bytes(&mut self) -> Vec<u8> {
+    fn read_bytes(&mut self) -> std::io::Result<Vec<u8>> {
         let mut data = vec![0; BUFFER_SIZE];
         if let Some(decoder) = &mut self.decoder {
             debug!(
@@ -37,25 +45,20 @@ impl MudReceiver {
             match decoder.read(&mut data) {
                 Ok(bytes_read) => {
                     debug!("Read {} bytes from zlib stream", bytes_read);
-                    if bytes_read > 0 {
-                        data = data[..bytes_read].to_vec();
-                    } else {
-                        data = vec![];
-                    }
+                    data.truncate(bytes_read);
                 }
                 Err(err) => {
                     error!("Error: {}", err.to_string());
-                    data = vec![];
+                    return Err(err);
                 }
             }
-        } else if let Ok(bytes_read) = self.connection.read(&mut data) {
-            debug!("Read {} bytes from stream", bytes_read);
-            data = data[..bytes_read].to_vec();
         } else {
-            data = vec![];
+            let bytes_read = self.connection.read(&mut data)?;
+            debug!("Read {} bytes from stream", bytes_read);
+            data.truncate(bytes_read);
         }
         debug!("Bytes: {:?}", data);
-        data
+        Ok(data)
     }
 }
 
@@ -68,6 +71,54 @@ impl From<&Session> for MudReceiver {
     }
 }
 
+struct MudTransmitter {
+    connection: MudConnection,
+    encoder: Option<Encoder>,
+}
+
+impl MudTransmitter {
+    fn open_zlib_stream(&mut self) {
+        debug!("Opening outgoing Zlib stream");
+        let encoder = ZlibEncoder::new(self.connection.clone(), Compression::default());
+        self.encoder.replace(encoder);
+    }
+
+    fn close_zlib_stream(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            debug!(
+                "Closing outgoing Zlib stream ({} bytes in)",
+                encoder.total_in()
+            );
+            if let Err(err) = encoder.finish() {
+                debug!("Failed to finish zlib stream: {}", err);
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if let Some(encoder) = &mut self.encoder {
+            encoder.write_all(data)?;
+            // Sync flush so the server can decompress the data right away
+            encoder.flush()
+        } else {
+            self.connection.write_all(data)?;
+            if data == MCCP3_START {
+                self.open_zlib_stream();
+            }
+            Ok(())
+        }
+    }
+}
+
+impl From<&Session> for MudTransmitter {
+    fn from(session: &Session) -> Self {
+        Self {
+            connection: session.connection.lock().unwrap().clone(),
+            encoder: None,
+        }
+    }
+}
+
 pub fn spawn_connect_thread(
     mut session: Session,
     connection: Connection,
@@ -75,16 +126,17 @@ pub fn spawn_connect_thread(
     thread::Builder::new()
         .name("connect-thread".to_string())
         .spawn(move || {
-            let Connection {
-                host,
-                port,
-                tls,
-                verify_cert,
-            } = connection;
-            if !session.connect(&host, port, tls, verify_cert.into()) {
+            let connection = Connection {
+                proxy: connection.proxy.or_else(|| GlobalProxy::load().0),
+                ..connection
+            };
+            if !session.connect(&connection) {
                 session
                     .main_writer
-                    .send(Event::Error(format!("Failed to connect to {host}:{port}")))
+                    .send(Event::Error(format!(
+                        "Failed to connect to {}:{}",
+                        connection.host, connection.port
+                    )))
                     .unwrap();
             }
         })
@@ -106,15 +158,39 @@ pub fn spawn_receive_thread(session: Session) -> thread::JoinHandle<()> {
                     mud_receiver.open_zlib_stream(bytes);
                 }
 
-                let bytes = mud_receiver.read_bytes();
+                let bytes = match mud_receiver.read_bytes() {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        // Errors on a connection we closed ourselves are expected
+                        if session.connected() {
+                            writer
+                                .send(Event::Error(format!(
+                                    "Connection error: {}",
+                                    describe_io_error(&err)
+                                )))
+                                .unwrap();
+                        }
+                        vec![]
+                    }
+                };
                 if bytes.is_empty() {
                     writer
                         .send(Event::Info("Connection closed".to_string()))
                         .unwrap();
-                    writer.send(Event::Disconnect).unwrap();
+                    // Only a connection that wasn't closed by us is lost
+                    if session.connected() {
+                        writer
+                            .send(Event::ConnectionLost(mud_receiver.connection.id))
+                            .unwrap();
+                    }
                     break;
                 }
 
+                if let Some(recorder) = session.recorder.lock().unwrap().as_mut() {
+                    if let Err(err) = recorder.record(&bytes) {
+                        error!("Failed to record data: {}", err);
+                    }
+                }
                 remaining_bytes = telnet_handler.parse(&bytes);
             }
             debug!("Receive stream closing");
@@ -126,21 +202,51 @@ pub fn spawn_transmit_thread(
     mut session: Session,
     transmit_read: Receiver<Option<Bytes>>,
 ) -> thread::JoinHandle<()> {
-    let connection = session.connection.lock().unwrap().clone();
+    let mut mud_transmitter = MudTransmitter::from(&session);
     thread::Builder::new()
         .name("tcp-send-thread".to_string())
         .spawn(move || {
-            let mut connection = connection;
             let transmit_read = transmit_read;
             debug!("Transmit stream spawned");
-            while let Ok(Some(data)) = transmit_read.recv() {
-                if let Err(info) = connection.write_all(&data) {
-                    session.disconnect();
+            let mut last_keepalive = Instant::now();
+            loop {
+                let keepalive = *session.keepalive.lock().unwrap();
+                let received = match keepalive {
+                    Some(keepalive) => {
+                        let due = last_keepalive + keepalive.interval;
+                        transmit_read.recv_timeout(due.saturating_duration_since(Instant::now()))
+                    }
+                    None => transmit_read.recv().map_err(RecvTimeoutError::from),
+                };
+                let data = match received {
+                    Ok(Some(data)) => data,
+                    Ok(None) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        last_keepalive = Instant::now();
+                        if let Some(keepalive) = keepalive {
+                            debug!("Sending keepalive: {:?}", keepalive.mode);
+                            if keepalive.mode == KeepaliveMode::TimingMark {
+                                session.latency.lock().unwrap().sent();
+                            }
+                            Bytes::from_static(keepalive.data())
+                        } else {
+                            continue;
+                        }
+                    }
+                };
+                // Empty data only wakes us up to pick up keepalive changes
+                if data.is_empty() {
+                    continue;
+                }
+                if let Err(info) = mud_transmitter.write_bytes(&data) {
+                    // The stream is broken, there is nothing left to finish
+                    mud_transmitter.encoder.take();
                     let error = format!("Failed to write to socket: {info}").to_string();
                     session.send_event(Event::Error(error));
-                    session.send_event(Event::Disconnect);
+                    session.send_event(Event::ConnectionLost(mud_transmitter.connection.id));
                 }
             }
+            mud_transmitter.close_zlib_stream();
             debug!("Transmit stream closing");
         })
         .unwrap()
@@ -1,7 +1,9 @@
+use crate::audio::{Msp, MSP};
 use crate::event::Event;
-use crate::net::OutputBuffer;
+use crate::net::{Gmcp, GmcpMessage, LatencyMeter, NewEnviron, OutputBuffer, TelnetProxy, MXP};
 use crate::session::Session;
 use libtelnet_rs::{
+    bytes::Bytes,
     events::TelnetEvents,
     telnet::{op_command as cmd, op_option as opt},
     Parser,
@@ -9,6 +11,9 @@ use libtelnet_rs::{
 use log::debug;
 use std::sync::{mpsc::Sender, Arc, Mutex};
 
+/// Sent by the client to signal that all following data will be compressed
+pub const MCCP3_START: [u8; 5] = [cmd::IAC, cmd::SB, opt::MCCP3, cmd::IAC, cmd::SE];
+
 #[derive(Default, Eq, PartialEq, Clone, Debug)]
 pub enum TelnetMode {
     TerminatedPrompt,
@@ -20,16 +25,29 @@ pub struct TelnetHandler {
     parser: Arc<Mutex<Parser>>,
     main_writer: Sender<Event>,
     output_buffer: Arc<Mutex<OutputBuffer>>,
+    latency: Arc<Mutex<LatencyMeter>>,
+    telnet_proxy: Arc<Mutex<TelnetProxy>>,
+    gmcp: Arc<Mutex<Gmcp>>,
+    new_environ: Arc<Mutex<NewEnviron>>,
     mode: TelnetMode,
+    msp: Msp,
+    mccp3: bool,
 }
 
 impl TelnetHandler {
     pub fn new(session: Session) -> Self {
+        let msp = Msp::for_host(&session.host(), session.port());
         Self {
             parser: session.telnet_parser,
             main_writer: session.main_writer,
             output_buffer: session.output_buffer,
+            latency: session.latency,
+            telnet_proxy: session.telnet_proxy,
+            gmcp: session.gmcp,
+            new_environ: session.new_environ,
             mode: TelnetMode::UnterminatedPrompt,
+            msp,
+            mccp3: false,
         }
     }
 }
@@ -43,6 +61,7 @@ impl TelnetHandler {
             vec![]
         };
         for event in events {
+            self.telnet_proxy.lock().unwrap().forward(&event);
             match event {
                 TelnetEvents::IAC(iac) => {
                     debug!("IAC: {}", iac.command);
@@ -76,6 +95,12 @@ impl TelnetHandler {
                         _ => {}
                     }
                 }
+                TelnetEvents::Negotiation(neg) if neg.option == opt::TM => {
+                    // A WONT still answers our request
+                    if neg.command == cmd::WONT {
+                        self.timing_mark_reply();
+                    }
+                }
                 TelnetEvents::Negotiation(neg) => {
                     debug!("Telnet negotiation: {} -> {}", neg.command, neg.option);
                     if let Ok(mut parser) = self.parser.lock() {
@@ -91,6 +116,39 @@ impl TelnetHandler {
                             }
                         }
                     }
+                    // Once started, all following data is compressed so it can only start once
+                    if neg.option == opt::MCCP3 && neg.command == cmd::WILL && !self.mccp3 {
+                        debug!("Initiating MCCP3 compression");
+                        self.mccp3 = true;
+                        self.main_writer
+                            .send(Event::ServerSend(Bytes::copy_from_slice(&MCCP3_START)))
+                            .unwrap();
+                        self.main_writer
+                            .send(Event::AddTag("MCCP3".to_string()))
+                            .unwrap();
+                    }
+                    if neg.option == opt::GMCP && neg.command == cmd::WILL {
+                        self.enable_gmcp();
+                    }
+                    if neg.option == opt::NEWENVIRON && neg.command == cmd::DO {
+                        self.main_writer
+                            .send(Event::AddTag("MNES".to_string()))
+                            .unwrap();
+                    }
+                    if neg.option == MSP && neg.command == cmd::WILL {
+                        self.msp.enable();
+                        self.main_writer
+                            .send(Event::AddTag("MSP".to_string()))
+                            .unwrap();
+                    }
+                    if neg.option == MXP {
+                        if neg.command == cmd::WILL || neg.command == cmd::DO {
+                            self.enable_mxp();
+                        } else if let Ok(mut output_buffer) = self.output_buffer.lock() {
+                            debug!("Disabled MXP");
+                            output_buffer.disable_mxp();
+                        }
+                    }
                 }
                 TelnetEvents::DecompressImmediate(buffer) => {
                     debug!("Breaking on buff: {:?}", &buffer);
@@ -104,12 +162,44 @@ impl TelnetHandler {
                             .send(Event::AddTag("MCCP2".to_string()))
                             .unwrap();
                     }
+                    MXP => self.enable_mxp(),
+                    opt::NEWENVIRON => {
+                        let reply = self.new_environ.lock().unwrap().reply(&data.buffer);
+                        if let Some(reply) = reply {
+                            debug!("NEW-ENVIRON reply: {:?}", reply);
+                            if let Ok(mut parser) = self.parser.lock() {
+                                if let Some(TelnetEvents::DataSend(data)) =
+                                    parser.subnegotiation(opt::NEWENVIRON, reply)
+                                {
+                                    self.main_writer.send(Event::ServerSend(data)).unwrap();
+                                }
+                            }
+                        }
+                    }
+                    opt::GMCP => {
+                        if let Some(message) = GmcpMessage::parse(&data.buffer) {
+                            debug!("GMCP: {}", message);
+                            self.gmcp.lock().unwrap().receive(message.clone());
+                            self.main_writer.send(Event::GmcpReceive(message)).unwrap();
+                        }
+                        self.main_writer
+                            .send(Event::ProtoSubnegRecv(opt::GMCP, data.buffer))
+                            .unwrap();
+                    }
                     opt => {
                         self.main_writer
                             .send(Event::ProtoSubnegRecv(opt, data.buffer))
                             .unwrap();
                     }
                 },
+                // The parser refuses the WILL that answers our TIMING-MARK
+                // request, which must not be sent back to the server.
+                TelnetEvents::DataSend(msg)
+                    if msg[..] == [cmd::IAC, cmd::DONT, opt::TM]
+                        && self.latency.lock().unwrap().is_pending() =>
+                {
+                    self.timing_mark_reply();
+                }
                 TelnetEvents::DataSend(msg) => {
                     debug!("Telnet sending: {:?}", msg);
                     if !msg.is_empty() {
@@ -119,13 +209,20 @@ impl TelnetHandler {
                 TelnetEvents::DataReceive(msg) => {
                     debug!("Data receive: {:?}", msg);
                     if !msg.is_empty() && msg[0] != 0 {
-                        if let Ok(mut output_buffer) = self.output_buffer.lock() {
+                        let responses = if let Ok(mut output_buffer) = self.output_buffer.lock() {
                             let new_lines = output_buffer.receive(&msg);
-                            for line in new_lines {
+                            for mut line in new_lines {
+                                for event in self.msp.parse_line(&mut line) {
+                                    self.main_writer.send(event).unwrap();
+                                }
                                 self.main_writer.send(Event::MudOutput(line)).unwrap();
                             }
+                            output_buffer.mxp_responses()
+                        } else {
+                            vec![]
                         };
                         self.handle_prompt();
+                        self.send_mxp_responses(responses);
                     }
                 }
             };
@@ -133,6 +230,52 @@ impl TelnetHandler {
         result
     }
 
+    fn enable_mxp(&mut self) {
+        if let Ok(mut output_buffer) = self.output_buffer.lock() {
+            if output_buffer.enable_mxp() {
+                debug!("Enabled MXP");
+                self.main_writer
+                    .send(Event::AddTag("MXP".to_string()))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn enable_gmcp(&mut self) {
+        let messages = self.gmcp.lock().unwrap().enable();
+        if let Ok(mut parser) = self.parser.lock() {
+            for message in messages {
+                debug!("GMCP send: {}", message);
+                if let Some(TelnetEvents::DataSend(data)) =
+                    parser.subnegotiation(opt::GMCP, message.into_bytes())
+                {
+                    self.main_writer.send(Event::ServerSend(data)).unwrap();
+                }
+            }
+        }
+        self.main_writer
+            .send(Event::AddTag("GMCP".to_string()))
+            .unwrap();
+    }
+
+    fn timing_mark_reply(&mut self) {
+        if let Some(latency) = self.latency.lock().unwrap().received() {
+            debug!("TIMING-MARK round trip: {:?}", latency);
+            self.main_writer.send(Event::Latency(latency)).unwrap();
+        }
+    }
+
+    fn send_mxp_responses(&mut self, responses: Vec<String>) {
+        if let Ok(mut parser) = self.parser.lock() {
+            for response in responses {
+                debug!("MXP response: {:?}", response);
+                if let TelnetEvents::DataSend(data) = parser.send_text(&response) {
+                    self.main_writer.send(Event::ServerSend(data)).unwrap();
+                }
+            }
+        }
+    }
+
     pub fn handle_prompt(&mut self) {
         if self.mode == TelnetMode::UnterminatedPrompt {
             if let Ok(mut output_buffer) = self.output_buffer.lock() {
@@ -145,3 +288,32 @@ impl TelnetHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod telnet_tests {
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    use crate::session::SessionBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_mccp3_starts_once() {
+        let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
+        let (timer_writer, _timer_reader) = channel();
+        let session = SessionBuilder::new()
+            .main_writer(writer)
+            .timer_writer(timer_writer)
+            .screen_dimensions((80, 80))
+            .build();
+        let mut handler = TelnetHandler::new(session);
+        handler.parse(&[cmd::IAC, cmd::WILL, opt::MCCP3]);
+        handler.parse(&[cmd::IAC, cmd::WONT, opt::MCCP3]);
+        handler.parse(&[cmd::IAC, cmd::WILL, opt::MCCP3]);
+        let starts = reader
+            .try_iter()
+            .filter(|event| matches!(event, Event::ServerSend(data) if data[..] == MCCP3_START))
+            .count();
+        assert_eq!(starts, 1);
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    collections::BTreeSet,
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{sync_channel, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use libtelnet_rs::{
+    bytes::Bytes,
+    events::TelnetEvents,
+    telnet::{op_command as cmd, op_option as opt},
+    Parser,
+};
+use log::debug;
+use ring::constant_time::verify_slices_are_equal;
+
+use crate::{event::Event, model::Line};
+
+/// How often the listener checks if sharing has been stopped
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// Clients that can't keep up for this long are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How many chunks of output may wait for a client before it is dropped
+const CLIENT_QUEUE: usize = 1024;
+
+static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Where and how a session is shared with other telnet clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareOptions {
+    pub addr: String,
+    pub port: u16,
+    pub password: Option<String>,
+}
+
+impl ShareOptions {
+    pub fn new(port: u16) -> Self {
+        Self {
+            addr: "127.0.0.1".to_string(),
+            port,
+            password: None,
+        }
+    }
+}
+
+struct ProxyClient {
+    id: usize,
+    stream: TcpStream,
+    output: SyncSender<Bytes>,
+}
+
+impl ProxyClient {
+    /// Writes to the client on its own thread so a slow client can't hold up
+    /// the others
+    fn new(id: usize, stream: TcpStream) -> Option<Self> {
+        let mut writer = stream.try_clone().ok()?;
+        let (output, queue) = sync_channel::<Bytes>(CLIENT_QUEUE);
+        thread::Builder::new()
+            .name("share-writer-thread".to_string())
+            .spawn(move || {
+                while let Ok(data) = queue.recv() {
+                    if writer.write_all(&data).is_err() {
+                        break;
+                    }
+                }
+                writer.shutdown(Shutdown::Both).ok();
+            })
+            .ok()?;
+        Some(Self { id, stream, output })
+    }
+
+    /// Queues data for the client, fails if the client has fallen behind
+    fn send(&self, data: Bytes) -> bool {
+        if self.output.try_send(data).is_ok() {
+            true
+        } else {
+            self.stream.shutdown(Shutdown::Both).ok();
+            false
+        }
+    }
+}
+
+/// Mirrors the telnet stream of a session to attached clients
+#[derive(Default)]
+pub struct TelnetProxy {
+    clients: Vec<ProxyClient>,
+    /// WILL and DO negotiations from the server that are still in effect
+    negotiated: BTreeSet<(u8, u8)>,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+/// Options that only make sense between the server and us
+fn is_private(option: u8) -> bool {
+    matches!(option, opt::MCCP2 | opt::MCCP3 | opt::TM)
+}
+
+impl TelnetProxy {
+    /// Sends what was received from the server on to the attached clients
+    pub fn forward(&mut self, event: &TelnetEvents) {
+        let data: Bytes = match event {
+            TelnetEvents::IAC(iac) => (*iac).into(),
+            TelnetEvents::Negotiation(neg) if !is_private(neg.option) => {
+                match neg.command {
+                    cmd::WILL | cmd::DO => self.negotiated.insert((neg.command, neg.option)),
+                    cmd::WONT => self.negotiated.remove(&(cmd::WILL, neg.option)),
+                    cmd::DONT => self.negotiated.remove(&(cmd::DO, neg.option)),
+                    _ => false,
+                };
+                (*neg).into()
+            }
+            TelnetEvents::Subnegotiation(sub) if !is_private(sub.option) => sub.clone().into(),
+            TelnetEvents::DataReceive(data) => Parser::escape_iac(data.clone()),
+            _ => return,
+        };
+        self.clients.retain(|client| client.send(data.clone()));
+    }
+
+    /// Forgets the negotiations of a connection that has ended
+    pub fn reset(&mut self) {
+        self.negotiated.clear();
+    }
+
+    pub fn is_sharing(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    /// Replays the negotiations so far to a new client and starts mirroring
+    fn attach(&mut self, id: usize, stream: TcpStream) {
+        let replay: Vec<u8> = self
+            .negotiated
+            .iter()
+            .flat_map(|(command, option)| [cmd::IAC, *command, *option])
+            .collect();
+        if let Some(client) = ProxyClient::new(id, stream) {
+            if client.send(replay.into()) {
+                self.clients.push(client);
+            }
+        }
+    }
+
+    fn detach(&mut self, id: usize) {
+        self.clients.retain(|client| client.id != id);
+    }
+
+    /// Stops accepting clients and disconnects the attached ones once
+    /// their queued output is written
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        self.clients.clear();
+    }
+}
+
+/// Turns what a client sends into lines of input, dropping telnet commands
+#[derive(Default)]
+struct InputFilter {
+    line: Vec<u8>,
+    state: FilterState,
+}
+
+#[derive(Default, PartialEq, Eq)]
+enum FilterState {
+    #[default]
+    Data,
+    Iac,
+    Negotiation,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+impl InputFilter {
+    fn feed(&mut self, data: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+        for byte in data {
+            self.state = match self.state {
+                FilterState::Data if *byte == cmd::IAC => FilterState::Iac,
+                FilterState::Data => {
+                    match byte {
+                        b'\n' => {
+                            lines.push(String::from_utf8_lossy(&self.line).to_string());
+                            self.line.clear();
+                        }
+                        b'\r' | b'\0' => {}
+                        _ => self.line.push(*byte),
+                    }
+                    FilterState::Data
+                }
+                FilterState::Iac => match *byte {
+                    cmd::IAC => {
+                        self.line.push(cmd::IAC);
+                        FilterState::Data
+                    }
+                    cmd::WILL | cmd::WONT | cmd::DO | cmd::DONT => FilterState::Negotiation,
+                    cmd::SB => FilterState::Subnegotiation,
+                    _ => FilterState::Data,
+                },
+                FilterState::Negotiation => FilterState::Data,
+                FilterState::Subnegotiation if *byte == cmd::IAC => FilterState::SubnegotiationIac,
+                FilterState::Subnegotiation => FilterState::Subnegotiation,
+                FilterState::SubnegotiationIac if *byte == cmd::SE => FilterState::Data,
+                FilterState::SubnegotiationIac => FilterState::Subnegotiation,
+            };
+        }
+        lines
+    }
+}
+
+fn authenticate(stream: &mut TcpStream, filter: &mut InputFilter, password: &str) -> bool {
+    // Ask the client not to echo what is typed while the password is entered
+    let mut prompt = vec![cmd::IAC, cmd::WILL, opt::ECHO];
+    prompt.extend(b"Password: ");
+    if stream.write_all(&prompt).is_err() {
+        return false;
+    }
+    let mut buffer = [0; 1024];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return false,
+            Ok(n) => {
+                if let Some(line) = filter.feed(&buffer[..n]).first() {
+                    let accepted =
+                        verify_slices_are_equal(line.as_bytes(), password.as_bytes()).is_ok();
+                    let mut reply = b"\r\n".to_vec();
+                    if !accepted {
+                        reply.extend(b"Wrong password\r\n");
+                    }
+                    reply.extend([cmd::IAC, cmd::WONT, opt::ECHO]);
+                    return stream.write_all(&reply).is_ok() && accepted;
+                }
+            }
+        }
+    }
+}
+
+fn handle_client(
+    proxy: Arc<Mutex<TelnetProxy>>,
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    password: Option<String>,
+    writer: Sender<Event>,
+) {
+    let mut filter = InputFilter::default();
+    if let Some(password) = password.filter(|password| !password.is_empty()) {
+        if !authenticate(&mut stream, &mut filter, &password) {
+            stream.shutdown(Shutdown::Both).ok();
+            return;
+        }
+    }
+    let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+    match stream.try_clone() {
+        Ok(output) => proxy.lock().unwrap().attach(id, output),
+        Err(_) => return,
+    }
+    writer
+        .send(Event::Info(format!("Telnet client attached from {addr}")))
+        .ok();
+
+    let mut buffer = [0; 1024];
+    while let Ok(n) = stream.read(&mut buffer) {
+        if n == 0 {
+            break;
+        }
+        for input in filter.feed(&buffer[..n]) {
+            let mut line = Line::from(input);
+            line.flags.source = Some("proxy".to_string());
+            // Attached clients may only talk to the server, never to our scripts
+            line.flags.bypass_script = true;
+            if writer.send(Event::ServerInput(line)).is_err() {
+                break;
+            }
+        }
+    }
+    proxy.lock().unwrap().detach(id);
+    writer
+        .send(Event::Info(format!("Telnet client detached from {addr}")))
+        .ok();
+}
+
+/// Starts accepting telnet clients that mirror the session. Returns the
+/// address that is listened on.
+pub fn start_sharing(
+    proxy: Arc<Mutex<TelnetProxy>>,
+    options: ShareOptions,
+    writer: Sender<Event>,
+) -> Result<SocketAddr> {
+    let has_password = options.password.as_ref().is_some_and(|p| !p.is_empty());
+    let local_only = (options.addr.as_str(), options.port)
+        .to_socket_addrs()?
+        .all(|addr| addr.ip().is_loopback());
+    if !has_password && !local_only {
+        bail!("A password is required to share on a public address");
+    }
+    let listener = TcpListener::bind((options.addr.as_str(), options.port))?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut proxy = proxy.lock().unwrap();
+        proxy.stop();
+        proxy.stop = Some(stop.clone());
+    }
+    thread::Builder::new()
+        .name("share-thread".to_string())
+        .spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        debug!("Telnet client connected from {}", addr);
+                        if stream.set_nonblocking(false).is_err()
+                            || stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+                        {
+                            continue;
+                        }
+                        let proxy = proxy.clone();
+                        let password = options.password.clone();
+                        let writer = writer.clone();
+                        thread::Builder::new()
+                            .name("share-client-thread".to_string())
+                            .spawn(move || handle_client(proxy, stream, addr, password, writer))
+                            .unwrap();
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL)
+                    }
+                    Err(err) => {
+                        debug!("Sharing stopped: {}", err);
+                        break;
+                    }
+                }
+            }
+        })
+        .unwrap();
+    Ok(local_addr)
+}
+
+#[cfg(test)]
+mod test_telnet_proxy {
+    use libtelnet_rs::events::{TelnetNegotiation, TelnetSubnegotiation};
+
+    use super::*;
+
+    #[test]
+    fn test_input_filter() {
+        let mut filter = InputFilter::default();
+        assert!(filter.feed(&[cmd::IAC, cmd::DO, opt::GMCP]).is_empty());
+        assert_eq!(filter.feed(b"look\r\nsay h"), vec!["look"]);
+        let mut data = vec![cmd::IAC, cmd::SB, opt::NAWS, 0, 80, cmd::IAC, cmd::IAC];
+        data.extend([0, 24, cmd::IAC, cmd::SE]);
+        data.extend(b"i\r\0\n");
+        assert_eq!(filter.feed(&data), vec!["say hi"]);
+        assert_eq!(filter.feed(&[cmd::IAC, cmd::IAC, b'\n']), vec!["\u{fffd}"]);
+    }
+
+    #[test]
+    fn test_forward() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut proxy = TelnetProxy::default();
+        let negotiation =
+            |command, option| TelnetEvents::Negotiation(TelnetNegotiation::new(command, option));
+        proxy.forward(&negotiation(cmd::WILL, opt::GMCP));
+        proxy.forward(&negotiation(cmd::WILL, opt::MCCP2));
+        proxy.forward(&negotiation(cmd::DO, opt::NAWS));
+        proxy.forward(&negotiation(cmd::WILL, opt::ECHO));
+        proxy.forward(&negotiation(cmd::WONT, opt::ECHO));
+        proxy.attach(1, stream);
+
+        proxy.forward(&TelnetEvents::Subnegotiation(TelnetSubnegotiation::new(
+            opt::MCCP2,
+            Bytes::new(),
+        )));
+        proxy.forward(&TelnetEvents::DataReceive(Bytes::from_static(
+            b"Hello\xff\r\n",
+        )));
+        proxy.stop();
+
+        let mut received = vec![];
+        (&client).read_to_end(&mut received).unwrap();
+        let mut expected = vec![cmd::IAC, cmd::WILL, opt::GMCP];
+        expected.extend([cmd::IAC, cmd::DO, opt::NAWS]);
+        expected.extend(b"Hello\xff\xff\r\n");
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_public_share_requires_password() {
+        let (writer, _reader) = std::sync::mpsc::channel();
+        let proxy = Arc::new(Mutex::new(TelnetProxy::default()));
+        let options = ShareOptions {
+            addr: "0.0.0.0".to_string(),
+            ..ShareOptions::new(0)
+        };
+        assert!(start_sharing(proxy.clone(), options, writer).is_err());
+        assert!(!proxy.lock().unwrap().is_sharing());
+    }
+}
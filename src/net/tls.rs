@@ -1,9 +1,20 @@
+use crate::io::SaveData;
+use crate::model::CertPins;
 use crate::net::RwStream;
-use anyhow::Result;
-use rustls::{ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, StreamOwned};
+use crate::tools::util::expand_tilde;
+use anyhow::{bail, Context, Result};
+use ring::digest;
+use rustls::client::ServerCertVerifier;
+use rustls::{
+    AlertDescription, Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, PrivateKey,
+    RootCertStore, StreamOwned,
+};
 use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
 use std::net::TcpStream;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Indicates a user's preference for certificate validation.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -14,6 +25,9 @@ pub enum CertificateValidation {
     /// DANGER: Certificate validation is **not** performed and connections will be vulnerable
     /// to person-in the-middle attacks and tampering.
     DangerousDisabled,
+    /// The certificate presented on the first connection is trusted and its fingerprint
+    /// recorded. Later connections are refused if the server presents a different certificate.
+    TrustOnFirstUse,
 }
 
 impl Display for CertificateValidation {
@@ -22,8 +36,9 @@ impl Display for CertificateValidation {
             f,
             "{}",
             match self {
-                CertificateValidation::Enabled => true,
-                CertificateValidation::DangerousDisabled => false,
+                CertificateValidation::Enabled => "true",
+                CertificateValidation::DangerousDisabled => "false",
+                CertificateValidation::TrustOnFirstUse => "pinned",
             }
         )
     }
@@ -38,6 +53,93 @@ impl From<bool> for CertificateValidation {
     }
 }
 
+/// A PEM encoded certificate chain and private key presented to servers that
+/// authenticate clients by certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Path to the PEM certificate chain
+    pub cert: String,
+    /// Path to the PEM private key. The key is read from `cert` when not set.
+    pub key: Option<String>,
+}
+
+impl ClientCertificate {
+    fn load(&self) -> Result<(Vec<Certificate>, PrivateKey)> {
+        let cert_path = expand_tilde(&self.cert).to_string();
+        let key_path = match &self.key {
+            Some(key) => expand_tilde(key).to_string(),
+            None => cert_path.clone(),
+        };
+
+        let file = File::open(&cert_path)
+            .with_context(|| format!("Unable to open client certificate {cert_path}"))?;
+        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut BufReader::new(file))
+            .with_context(|| format!("Unable to read client certificate {cert_path}"))?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+        if certs.is_empty() {
+            bail!("No certificate found in {}", cert_path);
+        }
+
+        let file = File::open(&key_path)
+            .with_context(|| format!("Unable to open client key {key_path}"))?;
+        let mut reader = BufReader::new(file);
+        loop {
+            match rustls_pemfile::read_one(&mut reader)
+                .with_context(|| format!("Unable to read client key {key_path}"))?
+            {
+                Some(rustls_pemfile::Item::RSAKey(key))
+                | Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::ECKey(key)) => return Ok((certs, PrivateKey(key))),
+                Some(_) => {}
+                None => bail!("No private key found in {}", key_path),
+            }
+        }
+    }
+}
+
+/// The SHA-256 fingerprint of a certificate as colon separated hex, eg. `AB:CD:...`
+pub fn fingerprint(cert: &Certificate) -> String {
+    digest::digest(&digest::SHA256, &cert.0)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Describes a TLS failure in terms a user can act on.
+pub fn describe_tls_error(err: &rustls::Error) -> String {
+    match err {
+        rustls::Error::AlertReceived(
+            AlertDescription::CertificateRequired
+            | AlertDescription::BadCertificate
+            | AlertDescription::UnsupportedCertificate
+            | AlertDescription::CertificateRevoked
+            | AlertDescription::CertificateExpired
+            | AlertDescription::CertificateUnknown
+            | AlertDescription::UnknownCA
+            | AlertDescription::AccessDenied,
+        ) => format!("{err} (the server rejected the client certificate)"),
+        rustls::Error::InvalidCertificate(_) => {
+            format!("{err} (disable certificate verification to connect anyway)")
+        }
+        _ => err.to_string(),
+    }
+}
+
+/// Describes an io error, unwrapping TLS failures where present.
+pub fn describe_io_error(err: &std::io::Error) -> String {
+    match err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+        Some(tls_err) => format!("TLS error: {}", describe_tls_error(tls_err)),
+        None => err.to_string(),
+    }
+}
+
 /// TlsStream is an alias for a read/write stream over an owned TLS client connection stream
 /// using a TCP transport.
 pub(super) type TlsStream = RwStream<StreamOwned<ClientConnection, TcpStream>>;
@@ -51,12 +153,57 @@ impl TlsStream {
     /// ## DANGER
     /// If the `verify_cert` bool is set to false no certificate verification is performed and
     /// the connection is vulnerable to person-in-the-middle attacks and tampering.
+    ///
+    /// With [CertificateValidation::TrustOnFirstUse] the certificate is instead checked against
+    /// the fingerprint pinned for `host:port`, pinning it if there is none yet.
     pub(super) fn tls_init(
         stream: TcpStream,
         host: &str,
+        port: u16,
         validation: CertificateValidation,
+        client_cert: Option<&ClientCertificate>,
     ) -> Result<TlsStream> {
-        Self::tls_init_with_roots(stream, host, validation, Self::default_root_certs())
+        match validation {
+            CertificateValidation::TrustOnFirstUse => {
+                let mut pins = CertPins::load();
+                let pinned = pins.get(host, port).cloned();
+                let verifier = Arc::new(tofu::PinnedCertVerifier::new(pinned.clone()));
+                let result = Self::tls_init_with_verifier(
+                    stream,
+                    host,
+                    client_cert,
+                    Self::default_root_certs(),
+                    Some(verifier.clone()),
+                );
+                match (pinned, verifier.seen()) {
+                    (Some(pinned), Some(seen)) if pinned != seen => bail!(
+                        "WARNING: The certificate of {}:{} has CHANGED since it was pinned! \
+                        Expected {} but the server presented {}. Someone could be intercepting \
+                        the connection. If the server replaced its certificate, forget the old \
+                        pin with '/forget_pin {} {}' and connect again.",
+                        host,
+                        port,
+                        pinned,
+                        seen,
+                        host,
+                        port
+                    ),
+                    (None, Some(seen)) if result.is_ok() => {
+                        pins.insert(host, port, &seen);
+                        pins.save();
+                    }
+                    _ => {}
+                }
+                result
+            }
+            _ => Self::tls_init_with_roots(
+                stream,
+                host,
+                validation,
+                client_cert,
+                Self::default_root_certs(),
+            ),
+        }
     }
 
     // tls_init, but also accepts a RootCertStore. Presently this is only used by tests to
@@ -65,21 +212,64 @@ impl TlsStream {
         stream: TcpStream,
         host: &str,
         validation: CertificateValidation,
+        client_cert: Option<&ClientCertificate>,
         roots: RootCertStore,
     ) -> Result<TlsStream> {
-        let mut config = ClientConfig::builder()
+        let verifier: Option<Arc<dyn ServerCertVerifier>> = match validation {
+            CertificateValidation::DangerousDisabled => {
+                Some(Arc::new(danger::NoCertificateVerification {}))
+            }
+            _ => None,
+        };
+        Self::tls_init_with_verifier(stream, host, client_cert, roots, verifier)
+    }
+
+    // Establishes the TLS session, replacing the webpki certificate validation with the
+    // provided verifier if any.
+    fn tls_init_with_verifier(
+        mut stream: TcpStream,
+        host: &str,
+        client_cert: Option<&ClientCertificate>,
+        roots: RootCertStore,
+        verifier: Option<Arc<dyn ServerCertVerifier>>,
+    ) -> Result<TlsStream> {
+        let builder = ClientConfig::builder()
             .with_safe_defaults()
-            .with_root_certificates(roots)
-            .with_no_client_auth();
+            .with_root_certificates(roots);
+        let mut config = match client_cert {
+            Some(client_cert) => {
+                let (certs, key) = client_cert.load()?;
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .context("Invalid client certificate")?
+            }
+            None => builder.with_no_client_auth(),
+        };
 
-        if let CertificateValidation::DangerousDisabled = validation {
-            config
-                .dangerous()
-                .set_certificate_verifier(Arc::new(danger::NoCertificateVerification {}));
+        if let Some(verifier) = verifier {
+            config.dangerous().set_certificate_verifier(verifier);
         };
 
         let server_name = host.try_into()?;
-        let conn = ClientConnection::new(Arc::new(config), server_name)?;
+        let mut conn = ClientConnection::new(Arc::new(config), server_name)?;
+
+        // Complete the handshake up front so failures are reported when connecting
+        // rather than as a dropped connection.
+        stream.set_read_timeout(Some(Duration::new(10, 0)))?;
+        while conn.is_handshaking() {
+            if let Err(err) = conn.complete_io(&mut stream) {
+                let reason = match err
+                    .get_ref()
+                    .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+                {
+                    Some(tls_err) => describe_tls_error(tls_err),
+                    None => err.to_string(),
+                };
+                bail!("TLS handshake with {} failed: {}", host, reason);
+            }
+        }
+        stream.set_read_timeout(None)?;
+
         Ok(RwStream::new(StreamOwned::new(conn, stream)))
     }
 
@@ -119,14 +309,68 @@ mod danger {
     }
 }
 
+/// Trust on first use.
+mod tofu {
+    use super::fingerprint;
+    use rustls::{client, Certificate, CertificateError, ServerName};
+    use std::sync::Mutex;
+
+    /// PinnedCertVerifier accepts any certificate matching the pinned fingerprint, or any
+    /// certificate at all when nothing is pinned yet. The fingerprint of the presented
+    /// certificate is kept so the caller can pin it or report a mismatch.
+    pub struct PinnedCertVerifier {
+        pinned: Option<String>,
+        seen: Mutex<Option<String>>,
+    }
+
+    impl PinnedCertVerifier {
+        pub fn new(pinned: Option<String>) -> Self {
+            Self {
+                pinned,
+                seen: Mutex::new(None),
+            }
+        }
+
+        /// The fingerprint of the certificate presented by the server
+        pub fn seen(&self) -> Option<String> {
+            self.seen.lock().unwrap().clone()
+        }
+    }
+
+    impl client::ServerCertVerifier for PinnedCertVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp: &[u8],
+            _now: std::time::SystemTime,
+        ) -> Result<client::ServerCertVerified, rustls::Error> {
+            let seen = fingerprint(end_entity);
+            let matches = self.pinned.as_ref().is_none_or(|pinned| *pinned == seen);
+            self.seen.lock().unwrap().replace(seen);
+            if matches {
+                Ok(client::ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_tls {
-    use crate::net::tls::TlsStream;
+    use crate::net::tls::{
+        describe_io_error, fingerprint, tofu::PinnedCertVerifier, ClientCertificate, TlsStream,
+    };
     use crate::net::CertificateValidation;
     use log::debug;
+    use rustls::server::AllowAnyAuthenticatedClient;
     use rustls::{
-        Certificate, CertificateError, Error::InvalidCertificate, PrivateKey, RootCertStore,
-        ServerConfig, ServerConnection, StreamOwned,
+        Certificate, PrivateKey, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
     };
     use std::io::{BufReader, Read};
     use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
@@ -138,6 +382,8 @@ mod test_tls {
     const TEST_SERVER_CERTS: &str = "tests/certs/localhost/cert.pem";
     const TEST_SERVER_KEY: &str = "tests/certs/localhost/key.pem";
     const TEST_CA_CERTS: &str = "tests/certs/minica.pem";
+    const TEST_CLIENT_CERTS: &str = "tests/certs/client/cert.pem";
+    const TEST_CLIENT_KEY: &str = "tests/certs/client/key.pem";
 
     fn load_certs(filename: &str) -> Vec<Certificate> {
         let certfile = fs::File::open(filename).expect("cannot open certificate file");
@@ -176,13 +422,28 @@ mod test_tls {
     fn test_server(addr: SocketAddr) -> (SocketAddr, JoinHandle<()>) {
         let cert_chain = load_certs(TEST_SERVER_CERTS);
         let priv_key = load_private_key(TEST_SERVER_KEY);
-        let config = Arc::new(
-            ServerConfig::builder()
-                .with_safe_defaults()
-                .with_no_client_auth()
-                .with_single_cert(cert_chain, priv_key)
-                .unwrap(),
-        );
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, priv_key)
+            .unwrap();
+        spawn_server(addr, Arc::new(config))
+    }
+
+    // A test server that only accepts clients presenting a certificate issued by the test CA.
+    fn client_auth_server(addr: SocketAddr) -> (SocketAddr, JoinHandle<()>) {
+        let cert_chain = load_certs(TEST_SERVER_CERTS);
+        let priv_key = load_private_key(TEST_SERVER_KEY);
+        let verifier = AllowAnyAuthenticatedClient::new(test_ca_roots());
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(Arc::new(verifier))
+            .with_single_cert(cert_chain, priv_key)
+            .unwrap();
+        spawn_server(addr, Arc::new(config))
+    }
+
+    fn spawn_server(addr: SocketAddr, config: Arc<ServerConfig>) -> (SocketAddr, JoinHandle<()>) {
         let listener = TcpListener::bind(addr).expect("cannot listen on port");
         let bound_addr = listener.local_addr().unwrap();
 
@@ -246,6 +507,7 @@ mod test_tls {
             connect_to_server(bound_addr),
             "localhost",
             CertificateValidation::Enabled,
+            None,
             test_ca_roots(),
         )
         .unwrap();
@@ -278,32 +540,25 @@ mod test_tls {
         debug!("test server listening at {}", bound_addr);
 
         // Setup a TLS stream to the server.  We enable certificate validation but *don't* provide
-        // the test CA roots. This should cause the connection attempt to fail when we try to write
-        // as the server's certificate isn't issued by a CA we know about.
+        // the test CA roots. This should cause the handshake to fail as the server's certificate
+        // isn't issued by a CA we know about.
         debug!("connecting to {}", bound_addr);
-        let tls_stream = TlsStream::tls_init(
+        let res = TlsStream::tls_init(
             connect_to_server(bound_addr),
             "localhost",
+            bound_addr.port(),
             CertificateValidation::Enabled,
-        )
-        .unwrap();
-
-        // Write a message so that we perform the full TLS handshake.
-        debug!("writing message");
-        let mut out_writer = tls_stream.output_stream.lock().unwrap();
-        let res = out_writer.write("Hey!!!!".as_ref());
+            None,
+        );
 
-        // The write should have errored because of an unknown issuer certificate error.
+        // The handshake should have errored because of an unknown issuer certificate error.
         match res {
             Ok(_) => panic!("did not error from invalid certificate"),
             Err(e) => {
-                let tls_err = e.into_inner().unwrap().downcast::<rustls::Error>().unwrap();
-
-                debug!("tls_err: {:?}", tls_err);
-                assert_eq!(
-                    *tls_err,
-                    InvalidCertificate(CertificateError::UnknownIssuer)
-                );
+                debug!("tls_err: {:?}", e);
+                assert!(e.to_string().starts_with(
+                    "TLS handshake with localhost failed: invalid peer certificate: UnknownIssuer"
+                ));
             }
         }
     }
@@ -327,7 +582,9 @@ mod test_tls {
         let tls_stream = TlsStream::tls_init(
             connect_to_server(bound_addr),
             "localhost",
+            bound_addr.port(),
             CertificateValidation::DangerousDisabled,
+            None,
         )
         .unwrap();
 
@@ -346,4 +603,123 @@ mod test_tls {
         server_handle.join().unwrap();
         debug!("all done!");
     }
+
+    #[test]
+    /// Test that a client certificate is presented to servers that require one.
+    fn test_tls_init_client_cert() {
+        let _ = env_logger::try_init();
+
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (bound_addr, server_handle) = client_auth_server(addr);
+        debug!("test server listening at {}", bound_addr);
+
+        let client_cert = ClientCertificate {
+            cert: TEST_CLIENT_CERTS.to_string(),
+            key: Some(TEST_CLIENT_KEY.to_string()),
+        };
+        let tls_stream = TlsStream::tls_init(
+            connect_to_server(bound_addr),
+            "localhost",
+            bound_addr.port(),
+            CertificateValidation::DangerousDisabled,
+            Some(&client_cert),
+        )
+        .unwrap();
+
+        {
+            let mut out_writer = tls_stream.output_stream.lock().unwrap();
+            out_writer.write_all("Hey!!!!".as_ref()).unwrap();
+        }
+
+        tls_stream.inner().sock.shutdown(Shutdown::Both).unwrap();
+        drop(tls_stream);
+        server_handle.join().unwrap();
+    }
+
+    #[test]
+    /// Test that a server rejecting the connection for lack of a client certificate is reported.
+    fn test_tls_init_client_cert_required() {
+        let _ = env_logger::try_init();
+
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let (bound_addr, _) = client_auth_server(addr);
+
+        // With TLS 1.3 the server rejects the client after the handshake has completed on our
+        // side, so the rejection may only surface on the first read.
+        let err = match TlsStream::tls_init(
+            connect_to_server(bound_addr),
+            "localhost",
+            bound_addr.port(),
+            CertificateValidation::DangerousDisabled,
+            None,
+        ) {
+            Ok(tls_stream) => {
+                let mut buf = [0u8; 16];
+                let mut input = tls_stream.input_stream.lock().unwrap();
+                match input.read(&mut buf) {
+                    Ok(_) => panic!("server accepted a client without certificate"),
+                    Err(e) => describe_io_error(&e),
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        debug!("tls_err: {}", err);
+        assert!(err.ends_with("(the server rejected the client certificate)"));
+    }
+
+    #[test]
+    fn test_client_cert_load_errors() {
+        let missing = ClientCertificate {
+            cert: "tests/certs/client/missing.pem".to_string(),
+            key: None,
+        };
+        assert!(missing
+            .load()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unable to open client certificate tests/certs/client/missing.pem"));
+
+        let no_key = ClientCertificate {
+            cert: TEST_CLIENT_CERTS.to_string(),
+            key: None,
+        };
+        assert_eq!(
+            no_key.load().unwrap_err().to_string(),
+            format!("No private key found in {TEST_CLIENT_CERTS}")
+        );
+
+        let valid = ClientCertificate {
+            cert: TEST_CLIENT_CERTS.to_string(),
+            key: Some(TEST_CLIENT_KEY.to_string()),
+        };
+        let (certs, _) = valid.load().unwrap();
+        assert_eq!(certs.len(), 1);
+    }
+
+    #[test]
+    /// Test that trust-on-first-use accepts an unknown certificate and later only the same one.
+    fn test_tls_init_pinned() {
+        let _ = env_logger::try_init();
+
+        let expected = fingerprint(&load_certs(TEST_SERVER_CERTS)[0]);
+        for (pinned, ok) in [
+            (None, true),
+            (Some(expected.clone()), true),
+            (Some("AB:CD".to_string()), false),
+        ] {
+            let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+            let (bound_addr, _) = test_server(addr);
+
+            let verifier = Arc::new(PinnedCertVerifier::new(pinned));
+            let res = TlsStream::tls_init_with_verifier(
+                connect_to_server(bound_addr),
+                "localhost",
+                None,
+                RootCertStore::empty(),
+                Some(verifier.clone()),
+            );
+            assert_eq!(res.is_ok(), ok);
+            assert_eq!(verifier.seen(), Some(expected.clone()));
+        }
+    }
 }
@@ -6,9 +6,12 @@ use std::{
 use anyhow::{bail, Result};
 
 pub fn open_tcp_stream(host: &str, port: u16) -> Result<TcpStream> {
+    open_tcp_stream_timeout(host, port, Duration::new(3, 0))
+}
+
+pub fn open_tcp_stream_timeout(host: &str, port: u16, timeout: Duration) -> Result<TcpStream> {
     let mut addr_iter = (host, port).to_socket_addrs()?;
 
-    let timeout = Duration::new(3, 0);
     let stream = addr_iter.find_map(|addr| TcpStream::connect_timeout(&addr, timeout).ok());
 
     if let Some(stream) = stream {
@@ -1,29 +1,43 @@
 use anyhow::Result;
-use libtelnet_rs::{compatibility::CompatibilityTable, telnet::op_option as opt, Parser};
+use libtelnet_rs::{
+    bytes::Bytes, compatibility::CompatibilityTable, telnet::op_option as opt, Parser,
+};
 use log::debug;
-use std::sync::{atomic::AtomicBool, mpsc::Sender, Arc, Mutex};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Sender,
+    Arc, Mutex,
+};
 
 use crate::{
+    audio::MSP,
     event::QuitMethod,
-    io::{LogWriter, Logger},
+    io::{LogWriter, Logger, Recorder, SaveData},
     lua::{LuaScript, LuaScriptBuilder},
+    model::{CertPins, Connection, Profile, Proxy, Settings},
     net::MudConnection,
     net::BUFFER_SIZE,
-    net::{OutputBuffer, TelnetMode},
+    net::{
+        start_sharing, Encoding, Gmcp, Keepalive, LatencyMeter, NewEnviron, OutputBuffer,
+        ReconnectPolicy, SendQueue, ShareOptions, TelnetMode, TelnetProxy, MXP,
+    },
     timer::TimerEvent,
+    tools::util::expand_tilde,
     tts::TTSController,
     ui::CommandBuffer,
     Event,
 };
 
-use crate::net::CertificateValidation;
+use crate::net::{CertificateValidation, ClientCertificate};
 #[cfg(test)]
 use mockall::automock;
 
 #[derive(Clone)]
 pub struct Session {
     pub connection: Arc<Mutex<MudConnection>>,
-    pub gmcp: Arc<AtomicBool>,
+    pub gmcp: Arc<Mutex<Gmcp>>,
+    pub new_environ: Arc<Mutex<NewEnviron>>,
     pub main_writer: Sender<Event>,
     pub timer_writer: Sender<TimerEvent>,
     pub telnet_parser: Arc<Mutex<Parser>>,
@@ -32,24 +46,41 @@ pub struct Session {
     pub save_history: Arc<AtomicBool>,
     pub lua_script: Arc<Mutex<LuaScript>>,
     pub logger: Arc<Mutex<dyn LogWriter + Send>>,
+    pub recorder: Arc<Mutex<Option<Recorder>>>,
     pub tts_ctrl: Arc<Mutex<TTSController>>,
     pub command_buffer: Arc<Mutex<CommandBuffer>>,
     pub echo_input: Arc<AtomicBool>,
+    /// Set while `$EDITOR` has the terminal, pausing the input thread
+    pub external_editor: Arc<AtomicBool>,
+    /// Cleared while another session is on screen
+    pub on_screen: Arc<AtomicBool>,
+    pub reconnect_policy: Arc<Mutex<Option<ReconnectPolicy>>>,
+    pub reconnect_cancel: Arc<Mutex<Option<Sender<()>>>>,
+    pub keepalive: Arc<Mutex<Option<Keepalive>>>,
+    pub latency: Arc<Mutex<LatencyMeter>>,
+    pub send_queue: Arc<Mutex<SendQueue>>,
+    pub telnet_proxy: Arc<Mutex<TelnetProxy>>,
 }
 
 #[cfg_attr(test, automock)]
 impl Session {
-    pub fn connect(
-        &mut self,
-        host: &str,
-        port: u16,
-        tls: bool,
-        tls_validation: CertificateValidation,
-    ) -> bool {
+    pub fn connect(&mut self, info: &Connection) -> bool {
+        let pinning = info.tls && info.pin_cert;
+        let pinned = pinning && CertPins::load().get(&info.host, info.port).is_some();
         let mut connected = false;
         let mut conn_id = 0u16;
+        let encoding = match &info.encoding {
+            Some(label) => Encoding::for_label(label).unwrap_or_else(|| {
+                self.main_writer
+                    .send(Event::Error(format!("Unknown encoding: {label}")))
+                    .unwrap();
+                Encoding::default()
+            }),
+            None => Encoding::default(),
+        };
+        self.set_encoding(encoding);
         if let Ok(mut connection) = self.connection.lock() {
-            connected = match connection.connect(host, port, tls, tls_validation) {
+            connected = match connection.connect(info) {
                 Ok(_) => {
                     conn_id = connection.id;
                     true
@@ -64,8 +95,18 @@ impl Session {
             };
         }
         if connected {
+            if pinning && !pinned {
+                if let Some(fingerprint) = CertPins::load().get(&info.host, info.port) {
+                    self.main_writer
+                        .send(Event::Info(format!(
+                            "Pinned certificate of {}:{} on first use: {}",
+                            info.host, info.port, fingerprint
+                        )))
+                        .unwrap();
+                }
+            }
             self.main_writer
-                .send(Event::StartLogging(host.to_string(), false))
+                .send(Event::StartLogging(info.host.clone(), false))
                 .unwrap();
             self.main_writer.send(Event::Connected(conn_id)).unwrap();
         }
@@ -79,6 +120,11 @@ impl Session {
             if let Ok(mut output_buffer) = self.output_buffer.lock() {
                 output_buffer.clear()
             }
+            self.latency.lock().unwrap().reset();
+            self.send_queue.lock().unwrap().clear();
+            self.telnet_proxy.lock().unwrap().reset();
+            self.gmcp.lock().unwrap().reset();
+            self.new_environ.lock().unwrap().reset();
 
             if let Ok(mut parser) = self.telnet_parser.lock() {
                 parser.options.reset_states();
@@ -95,6 +141,11 @@ impl Session {
                 if let Ok(mut output_buffer) = self.output_buffer.lock() {
                     output_buffer.clear()
                 }
+                self.latency.lock().unwrap().reset();
+                self.send_queue.lock().unwrap().clear();
+                self.telnet_proxy.lock().unwrap().reset();
+                self.gmcp.lock().unwrap().reset();
+                self.new_environ.lock().unwrap().reset();
 
                 if let Ok(mut parser) = self.telnet_parser.lock() {
                     parser.options.reset_states();
@@ -110,6 +161,11 @@ impl Session {
         connection.connected()
     }
 
+    pub fn connection_id(&self) -> u16 {
+        let connection = self.connection.lock().unwrap();
+        connection.id
+    }
+
     pub fn host(&self) -> String {
         let connection = self.connection.lock().unwrap();
         connection.host.clone()
@@ -125,20 +181,90 @@ impl Session {
         connection.tls
     }
 
+    pub fn proxy(&self) -> Option<Proxy> {
+        let connection = self.connection.lock().unwrap();
+        connection.proxy.clone()
+    }
+
+    /// The encoding configured for the current server
+    pub fn encoding(&self) -> Option<String> {
+        let connection = self.connection.lock().unwrap();
+        connection.encoding.clone()
+    }
+
+    pub fn set_encoding(&self, encoding: Encoding) {
+        let info = self
+            .new_environ
+            .lock()
+            .unwrap()
+            .set_charset(encoding.name());
+        self.send_new_environ_info(info);
+        self.output_buffer.lock().unwrap().set_encoding(encoding);
+    }
+
+    /// Tells the server about changed NEW-ENVIRON variables
+    pub fn send_new_environ_info(&self, info: Option<Bytes>) {
+        if let Some(info) = info {
+            self.main_writer
+                .send(Event::ProtoSubnegSend(opt::NEWENVIRON, info))
+                .unwrap();
+        }
+    }
+
+    pub fn client_cert(&self) -> Option<ClientCertificate> {
+        let connection = self.connection.lock().unwrap();
+        connection.client_cert.clone()
+    }
+
     pub fn verify_cert(&self) -> bool {
         let connection = self.connection.lock().unwrap();
         match connection.tls_validation {
             CertificateValidation::Enabled => true,
             CertificateValidation::DangerousDisabled => false,
+            CertificateValidation::TrustOnFirstUse => false,
+        }
+    }
+
+    pub fn pin_cert(&self) -> bool {
+        let connection = self.connection.lock().unwrap();
+        connection.tls_validation == CertificateValidation::TrustOnFirstUse
+    }
+
+    /// The world profile of the current (or last) connection
+    pub fn profile(&self) -> Profile {
+        let connection = self.connection.lock().unwrap();
+        connection.profile.clone()
+    }
+
+    /// Overrides settings with those of the world profile while connected,
+    /// going back to the saved settings otherwise. Sessions in the background
+    /// leave the settings to the one on screen.
+    pub fn apply_profile_settings(&self) {
+        if !self.on_screen.load(Ordering::Relaxed) {
+            return;
+        }
+        let overrides = if self.connected() {
+            self.profile().settings
+        } else {
+            Default::default()
+        };
+        for (key, value) in Settings::set_overrides(overrides) {
+            self.main_writer
+                .send(Event::SettingChanged(key, value))
+                .unwrap();
         }
     }
 
     pub fn start_logging(&self, host: &str) {
+        let dir = self
+            .profile()
+            .log_dir
+            .map(|dir| PathBuf::from(expand_tilde(&dir).as_ref()));
         if let Ok(mut logger) = self.logger.lock() {
             self.main_writer
                 .send(Event::Info(format!("Started logging for: {host}")))
                 .unwrap();
-            logger.start_logging(host).ok();
+            logger.start_logging(host, dir).ok();
         }
     }
 
@@ -151,10 +277,77 @@ impl Session {
         }
     }
 
+    /// Starts recording the raw data received from the server
+    pub fn start_recording(&self, path: Option<String>) {
+        let path = match path {
+            Some(path) => PathBuf::from(expand_tilde(&path).as_ref()),
+            None => Recorder::default_path(&self.host()),
+        };
+        let event = match Recorder::create(&path) {
+            Ok(recorder) => {
+                self.recorder.lock().unwrap().replace(recorder);
+                Event::Info(format!("Recording to: {}", path.display()))
+            }
+            Err(err) => Event::Error(format!("Unable to record to {}: {}", path.display(), err)),
+        };
+        self.main_writer.send(event).unwrap();
+    }
+
+    pub fn stop_recording(&self) {
+        if let Some(recorder) = self.recorder.lock().unwrap().take() {
+            self.main_writer
+                .send(Event::Info(format!(
+                    "Recording stopped: {}",
+                    recorder.path().display()
+                )))
+                .unwrap();
+        }
+    }
+
+    /// Lets other telnet clients attach to the session, or stops it with None
+    pub fn share(&self, options: Option<ShareOptions>) {
+        let event = match options {
+            Some(options) => {
+                let addr = format!("{}:{}", options.addr, options.port);
+                match start_sharing(self.telnet_proxy.clone(), options, self.main_writer.clone()) {
+                    Ok(addr) => Event::Info(format!("Sharing session on: {addr}")),
+                    Err(err) => Event::Error(format!("Unable to share session on {addr}: {err}")),
+                }
+            }
+            None => {
+                let mut proxy = self.telnet_proxy.lock().unwrap();
+                if !proxy.is_sharing() {
+                    return;
+                }
+                proxy.stop();
+                Event::Info("Stopped sharing session".to_string())
+            }
+        };
+        self.main_writer.send(event).unwrap();
+    }
+
+    /// Aborts a pending automatic reconnect. Returns true if there was one.
+    pub fn cancel_reconnect(&self) -> bool {
+        if let Some(cancel) = self.reconnect_cancel.lock().unwrap().take() {
+            cancel.send(()).is_ok()
+        } else {
+            false
+        }
+    }
+
     pub fn send_event(&mut self, event: Event) {
         self.main_writer.send(event).unwrap();
     }
 
+    /// Tears down a session that is closed while the client keeps running
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.cancel_reconnect();
+        self.telnet_proxy.lock().unwrap().stop();
+        self.try_disconnect();
+        self.timer_writer.send(TimerEvent::Quit)?;
+        Ok(())
+    }
+
     pub fn close(&mut self) -> Result<()> {
         self.try_disconnect();
         self.main_writer.send(Event::Quit(QuitMethod::System))?;
@@ -174,6 +367,7 @@ pub struct SessionBuilder {
     save_history: bool,
     headless: bool,
     echo_input: bool,
+    tts_ctrl: Option<Arc<Mutex<TTSController>>>,
 }
 
 impl SessionBuilder {
@@ -187,6 +381,7 @@ impl SessionBuilder {
             save_history: false,
             headless: false,
             echo_input: true,
+            tts_ctrl: None,
         }
     }
 
@@ -230,6 +425,12 @@ impl SessionBuilder {
         self
     }
 
+    /// Share an existing TTS controller rather than creating a new one
+    pub fn tts_ctrl(mut self, tts_ctrl: Arc<Mutex<TTSController>>) -> Self {
+        self.tts_ctrl = Some(tts_ctrl);
+        self
+    }
+
     pub fn build(self) -> Session {
         let main_writer = self.main_writer.unwrap();
         let timer_writer = self.timer_writer.unwrap();
@@ -238,18 +439,27 @@ impl SessionBuilder {
         let save_history = self.save_history;
         let reader_mode = self.reader_mode;
         let headless = self.headless;
-        let tts_ctrl = Arc::new(Mutex::new(TTSController::new(tts_enabled, headless)));
+        let tts_ctrl = self
+            .tts_ctrl
+            .unwrap_or_else(|| Arc::new(Mutex::new(TTSController::new(tts_enabled, headless))));
         let echo_input = self.echo_input;
 
+        let send_queue = Arc::new(Mutex::new(SendQueue::default()));
+        let gmcp = Arc::new(Mutex::new(Gmcp::default()));
+        let new_environ = Arc::new(Mutex::new(NewEnviron::new(reader_mode, tts_enabled)));
         let lua_builder = LuaScriptBuilder::new(main_writer.clone())
             .dimensions(dimensions)
+            .send_queue(send_queue.clone())
+            .gmcp(gmcp.clone())
+            .new_environ(new_environ.clone())
             .tts_enabled(tts_enabled)
             .reader_mode(reader_mode);
 
         let lua_script = Arc::new(Mutex::new(lua_builder.build()));
         Session {
             connection: Arc::new(Mutex::new(MudConnection::new())),
-            gmcp: Arc::new(AtomicBool::new(false)),
+            gmcp,
+            new_environ,
             main_writer,
             timer_writer,
             telnet_parser: Arc::new(Mutex::new(Parser::with_support_and_capacity(
@@ -263,9 +473,18 @@ impl SessionBuilder {
             save_history: Arc::new(AtomicBool::new(save_history)),
             lua_script: lua_script.clone(),
             logger: Arc::new(Mutex::new(Logger::default())),
+            recorder: Arc::new(Mutex::new(None)),
             tts_ctrl: tts_ctrl.clone(),
             command_buffer: Arc::new(Mutex::new(CommandBuffer::new(tts_ctrl, lua_script))),
             echo_input: Arc::new(AtomicBool::new(echo_input)),
+            external_editor: Arc::new(AtomicBool::new(false)),
+            on_screen: Arc::new(AtomicBool::new(true)),
+            reconnect_policy: Arc::new(Mutex::new(None)),
+            reconnect_cancel: Arc::new(Mutex::new(None)),
+            keepalive: Arc::new(Mutex::new(None)),
+            latency: Arc::new(Mutex::new(LatencyMeter::default())),
+            send_queue,
+            telnet_proxy: Arc::new(Mutex::new(TelnetProxy::default())),
         }
     }
 }
@@ -275,6 +494,11 @@ fn build_compatibility_table() -> CompatibilityTable {
     telnet_compat.support(opt::MCCP2);
     telnet_compat.support(opt::EOR);
     telnet_compat.support(opt::ECHO);
+    telnet_compat.support(opt::GMCP);
+    telnet_compat.support_local(opt::NEWENVIRON);
+    telnet_compat.support(MXP);
+    telnet_compat.support_remote(opt::MCCP3);
+    telnet_compat.support_remote(MSP);
     telnet_compat
 }
 
@@ -324,9 +548,9 @@ mod session_test {
         let mut logger = MockLogWriter::new();
         logger
             .expect_start_logging()
-            .with(eq("mysteryhost"))
+            .with(eq("mysteryhost"), eq(None))
             .times(1)
-            .returning(|_| Ok(()));
+            .returning(|_, _| Ok(()));
         logger.expect_stop_logging().times(1).returning(|| Ok(()));
         session.logger = Arc::new(Mutex::new(logger));
 
@@ -342,6 +566,35 @@ mod session_test {
         );
     }
 
+    #[test]
+    fn test_recording() {
+        let (session, reader, _timer_reader) = build_session();
+        let path = crate::DATA_DIR.join("recordings").join("session.rec");
+        session.start_recording(Some(path.to_str().unwrap().to_string()));
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::Info(format!("Recording to: {}", path.display())))
+        );
+        assert!(session.recorder.lock().unwrap().is_some());
+        session.stop_recording();
+        assert_eq!(
+            reader.recv(),
+            Ok(Event::Info(format!(
+                "Recording stopped: {}",
+                path.display()
+            )))
+        );
+        session.stop_recording();
+        assert!(reader.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let (mut session, _reader, timer_reader) = build_session();
+        session.shutdown().unwrap();
+        assert_eq!(timer_reader.recv(), Ok(TimerEvent::Quit));
+    }
+
     #[test]
     fn test_close() {
         let (mut session, reader, timer_reader) = build_session();
@@ -0,0 +1,474 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::Ordering,
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::{bail, Result};
+use log::debug;
+
+use crate::{
+    event::{BadEventRoutingError, Event, EventHandler},
+    lua::Sessions,
+    session::{Session, SessionBuilder},
+    timer::spawn_timer_thread,
+    ui::{init_completions, HeadlessScreen, SessionTab, UiWrapper, UserInterface},
+    TelnetData,
+};
+
+/// The name of the session created at startup
+pub const MAIN_SESSION: &str = "main";
+
+/// A session along with the screen and connection plumbing that belongs to it
+pub struct SessionSlot {
+    pub id: usize,
+    pub name: String,
+    pub session: Session,
+    pub screen: Box<dyn UserInterface>,
+    pub event_handler: EventHandler,
+    pub transmit_writer: Option<Sender<TelnetData>>,
+    pub activity: bool,
+}
+
+/// Keeps track of all open sessions and which one of them is on screen.
+///
+/// Every session gets its own event channel. Events sent on it reach the main
+/// thread wrapped in `Event::FromSession` so they can be routed back to the
+/// session they came from.
+pub struct SessionManager {
+    slots: Vec<SessionSlot>,
+    active: usize,
+    next_id: usize,
+    main_writer: Sender<Event>,
+    builder: SessionBuilder,
+    active_session: Arc<Mutex<Session>>,
+    headless: bool,
+    scripts: Vec<PathBuf>,
+}
+
+impl SessionManager {
+    pub fn new(
+        main_writer: Sender<Event>,
+        builder: SessionBuilder,
+        headless: bool,
+        scripts: Vec<PathBuf>,
+    ) -> Result<Self> {
+        let session = build_session(0, &main_writer, builder.clone());
+        let screen: Box<dyn UserInterface> = if headless {
+            Box::new(UiWrapper::headless(&session)?)
+        } else {
+            Box::new(UiWrapper::new(&session)?)
+        };
+        let mut manager = Self {
+            slots: vec![],
+            active: 0,
+            next_id: 1,
+            main_writer,
+            // All sessions share the same speech controller
+            builder: builder.tts_ctrl(session.tts_ctrl.clone()),
+            active_session: Arc::new(Mutex::new(session.clone())),
+            headless,
+            scripts,
+        };
+        manager.add_slot(0, MAIN_SESSION, session, screen);
+        manager.update_sessions()?;
+        Ok(manager)
+    }
+
+    /// The session currently on screen, shared with the input thread
+    pub fn active_session(&self) -> Arc<Mutex<Session>> {
+        self.active_session.clone()
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// The position of the session with the given id, if it's still open
+    pub fn index_of(&self, id: usize) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.id == id)
+    }
+
+    pub fn slot_mut(&mut self, index: usize) -> &mut SessionSlot {
+        &mut self.slots[index]
+    }
+
+    pub fn active_slot_mut(&mut self) -> &mut SessionSlot {
+        &mut self.slots[self.active]
+    }
+
+    pub fn tabs(&self) -> Vec<SessionTab> {
+        self.slots
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| SessionTab {
+                name: slot.name.clone(),
+                active: index == self.active,
+                activity: slot.activity,
+            })
+            .collect()
+    }
+
+    /// Handles the events that concern more than the session they were sent from
+    pub fn handle_events(&mut self, event: Event) -> Result<()> {
+        let result = match event {
+            Event::OpenSession(name) => self.open(&name),
+            Event::SwitchSession(target) => match self.find(&target) {
+                Some(index) => self.switch_to(index),
+                None => Err(anyhow::anyhow!("No session named '{}'", target)),
+            },
+            Event::NextSession => self.switch_to((self.active + 1) % self.slots.len()),
+            Event::PrevSession => {
+                self.switch_to((self.active + self.slots.len() - 1) % self.slots.len())
+            }
+            Event::CloseSession(target) => self.close(target.as_deref()),
+            Event::FSEvent(event) => {
+                for slot in &mut self.slots {
+                    if let Ok(script) = slot.session.lua_script.lock() {
+                        script.handle_fs_event(event.clone())?;
+                        script.get_output_lines().iter().for_each(|l| {
+                            slot.screen.print_output(l);
+                        });
+                    }
+                }
+                Ok(())
+            }
+            _ => return Err(BadEventRoutingError.into()),
+        };
+        if let Err(err) = result {
+            self.active_slot_mut().screen.print_error(&err.to_string());
+        }
+        Ok(())
+    }
+
+    /// Flags output arriving in a background session in the top bar
+    pub fn note_activity(&mut self, index: usize, event: &Event) -> Result<()> {
+        let slot = &mut self.slots[index];
+        if index != self.active
+            && !slot.activity
+            && matches!(event, Event::MudOutput(_) | Event::Prompt(_))
+        {
+            slot.activity = true;
+            self.refresh_tabs()?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the screens of all sessions when toggling reader mode
+    pub fn set_reader_mode(&mut self, reader_mode: bool) -> Result<()> {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if let Ok(mut lua) = slot.session.lua_script.lock() {
+                lua.set_reader_mode(reader_mode);
+            }
+            let info = slot
+                .session
+                .new_environ
+                .lock()
+                .unwrap()
+                .set_reader_mode(reader_mode);
+            slot.session.send_new_environ_info(info);
+            let screen = std::mem::replace(&mut slot.screen, Box::new(HeadlessScreen {}));
+            slot.screen = Box::new(UiWrapper::new_from(screen, &slot.session, reader_mode)?);
+            if index != self.active {
+                slot.screen.release_writer();
+            }
+        }
+        self.refresh_tabs()
+    }
+
+    /// Runs the quit handlers of every session and closes them all
+    pub fn close_all(&mut self) -> Result<()> {
+        for slot in &mut self.slots {
+            if let Ok(lua) = slot.session.lua_script.lock() {
+                lua.on_quit();
+                lua.get_output_lines().iter().for_each(|l| {
+                    slot.screen.print_output(l);
+                });
+            }
+        }
+        let screen = &mut self.slots[self.active].screen;
+        screen.flush();
+        screen.reset()?;
+        for slot in &mut self.slots {
+            slot.session.close()?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.parse::<usize>().is_ok() {
+            bail!("Invalid session name: '{}'", name);
+        }
+        if self.slots.iter().any(|slot| slot.name == name) {
+            bail!("A session named '{}' already exists", name);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let session = build_session(id, &self.main_writer, self.builder.clone());
+        let screen: Box<dyn UserInterface> = if self.headless {
+            Box::new(UiWrapper::headless(&session)?)
+        } else {
+            Box::new(UiWrapper::background(&session)?)
+        };
+        debug!("Opening session {}: {}", id, name);
+        self.add_slot(id, name, session, screen);
+        self.switch_to(self.slots.len() - 1)
+    }
+
+    fn close(&mut self, target: Option<&str>) -> Result<()> {
+        let index = match target {
+            Some(target) => match self.find(target) {
+                Some(index) => index,
+                None => bail!("No session named '{}'", target),
+            },
+            None => self.active,
+        };
+        if self.slots.len() == 1 {
+            bail!("Can't close the last session");
+        }
+        if index == self.active {
+            self.switch_to(if index > 0 { index - 1 } else { 1 })?;
+        }
+        let mut slot = self.slots.remove(index);
+        if index < self.active {
+            self.active -= 1;
+        }
+        debug!("Closing session {}: {}", slot.id, slot.name);
+        if let Some(transmit_writer) = &slot.transmit_writer {
+            transmit_writer.send(None).ok();
+        }
+        if let Ok(lua) = slot.session.lua_script.lock() {
+            lua.on_quit();
+        }
+        slot.session.shutdown()?;
+        self.slots[self.active]
+            .screen
+            .print_info(&format!("Closed session: {}", slot.name));
+        self.update_sessions()
+    }
+
+    fn switch_to(&mut self, index: usize) -> Result<()> {
+        if index == self.active {
+            return Ok(());
+        }
+        let writer = self.slots[self.active].screen.release_writer();
+        self.slots[self.active]
+            .session
+            .on_screen
+            .store(false, Ordering::Relaxed);
+        self.active = index;
+        self.slots[index].activity = false;
+        self.update_sessions()?;
+
+        let slot = &mut self.slots[index];
+        *self.active_session.lock().unwrap() = slot.session.clone();
+        slot.session.on_screen.store(true, Ordering::Relaxed);
+        slot.session.apply_profile_settings();
+        slot.screen.acquire_writer(writer)?;
+        if let Ok(mut script) = slot.session.lua_script.lock() {
+            script.set_dimensions((slot.screen.width(), slot.screen.height()));
+        }
+        slot.screen
+            .print_info(&format!("Switched to session: {}", slot.name));
+        Ok(())
+    }
+
+    /// Finds a session by name or by its 1-based position
+    fn find(&self, target: &str) -> Option<usize> {
+        match target.parse::<usize>() {
+            Ok(index) => (1..=self.slots.len()).contains(&index).then_some(index - 1),
+            Err(_) => self.slots.iter().position(|slot| slot.name == target),
+        }
+    }
+
+    fn add_slot(
+        &mut self,
+        id: usize,
+        name: &str,
+        session: Session,
+        screen: Box<dyn UserInterface>,
+    ) {
+        if let Ok(mut buffer) = session.command_buffer.lock() {
+            init_completions(&mut buffer);
+        }
+        for script in &self.scripts {
+            session
+                .main_writer
+                .send(Event::LoadScript(script.to_str().unwrap().to_string()))
+                .ok();
+        }
+        self.slots.push(SessionSlot {
+            id,
+            name: name.to_string(),
+            event_handler: EventHandler::from(&session),
+            session,
+            screen,
+            transmit_writer: None,
+            activity: false,
+        });
+    }
+
+    /// Tells every Lua state and the top bar about the current sessions
+    fn update_sessions(&mut self) -> Result<()> {
+        let names: Vec<String> = self.slots.iter().map(|slot| slot.name.clone()).collect();
+        let active = names[self.active].clone();
+        for slot in &self.slots {
+            if let Ok(mut script) = slot.session.lua_script.lock() {
+                script.set_sessions(Sessions {
+                    name: slot.name.clone(),
+                    active: active.clone(),
+                    names: names.clone(),
+                });
+            }
+        }
+        self.refresh_tabs()
+    }
+
+    fn refresh_tabs(&mut self) -> Result<()> {
+        let tabs = self.tabs();
+        self.slots[self.active].screen.set_sessions(&tabs)
+    }
+}
+
+/// Builds a session whose events reach the main thread tagged with its id
+fn build_session(id: usize, main_writer: &Sender<Event>, builder: SessionBuilder) -> Session {
+    let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
+    let main_writer = main_writer.clone();
+    thread::Builder::new()
+        .name(format!("session-{id}-thread"))
+        .spawn(move || {
+            while let Ok(event) = reader.recv() {
+                if main_writer
+                    .send(Event::FromSession(id, Box::new(event)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        })
+        .unwrap();
+    let timer_writer = spawn_timer_thread(writer.clone());
+    builder
+        .main_writer(writer)
+        .timer_writer(timer_writer)
+        .build()
+}
+
+#[cfg(test)]
+mod test_session_manager {
+    use super::*;
+
+    fn build_manager() -> (SessionManager, Receiver<Event>) {
+        let (writer, reader): (Sender<Event>, Receiver<Event>) = channel();
+        let builder = SessionBuilder::new()
+            .screen_dimensions((80, 80))
+            .headless(true);
+        let manager = SessionManager::new(writer, builder, true, vec![]).unwrap();
+        (manager, reader)
+    }
+
+    fn names(manager: &SessionManager) -> Vec<(String, bool)> {
+        manager
+            .tabs()
+            .into_iter()
+            .map(|tab| (tab.name, tab.active))
+            .collect()
+    }
+
+    #[test]
+    fn test_open_and_switch() {
+        let (mut manager, _reader) = build_manager();
+        manager
+            .handle_events(Event::OpenSession("alt".to_string()))
+            .unwrap();
+        assert_eq!(
+            names(&manager),
+            vec![("main".to_string(), false), ("alt".to_string(), true)]
+        );
+        manager
+            .handle_events(Event::SwitchSession("1".to_string()))
+            .unwrap();
+        assert_eq!(manager.active_index(), 0);
+        manager
+            .handle_events(Event::SwitchSession("alt".to_string()))
+            .unwrap();
+        assert_eq!(manager.active_index(), 1);
+        manager.handle_events(Event::NextSession).unwrap();
+        assert_eq!(manager.active_index(), 0);
+        manager.handle_events(Event::PrevSession).unwrap();
+        assert_eq!(manager.active_index(), 1);
+        assert!(!manager
+            .slot_mut(0)
+            .session
+            .on_screen
+            .load(Ordering::Relaxed));
+        assert!(manager
+            .slot_mut(1)
+            .session
+            .on_screen
+            .load(Ordering::Relaxed));
+
+        // Unknown targets and duplicate names leave things as they were
+        manager
+            .handle_events(Event::SwitchSession("3".to_string()))
+            .unwrap();
+        manager
+            .handle_events(Event::OpenSession("main".to_string()))
+            .unwrap();
+        assert_eq!(manager.active_index(), 1);
+        assert_eq!(manager.tabs().len(), 2);
+
+        let active = manager.active_session();
+        let script = active.lock().unwrap().lua_script.clone();
+        assert!(Arc::ptr_eq(
+            &script,
+            &manager.slot_mut(1).session.lua_script
+        ));
+    }
+
+    #[test]
+    fn test_activity() {
+        let (mut manager, _reader) = build_manager();
+        manager
+            .handle_events(Event::OpenSession("alt".to_string()))
+            .unwrap();
+        let output = Event::MudOutput(crate::model::Line::from("hello"));
+        manager.note_activity(1, &output).unwrap();
+        assert!(!manager.tabs()[1].activity);
+        manager.note_activity(0, &output).unwrap();
+        assert!(manager.tabs()[0].activity);
+        manager.handle_events(Event::PrevSession).unwrap();
+        assert!(!manager.tabs()[0].activity);
+    }
+
+    #[test]
+    fn test_close() {
+        let (mut manager, _reader) = build_manager();
+        manager
+            .handle_events(Event::OpenSession("alt".to_string()))
+            .unwrap();
+        manager
+            .handle_events(Event::OpenSession("bank".to_string()))
+            .unwrap();
+        let id = manager.slot_mut(2).id;
+        manager
+            .handle_events(Event::CloseSession(Some("alt".to_string())))
+            .unwrap();
+        assert_eq!(
+            names(&manager),
+            vec![("main".to_string(), false), ("bank".to_string(), true)]
+        );
+        assert_eq!(manager.index_of(id), Some(1));
+        manager.handle_events(Event::CloseSession(None)).unwrap();
+        assert_eq!(names(&manager), vec![("main".to_string(), true)]);
+        assert_eq!(manager.index_of(id), None);
+
+        manager.handle_events(Event::CloseSession(None)).unwrap();
+        assert_eq!(manager.tabs().len(), 1);
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::tools::util::base64;
+
 pub struct ScrollRegion(pub u16, pub u16);
 impl fmt::Display for ScrollRegion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -20,3 +22,24 @@ impl fmt::Display for DisableOriginMode {
         write!(f, "\x1b[?6l")
     }
 }
+
+/// Copies text to the clipboard of the terminal (OSC 52)
+pub struct Osc52<'a>(pub &'a str);
+impl fmt::Display for Osc52<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1b]52;c;{}\x07", base64(self.0.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod test_ansi {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(Osc52("").to_string(), "\x1b]52;c;\x07");
+        assert_eq!(Osc52("f").to_string(), "\x1b]52;c;Zg==\x07");
+        assert_eq!(Osc52("fo").to_string(), "\x1b]52;c;Zm8=\x07");
+        assert_eq!(Osc52("foobar").to_string(), "\x1b]52;c;Zm9vYmFy\x07");
+    }
+}
@@ -1,11 +1,14 @@
 use crate::event::QuitMethod;
-use crate::model::{Completions, Line, PromptMask, Servers};
+use crate::model::{Completions, Line, PromptMask, Servers, Settings, VI_MODE};
+use crate::ui::vi::{self, InputMode, InsertAt, Motion, Operator, ViCommand, ViParser};
 use crate::{event::Event, tts::TTSController};
 use crate::{lua::LuaScript, lua::UiEvent, session::Session, SaveData};
 use log::debug;
 use rs_complete::CompletionTree;
 use std::collections::HashSet;
+use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::Duration;
 use std::{
     io::stdin,
     sync::{mpsc::Sender, Arc, Mutex},
@@ -45,6 +48,18 @@ impl CompletionStepData {
     }
 }
 
+const KILL_RING_SIZE: usize = 20;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+const UNDO_LIMIT: usize = 100;
+
+/// What an edit did, typing or deleting characters in a row is undone at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert,
+    Remove,
+}
+
 pub struct CommandBuffer {
     buffer: Vec<char>,
     cursor_pos: usize,
@@ -53,12 +68,34 @@ pub struct CommandBuffer {
     prompt_mask: PromptMask,
     script: Arc<Mutex<LuaScript>>,
     tts_ctrl: Arc<Mutex<TTSController>>,
+    kill_ring: Vec<String>,
+    /// Start, end and kill ring index of the text last yanked
+    last_yank: Option<(usize, usize, usize)>,
+    undo: Vec<(Vec<char>, usize)>,
+    redo: Vec<(Vec<char>, usize)>,
+    /// The last edit and where it left the cursor
+    last_edit: Option<(Edit, usize)>,
+    mode: InputMode,
+    vi: ViParser,
+    last_find: Option<Motion>,
+    /// The last vi change along with the text typed in the insert mode it began
+    last_change: Option<(ViCommand, usize, String)>,
+    recording: Option<(ViCommand, usize)>,
+    inserted: String,
+    composing: bool,
+    /// Sent after the composed lines, eg. to close an in-game editor
+    terminator: Option<String>,
+    pasting: bool,
+    open_editor: bool,
 }
 
 impl CommandBuffer {
     pub fn new(tts_ctrl: Arc<Mutex<TTSController>>, script: Arc<Mutex<LuaScript>>) -> Self {
         let mut completion = CompletionTree::with_inclusions(&['/', '_']);
         completion.set_min_word_len(3);
+        let vi_mode = Settings::try_load()
+            .and_then(|settings| settings.get(VI_MODE))
+            .unwrap_or_default();
 
         Self {
             buffer: vec![],
@@ -68,7 +105,142 @@ impl CommandBuffer {
             prompt_mask: PromptMask::new(),
             script,
             tts_ctrl,
+            kill_ring: vec![],
+            last_yank: None,
+            undo: vec![],
+            redo: vec![],
+            last_edit: None,
+            mode: if vi_mode {
+                InputMode::ViInsert
+            } else {
+                InputMode::Emacs
+            },
+            vi: ViParser::default(),
+            last_find: None,
+            last_change: None,
+            recording: None,
+            inserted: String::new(),
+            composing: false,
+            terminator: None,
+            pasting: false,
+            open_editor: false,
+        }
+    }
+
+    pub fn is_composing(&self) -> bool {
+        self.composing
+    }
+
+    /// Starts composing several lines, keeping the current input unless given a text
+    pub fn start_compose(&mut self, text: Option<String>, terminator: Option<String>) {
+        if let Some(text) = text {
+            self.clear();
+            self.set(text);
+        }
+        self.composing = true;
+        self.terminator = terminator;
+    }
+
+    /// Ends composing, returning the lines to send followed by the terminator
+    fn submit_compose(&mut self) -> Vec<String> {
+        let text = self.submit();
+        self.composing = false;
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        lines.extend(self.terminator.take());
+        lines
+    }
+
+    fn cancel_compose(&mut self) {
+        self.clear();
+        self.composing = false;
+        self.terminator = None;
+    }
+
+    /// Ends a bracketed paste. Several pasted lines are composed, a single one
+    /// stays on the input line and is returned to be sent if it ended with a
+    /// newline.
+    fn end_paste(&mut self) -> Option<String> {
+        self.pasting = false;
+        if self.composing || !self.buffer.contains(&'\n') {
+            return None;
+        }
+        let text = self.get_buffer();
+        if text
+            .split('\n')
+            .filter(|line| !line.trim().is_empty())
+            .count()
+            > 1
+        {
+            self.composing = true;
+            return None;
+        }
+        let submit = self.cursor_pos > 0 && self.buffer[self.cursor_pos - 1] == '\n';
+        let newlines = self.buffer[..self.cursor_pos]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        self.buffer.retain(|c| *c != '\n');
+        self.cursor_pos -= newlines;
+        submit.then(|| self.submit())
+    }
+
+    /// Takes the text saved in `$EDITOR`, returning the lines to send. An
+    /// empty text leaves the input as it was.
+    pub fn finish_editor(&mut self, text: &str) -> Vec<String> {
+        let text = text.trim_end_matches('\n');
+        if text.trim().is_empty() {
+            return vec![];
         }
+        self.clear();
+        self.set(text.to_string());
+        self.submit_compose()
+    }
+
+    /// The start and end of the line the position is on
+    fn line_bounds(&self, pos: usize) -> (usize, usize) {
+        let start = self.buffer[..pos]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |i| i + 1);
+        let end = self.buffer[pos..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(self.buffer.len(), |i| pos + i);
+        (start, end)
+    }
+
+    fn step_line_up(&mut self) {
+        let (start, _) = self.line_bounds(self.cursor_pos);
+        if start > 0 {
+            let column = self.cursor_pos - start;
+            let (start, end) = self.line_bounds(start - 1);
+            self.cursor_pos = (start + column).min(end);
+            self.clamp_cursor();
+        }
+    }
+
+    fn step_line_down(&mut self) {
+        let (start, end) = self.line_bounds(self.cursor_pos);
+        if end < self.buffer.len() {
+            let column = self.cursor_pos - start;
+            let (start, end) = self.line_bounds(end + 1);
+            self.cursor_pos = (start + column).min(end);
+            self.clamp_cursor();
+        }
+    }
+
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
+    pub fn set_vi_mode(&mut self, enabled: bool) {
+        self.mode = if enabled {
+            InputMode::ViInsert
+        } else {
+            InputMode::Emacs
+        };
+        self.vi.reset();
+        self.recording = None;
     }
 
     pub fn get_buffer(&mut self) -> String {
@@ -100,10 +272,313 @@ impl CommandBuffer {
         self.buffer.clear();
         self.clear_mask();
         self.cursor_pos = 0;
+        self.undo.clear();
+        self.redo.clear();
+        self.last_edit = None;
+        if self.mode == InputMode::ViNormal {
+            self.enter_insert_mode();
+        }
 
         cmd
     }
 
+    /// Saves the line so it can be restored by `undo`
+    fn checkpoint(&mut self, edit: Option<Edit>) {
+        let grouped = edit.is_some() && self.last_edit == edit.map(|e| (e, self.cursor_pos));
+        if !grouped {
+            self.undo.push((self.buffer.clone(), self.cursor_pos));
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.last_edit = None;
+        self.last_yank = None;
+    }
+
+    fn restore(&mut self, (buffer, pos): (Vec<char>, usize)) -> (Vec<char>, usize) {
+        let previous = (std::mem::replace(&mut self.buffer, buffer), self.cursor_pos);
+        self.cursor_pos = pos.min(self.buffer.len());
+        self.clear_mask();
+        self.last_edit = None;
+        self.last_yank = None;
+        previous
+    }
+
+    fn undo(&mut self) {
+        if let Some(state) = self.undo.pop() {
+            let current = self.restore(state);
+            self.redo.push(current);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(state) = self.redo.pop() {
+            let current = self.restore(state);
+            self.undo.push(current);
+        }
+    }
+
+    fn kill(&mut self, text: String) {
+        if !text.is_empty() {
+            self.kill_ring.push(text);
+            if self.kill_ring.len() > KILL_RING_SIZE {
+                self.kill_ring.remove(0);
+            }
+        }
+    }
+
+    fn insert_text(&mut self, pos: usize, text: &str) -> usize {
+        let len = self.buffer.len();
+        self.buffer.splice(pos..pos, text.chars());
+        self.clear_mask();
+        self.buffer.len() - len
+    }
+
+    fn yank(&mut self) {
+        if let Some(text) = self.kill_ring.last().cloned() {
+            self.checkpoint(None);
+            let start = self.cursor_pos;
+            self.cursor_pos += self.insert_text(start, &text);
+            self.last_yank = Some((start, self.cursor_pos, self.kill_ring.len() - 1));
+        }
+    }
+
+    /// Replaces the text just yanked with the previous entry of the kill ring
+    fn yank_pop(&mut self) {
+        if let Some((start, end, index)) = self.last_yank {
+            if end > self.buffer.len() || self.kill_ring.len() < 2 {
+                return;
+            }
+            let index = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+            let text = self.kill_ring[index].clone();
+            self.buffer.drain(start..end);
+            self.cursor_pos = start + self.insert_text(start, &text);
+            self.last_yank = Some((start, self.cursor_pos, index));
+        }
+    }
+
+    fn transpose_chars(&mut self) {
+        let len = self.buffer.len();
+        if len > 1 && self.cursor_pos > 0 {
+            // At the end of the line the last two characters are swapped
+            let pos = self.cursor_pos.min(len - 1);
+            self.checkpoint(None);
+            self.buffer.swap(pos - 1, pos);
+            self.cursor_pos = pos + 1;
+            self.clear_mask();
+        }
+    }
+
+    /// Swaps the word at or after the cursor with the one before it
+    fn transpose_words(&mut self) {
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        let buffer = &self.buffer;
+        let len = buffer.len();
+        let mut end2 = self.cursor_pos.min(len);
+        while end2 < len && !is_word(&buffer[end2]) {
+            end2 += 1;
+        }
+        while end2 < len && is_word(&buffer[end2]) {
+            end2 += 1;
+        }
+        while end2 > 0 && !is_word(&buffer[end2 - 1]) {
+            end2 -= 1;
+        }
+        let mut start2 = end2;
+        while start2 > 0 && is_word(&buffer[start2 - 1]) {
+            start2 -= 1;
+        }
+        let mut end1 = start2;
+        while end1 > 0 && !is_word(&buffer[end1 - 1]) {
+            end1 -= 1;
+        }
+        let mut start1 = end1;
+        while start1 > 0 && is_word(&buffer[start1 - 1]) {
+            start1 -= 1;
+        }
+        if start1 < end1 && start2 < end2 {
+            let mut swapped = buffer[..start1].to_vec();
+            swapped.extend_from_slice(&buffer[start2..end2]);
+            swapped.extend_from_slice(&buffer[end1..start2]);
+            swapped.extend_from_slice(&buffer[start1..end1]);
+            swapped.extend_from_slice(&buffer[end2..]);
+            self.checkpoint(None);
+            self.buffer = swapped;
+            self.cursor_pos = end2;
+            self.clear_mask();
+        }
+    }
+
+    fn enter_insert_mode(&mut self) {
+        self.mode = InputMode::ViInsert;
+        self.inserted.clear();
+        self.last_edit = None;
+    }
+
+    fn enter_normal_mode(&mut self) {
+        if let Some((command, count)) = self.recording.take() {
+            self.last_change = Some((command, count, std::mem::take(&mut self.inserted)));
+        }
+        self.mode = InputMode::ViNormal;
+        self.vi.reset();
+        self.last_edit = None;
+        self.step_left();
+    }
+
+    /// Keeps the cursor on a character, as normal mode has no position after the line
+    fn clamp_cursor(&mut self) {
+        if self.mode == InputMode::ViNormal && self.cursor_pos >= self.buffer.len() {
+            self.cursor_pos = self.buffer.len().saturating_sub(1);
+        }
+    }
+
+    /// Handles a key typed in normal mode. Returns the commands that has to be
+    /// handled outside of the input line, like history navigation.
+    fn normal_key(&mut self, c: char) -> Option<ViCommand> {
+        let (command, count) = self.vi.feed(c)?;
+        self.run_vi(command, count)
+    }
+
+    fn resolve_motion(&mut self, motion: Motion) -> Option<Motion> {
+        match motion {
+            Motion::Find { .. } => {
+                self.last_find = Some(motion);
+                Some(motion)
+            }
+            Motion::RepeatFind(reverse) => match self.last_find? {
+                Motion::Find {
+                    target,
+                    forward,
+                    till,
+                } => Some(Motion::Find {
+                    target,
+                    forward: forward != reverse,
+                    till,
+                }),
+                _ => None,
+            },
+            _ => Some(motion),
+        }
+    }
+
+    fn run_vi(&mut self, command: ViCommand, count: usize) -> Option<ViCommand> {
+        let pos = self.cursor_pos;
+        let len = self.buffer.len();
+        match command {
+            ViCommand::HistoryPrevious | ViCommand::HistoryNext => return Some(command),
+            ViCommand::Repeat => {
+                if let Some((command, last_count, text)) = self.last_change.clone() {
+                    let count = if count > 1 { count } else { last_count };
+                    self.run_vi(command, count);
+                    if self.mode == InputMode::ViInsert {
+                        text.chars().for_each(|c| self.push_key(c));
+                        self.enter_normal_mode();
+                    }
+                }
+            }
+            ViCommand::Undo => (0..count).for_each(|_| self.undo()),
+            ViCommand::Redo => (0..count).for_each(|_| self.redo()),
+            ViCommand::Move(motion) => {
+                let motion = self.resolve_motion(motion)?;
+                if let Some((target, _)) = vi::target(&self.buffer, pos, motion, count) {
+                    self.cursor_pos = target;
+                }
+            }
+            ViCommand::Operate(op, motion) => self.operate(op, motion, count)?,
+            ViCommand::Insert(at) => {
+                self.cursor_pos = match at {
+                    InsertAt::Cursor => pos,
+                    InsertAt::After => (pos + 1).min(len),
+                    InsertAt::Start => 0,
+                    InsertAt::End => len,
+                };
+                self.enter_insert_mode();
+            }
+            ViCommand::Replace(c) => {
+                if pos + count > len {
+                    return None;
+                }
+                self.checkpoint(None);
+                self.buffer[pos..pos + count].fill(c);
+                self.cursor_pos = pos + count - 1;
+                self.clear_mask();
+            }
+            ViCommand::ToggleCase => {
+                let end = (pos + count).min(len);
+                if pos < end {
+                    self.checkpoint(None);
+                    for c in &mut self.buffer[pos..end] {
+                        *c = if c.is_uppercase() {
+                            c.to_lowercase().next().unwrap_or(*c)
+                        } else {
+                            c.to_uppercase().next().unwrap_or(*c)
+                        };
+                    }
+                    self.cursor_pos = end;
+                    self.clear_mask();
+                }
+            }
+            ViCommand::Put { before } => {
+                let text = self.kill_ring.last()?.repeat(count);
+                self.checkpoint(None);
+                let at = if before { pos } else { (pos + 1).min(len) };
+                self.cursor_pos = at + self.insert_text(at, &text) - 1;
+            }
+        }
+        if command.is_change() {
+            if self.mode == InputMode::ViInsert {
+                self.recording = Some((command, count));
+            } else {
+                self.last_change = Some((command, count, String::new()));
+            }
+        }
+        self.clamp_cursor();
+        None
+    }
+
+    fn operate(&mut self, op: Operator, motion: Motion, count: usize) -> Option<()> {
+        let pos = self.cursor_pos;
+        let len = self.buffer.len();
+        let (start, end) = match motion {
+            Motion::Line => (0, len),
+            // `cw` on a word changes to its end, like `ce`
+            Motion::WordForward(big)
+                if op == Operator::Change
+                    && self.buffer.get(pos).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                (pos, vi::change_word_end(&self.buffer, pos, count, big))
+            }
+            _ => {
+                let motion = self.resolve_motion(motion)?;
+                let (target, inclusive) = vi::target(&self.buffer, pos, motion, count)?;
+                if target >= pos {
+                    (pos, (target + inclusive as usize).min(len))
+                } else {
+                    (target, pos)
+                }
+            }
+        };
+        if start == end && op != Operator::Change {
+            return None;
+        }
+        self.kill(self.buffer[start..end].iter().collect());
+        if op == Operator::Yank {
+            if motion != Motion::Line {
+                self.cursor_pos = start;
+            }
+        } else {
+            self.checkpoint(None);
+            self.buffer.drain(start..end);
+            self.cursor_pos = start;
+            self.clear_mask();
+            if op == Operator::Change {
+                self.enter_insert_mode();
+            }
+        }
+        Some(())
+    }
+
     fn step_left(&mut self) {
         if self.cursor_pos > 0 {
             self.cursor_pos -= 1;
@@ -143,44 +618,54 @@ impl CommandBuffer {
         }
     }
 
+    /// Deletes a range of the line, saving it to the kill ring
+    fn kill_range(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.checkpoint(None);
+            let killed = self.buffer.drain(start..end).collect();
+            self.kill(killed);
+            self.clear_mask();
+        }
+    }
+
     fn delete_to_end(&mut self) {
-        self.buffer.drain(self.cursor_pos..self.buffer.len());
-        self.clear_mask();
+        self.kill_range(self.cursor_pos, self.buffer.len());
     }
 
     fn delete_from_start(&mut self) {
-        self.buffer.drain(0..self.cursor_pos);
+        self.kill_range(0, self.cursor_pos);
         self.cursor_pos = 0;
     }
 
     fn delete_right(&mut self) {
         if self.cursor_pos < self.buffer.len() {
+            self.checkpoint(Some(Edit::Remove));
             self.buffer.remove(self.cursor_pos);
             self.clear_mask();
+            self.last_edit = Some((Edit::Remove, self.cursor_pos));
         }
     }
 
     fn delete_word_right(&mut self) {
         let origin = self.cursor_pos;
         self.step_word_right();
-        if origin != self.cursor_pos {
-            self.buffer.drain(origin..self.cursor_pos);
-            self.clear_mask();
-            self.cursor_pos = origin;
-        }
+        let end = self.cursor_pos;
+        self.cursor_pos = origin;
+        self.kill_range(origin, end);
     }
 
     fn delete_word_left(&mut self) {
         let origin = self.cursor_pos;
         self.step_word_left();
-        if origin != self.cursor_pos {
-            self.buffer.drain(self.cursor_pos..origin);
-            self.clear_mask();
-        }
+        self.kill_range(self.cursor_pos, origin);
     }
 
     fn remove(&mut self) -> Option<char> {
         if self.cursor_pos > 0 {
+            self.checkpoint(Some(Edit::Remove));
+            if self.recording.is_some() {
+                self.inserted.pop();
+            }
             let removed = if self.cursor_pos < self.buffer.len() {
                 Some(self.buffer.remove(self.cursor_pos - 1))
             } else {
@@ -188,6 +673,7 @@ impl CommandBuffer {
             };
             self.clear_mask();
             self.step_left();
+            self.last_edit = Some((Edit::Remove, self.cursor_pos));
             removed
         } else {
             None
@@ -195,6 +681,10 @@ impl CommandBuffer {
     }
 
     fn push_key(&mut self, c: char) {
+        self.checkpoint(Some(Edit::Insert));
+        if self.recording.is_some() {
+            self.inserted.push(c);
+        }
         if self.cursor_pos >= self.buffer.len() {
             self.buffer.push(c);
         } else {
@@ -203,6 +693,7 @@ impl CommandBuffer {
         self.clear_mask();
         self.completion.clear();
         self.step_right();
+        self.last_edit = Some((Edit::Insert, self.cursor_pos));
     }
 
     fn tab_complete(&mut self) {
@@ -229,6 +720,8 @@ impl CommandBuffer {
             }
             if let Some(comp) = self.completion.next() {
                 self.tts_ctrl.lock().unwrap().speak(comp, true);
+                let comp = comp.clone();
+                self.checkpoint(None);
                 self.buffer = comp.chars().collect();
                 self.clear_mask();
                 self.cursor_pos = self.buffer.len();
@@ -237,6 +730,7 @@ impl CommandBuffer {
     }
 
     pub fn clear(&mut self) {
+        self.checkpoint(None);
         self.buffer.clear();
         self.clear_mask();
         self.cursor_pos = self.buffer.len();
@@ -246,10 +740,12 @@ impl CommandBuffer {
         self.buffer = line.chars().collect();
         self.clear_mask();
         self.cursor_pos = self.buffer.len();
+        self.clamp_cursor();
     }
 
     pub fn set_pos(&mut self, pos: usize) {
         self.cursor_pos = pos.min(self.buffer.len());
+        self.clamp_cursor();
     }
 
     pub fn set_mask(&mut self, mask: PromptMask) -> &PromptMask {
@@ -267,6 +763,9 @@ fn parse_mouse_event(event: termion::event::MouseEvent, writer: &Sender<Event>)
     match event {
         MouseEvent::Press(MouseButton::WheelUp, ..) => writer.send(Event::ScrollUp).unwrap(),
         MouseEvent::Press(MouseButton::WheelDown, ..) => writer.send(Event::ScrollDown).unwrap(),
+        MouseEvent::Press(MouseButton::Left, x, y) => writer.send(Event::MousePress(x, y)).unwrap(),
+        MouseEvent::Hold(x, y) => writer.send(Event::MouseHold(x, y)).unwrap(),
+        MouseEvent::Release(x, y) => writer.send(Event::MouseRelease(x, y)).unwrap(),
         _ => {}
     }
 }
@@ -278,15 +777,51 @@ fn parse_key_event(
     tts_ctrl: &mut Arc<Mutex<TTSController>>,
     script: &mut Arc<Mutex<LuaScript>>,
 ) {
+    if buffer.mode() == InputMode::ViNormal && !buffer.pasting {
+        if let Key::Char(c) = key {
+            if c != '\n' {
+                let binding = match buffer.normal_key(c) {
+                    Some(ViCommand::HistoryPrevious) if buffer.composing => {
+                        buffer.step_line_up();
+                        None
+                    }
+                    Some(ViCommand::HistoryNext) if buffer.composing => {
+                        buffer.step_line_down();
+                        None
+                    }
+                    Some(ViCommand::HistoryPrevious) => Some("up"),
+                    Some(ViCommand::HistoryNext) => Some("down"),
+                    _ => None,
+                };
+                if let Some(binding) = binding {
+                    if let Ok(mut script) = script.lock() {
+                        script.check_bindings(binding);
+                    }
+                    handle_script_ui_io(buffer, script, writer);
+                }
+                if let Ok(mut script) = script.lock() {
+                    script.set_prompt_content(buffer.get_buffer(), buffer.get_pos());
+                }
+                return;
+            }
+        }
+    }
     match key {
+        Key::Char('\n') if buffer.pasting => buffer.push_key('\n'),
+        Key::Char('\n') if buffer.composing => {
+            if buffer.mode() == InputMode::ViNormal {
+                buffer.step_line_down();
+            } else {
+                buffer.push_key('\n');
+            }
+        }
         Key::Char('\n') => {
-            let mut line = Line::from(buffer.submit());
-            line.flags.source = Some("user".to_string());
-            writer.send(Event::ServerInput(line)).unwrap();
+            send_input(vec![buffer.submit()], writer);
             if let Ok(mut script) = script.lock() {
                 script.set_prompt_content(String::new(), 0);
             }
         }
+        Key::Char('\t') if buffer.pasting => buffer.push_key('\t'),
         Key::Char('\t') => buffer.tab_complete(),
         Key::Char(c) => {
             tts_ctrl.lock().unwrap().key_press(c);
@@ -296,6 +831,14 @@ fn parse_key_event(
             }
         }
         Key::Ctrl('l') => writer.send(Event::Redraw).unwrap(),
+        Key::Esc => match buffer.mode() {
+            InputMode::ViInsert => buffer.enter_normal_mode(),
+            InputMode::ViNormal if buffer.vi.is_pending() => buffer.vi.reset(),
+            _ => writer.send(Event::CancelReconnect).unwrap(),
+        },
+        Key::Ctrl('r') if buffer.mode() == InputMode::ViNormal => {
+            buffer.run_vi(ViCommand::Redo, 1);
+        }
         Key::Ctrl('c') => {
             writer.send(Event::Quit(QuitMethod::CtrlC)).unwrap();
         }
@@ -305,8 +848,14 @@ fn parse_key_event(
         Key::End => writer.send(Event::ScrollBottom).unwrap(),
 
         // Input navigation
+        Key::Up => buffer.step_line_up(),
+        Key::Down => buffer.step_line_down(),
         Key::Left => buffer.step_left(),
-        Key::Right => buffer.step_right(),
+        Key::Right => {
+            buffer.step_right();
+            buffer.clamp_cursor();
+        }
+        Key::Backspace if buffer.mode() == InputMode::ViNormal => buffer.step_left(),
         Key::Backspace => {
             if let Some(c) = buffer.remove() {
                 if let Ok(mut tts_ctrl) = tts_ctrl.lock() {
@@ -317,7 +866,10 @@ fn parse_key_event(
                 script.set_prompt_content(buffer.get_buffer(), buffer.get_pos());
             }
         }
-        Key::Delete => buffer.delete_right(),
+        Key::Delete => {
+            buffer.delete_right();
+            buffer.clamp_cursor();
+        }
         _ => {}
     };
 }
@@ -340,8 +892,9 @@ fn check_command_binds(
             }
             Key::Home => script.check_bindings("home"),
             Key::End => script.check_bindings("end"),
-            Key::Up => script.check_bindings("up"),
-            Key::Down => script.check_bindings("down"),
+            // Composing uses the arrows to move between lines
+            Key::Up if !buffer.composing => script.check_bindings("up"),
+            Key::Down if !buffer.composing => script.check_bindings("down"),
             _ => false,
         }
     }
@@ -408,8 +961,23 @@ fn handle_script_ui_io(
             UiEvent::ScrollTop => writer.send(Event::ScrollTop).unwrap(),
             UiEvent::ScrollBottom => writer.send(Event::ScrollBottom).unwrap(),
             UiEvent::Complete => buffer.tab_complete(),
+            UiEvent::Yank => buffer.yank(),
+            UiEvent::YankPop => buffer.yank_pop(),
+            UiEvent::TransposeChars => buffer.transpose_chars(),
+            UiEvent::TransposeWords => buffer.transpose_words(),
+            UiEvent::Undo => buffer.undo(),
+            UiEvent::Redo => buffer.redo(),
+            UiEvent::Compose => buffer.start_compose(None, None),
+            UiEvent::ComposeSend => {
+                if buffer.composing {
+                    send_input(buffer.submit_compose(), writer);
+                }
+            }
+            UiEvent::ComposeCancel => buffer.cancel_compose(),
+            UiEvent::ComposeEditor => buffer.open_editor = true,
             UiEvent::Unknown(_) => {}
         });
+        buffer.clamp_cursor();
         script.set_prompt_content(buffer.get_buffer(), buffer.get_pos());
         script.get_output_lines().iter().for_each(|l| {
             writer.send(Event::Output(Line::from(l))).unwrap();
@@ -417,32 +985,46 @@ fn handle_script_ui_io(
     }
 }
 
-pub fn spawn_input_thread(session: Session) -> thread::JoinHandle<()> {
+/// Sends lines typed by the user to the server
+pub fn send_input(lines: Vec<String>, writer: &Sender<Event>) {
+    for line in lines {
+        let mut line = Line::from(line);
+        line.flags.source = Some("user".to_string());
+        writer.send(Event::ServerInput(line)).unwrap();
+    }
+}
+
+/// Adds the saved servers and builtin commands to the tab completion of a session
+pub fn init_completions(buffer: &mut CommandBuffer) {
+    for server in Servers::load().keys() {
+        buffer.completion_tree.insert(server);
+    }
+    buffer
+        .completion_tree
+        .insert(include_str!("../../resources/completions.txt"));
+}
+
+/// Reads terminal input and feeds it to the session currently on screen
+pub fn spawn_input_thread(active_session: Arc<Mutex<Session>>) -> thread::JoinHandle<()> {
     thread::Builder::new()
         .name("input-thread".to_string())
         .spawn(move || {
             debug!("Input stream spawned");
-            let writer = session.main_writer.clone();
-            let mut script = session.lua_script.clone();
             let stdin = stdin();
-            let buffer = session.command_buffer.clone();
-            let mut tts_ctrl = session.tts_ctrl;
-
-            if let Ok(mut buffer) = buffer.lock() {
-                for server in Servers::load().keys() {
-                    buffer.completion_tree.insert(server);
-                }
-                buffer
-                    .completion_tree
-                    .insert(include_str!("../../resources/completions.txt"));
-            }
 
             for e in stdin.events() {
+                let session = active_session.lock().unwrap().clone();
+                let writer = session.main_writer.clone();
+                let mut script = session.lua_script.clone();
+                let buffer = session.command_buffer.clone();
+                let mut tts_ctrl = session.tts_ctrl;
                 match e.unwrap() {
                     termion::event::Event::Key(key) => {
                         if let Ok(mut buffer) = buffer.lock() {
                             let orig_pos = buffer.get_pos();
-                            let orig_len = buffer.buffer.len();
+                            let orig_buffer = buffer.buffer.clone();
+                            let orig_mode = buffer.mode();
+                            let orig_composing = buffer.is_composing();
                             let bind_ran = check_command_binds(key, &mut buffer, &script, &writer);
                             if !bind_ran {
                                 parse_key_event(
@@ -453,11 +1035,11 @@ pub fn spawn_input_thread(session: Session) -> thread::JoinHandle<()> {
                                     &mut script,
                                 );
                             }
-                            if orig_len == buffer.buffer.len() && orig_pos != buffer.get_pos() {
+                            if orig_buffer == buffer.buffer && orig_pos != buffer.get_pos() {
                                 writer
                                     .send(Event::UserInputCursor(buffer.get_pos()))
                                     .unwrap();
-                            } else if !bind_ran || orig_len != buffer.buffer.len() {
+                            } else if !bind_ran || orig_buffer != buffer.buffer {
                                 if let Ok(mut luascript) = script.lock() {
                                     luascript.set_prompt_mask_content(&buffer.prompt_mask);
                                     luascript
@@ -470,9 +1052,52 @@ pub fn spawn_input_thread(session: Session) -> thread::JoinHandle<()> {
                                     ))
                                     .unwrap();
                             }
+                            if orig_mode != buffer.mode() {
+                                writer.send(Event::InputMode(buffer.mode())).unwrap();
+                            }
+                            if orig_composing != buffer.is_composing() {
+                                writer
+                                    .send(Event::Composing(buffer.is_composing()))
+                                    .unwrap();
+                            }
+                            if std::mem::take(&mut buffer.open_editor) {
+                                session.external_editor.store(true, Ordering::Relaxed);
+                                writer
+                                    .send(Event::ExternalEditor(buffer.get_buffer()))
+                                    .unwrap();
+                            }
                         }
                     }
                     termion::event::Event::Mouse(event) => parse_mouse_event(event, &writer),
+                    termion::event::Event::Unsupported(bytes)
+                        if bytes == PASTE_START || bytes == PASTE_END =>
+                    {
+                        if let Ok(mut buffer) = buffer.lock() {
+                            if bytes == PASTE_START {
+                                buffer.pasting = true;
+                            } else {
+                                let orig_composing = buffer.is_composing();
+                                if let Some(line) = buffer.end_paste() {
+                                    send_input(vec![line], &writer);
+                                }
+                                if let Ok(mut luascript) = script.lock() {
+                                    luascript
+                                        .set_prompt_content(buffer.get_buffer(), buffer.get_pos());
+                                }
+                                writer
+                                    .send(Event::UserInputBuffer(
+                                        buffer.get_buffer(),
+                                        buffer.get_pos(),
+                                    ))
+                                    .unwrap();
+                                if orig_composing != buffer.is_composing() {
+                                    writer
+                                        .send(Event::Composing(buffer.is_composing()))
+                                        .unwrap();
+                                }
+                            }
+                        }
+                    }
                     termion::event::Event::Unsupported(bytes) => {
                         if let Ok(escape) = String::from_utf8(bytes.clone()) {
                             if let Ok(mut buffer) = buffer.lock() {
@@ -490,6 +1115,10 @@ pub fn spawn_input_thread(session: Session) -> thread::JoinHandle<()> {
                         }
                     }
                 }
+                // The editor reads the keyboard until it exits
+                while session.external_editor.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(50));
+                }
             }
             debug!("Input stream closing");
         })
@@ -502,7 +1131,7 @@ mod command_test {
     use std::sync::mpsc::{channel, Receiver, Sender};
     use std::sync::{Arc, Mutex};
 
-    use super::CommandBuffer;
+    use super::{parse_mouse_event, CommandBuffer};
     use crate::lua::LuaScriptBuilder;
     use crate::tts::TTSController;
     use crate::Event;
@@ -522,6 +1151,26 @@ mod command_test {
         (buffer, rx)
     }
 
+    #[test]
+    fn test_mouse_events() {
+        use termion::event::{MouseButton, MouseEvent};
+        let (tx, rx): (Sender<Event>, Receiver<Event>) = channel();
+        for event in [
+            MouseEvent::Press(MouseButton::WheelUp, 1, 1),
+            MouseEvent::Press(MouseButton::Left, 3, 4),
+            MouseEvent::Hold(5, 4),
+            MouseEvent::Release(6, 5),
+            MouseEvent::Press(MouseButton::Right, 1, 1),
+        ] {
+            parse_mouse_event(event, &tx);
+        }
+        assert_eq!(rx.try_recv(), Ok(Event::ScrollUp));
+        assert_eq!(rx.try_recv(), Ok(Event::MousePress(3, 4)));
+        assert_eq!(rx.try_recv(), Ok(Event::MouseHold(5, 4)));
+        assert_eq!(rx.try_recv(), Ok(Event::MouseRelease(6, 5)));
+        assert!(rx.try_recv().is_err());
+    }
+
     #[test]
     fn test_editing() {
         let mut buffer = get_command().0;
@@ -650,6 +1299,169 @@ mod command_test {
         assert_eq!(buffer.get_buffer(), " words");
     }
 
+    #[test]
+    fn test_kill_ring() {
+        let mut buffer = get_command().0;
+        push_string(&mut buffer, "some random words");
+        buffer.delete_word_left();
+        buffer.move_to_start();
+        buffer.delete_word_right();
+        assert_eq!(buffer.get_buffer(), " random ");
+        buffer.yank();
+        assert_eq!(buffer.get_buffer(), "some random ");
+        assert_eq!(buffer.get_pos(), 4);
+        buffer.yank_pop();
+        assert_eq!(buffer.get_buffer(), "words random ");
+        assert_eq!(buffer.get_pos(), 5);
+        buffer.yank_pop();
+        assert_eq!(buffer.get_buffer(), "some random ");
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut buffer = get_command().0;
+        push_string(&mut buffer, "abc");
+        buffer.transpose_chars();
+        assert_eq!(buffer.get_buffer(), "acb");
+        buffer.set_pos(1);
+        buffer.transpose_chars();
+        assert_eq!(buffer.get_buffer(), "cab");
+        assert_eq!(buffer.get_pos(), 2);
+
+        buffer.set("go north, then".to_string());
+        buffer.transpose_words();
+        assert_eq!(buffer.get_buffer(), "go then, north");
+        buffer.set_pos(3);
+        buffer.transpose_words();
+        assert_eq!(buffer.get_buffer(), "then go, north");
+        assert_eq!(buffer.get_pos(), 7);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut buffer = get_command().0;
+        push_string(&mut buffer, "hello world");
+        buffer.delete_word_left();
+        buffer.remove();
+        buffer.remove();
+        assert_eq!(buffer.get_buffer(), "hell");
+        buffer.undo();
+        assert_eq!(buffer.get_buffer(), "hello ");
+        buffer.undo();
+        assert_eq!(buffer.get_buffer(), "hello world");
+        buffer.undo();
+        assert_eq!(buffer.get_buffer(), "");
+        buffer.redo();
+        buffer.redo();
+        assert_eq!(buffer.get_buffer(), "hello ");
+        push_string(&mut buffer, "there");
+        buffer.redo();
+        assert_eq!(buffer.get_buffer(), "hello there");
+    }
+
+    #[test]
+    fn test_vi_mode() {
+        use super::InputMode;
+
+        let normal = |buffer: &mut CommandBuffer, keys: &str| {
+            keys.chars().for_each(|c| {
+                buffer.normal_key(c);
+            })
+        };
+        let mut buffer = get_command().0;
+        buffer.set_vi_mode(true);
+        assert_eq!(buffer.mode(), InputMode::ViInsert);
+        push_string(&mut buffer, "say hello world");
+        buffer.enter_normal_mode();
+        assert_eq!(buffer.mode(), InputMode::ViNormal);
+        assert_eq!(buffer.get_pos(), 14);
+
+        normal(&mut buffer, "0dw");
+        assert_eq!(buffer.get_buffer(), "hello world");
+        normal(&mut buffer, ".");
+        assert_eq!(buffer.get_buffer(), "world");
+        normal(&mut buffer, "u");
+        assert_eq!(buffer.get_buffer(), "hello world");
+
+        normal(&mut buffer, "cw");
+        assert_eq!(buffer.mode(), InputMode::ViInsert);
+        push_string(&mut buffer, "bye");
+        buffer.enter_normal_mode();
+        assert_eq!(buffer.get_buffer(), "bye world");
+        assert_eq!(buffer.get_pos(), 2);
+        normal(&mut buffer, "w.");
+        assert_eq!(buffer.get_buffer(), "bye bye");
+        assert_eq!(buffer.get_pos(), 6);
+
+        normal(&mut buffer, "0ywP");
+        assert_eq!(buffer.get_buffer(), "bye bye bye");
+        normal(&mut buffer, "$2X");
+        assert_eq!(buffer.get_buffer(), "bye bye e");
+        normal(&mut buffer, "0fbrB$~");
+        assert_eq!(buffer.get_buffer(), "bye Bye E");
+        normal(&mut buffer, "0fe;x");
+        assert_eq!(buffer.get_buffer(), "bye By E");
+        normal(&mut buffer, "d$");
+        assert_eq!(buffer.get_buffer(), "bye By");
+        assert_eq!(buffer.get_pos(), 5);
+
+        buffer.submit();
+        assert_eq!(buffer.mode(), InputMode::ViInsert);
+        buffer.set_vi_mode(false);
+        assert_eq!(buffer.mode(), InputMode::Emacs);
+    }
+
+    #[test]
+    fn test_compose() {
+        let mut buffer = get_command().0;
+        push_string(&mut buffer, "Dear Bob,");
+        buffer.start_compose(None, Some(".".to_string()));
+        assert!(buffer.is_composing());
+        buffer.push_key('\n');
+        push_string(&mut buffer, "hi there");
+        buffer.step_line_up();
+        assert_eq!(buffer.get_pos(), 8);
+        buffer.step_line_down();
+        assert_eq!(buffer.get_pos(), 18);
+        buffer.move_to_start();
+        buffer.step_line_down();
+        assert_eq!(buffer.get_pos(), 10);
+        assert_eq!(buffer.submit_compose(), vec!["Dear Bob,", "hi there", "."]);
+        assert!(!buffer.is_composing());
+        assert_eq!(buffer.get_buffer(), "");
+
+        buffer.start_compose(Some("draft".to_string()), None);
+        assert_eq!(buffer.finish_editor("\n\n"), Vec::<String>::new());
+        assert_eq!(buffer.get_buffer(), "draft");
+        assert_eq!(buffer.finish_editor("one\ntwo\n"), vec!["one", "two"]);
+        assert!(!buffer.is_composing());
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut buffer = get_command().0;
+        let paste = |buffer: &mut CommandBuffer, text: &str| {
+            buffer.pasting = true;
+            text.chars().for_each(|c| buffer.push_key(c));
+            buffer.end_paste()
+        };
+        assert_eq!(paste(&mut buffer, "look\n"), Some("look".to_string()));
+        assert!(!buffer.is_composing());
+        assert_eq!(buffer.get_buffer(), "");
+
+        push_string(&mut buffer, "say ");
+        assert_eq!(paste(&mut buffer, "hi\n\n"), Some("say hi".to_string()));
+        assert_eq!(paste(&mut buffer, "north"), None);
+        assert_eq!(buffer.get_buffer(), "north");
+
+        buffer.clear();
+        assert_eq!(paste(&mut buffer, "n\ne\n"), None);
+        assert!(buffer.is_composing());
+        assert_eq!(buffer.get_buffer(), "n\ne\n");
+        assert_eq!(paste(&mut buffer, "s\n"), None);
+        assert_eq!(buffer.get_buffer(), "n\ne\ns\n");
+    }
+
     #[test]
     fn test_fancy_chars() {
         let mut buffer = get_command().0;
@@ -91,6 +91,74 @@ impl UserInterface for HeadlessScreen {
         Ok(())
     }
 
+    fn set_status_layout(
+        &mut self,
+        _line: usize,
+        _layout: Option<crate::model::StatusLayout>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_status_widget(
+        &mut self,
+        _name: &str,
+        _widget: Option<crate::model::StatusWidget>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_sessions(&mut self, _sessions: &[super::SessionTab]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_latency(&mut self, _latency: Option<std::time::Duration>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_input_mode(&mut self, _mode: crate::ui::InputMode) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_composing(&mut self, _composing: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_window(
+        &mut self,
+        _name: &str,
+        _options: crate::model::WindowOptions,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn remove_window(&mut self, _name: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn clear_window(&mut self, _name: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn scroll_window(&mut self, _name: &str, _lines: Option<i32>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn windows(&self) -> Vec<(String, crate::model::WindowOptions)> {
+        vec![]
+    }
+
+    fn mouse_press(&mut self, _x: u16, _y: u16) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn mouse_hold(&mut self, _x: u16, _y: u16) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn mouse_release(&mut self, _x: u16, _y: u16) -> anyhow::Result<Option<crate::model::Link>> {
+        Ok(None)
+    }
+
     fn flush(&mut self) {
         std::io::stdout().flush().ok();
     }
@@ -108,4 +176,12 @@ impl UserInterface for HeadlessScreen {
     ) -> anyhow::Result<(Box<dyn std::io::Write>, super::history::History)> {
         bail!("Can't destroy a headless ui")
     }
+
+    fn release_writer(&mut self) -> Box<dyn std::io::Write> {
+        Box::new(std::io::sink())
+    }
+
+    fn acquire_writer(&mut self, _writer: Box<dyn std::io::Write>) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
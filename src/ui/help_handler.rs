@@ -220,6 +220,7 @@ fn load_files() -> HashMap<&'static str, &'static str> {
         #[cfg(not(feature = "tts"))]
         "tts" => "no_tts.md",
         "status_area" => "status_area.md",
+        "ui" => "ui.md",
         "alias" => "aliases.md",
         "script" => "script.md",
         "spellcheck" => "spellcheck.md",
@@ -228,6 +229,8 @@ fn load_files() -> HashMap<&'static str, &'static str> {
         "gmcp" => "gmcp.md",
         "msdp" => "msdp.md",
         "mssp" => "mssp.md",
+        "mnes" => "mnes.md",
+        "mxp" => "mxp.md",
         "regex" => "regex.md",
         "line" => "line.md",
         "mud" => "mud.md",
@@ -241,9 +244,11 @@ fn load_files() -> HashMap<&'static str, &'static str> {
         "colors" => "colors.md",
         "tasks" => "tasks.md",
         "socket" => "socket.md",
+        "http" => "http.md",
         "plugin" => "plugin.md",
         "plugin_developer" => "plugin_developer.md",
         "servers" => "servers.md",
+        "sessions" => "sessions.md",
         "search" => "search.md",
         "scrolling" => "scrolling.md",
         "ttype" => "ttype.md",
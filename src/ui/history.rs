@@ -4,6 +4,9 @@ pub struct History {
     pub inner: Vec<String>,
     pub capacity: usize,
     pub drain_length: usize,
+    /// Lines drained so far, the index a line had when appended is this plus
+    /// its current index
+    pub drained: usize,
 }
 
 impl History {
@@ -14,12 +17,24 @@ impl History {
             inner: Vec::with_capacity(capacity),
             capacity,
             drain_length,
+            drained: 0,
+        }
+    }
+
+    /// A history keeping at most `capacity` lines
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: vec![],
+            capacity: capacity + 1,
+            drain_length: (capacity / 4).max(1),
+            drained: 0,
         }
     }
 
     pub fn drain(&mut self) {
         if self.inner.len() >= self.capacity {
             self.inner.drain(0..self.drain_length);
+            self.drained += self.drain_length;
         }
     }
 
@@ -108,6 +123,7 @@ mod test {
             history.append("test");
         }
         assert_eq!(history.len(), 31 * 1024);
+        assert_eq!(history.drained, 1024);
     }
 
     #[test]
@@ -1,13 +1,14 @@
 pub use self::{
     ansi::*,
-    command::spawn_input_thread,
     command::CommandBuffer,
+    command::{init_completions, send_input, spawn_input_thread},
     headless_screen::HeadlessScreen,
     help_handler::HelpHandler,
     reader_screen::ReaderScreen,
     split_screen::SplitScreen,
-    ui_wrapper::UiWrapper,
-    user_interface::{wrap_line, UserInterface},
+    ui_wrapper::{suspend_screen, UiWrapper},
+    user_interface::{cursor_line, wrap_line, SessionTab, UserInterface},
+    vi::InputMode,
 };
 
 #[cfg(test)]
@@ -22,5 +23,8 @@ mod printable_chars;
 mod reader_screen;
 mod scroll_data;
 mod split_screen;
+mod status;
 mod ui_wrapper;
 mod user_interface;
+mod vi;
+mod window;
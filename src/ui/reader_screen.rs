@@ -1,4 +1,7 @@
-use std::io::Write;
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
 
 use anyhow::Result;
 use termion::{
@@ -7,15 +10,19 @@ use termion::{
 };
 
 use crate::{
-    model::{Line, Regex},
+    model::{Line, Link, Regex, StatusLayout, StatusWidget, WindowOptions},
     ui::{
         printable_chars::PrintableCharsIterator, DisableOriginMode, ResetScrollRegion, ScrollRegion,
     },
 };
 
 use super::{
-    history::History, scroll_data::ScrollData, user_interface::TerminalSizeError, wrap_line,
-    UserInterface,
+    cursor_line,
+    history::History,
+    scroll_data::ScrollData,
+    user_interface::TerminalSizeError,
+    window::{find_window, Window},
+    wrap_line, InputMode, SessionTab, UserInterface,
 };
 
 pub struct ReaderScreen {
@@ -27,6 +34,11 @@ pub struct ReaderScreen {
     width: u16,
     height: u16,
     prompt_input: Option<(String, usize)>,
+    /// None until the screen is first told the mode, which isn't announced
+    input_mode: Option<InputMode>,
+    /// Windows keep their scrollback and logs, their lines are read in the
+    /// main output
+    windows: Vec<Window>,
 }
 
 impl ReaderScreen {
@@ -44,6 +56,8 @@ impl ReaderScreen {
             width,
             height,
             prompt_input: None,
+            input_mode: None,
+            windows: vec![],
         })
     }
 
@@ -182,6 +196,14 @@ impl UserInterface for ReaderScreen {
     }
 
     fn print_output(&mut self, line: &Line) {
+        let name = line.flags.window.as_deref().unwrap_or_default();
+        if let Some(window) = find_window(&mut self.windows, name) {
+            window.append(line);
+            let mut prefixed = Line::from(format!("[{}] {}", window.name, line));
+            prefixed.flags = line.flags.clone();
+            prefixed.flags.window = None;
+            return self.print_output(&prefixed);
+        }
         if line.flags.separate_receives {
             if let Some(print_line) = line.print_line() {
                 self.history.remove_last_if_prefix(print_line);
@@ -214,6 +236,13 @@ impl UserInterface for ReaderScreen {
 
     // This is fancy logic to make 'tdsr' less noisy
     fn print_prompt_input(&mut self, input: &str, pos: usize) {
+        // Only the line being edited is shown when composing several lines
+        let (input, pos) = if input.contains('\n') {
+            let (line, column) = cursor_line(input, pos);
+            (input.split('\n').nth(line).unwrap_or_default(), column)
+        } else {
+            (input, pos)
+        };
         // Reader screens only operate on printable input characters (no term control sequences, e.g. ANSI colour).
         let sanitized_input = input.printable_chars().collect::<String>();
         let input = sanitized_input.as_str();
@@ -401,6 +430,82 @@ impl UserInterface for ReaderScreen {
         Ok(())
     }
 
+    fn set_status_layout(&mut self, _line: usize, _layout: Option<StatusLayout>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_status_widget(&mut self, _name: &str, _widget: Option<StatusWidget>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_sessions(&mut self, _sessions: &[SessionTab]) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_latency(&mut self, _latency: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<()> {
+        let changed = self.input_mode.replace(mode).is_some_and(|old| old != mode);
+        if let (true, Some(label)) = (changed, mode.label()) {
+            self.print_info(&format!("{} mode", label.to_lowercase()));
+        }
+        Ok(())
+    }
+
+    fn set_composing(&mut self, composing: bool) -> Result<()> {
+        self.print_info(if composing {
+            "composing"
+        } else {
+            "done composing"
+        });
+        Ok(())
+    }
+
+    fn add_window(&mut self, name: &str, options: WindowOptions) -> Result<()> {
+        match find_window(&mut self.windows, name) {
+            Some(window) => window.set_options(options),
+            None => self.windows.push(Window::new(name, options)),
+        }
+        Ok(())
+    }
+
+    fn remove_window(&mut self, name: &str) -> Result<()> {
+        self.windows.retain(|window| window.name != name);
+        Ok(())
+    }
+
+    fn clear_window(&mut self, name: &str) -> Result<()> {
+        if let Some(window) = find_window(&mut self.windows, name) {
+            window.clear();
+        }
+        Ok(())
+    }
+
+    fn scroll_window(&mut self, _name: &str, _lines: Option<i32>) -> Result<()> {
+        Ok(())
+    }
+
+    fn windows(&self) -> Vec<(String, WindowOptions)> {
+        self.windows
+            .iter()
+            .map(|window| (window.name.clone(), window.options.clone()))
+            .collect()
+    }
+
+    fn mouse_press(&mut self, _x: u16, _y: u16) -> Result<()> {
+        Ok(())
+    }
+
+    fn mouse_hold(&mut self, _x: u16, _y: u16) -> Result<()> {
+        Ok(())
+    }
+
+    fn mouse_release(&mut self, _x: u16, _y: u16) -> Result<Option<Link>> {
+        Ok(None)
+    }
+
     fn flush(&mut self) {
         self.screen.flush().unwrap();
     }
@@ -417,4 +522,17 @@ impl UserInterface for ReaderScreen {
         self.reset()?;
         Ok((self.screen, self.history))
     }
+
+    fn release_writer(&mut self) -> Box<dyn Write> {
+        std::mem::replace(&mut self.screen, Box::new(io::sink()))
+    }
+
+    fn acquire_writer(&mut self, writer: Box<dyn Write>) -> Result<()> {
+        self.screen = writer;
+        self.setup()?;
+        if let Some((input, pos)) = self.prompt_input.clone() {
+            self.print_wrapped_prompt_input(&input, pos);
+        }
+        Ok(())
+    }
 }
@@ -1,20 +1,23 @@
 use super::history::History;
 use super::scroll_data::ScrollData;
+use super::status::render_status_line;
 use super::user_interface::TerminalSizeError;
-use super::wrap_line;
+use super::window::{find_window, layout_windows, Rect, Window};
+use super::{cursor_line, wrap_line};
+use super::{InputMode, SessionTab};
 use crate::io::SaveData;
-use crate::model::{Settings, HIDE_TOPBAR};
+use crate::model::{Link, Settings, StatusLayout, StatusWidget, WindowOptions, HIDE_TOPBAR};
 use crate::{model::Line, model::Regex, ui::ansi::*, ui::printable_chars::PrintableCharsIterator};
 use anyhow::Result;
-use std::collections::HashSet;
-use std::io::Write;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::time::Duration;
 use termion::color::{self, Bg, Fg};
-use termion::cursor;
+use termion::{cursor, style};
 
 use super::UserInterface;
 
 const SCROLL_LIVE_BUFFER_SIZE: u16 = 10;
-const PROMPT_HEIGHT: u16 = 1;
 const STATUS_HEIGHT_MIN: u16 = 0;
 const STATUS_HEIGHT_MAX: u16 = 5;
 
@@ -22,6 +25,9 @@ struct StatusArea {
     start_line: u16,
     width: u16,
     status_lines: Vec<Option<String>>,
+    /// Lines made of widgets, shown instead of the plain line
+    layouts: Vec<Option<StatusLayout>>,
+    widgets: HashMap<String, StatusWidget>,
     scroll_marker: bool,
 }
 
@@ -32,6 +38,8 @@ impl StatusArea {
             start_line,
             width,
             status_lines: vec![None; height],
+            layouts: vec![None; height],
+            widgets: HashMap::new(),
             scroll_marker: false,
         }
     }
@@ -51,6 +59,7 @@ impl StatusArea {
     fn set_height(&mut self, height: u16, start_line: u16) {
         self.clear();
         self.status_lines.resize(Self::clamp_height(height), None);
+        self.layouts.resize(Self::clamp_height(height), None);
         self.update_pos(start_line);
     }
 
@@ -64,6 +73,7 @@ impl StatusArea {
 
     fn set_status_line(&mut self, index: usize, line: String) {
         let index = self.clamp_index(index);
+        self.layouts[index] = None;
         if !line.trim().is_empty() {
             self.status_lines[index] = Some(line);
         } else {
@@ -71,8 +81,34 @@ impl StatusArea {
         }
     }
 
+    /// Returns the index of the line changed, None without a status area
+    fn set_layout(&mut self, index: usize, layout: Option<StatusLayout>) -> Option<usize> {
+        if self.status_lines.is_empty() {
+            return None;
+        }
+        let index = self.clamp_index(index);
+        self.status_lines[index] = None;
+        self.layouts[index] = layout;
+        Some(index)
+    }
+
+    /// Sets or removes a widget, returning the lines showing it
+    fn set_widget(&mut self, name: &str, widget: Option<StatusWidget>) -> Vec<usize> {
+        match widget {
+            Some(widget) => self.widgets.insert(name.to_string(), widget),
+            None => self.widgets.remove(name),
+        };
+        self.layouts
+            .iter()
+            .enumerate()
+            .filter(|(_, layout)| matches!(layout, Some(layout) if layout.uses(name)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
     fn clear(&mut self) {
         self.status_lines = vec![None; self.status_lines.len()];
+        self.layouts = vec![None; self.layouts.len()];
     }
 
     fn redraw_line(&mut self, screen: &mut impl Write, line_no: usize) -> Result<()> {
@@ -84,6 +120,35 @@ impl StatusArea {
         } else {
             String::new()
         };
+        let bar = line_no == 0 || line_no == self.status_lines.len() - 1;
+
+        if let Some(Some(layout)) = self.layouts.get(line_no) {
+            let fill = |count: usize| {
+                if bar {
+                    format!(
+                        "{}{}{}",
+                        Fg(color::Green),
+                        "━".repeat(count),
+                        Fg(color::Reset)
+                    )
+                } else {
+                    " ".repeat(count)
+                }
+            };
+            let width = (self.width as usize).saturating_sub(info.len());
+            let line = render_status_line(layout, &self.widgets, width, &fill);
+            write!(
+                screen,
+                "{}{}{}{}{}{}",
+                termion::cursor::Goto(1, index as u16),
+                termion::clear::CurrentLine,
+                Fg(color::Green),
+                info,
+                Fg(color::Reset),
+                line,
+            )?;
+            return Ok(());
+        }
 
         if let Some(Some(custom_info)) = self.status_lines.get(line_no) {
             info = if info.is_empty() {
@@ -93,7 +158,7 @@ impl StatusArea {
             };
         }
 
-        if line_no == 0 || line_no == self.status_lines.len() - 1 {
+        if bar {
             self.draw_bar(index, screen, &info)?;
         } else {
             self.draw_line(index, screen, &info)?;
@@ -159,23 +224,132 @@ impl StatusArea {
     }
 }
 
+/// Text being selected with the mouse. Positions are (row, column), with rows
+/// counting drained history so they stay put as new output arrives.
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    anchor: (usize, usize),
+    head: (usize, usize),
+    dragged: bool,
+}
+
+impl Selection {
+    fn new(pos: (usize, usize)) -> Self {
+        Self {
+            anchor: pos,
+            head: pos,
+            dragged: false,
+        }
+    }
+
+    /// The selected columns of a row, the end excluded
+    fn columns(&self, row: usize) -> Option<(usize, usize)> {
+        let (start, end) = if self.anchor <= self.head {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        };
+        if row < start.0 || row > end.0 {
+            return None;
+        }
+        let from = if row == start.0 { start.1 } else { 0 };
+        let to = if row == end.0 { end.1 + 1 } else { usize::MAX };
+        Some((from, to))
+    }
+
+    fn rows(&self) -> std::ops::RangeInclusive<usize> {
+        self.anchor.0.min(self.head.0)..=self.anchor.0.max(self.head.0)
+    }
+}
+
+/// Splits the links of a line over the rows it was wrapped into, with their
+/// columns relative to each row
+/// The part of the input that fits the width, scrolled a screen width at a
+/// time to keep the cursor in view, and the cursor column within it
+fn visible_input(input: &str, pos: usize, width: usize) -> (&str, usize) {
+    let mut input = input;
+    let mut pos = pos;
+    while input.printable_chars().count() >= width && pos >= width {
+        if let Some((i, _)) = input.printable_char_indices().nth(width) {
+            input = input.split_at(i).1;
+        } else {
+            input = "";
+        }
+        pos -= width;
+    }
+    if input.printable_chars().count() >= width {
+        if let Some((i, _)) = input.printable_char_indices().nth(width) {
+            input = input.split_at(i).0;
+        }
+    }
+    (input, pos)
+}
+
+fn split_links(line: &Line, rows: &[&str]) -> Vec<Vec<Link>> {
+    let clean = line.clean_line();
+    let mut offset = 0;
+    rows.iter()
+        .map(|row| {
+            let text = Line::from(*row).clean_line().to_string();
+            let start = match clean[offset..].find(&text) {
+                Some(index) => offset + index,
+                None => return vec![],
+            };
+            offset = start + text.len();
+            let first = clean[..start].chars().count();
+            let last = first + text.chars().count();
+            line.links
+                .iter()
+                .filter(|link| link.start < last && link.end > first)
+                .map(|link| Link {
+                    start: link.start.max(first) - first,
+                    end: link.end.min(last) - first,
+                    ..link.clone()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The columns of a row's clean text, the end excluded
+fn slice_columns(text: &str, from: usize, to: usize) -> String {
+    text.chars()
+        .skip(from)
+        .take(to.saturating_sub(from))
+        .collect()
+}
+
 pub struct SplitScreen {
     screen: Box<dyn Write>,
     width: u16,
     height: u16,
     output_start_line: u16,
     output_line: u16,
+    /// The first column of the main output, moved right by left windows
+    output_x: u16,
+    output_width: u16,
     mud_prompt_line: u16,
     mud_prompt: Line,
+    /// The first line of the input, which takes more lines when composing
     prompt_line: u16,
+    input_height: u16,
     status_area: StatusArea,
     cursor_prompt_pos: u16,
+    cursor_prompt_line: u16,
     history: History,
     scroll_data: ScrollData,
     connection: Option<String>,
     tags: HashSet<String>,
+    sessions: Vec<SessionTab>,
+    latency: Option<Duration>,
     prompt_input: String,
     prompt_input_pos: usize,
+    input_mode: InputMode,
+    composing: bool,
+    windows: Vec<Window>,
+    /// Links of printed rows, by row counting drained history
+    row_links: VecDeque<(usize, Vec<Link>)>,
+    selection: Option<Selection>,
 }
 
 impl UserInterface for SplitScreen {
@@ -189,10 +363,23 @@ impl UserInterface for SplitScreen {
         if width > 0 && height > 0 {
             self.width = width;
             self.height = height;
-            self.output_line = height - self.status_area.height() - 2;
-            self.mud_prompt_line = height - self.status_area.height() - 1;
-            self.prompt_line = height;
-            self.output_start_line = if settings.get(HIDE_TOPBAR)? { 1 } else { 2 };
+            self.input_height = if self.composing {
+                (height / 3).clamp(3, 12)
+            } else {
+                1
+            };
+            self.mud_prompt_line = height - self.status_area.height() - self.input_height;
+            self.prompt_line = height - self.input_height + 1;
+            let top_line = if settings.get(HIDE_TOPBAR)? { 1 } else { 2 };
+            let output_height = height - self.status_area.height() - self.input_height - top_line;
+            let main = layout_windows(
+                &mut self.windows,
+                Rect::new(1, top_line, width, output_height),
+            );
+            self.output_start_line = main.y;
+            self.output_line = main.y + main.height - 1;
+            self.output_x = main.x;
+            self.output_width = main.width;
 
             write!(
                 self.screen,
@@ -204,6 +391,9 @@ impl UserInterface for SplitScreen {
             self.redraw_top_bar()?;
             self.reset_scroll()?;
             self.redraw_status_area()?;
+            for window in &self.windows {
+                window.draw(&mut self.screen)?;
+            }
             self.screen.flush()?;
             write!(
                 self.screen,
@@ -219,26 +409,49 @@ impl UserInterface for SplitScreen {
 
     fn print_error(&mut self, output: &str) {
         let line = &format!("{}[!!] {}{}", Fg(color::Red), output, Fg(color::Reset));
-        self.print_line(line);
+        self.print_wrapped(line);
     }
 
     fn print_info(&mut self, output: &str) {
         let line = &format!("[**] {output}");
-        self.print_line(line);
+        self.print_wrapped(line);
     }
 
     fn print_output(&mut self, line: &Line) {
         //debug!("UI: {:?}", line);
+        let name = line.flags.window.as_deref().unwrap_or_default();
+        if let Some(window) = find_window(&mut self.windows, name) {
+            window.append(line);
+            window.draw(&mut self.screen).ok();
+            write!(self.screen, "{}", self.goto_prompt()).unwrap();
+            return;
+        }
         if let Some(print_line) = line.print_line() {
             if !line.is_utf8() || print_line.trim().is_empty() {
                 self.print_line(print_line);
             } else {
                 let mut count = 0;
                 let cur_line = self.history.len();
-                for l in wrap_line(print_line, self.width as usize) {
+                let rows = wrap_line(print_line, self.output_width as usize);
+                let mut links = if line.links.is_empty() {
+                    vec![]
+                } else {
+                    split_links(line, &rows)
+                }
+                .into_iter();
+                for l in rows {
+                    let row = self.history.drained + self.history.len();
                     self.print_line(l);
+                    match links.next() {
+                        Some(links) if !links.is_empty() => self.row_links.push_back((row, links)),
+                        _ => {}
+                    }
                     count += 1;
                 }
+                while matches!(self.row_links.front(), Some((row, _)) if *row < self.history.drained)
+                {
+                    self.row_links.pop_front();
+                }
                 if self.scroll_data.scroll_lock && count > self.height {
                     self.scroll_to(cur_line).ok();
                 }
@@ -258,27 +471,20 @@ impl UserInterface for SplitScreen {
 
         self.prompt_input = input.to_string();
         self.prompt_input_pos = pos;
-
-        let mut input = input;
-        let mut pos = pos;
-        let width = self.width as usize;
-        while input.printable_chars().count() >= width && pos >= width {
-            if let Some((i, _)) = input.printable_char_indices().nth(width) {
-                input = input.split_at(i).1;
-            } else {
-                input = "";
-            }
-            pos -= width;
-        }
-        if input.printable_chars().count() >= width {
-            if let Some((i, _)) = input.printable_char_indices().nth(width) {
-                input = input.split_at(i).0;
-            }
+        if self.composing {
+            return self.print_compose_input(input, pos);
         }
+
+        // The vi mode is shown at the end of the line
+        let label = self.input_mode.label().unwrap_or_default();
+        let label_width = if label.is_empty() { 0 } else { label.len() + 1 };
+        let width = (self.width as usize).saturating_sub(label_width).max(1);
+        let (input, pos) = visible_input(input, pos, width);
         self.cursor_prompt_pos = pos as u16 + 1;
+        self.cursor_prompt_line = self.prompt_line;
         write!(
             self.screen,
-            "{}{}{}{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}",
             termion::cursor::Save,
             termion::cursor::Goto(1, self.prompt_line),
             Fg(termion::color::Reset),
@@ -286,8 +492,24 @@ impl UserInterface for SplitScreen {
             termion::style::Reset,
             termion::clear::CurrentLine,
             input,
+        )
+        .unwrap();
+        if !label.is_empty() && label_width < self.width as usize {
+            write!(
+                self.screen,
+                "{}{}{}{}",
+                termion::cursor::Goto(self.width - label.len() as u16 + 1, self.prompt_line),
+                Fg(color::LightBlack),
+                label,
+                Fg(color::Reset),
+            )
+            .unwrap();
+        }
+        write!(
+            self.screen,
+            "{}{}",
             termion::cursor::Restore,
-            self.goto_prompt(),
+            self.goto_prompt()
         )
         .unwrap();
     }
@@ -304,7 +526,7 @@ impl UserInterface for SplitScreen {
                 line,
                 Fg(color::Reset),
             );
-            for line in wrap_line(line, self.width as usize) {
+            for line in wrap_line(line, self.output_width as usize) {
                 self.print_line(line);
             }
         }
@@ -343,7 +565,7 @@ impl UserInterface for SplitScreen {
                 write!(
                     self.screen,
                     "{}{}{}",
-                    termion::cursor::Goto(1, line_no),
+                    termion::cursor::Goto(self.output_x, line_no),
                     termion::clear::CurrentLine,
                     self.history.inner[index],
                 )?;
@@ -353,12 +575,12 @@ impl UserInterface for SplitScreen {
                 write!(
                     self.screen,
                     "{}\n{}",
-                    termion::cursor::Goto(1, self.output_line),
+                    termion::cursor::Goto(self.output_x, self.output_line),
                     line,
                 )?;
             }
         }
-        Ok(())
+        self.redraw_side_windows()
     }
 
     fn scroll_down(&mut self) -> Result<()> {
@@ -471,7 +693,7 @@ impl UserInterface for SplitScreen {
     fn set_status_area_height(&mut self, height: u16) -> Result<()> {
         let height = StatusArea::clamp_height(height) as u16;
         self.status_area
-            .set_height(height, self.height - height - PROMPT_HEIGHT);
+            .set_height(height, self.height - height - self.input_height);
         self.setup()?;
         let input_str = self.prompt_input.as_str().to_owned();
         self.print_prompt_input(&input_str, self.prompt_input_pos);
@@ -485,6 +707,132 @@ impl UserInterface for SplitScreen {
         Ok(())
     }
 
+    fn set_status_layout(&mut self, line: usize, layout: Option<StatusLayout>) -> Result<()> {
+        if let Some(line) = self.status_area.set_layout(line, layout) {
+            self.status_area.redraw_line(&mut self.screen, line)?;
+            write!(self.screen, "{}", self.goto_prompt())?;
+        }
+        Ok(())
+    }
+
+    fn set_status_widget(&mut self, name: &str, widget: Option<StatusWidget>) -> Result<()> {
+        let lines = self.status_area.set_widget(name, widget);
+        for line in &lines {
+            self.status_area.redraw_line(&mut self.screen, *line)?;
+        }
+        if !lines.is_empty() {
+            write!(self.screen, "{}", self.goto_prompt())?;
+        }
+        Ok(())
+    }
+
+    fn set_sessions(&mut self, sessions: &[SessionTab]) -> Result<()> {
+        self.sessions = sessions.to_vec();
+        self.redraw_top_bar()
+    }
+
+    fn set_latency(&mut self, latency: Option<Duration>) -> Result<()> {
+        self.latency = latency;
+        self.redraw_top_bar()
+    }
+
+    fn set_composing(&mut self, composing: bool) -> Result<()> {
+        if self.composing != composing {
+            self.composing = composing;
+            self.setup()?;
+            self.redraw_prompt();
+            let input_str = self.prompt_input.as_str().to_owned();
+            self.print_prompt_input(&input_str, self.prompt_input_pos);
+        }
+        Ok(())
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<()> {
+        self.input_mode = mode;
+        let input_str = self.prompt_input.as_str().to_owned();
+        self.print_prompt_input(&input_str, self.prompt_input_pos);
+        Ok(())
+    }
+
+    fn add_window(&mut self, name: &str, options: WindowOptions) -> Result<()> {
+        match find_window(&mut self.windows, name) {
+            Some(window) => window.set_options(options),
+            None => self.windows.push(Window::new(name, options)),
+        }
+        self.redraw_layout()
+    }
+
+    fn remove_window(&mut self, name: &str) -> Result<()> {
+        let count = self.windows.len();
+        self.windows.retain(|window| window.name != name);
+        if self.windows.len() < count {
+            self.redraw_layout()?;
+        }
+        Ok(())
+    }
+
+    fn clear_window(&mut self, name: &str) -> Result<()> {
+        if let Some(window) = find_window(&mut self.windows, name) {
+            window.clear();
+            window.draw(&mut self.screen)?;
+            write!(self.screen, "{}", self.goto_prompt())?;
+        }
+        Ok(())
+    }
+
+    fn scroll_window(&mut self, name: &str, lines: Option<i32>) -> Result<()> {
+        if let Some(window) = find_window(&mut self.windows, name) {
+            window.scroll(lines);
+            window.draw(&mut self.screen)?;
+            write!(self.screen, "{}", self.goto_prompt())?;
+        }
+        Ok(())
+    }
+
+    fn windows(&self) -> Vec<(String, WindowOptions)> {
+        self.windows
+            .iter()
+            .map(|window| (window.name.clone(), window.options.clone()))
+            .collect()
+    }
+
+    fn mouse_press(&mut self, x: u16, y: u16) -> Result<()> {
+        self.selection = self.position_at(x, y).map(Selection::new);
+        Ok(())
+    }
+
+    fn mouse_hold(&mut self, x: u16, y: u16) -> Result<()> {
+        if let (Some(pos), Some(selection)) = (self.position_at(x, y), &mut self.selection) {
+            selection.head = pos;
+            selection.dragged = true;
+            self.draw_output_rows()?;
+        }
+        Ok(())
+    }
+
+    fn mouse_release(&mut self, x: u16, y: u16) -> Result<Option<Link>> {
+        let mut selection = match self.selection {
+            Some(selection) => selection,
+            None => return Ok(None),
+        };
+        if !selection.dragged {
+            self.selection = None;
+            return Ok(self.link_at(selection.anchor));
+        }
+        if let Some(pos) = self.position_at(x, y) {
+            selection.head = pos;
+        }
+        let text = self.selected_text(&selection);
+        self.selection = None;
+        write!(self.screen, "{}", Osc52(&text))?;
+        self.draw_output_rows()?;
+        if self.scroll_data.active {
+            self.draw_scroll()?;
+        }
+        write!(self.screen, "{}", self.goto_prompt())?;
+        Ok(None)
+    }
+
     fn flush(&mut self) {
         self.screen.flush().unwrap();
     }
@@ -501,6 +849,18 @@ impl UserInterface for SplitScreen {
         self.reset()?;
         Ok((self.screen, self.history))
     }
+
+    fn release_writer(&mut self) -> Box<dyn Write> {
+        std::mem::replace(&mut self.screen, Box::new(io::sink()))
+    }
+
+    fn acquire_writer(&mut self, writer: Box<dyn Write>) -> Result<()> {
+        self.screen = writer;
+        self.setup()?;
+        let input_str = self.prompt_input.as_str().to_owned();
+        self.print_prompt_input(&input_str, self.prompt_input_pos);
+        Ok(())
+    }
 }
 
 impl SplitScreen {
@@ -521,17 +881,28 @@ impl SplitScreen {
             height,
             output_start_line,
             output_line,
+            output_x: 1,
+            output_width: width,
             mud_prompt_line,
             mud_prompt: Line::from(""),
             status_area,
             prompt_line,
+            input_height: 1,
             cursor_prompt_pos: 1,
+            cursor_prompt_line: prompt_line,
             history,
             scroll_data: ScrollData::new(),
             connection: None,
             tags: HashSet::new(),
+            sessions: vec![],
+            latency: None,
             prompt_input: String::new(),
             prompt_input_pos: 0,
+            input_mode: InputMode::default(),
+            composing: false,
+            windows: vec![],
+            row_links: VecDeque::new(),
+            selection: None,
         })
     }
 
@@ -542,11 +913,40 @@ impl SplitScreen {
                 self.screen,
                 "{}\r\n{}{}",
                 termion::cursor::Goto(1, self.output_line),
+                termion::cursor::Goto(self.output_x, self.output_line),
                 &line,
-                self.goto_prompt(),
             )
             .unwrap();
+            // Side windows scroll along with the rows they share
+            self.redraw_side_windows().unwrap();
+            write!(self.screen, "{}", self.goto_prompt()).unwrap();
+        }
+    }
+
+    /// Wraps lines to the main output, so they don't run into side windows
+    fn print_wrapped(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            self.print_line(line);
+        } else {
+            for l in wrap_line(line, self.output_width as usize) {
+                self.print_line(l);
+            }
+        }
+    }
+
+    fn redraw_side_windows(&mut self) -> Result<()> {
+        for window in self.windows.iter().filter(|window| window.is_side()) {
+            window.draw(&mut self.screen)?;
         }
+        Ok(())
+    }
+
+    /// Recomputes the layout after a change of windows
+    fn redraw_layout(&mut self) -> Result<()> {
+        self.setup()?;
+        let input_str = self.prompt_input.as_str().to_owned();
+        self.print_prompt_input(&input_str, self.prompt_input_pos);
+        Ok(())
     }
 
     fn clear_prompt(&mut self) {
@@ -584,10 +984,13 @@ impl SplitScreen {
                 termion::clear::CurrentLine,
                 Fg(color::Green),
             )?;
-            let host = if let Some(connection) = &self.connection {
-                format!("═ {connection} ")
-            } else {
-                "".to_string()
+            let sessions = format_session_tabs(&self.sessions);
+            let host = match (&self.connection, self.latency) {
+                (Some(connection), Some(latency)) => {
+                    format!("═ {connection} ({}ms) ", latency.as_millis())
+                }
+                (Some(connection), None) => format!("═ {connection} "),
+                _ => "".to_string(),
             };
             let mut tags = self
                 .tags
@@ -596,7 +999,7 @@ impl SplitScreen {
                 .collect::<Vec<String>>();
             tags.sort();
             let tags = tags.join("");
-            let mut output = format!("{host}{tags}");
+            let mut output = format!("{sessions}{host}{tags}");
             if !output.is_empty() {
                 output.push(' ');
             }
@@ -617,10 +1020,67 @@ impl SplitScreen {
     fn goto_prompt(&self) -> String {
         format!(
             "{}",
-            termion::cursor::Goto(self.cursor_prompt_pos, self.prompt_line),
+            termion::cursor::Goto(self.cursor_prompt_pos, self.cursor_prompt_line),
         )
     }
 
+    /// Draws the lines being composed below a header, scrolled to the cursor
+    fn print_compose_input(&mut self, input: &str, pos: usize) {
+        let lines: Vec<&str> = input.split('\n').collect();
+        let (row, column) = cursor_line(input, pos);
+        let text_rows = self.input_height.saturating_sub(1) as usize;
+        let first = (row + 1).saturating_sub(text_rows);
+        let width = self.width as usize;
+        let header = format!(
+            "-- COMPOSE {}/{} -- {}",
+            row + 1,
+            lines.len(),
+            self.input_mode.label().unwrap_or_default()
+        );
+        write!(
+            self.screen,
+            "{}{}{}{}{}{}{}{}",
+            termion::cursor::Save,
+            termion::cursor::Goto(1, self.prompt_line),
+            termion::style::Reset,
+            termion::clear::CurrentLine,
+            Fg(color::LightBlack),
+            header.trim_end(),
+            Fg(color::Reset),
+            Bg(color::Reset),
+        )
+        .unwrap();
+        for i in 0..text_rows {
+            let index = first + i;
+            let screen_line = self.prompt_line + 1 + i as u16;
+            let text = match lines.get(index) {
+                Some(line) if index == row => {
+                    let (text, column) = visible_input(line, column, width);
+                    self.cursor_prompt_pos = column as u16 + 1;
+                    self.cursor_prompt_line = screen_line;
+                    text
+                }
+                Some(line) => visible_input(line, 0, width).0,
+                None => "",
+            };
+            write!(
+                self.screen,
+                "{}{}{}",
+                termion::cursor::Goto(1, screen_line),
+                termion::clear::CurrentLine,
+                text,
+            )
+            .unwrap();
+        }
+        write!(
+            self.screen,
+            "{}{}",
+            termion::cursor::Restore,
+            self.goto_prompt()
+        )
+        .unwrap();
+    }
+
     fn init_scroll(&mut self) -> Result<()> {
         self.scroll_data.active = true;
         if self.scroll_range() < self.output_range() {
@@ -630,17 +1090,17 @@ impl SplitScreen {
             write!(
                 self.screen,
                 "{}{}",
-                ScrollRegion(scroll_range + 3, self.output_line),
+                ScrollRegion(scroll_range + self.output_start_line + 1, self.output_line),
                 DisableOriginMode
             )?;
             write!(
                 self.screen,
                 "{}{}{:━<4$}{}",
-                cursor::Goto(1, scroll_range + self.output_start_line),
+                cursor::Goto(self.output_x, scroll_range + self.output_start_line),
                 color::Fg(color::Green),
                 "━ (scroll) ",
                 color::Fg(color::Reset),
-                self.width as usize
+                self.output_width as usize
             )?;
         } else {
             self.status_area.set_scroll_marker(true);
@@ -673,16 +1133,118 @@ impl SplitScreen {
             write!(
                 self.screen,
                 "{}{}{}",
-                termion::cursor::Goto(1, line_no),
+                termion::cursor::Goto(self.output_x, line_no),
                 termion::clear::CurrentLine,
                 line,
             )?;
         }
+        self.redraw_side_windows()
+    }
+
+    /// The history index of the row shown on a line of the output area
+    fn row_at(&self, y: u16) -> Option<usize> {
+        if y < self.output_start_line || y > self.output_line {
+            return None;
+        }
+        let scroll_end = self.output_start_line + self.scroll_range();
+        if self.scroll_data.active && y < scroll_end {
+            let index = self.scroll_data.pos + (y - self.output_start_line) as usize;
+            return (index < self.history.len()).then_some(index);
+        }
+        if self.scroll_data.split && y == scroll_end {
+            return None;
+        }
+        // Rows below any scrolled part are aligned with the newest line
+        let from_bottom = (self.output_line - y) as usize;
+        self.history.len().checked_sub(from_bottom + 1)
+    }
+
+    /// The row, counting drained history, and column of a screen position
+    fn position_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        if x < self.output_x || x >= self.output_x + self.output_width {
+            return None;
+        }
+        let index = self.row_at(y)?;
+        Some((self.history.drained + index, (x - self.output_x) as usize))
+    }
+
+    fn clean_row(&self, row: usize) -> Option<String> {
+        let index = row.checked_sub(self.history.drained)?;
+        let line = self.history.inner.get(index)?;
+        Some(Line::from(line.as_str()).clean_line().to_string())
+    }
+
+    /// The link of a row at a column, a script or MXP link or an url
+    fn link_at(&self, (row, column): (usize, usize)) -> Option<Link> {
+        let contains = |link: &Link| link.start <= column && column < link.end;
+        let links = self
+            .row_links
+            .iter()
+            .find(|(link_row, _)| *link_row == row)
+            .and_then(|(_, links)| links.iter().find(|link| contains(link)));
+        match links {
+            Some(link) => Some(link.clone()),
+            None => Link::find_urls(&self.clean_row(row)?)
+                .into_iter()
+                .find(contains),
+        }
+    }
+
+    fn selected_text(&self, selection: &Selection) -> String {
+        selection
+            .rows()
+            .filter_map(|row| {
+                let (from, to) = selection.columns(row)?;
+                let text = self.clean_row(row)?;
+                Some(slice_columns(&text, from, to).trim_end().to_string())
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Redraws the rows of the main output, showing any selection
+    fn draw_output_rows(&mut self) -> Result<()> {
+        for y in self.output_start_line..=self.output_line {
+            if self.scroll_data.split && y == self.output_start_line + self.scroll_range() {
+                continue;
+            }
+            write!(
+                self.screen,
+                "{}{}",
+                termion::cursor::Goto(self.output_x, y),
+                termion::clear::CurrentLine,
+            )?;
+            let index = match self.row_at(y) {
+                Some(index) => index,
+                None => continue,
+            };
+            let row = self.history.drained + index;
+            match self.selection.and_then(|selection| selection.columns(row)) {
+                Some((from, to)) => {
+                    let text = self.clean_row(row).unwrap_or_default();
+                    write!(
+                        self.screen,
+                        "{}{}{}{}{}",
+                        slice_columns(&text, 0, from),
+                        style::Invert,
+                        slice_columns(&text, from, to),
+                        style::NoInvert,
+                        slice_columns(&text, to, usize::MAX),
+                    )?;
+                }
+                None => write!(self.screen, "{}", self.history.inner[index])?,
+            }
+        }
+        self.redraw_side_windows()?;
+        write!(self.screen, "{}", self.goto_prompt())?;
         Ok(())
     }
 
     fn scroll_range(&self) -> u16 {
-        if self.scroll_data.allow_split && self.height > SCROLL_LIVE_BUFFER_SIZE * 2 {
+        if self.scroll_data.allow_split
+            && self.height > SCROLL_LIVE_BUFFER_SIZE * 2
+            && self.output_range() > SCROLL_LIVE_BUFFER_SIZE
+        {
             self.output_line - self.output_start_line - SCROLL_LIVE_BUFFER_SIZE + 1
         } else {
             self.output_range()
@@ -694,6 +1256,29 @@ impl SplitScreen {
     }
 }
 
+/// Renders the session list for the top bar, e.g. `═ [1:main] 2:alt* `.
+/// Nothing is shown while there is only one session.
+fn format_session_tabs(sessions: &[SessionTab]) -> String {
+    if sessions.len() < 2 {
+        return String::new();
+    }
+    let tabs = sessions
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| {
+            let mut label = format!("{}:{}", i + 1, tab.name);
+            if tab.active {
+                label = format!("[{label}]");
+            } else if tab.activity {
+                label.push('*');
+            }
+            label
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    format!("═ {tabs} ")
+}
+
 #[cfg(test)]
 mod screen_test {
     use super::*;
@@ -730,6 +1315,63 @@ mod screen_test {
         assert_eq!(history.find_backward(&re, 2), None);
     }
 
+    #[test]
+    fn test_format_session_tabs() {
+        let tab = |name: &str, active: bool, activity: bool| SessionTab {
+            name: name.to_string(),
+            active,
+            activity,
+        };
+        assert_eq!(format_session_tabs(&[]), "");
+        assert_eq!(format_session_tabs(&[tab("main", true, false)]), "");
+        assert_eq!(
+            format_session_tabs(&[
+                tab("main", true, false),
+                tab("alt", false, true),
+                tab("bank", false, false),
+            ]),
+            "═ [1:main] 2:alt* 3:bank "
+        );
+    }
+
+    #[test]
+    fn test_split_links() {
+        let mut line = Line::from("\x1b[31mExits:\x1b[0m north south");
+        let link = |start: usize, end: usize| Link {
+            text: "north".to_string(),
+            action: crate::model::LinkAction::Send(vec!["north".to_string()]),
+            hint: None,
+            prompt: false,
+            start,
+            end,
+        };
+        line.links = vec![link(7, 12), link(5, 15)];
+        let rows = wrap_line(line.line(), 9);
+        assert_eq!(rows.len(), 3);
+        let links = split_links(&line, &rows);
+        let columns: Vec<Vec<(usize, usize)>> = links
+            .iter()
+            .map(|row| row.iter().map(|link| (link.start, link.end)).collect())
+            .collect();
+        assert_eq!(
+            columns,
+            vec![vec![(5, 6)], vec![(0, 5), (0, 5)], vec![(0, 2)]]
+        );
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut selection = Selection::new((5, 8));
+        selection.head = (3, 2);
+        assert_eq!(selection.columns(2), None);
+        assert_eq!(selection.columns(3), Some((2, usize::MAX)));
+        assert_eq!(selection.columns(4), Some((0, usize::MAX)));
+        assert_eq!(selection.columns(5), Some((0, 9)));
+        assert_eq!(selection.rows(), 3..=5);
+        assert_eq!(slice_columns("a nice line", 2, 6), "nice");
+        assert_eq!(slice_columns("ä", 0, usize::MAX), "ä");
+    }
+
     #[test]
     fn test_drain_history() {
         let mut history = History::new();
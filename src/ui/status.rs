@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use termion::style;
+
+use crate::model::{Gauge, StatusItem, StatusLayout, StatusWidget};
+
+use super::printable_chars::PrintableCharsIterator;
+
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < 1e15 {
+        format!("{}", number as i64)
+    } else {
+        format!("{number:.1}")
+    }
+}
+
+fn render_gauge(gauge: &Gauge) -> String {
+    let filled = gauge.filled() as usize;
+    let mut text = String::new();
+    if !gauge.label.is_empty() {
+        text.push_str(&gauge.label);
+        text.push(' ');
+    }
+    text.push_str(&format!(
+        "{}{}{}{}{}",
+        gauge.color,
+        "█".repeat(filled),
+        gauge.empty_color,
+        "░".repeat(gauge.width as usize - filled),
+        style::Reset,
+    ));
+    if gauge.show_value {
+        text.push_str(&format!(
+            " {}/{}",
+            format_number(gauge.value),
+            format_number(gauge.max)
+        ));
+    }
+    text
+}
+
+fn render_items(items: &[StatusItem], widgets: &HashMap<String, StatusWidget>) -> String {
+    items
+        .iter()
+        .map(|item| match item {
+            StatusItem::Text(text) => text.clone(),
+            StatusItem::Widget(name) => match widgets.get(name) {
+                Some(StatusWidget::Text(text)) => text.clone(),
+                Some(StatusWidget::Gauge(gauge)) => render_gauge(gauge),
+                None => String::new(),
+            },
+        })
+        .collect()
+}
+
+fn width_of(text: &str) -> usize {
+    text.printable_chars().count()
+}
+
+/// Cuts text to `width` printable chars, ending it with `…` when cut
+fn truncate(text: &str, width: usize) -> String {
+    if width_of(text) <= width {
+        return text.to_string();
+    }
+    match width
+        .checked_sub(1)
+        .and_then(|last| text.printable_char_indices().nth(last))
+    {
+        Some((index, _)) => format!("{}…{}", &text[..index], style::Reset),
+        None => String::new(),
+    }
+}
+
+/// Renders a status line exactly `width` cells wide. The left segment goes
+/// first, then the right one, and the centered one gets what space is left.
+/// Gaps are filled by `fill`, given the number of cells.
+pub fn render_status_line(
+    layout: &StatusLayout,
+    widgets: &HashMap<String, StatusWidget>,
+    width: usize,
+    fill: &dyn Fn(usize) -> String,
+) -> String {
+    let left = truncate(&render_items(&layout.left, widgets), width);
+    let left_width = width_of(&left);
+    let spacing = usize::from(left_width > 0);
+    let right = truncate(
+        &render_items(&layout.right, widgets),
+        width.saturating_sub(left_width + spacing),
+    );
+    let right_width = width_of(&right);
+    let gap = width.saturating_sub(left_width + right_width);
+    let center = truncate(
+        &render_items(&layout.center, widgets),
+        gap.saturating_sub(2),
+    );
+    let center_width = width_of(&center);
+    if center_width == 0 {
+        return format!("{left}{}{right}", fill(gap));
+    }
+    let start =
+        ((width - center_width) / 2).clamp(left_width + 1, width - right_width - center_width - 1);
+    format!(
+        "{left}{}{center}{}{right}",
+        fill(start - left_width),
+        fill(width - right_width - center_width - start),
+    )
+}
+
+#[cfg(test)]
+mod test_status {
+    use super::*;
+
+    fn spaces(count: usize) -> String {
+        " ".repeat(count)
+    }
+
+    fn text(text: &str) -> StatusItem {
+        StatusItem::Text(text.to_string())
+    }
+
+    #[test]
+    fn test_gauge() {
+        let gauge = Gauge {
+            label: "HP".to_string(),
+            value: 30.0,
+            max: 40.0,
+            width: 4,
+            color: String::new(),
+            empty_color: String::new(),
+            show_value: true,
+        };
+        assert_eq!(
+            render_gauge(&gauge),
+            format!("HP ███░{} 30/40", style::Reset)
+        );
+        let gauge = Gauge {
+            value: 2.5,
+            label: String::new(),
+            ..gauge
+        };
+        assert_eq!(render_gauge(&gauge), format!("░░░░{} 2.5/40", style::Reset));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("too long", 5), format!("too …{}", style::Reset));
+        assert_eq!(
+            truncate("\x1b[31mred\x1b[0m text", 4),
+            format!("\x1b[31mred\x1b[0m…{}", style::Reset)
+        );
+        assert_eq!(truncate("text", 0), "");
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut widgets = HashMap::new();
+        widgets.insert("room".to_string(), StatusWidget::Text("Temple".to_string()));
+        let layout = StatusLayout {
+            left: vec![text("L")],
+            center: vec![StatusItem::Widget("room".to_string())],
+            right: vec![text("RR"), StatusItem::Widget("missing".to_string())],
+        };
+        assert_eq!(
+            render_status_line(&layout, &widgets, 20, &spaces),
+            "L      Temple     RR"
+        );
+        assert_eq!(
+            render_status_line(&layout, &widgets, 8, &spaces),
+            format!("L Te…{} RR", style::Reset)
+        );
+        assert_eq!(
+            render_status_line(&layout, &widgets, 3, &spaces),
+            format!("L …{}", style::Reset)
+        );
+        let layout = StatusLayout {
+            right: vec![text("right")],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_status_line(&layout, &widgets, 8, &|n| "━".repeat(n)),
+            "━━━right"
+        );
+    }
+}
@@ -1,22 +1,58 @@
 use std::{
-    io::{stdout, Write},
+    io::{self, stdout, Write},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::{
     io::SaveData,
-    model::{Settings, MOUSE_ENABLED, READER_MODE},
+    model::{
+        Link, Settings, StatusLayout, StatusWidget, WindowOptions, MOUSE_ENABLED, READER_MODE,
+    },
     session::Session,
     tts::TTSController,
 };
 
-use super::{history::History, HeadlessScreen, ReaderScreen, SplitScreen, UserInterface};
+use super::{
+    history::History, HeadlessScreen, InputMode, ReaderScreen, SessionTab, SplitScreen,
+    UserInterface,
+};
 use anyhow::Result;
 use termion::{input::MouseTerminal, raw::IntoRawMode, screen::IntoAlternateScreen};
 
+/// Has the terminal mark pasted text, so pasted lines aren't sent one by one
+struct BracketedPasteTerminal<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BracketedPasteTerminal<W> {
+    fn new(mut writer: W) -> Result<Self> {
+        write!(writer, "\x1b[?2004h")?;
+        writer.flush()?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> Drop for BracketedPasteTerminal<W> {
+    fn drop(&mut self) {
+        write!(self.writer, "\x1b[?2004l").ok();
+        self.writer.flush().ok();
+    }
+}
+
+impl<W: Write> Write for BracketedPasteTerminal<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
 /// Creates the io::Write terminal handler we draw to.
 fn create_screen_writer(mouse_support: bool) -> Result<Box<dyn Write>> {
-    let screen = stdout().into_alternate_screen()?.into_raw_mode()?;
+    let screen = BracketedPasteTerminal::new(stdout().into_alternate_screen()?.into_raw_mode()?)?;
     if mouse_support {
         Ok(Box::new(MouseTerminal::from(screen)))
     } else {
@@ -24,16 +60,41 @@ fn create_screen_writer(mouse_support: bool) -> Result<Box<dyn Write>> {
     }
 }
 
+/// Gives the terminal back to the shell while running `run`, eg. to open an
+/// editor, and redraws the screen with a new terminal handler after. The
+/// screen always gets a writer back, failures are printed on it.
+pub fn suspend_screen<T>(screen: &mut dyn UserInterface, run: impl FnOnce() -> T) -> T {
+    drop(screen.release_writer());
+    let result = run();
+    let mouse_support = Settings::try_load()
+        .and_then(|settings| Ok(!settings.get(READER_MODE)? && settings.get(MOUSE_ENABLED)?))
+        .unwrap_or(false);
+    let (writer, error) = match create_screen_writer(mouse_support) {
+        Ok(writer) => (writer, None),
+        Err(err) => (Box::new(stdout()) as Box<dyn Write>, Some(err)),
+    };
+    if let Err(err) = screen.acquire_writer(writer) {
+        screen.print_error(&format!("Failed to restore the screen: {err}"));
+    }
+    if let Some(err) = error {
+        screen.print_error(&format!("Failed to restore the terminal: {err}"));
+    }
+    result
+}
+
 pub struct UiWrapper {
     screen: Box<dyn UserInterface>,
     tts_ctrl: Arc<Mutex<TTSController>>,
+    background: bool,
+    /// Only changes of the mode are announced
+    input_mode: InputMode,
 }
 
 impl UiWrapper {
     pub fn new(session: &Session) -> Result<Self> {
         let settings = Settings::try_load()?;
         let reader_mode = settings.get(READER_MODE)?;
-        let screen: Box<dyn UserInterface> = if reader_mode {
+        let mut screen: Box<dyn UserInterface> = if reader_mode {
             Box::new(ReaderScreen::new(
                 create_screen_writer(false)?,
                 History::new(),
@@ -44,9 +105,35 @@ impl UiWrapper {
                 History::new(),
             )?)
         };
+        let input_mode = session.command_buffer.lock().unwrap().mode();
+        screen.set_input_mode(input_mode)?;
         let tts_ctrl = session.tts_ctrl.clone();
 
-        Ok(Self { screen, tts_ctrl })
+        Ok(Self {
+            screen,
+            tts_ctrl,
+            background: false,
+            input_mode,
+        })
+    }
+
+    /// Creates a screen for a session running in the background. Nothing is
+    /// drawn or spoken until it's handed the terminal writer.
+    pub fn background(session: &Session) -> Result<Self> {
+        let reader_mode = Settings::try_load()?.get(READER_MODE)?;
+        let mut screen: Box<dyn UserInterface> = if reader_mode {
+            Box::new(ReaderScreen::new(Box::new(io::sink()), History::new())?)
+        } else {
+            Box::new(SplitScreen::new(Box::new(io::sink()), History::new())?)
+        };
+        let input_mode = session.command_buffer.lock().unwrap().mode();
+        screen.set_input_mode(input_mode)?;
+        Ok(Self {
+            screen,
+            tts_ctrl: session.tts_ctrl.clone(),
+            background: true,
+            input_mode,
+        })
     }
 
     pub fn new_from(
@@ -54,6 +141,7 @@ impl UiWrapper {
         session: &Session,
         reader_mode: bool,
     ) -> Result<Self> {
+        let windows = screen.windows();
         let (writer, history) = screen.destroy()?;
         let mut screen: Box<dyn UserInterface> = if reader_mode {
             Box::new(ReaderScreen::new(writer, history)?)
@@ -61,9 +149,16 @@ impl UiWrapper {
             Box::new(SplitScreen::new(writer, history)?)
         };
         screen.setup()?;
+        let input_mode = session.command_buffer.lock().unwrap().mode();
+        screen.set_input_mode(input_mode)?;
+        for (name, options) in windows {
+            screen.add_window(&name, options)?;
+        }
         Ok(Self {
             screen,
             tts_ctrl: session.tts_ctrl.clone(),
+            background: false,
+            input_mode,
         })
     }
 
@@ -71,8 +166,16 @@ impl UiWrapper {
         Ok(Self {
             screen: Box::new(HeadlessScreen {}),
             tts_ctrl: session.tts_ctrl.clone(),
+            background: false,
+            input_mode: session.command_buffer.lock().unwrap().mode(),
         })
     }
+
+    fn speak(&self, speak: impl FnOnce(&TTSController)) {
+        if !self.background {
+            speak(&self.tts_ctrl.lock().unwrap());
+        }
+    }
 }
 
 impl UserInterface for UiWrapper {
@@ -81,22 +184,22 @@ impl UserInterface for UiWrapper {
     }
 
     fn print_error(&mut self, output: &str) {
-        self.tts_ctrl.lock().unwrap().speak_error(output);
+        self.speak(|tts| tts.speak_error(output));
         self.screen.print_error(output);
     }
 
     fn print_info(&mut self, output: &str) {
-        self.tts_ctrl.lock().unwrap().speak_info(output);
+        self.speak(|tts| tts.speak_info(output));
         self.screen.print_info(output);
     }
 
     fn print_output(&mut self, line: &crate::model::Line) {
-        self.tts_ctrl.lock().unwrap().speak_line(line);
+        self.speak(|tts| tts.speak_line(line));
         self.screen.print_output(line);
     }
 
     fn print_prompt(&mut self, prompt: &crate::model::Line) {
-        self.tts_ctrl.lock().unwrap().speak_line(prompt);
+        self.speak(|tts| tts.speak_line(prompt));
         self.screen.print_prompt(prompt);
     }
 
@@ -106,7 +209,7 @@ impl UserInterface for UiWrapper {
 
     fn print_send(&mut self, send: &crate::model::Line) {
         if let Some(line) = send.print_line() {
-            self.tts_ctrl.lock().unwrap().speak_input(line);
+            self.speak(|tts| tts.speak_input(line));
         }
         self.screen.print_send(send);
     }
@@ -167,6 +270,73 @@ impl UserInterface for UiWrapper {
         self.screen.set_status_line(line, info)
     }
 
+    fn set_status_layout(&mut self, line: usize, layout: Option<StatusLayout>) -> Result<()> {
+        self.screen.set_status_layout(line, layout)
+    }
+
+    fn set_status_widget(&mut self, name: &str, widget: Option<StatusWidget>) -> Result<()> {
+        self.screen.set_status_widget(name, widget)
+    }
+
+    fn set_sessions(&mut self, sessions: &[SessionTab]) -> Result<()> {
+        self.screen.set_sessions(sessions)
+    }
+
+    fn set_latency(&mut self, latency: Option<Duration>) -> Result<()> {
+        self.screen.set_latency(latency)
+    }
+
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<()> {
+        if mode != std::mem::replace(&mut self.input_mode, mode) {
+            if let Some(label) = mode.label() {
+                self.speak(|tts| tts.speak(&format!("{} mode", label.to_lowercase()), true));
+            }
+        }
+        self.screen.set_input_mode(mode)
+    }
+
+    fn set_composing(&mut self, composing: bool) -> Result<()> {
+        let msg = if composing {
+            "composing"
+        } else {
+            "done composing"
+        };
+        self.speak(|tts| tts.speak(msg, true));
+        self.screen.set_composing(composing)
+    }
+
+    fn add_window(&mut self, name: &str, options: WindowOptions) -> Result<()> {
+        self.screen.add_window(name, options)
+    }
+
+    fn remove_window(&mut self, name: &str) -> Result<()> {
+        self.screen.remove_window(name)
+    }
+
+    fn clear_window(&mut self, name: &str) -> Result<()> {
+        self.screen.clear_window(name)
+    }
+
+    fn scroll_window(&mut self, name: &str, lines: Option<i32>) -> Result<()> {
+        self.screen.scroll_window(name, lines)
+    }
+
+    fn windows(&self) -> Vec<(String, WindowOptions)> {
+        self.screen.windows()
+    }
+
+    fn mouse_press(&mut self, x: u16, y: u16) -> Result<()> {
+        self.screen.mouse_press(x, y)
+    }
+
+    fn mouse_hold(&mut self, x: u16, y: u16) -> Result<()> {
+        self.screen.mouse_hold(x, y)
+    }
+
+    fn mouse_release(&mut self, x: u16, y: u16) -> Result<Option<Link>> {
+        self.screen.mouse_release(x, y)
+    }
+
     fn flush(&mut self) {
         self.screen.flush();
     }
@@ -182,4 +352,14 @@ impl UserInterface for UiWrapper {
     fn destroy(self: Box<Self>) -> Result<(Box<dyn Write>, History)> {
         self.screen.destroy()
     }
+
+    fn release_writer(&mut self) -> Box<dyn Write> {
+        self.background = true;
+        self.screen.release_writer()
+    }
+
+    fn acquire_writer(&mut self, writer: Box<dyn Write>) -> Result<()> {
+        self.background = false;
+        self.screen.acquire_writer(writer)
+    }
 }
@@ -1,13 +1,13 @@
-use std::{error, fmt, io::Write};
+use std::{error, fmt, io::Write, time::Duration};
 
 #[cfg(test)]
 use mockall::automock;
 
-use crate::model::{Line, Regex};
+use crate::model::{Line, Link, Regex, StatusLayout, StatusWidget, WindowOptions};
 
 use anyhow::Result;
 
-use super::history::History;
+use super::{history::History, InputMode};
 
 #[derive(Debug)]
 pub struct TerminalSizeError;
@@ -30,6 +30,14 @@ impl error::Error for TerminalSizeError {
     }
 }
 
+/// A session as presented in the top bar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionTab {
+    pub name: String,
+    pub active: bool,
+    pub activity: bool,
+}
+
 #[cfg_attr(test, automock)]
 pub trait UserInterface {
     fn setup(&mut self) -> Result<()>;
@@ -53,10 +61,48 @@ pub trait UserInterface {
     fn clear_tags(&mut self) -> Result<()>;
     fn set_status_area_height(&mut self, height: u16) -> Result<()>;
     fn set_status_line(&mut self, line: usize, info: String) -> Result<()>;
+    /// Shows a line of widgets in the status area, None clears the line
+    fn set_status_layout(&mut self, line: usize, layout: Option<StatusLayout>) -> Result<()>;
+    /// Adds or updates a status widget, None removes it
+    fn set_status_widget(&mut self, name: &str, widget: Option<StatusWidget>) -> Result<()>;
+    fn set_sessions(&mut self, sessions: &[SessionTab]) -> Result<()>;
+    fn set_latency(&mut self, latency: Option<Duration>) -> Result<()>;
+    /// Shows the editing mode of the input line
+    fn set_input_mode(&mut self, mode: InputMode) -> Result<()>;
+    /// Makes room for composing several lines of input, or goes back to one
+    fn set_composing(&mut self, composing: bool) -> Result<()>;
+    /// Adds a named output window, or changes the options of an existing one
+    fn add_window(&mut self, name: &str, options: WindowOptions) -> Result<()>;
+    fn remove_window(&mut self, name: &str) -> Result<()>;
+    fn clear_window(&mut self, name: &str) -> Result<()>;
+    /// Scrolls a window back by `lines`, or to its newest line when None
+    fn scroll_window(&mut self, name: &str, lines: Option<i32>) -> Result<()>;
+    fn windows(&self) -> Vec<(String, WindowOptions)>;
+    /// The left mouse button was pressed at a column and row
+    fn mouse_press(&mut self, x: u16, y: u16) -> Result<()>;
+    /// The mouse moved with the left button held, extending a selection
+    fn mouse_hold(&mut self, x: u16, y: u16) -> Result<()>;
+    /// Ends a selection, copying it. Returns the link clicked, if any.
+    fn mouse_release(&mut self, x: u16, y: u16) -> Result<Option<Link>>;
     fn flush(&mut self);
     fn width(&self) -> u16;
     fn height(&self) -> u16;
     fn destroy(self: Box<Self>) -> Result<(Box<dyn Write>, History)>;
+    /// Hands over the terminal writer, leaving the screen drawing nowhere
+    fn release_writer(&mut self) -> Box<dyn Write>;
+    /// Takes over the terminal writer and redraws the screen
+    fn acquire_writer(&mut self, writer: Box<dyn Write>) -> Result<()>;
+}
+
+/// The line of a multi-line input the cursor is on, and the column within it
+pub fn cursor_line(input: &str, pos: usize) -> (usize, usize) {
+    input
+        .chars()
+        .take(pos)
+        .fold((0, 0), |(line, column), c| match c {
+            '\n' => (line + 1, 0),
+            _ => (line, column + 1),
+        })
 }
 
 pub fn wrap_line(line: &str, width: usize) -> Vec<&str> {
@@ -138,6 +184,16 @@ mod tests {
         assert_eq!(iter.next(), Some(&"annoying\u{1b}[0m"));
     }
 
+    #[test]
+    fn test_cursor_line() {
+        let input = "first\nsecond\n\nlast";
+        assert_eq!(cursor_line(input, 0), (0, 0));
+        assert_eq!(cursor_line(input, 5), (0, 5));
+        assert_eq!(cursor_line(input, 6), (1, 0));
+        assert_eq!(cursor_line(input, 13), (2, 0));
+        assert_eq!(cursor_line(input, 18), (3, 4));
+    }
+
     #[test]
     fn test_long_line_no_space() {
         let mut line = String::new();
@@ -0,0 +1,439 @@
+/// How keys typed at the prompt edit the input line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputMode {
+    #[default]
+    Emacs,
+    ViInsert,
+    ViNormal,
+}
+
+impl InputMode {
+    /// The label shown next to the input line, emacs mode has none
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            InputMode::Emacs => None,
+            InputMode::ViInsert => Some("INSERT"),
+            InputMode::ViNormal => Some("NORMAL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Start,
+    FirstNonBlank,
+    End,
+    /// `w`, `W` when true
+    WordForward(bool),
+    /// `b`, `B` when true
+    WordBackward(bool),
+    /// `e`, `E` when true
+    WordEnd(bool),
+    Find {
+        target: char,
+        forward: bool,
+        till: bool,
+    },
+    /// `;`, or `,` when true
+    RepeatFind(bool),
+    /// The whole line, as in `dd`
+    Line,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    Start,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViCommand {
+    Move(Motion),
+    Operate(Operator, Motion),
+    Insert(InsertAt),
+    Replace(char),
+    ToggleCase,
+    Put { before: bool },
+    Undo,
+    Redo,
+    Repeat,
+    HistoryPrevious,
+    HistoryNext,
+}
+
+impl ViCommand {
+    /// If `.` should repeat this command
+    pub fn is_change(&self) -> bool {
+        match self {
+            ViCommand::Operate(op, _) => *op != Operator::Yank,
+            ViCommand::Insert(_)
+            | ViCommand::Replace(_)
+            | ViCommand::ToggleCase
+            | ViCommand::Put { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Turns the keys typed in normal mode into commands, keeping track of
+/// counts and operators waiting for a motion.
+#[derive(Debug, Default)]
+pub struct ViParser {
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    pending: Option<char>,
+}
+
+impl ViParser {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// If part of a command has been typed
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.pending.is_some()
+    }
+
+    /// Feeds a key, returning a command with its count once one is complete
+    pub fn feed(&mut self, c: char) -> Option<(ViCommand, usize)> {
+        if let Some(pending) = self.pending.take() {
+            let count = self.take_count();
+            let motion = match pending {
+                'r' => return self.command(ViCommand::Replace(c), count),
+                _ => Motion::Find {
+                    target: c,
+                    forward: pending == 'f' || pending == 't',
+                    till: pending == 't' || pending == 'T',
+                },
+            };
+            return self.motion(motion, count);
+        }
+
+        if let Some(digit) = c.to_digit(10) {
+            if digit != 0 || self.count.is_some() {
+                let count = self.count.unwrap_or(0).saturating_mul(10);
+                self.count = Some(count.saturating_add(digit as usize).min(1000));
+                return None;
+            }
+        }
+
+        let motion = match c {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            '0' => Motion::Start,
+            '^' => Motion::FirstNonBlank,
+            '$' => Motion::End,
+            'w' | 'W' => Motion::WordForward(c == 'W'),
+            'b' | 'B' => Motion::WordBackward(c == 'B'),
+            'e' | 'E' => Motion::WordEnd(c == 'E'),
+            ';' | ',' => Motion::RepeatFind(c == ','),
+            'f' | 'F' | 't' | 'T' | 'r' => {
+                self.pending = Some(c);
+                return None;
+            }
+            'd' | 'c' | 'y' => {
+                let op = match c {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    _ => Operator::Yank,
+                };
+                return match self.operator {
+                    Some((pending, _)) if pending == op => self.motion(Motion::Line, 1),
+                    Some(_) => {
+                        self.reset();
+                        None
+                    }
+                    None => {
+                        self.operator = Some((op, self.take_count()));
+                        None
+                    }
+                };
+            }
+            _ => {
+                let count = self.take_count();
+                let command = match c {
+                    'i' => ViCommand::Insert(InsertAt::Cursor),
+                    'a' => ViCommand::Insert(InsertAt::After),
+                    'I' => ViCommand::Insert(InsertAt::Start),
+                    'A' => ViCommand::Insert(InsertAt::End),
+                    'x' => ViCommand::Operate(Operator::Delete, Motion::Right),
+                    'X' => ViCommand::Operate(Operator::Delete, Motion::Left),
+                    's' => ViCommand::Operate(Operator::Change, Motion::Right),
+                    'S' => ViCommand::Operate(Operator::Change, Motion::Line),
+                    'C' => ViCommand::Operate(Operator::Change, Motion::End),
+                    'D' => ViCommand::Operate(Operator::Delete, Motion::End),
+                    'Y' => ViCommand::Operate(Operator::Yank, Motion::Line),
+                    '~' => ViCommand::ToggleCase,
+                    'p' => ViCommand::Put { before: false },
+                    'P' => ViCommand::Put { before: true },
+                    'u' => ViCommand::Undo,
+                    '.' => ViCommand::Repeat,
+                    'k' => ViCommand::HistoryPrevious,
+                    'j' => ViCommand::HistoryNext,
+                    _ => {
+                        self.reset();
+                        return None;
+                    }
+                };
+                return self.command(command, count);
+            }
+        };
+        let count = self.take_count();
+        self.motion(motion, count)
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1)
+    }
+
+    fn motion(&mut self, motion: Motion, count: usize) -> Option<(ViCommand, usize)> {
+        match self.operator.take() {
+            Some((op, op_count)) => Some((ViCommand::Operate(op, motion), op_count * count)),
+            None => Some((ViCommand::Move(motion), count)),
+        }
+    }
+
+    fn command(&mut self, command: ViCommand, count: usize) -> Option<(ViCommand, usize)> {
+        // A command that isn't a motion cancels a waiting operator
+        if self.operator.take().is_some() {
+            return None;
+        }
+        Some((command, count))
+    }
+}
+
+fn class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn word_forward(buffer: &[char], pos: usize, big: bool) -> usize {
+    let len = buffer.len();
+    let mut i = pos;
+    if i < len {
+        let start = class(buffer[i], big);
+        if start != 0 {
+            while i < len && class(buffer[i], big) == start {
+                i += 1;
+            }
+        }
+    }
+    while i < len && class(buffer[i], big) == 0 {
+        i += 1;
+    }
+    i
+}
+
+fn word_backward(buffer: &[char], pos: usize, big: bool) -> usize {
+    let mut i = pos.min(buffer.len());
+    while i > 0 && class(buffer[i - 1], big) == 0 {
+        i -= 1;
+    }
+    if i > 0 {
+        let start = class(buffer[i - 1], big);
+        while i > 0 && class(buffer[i - 1], big) == start {
+            i -= 1;
+        }
+    }
+    i
+}
+
+fn word_end(buffer: &[char], pos: usize, big: bool) -> usize {
+    let len = buffer.len();
+    let mut i = pos + 1;
+    while i < len && class(buffer[i], big) == 0 {
+        i += 1;
+    }
+    if i >= len {
+        return len.saturating_sub(1);
+    }
+    let start = class(buffer[i], big);
+    while i + 1 < len && class(buffer[i + 1], big) == start {
+        i += 1;
+    }
+    i
+}
+
+/// The end of the text `cw` changes, which stops at the end of the word under
+/// the cursor instead of the start of the next one
+pub fn change_word_end(buffer: &[char], pos: usize, count: usize, big: bool) -> usize {
+    let start = class(buffer[pos], big);
+    let mut end = pos;
+    while end + 1 < buffer.len() && class(buffer[end + 1], big) == start {
+        end += 1;
+    }
+    for _ in 1..count {
+        end = word_end(buffer, end, big);
+    }
+    (end + 1).min(buffer.len())
+}
+
+fn find(buffer: &[char], pos: usize, target: char, forward: bool, till: bool) -> Option<usize> {
+    let found = if forward {
+        let from = (pos + 1).min(buffer.len());
+        buffer[from..]
+            .iter()
+            .position(|c| *c == target)
+            .map(|i| from + i)
+    } else {
+        buffer[..pos.min(buffer.len())]
+            .iter()
+            .rposition(|c| *c == target)
+    }?;
+    Some(match (till, forward) {
+        (false, _) => found,
+        (true, true) => found - 1,
+        (true, false) => found + 1,
+    })
+}
+
+/// Where a motion ends, and if the character there is part of the text an
+/// operator works on. `RepeatFind` and `Line` have to be resolved first.
+pub fn target(buffer: &[char], pos: usize, motion: Motion, count: usize) -> Option<(usize, bool)> {
+    let len = buffer.len();
+    let repeat = |step: &dyn Fn(usize) -> usize| (0..count).fold(pos, |pos, _| step(pos));
+    Some(match motion {
+        Motion::Left => (pos.saturating_sub(count), false),
+        Motion::Right => ((pos + count).min(len), false),
+        Motion::Start => (0, false),
+        Motion::FirstNonBlank => (
+            buffer
+                .iter()
+                .position(|c| !c.is_whitespace())
+                .unwrap_or(len),
+            false,
+        ),
+        Motion::End => (len.saturating_sub(1), true),
+        Motion::WordForward(big) => (repeat(&|pos| word_forward(buffer, pos, big)), false),
+        Motion::WordBackward(big) => (repeat(&|pos| word_backward(buffer, pos, big)), false),
+        Motion::WordEnd(big) => (repeat(&|pos| word_end(buffer, pos, big)), true),
+        Motion::Find {
+            target,
+            forward,
+            till,
+        } => {
+            let mut pos = pos;
+            for _ in 0..count {
+                pos = find(buffer, pos, target, forward, till)?;
+            }
+            (pos, true)
+        }
+        Motion::RepeatFind(_) | Motion::Line => return None,
+    })
+}
+
+#[cfg(test)]
+mod test_vi {
+    use super::*;
+
+    fn feed(parser: &mut ViParser, keys: &str) -> Vec<(ViCommand, usize)> {
+        keys.chars().filter_map(|c| parser.feed(c)).collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let mut parser = ViParser::default();
+        assert_eq!(
+            feed(&mut parser, "3w"),
+            vec![(ViCommand::Move(Motion::WordForward(false)), 3)]
+        );
+        assert_eq!(
+            feed(&mut parser, "2d3w"),
+            vec![(
+                ViCommand::Operate(Operator::Delete, Motion::WordForward(false)),
+                6
+            )]
+        );
+        assert_eq!(
+            feed(&mut parser, "dd"),
+            vec![(ViCommand::Operate(Operator::Delete, Motion::Line), 1)]
+        );
+        assert_eq!(
+            feed(&mut parser, "ctx"),
+            vec![(
+                ViCommand::Operate(
+                    Operator::Change,
+                    Motion::Find {
+                        target: 'x',
+                        forward: true,
+                        till: true
+                    }
+                ),
+                1
+            )]
+        );
+        assert_eq!(
+            feed(&mut parser, "0"),
+            vec![(ViCommand::Move(Motion::Start), 1)]
+        );
+        assert_eq!(
+            feed(&mut parser, "10x").last(),
+            Some(&(ViCommand::Operate(Operator::Delete, Motion::Right), 10))
+        );
+        assert_eq!(feed(&mut parser, "rq"), vec![(ViCommand::Replace('q'), 1)]);
+        assert_eq!(feed(&mut parser, "dy"), vec![]);
+        assert_eq!(feed(&mut parser, "dp"), vec![]);
+        assert_eq!(feed(&mut parser, "."), vec![(ViCommand::Repeat, 1)]);
+
+        assert!(!parser.is_pending());
+        feed(&mut parser, "2d");
+        assert!(parser.is_pending());
+        parser.reset();
+        assert!(!parser.is_pending());
+    }
+
+    #[test]
+    fn test_motions() {
+        let buffer: Vec<char> = "  say hello, world".chars().collect();
+        assert_eq!(
+            target(&buffer, 2, Motion::WordForward(false), 1),
+            Some((6, false))
+        );
+        assert_eq!(
+            target(&buffer, 6, Motion::WordForward(false), 1),
+            Some((11, false))
+        );
+        assert_eq!(
+            target(&buffer, 6, Motion::WordForward(true), 1),
+            Some((13, false))
+        );
+        assert_eq!(
+            target(&buffer, 13, Motion::WordBackward(false), 2),
+            Some((6, false))
+        );
+        assert_eq!(
+            target(&buffer, 2, Motion::WordEnd(false), 2),
+            Some((10, true))
+        );
+        assert_eq!(
+            target(&buffer, 0, Motion::FirstNonBlank, 1),
+            Some((2, false))
+        );
+        assert_eq!(target(&buffer, 0, Motion::End, 1), Some((17, true)));
+        let find = |forward, till| Motion::Find {
+            target: 'o',
+            forward,
+            till,
+        };
+        assert_eq!(target(&buffer, 0, find(true, false), 2), Some((14, true)));
+        assert_eq!(target(&buffer, 0, find(true, true), 1), Some((9, true)));
+        assert_eq!(target(&buffer, 17, find(false, true), 1), Some((15, true)));
+        assert_eq!(target(&buffer, 0, find(true, false), 3), None);
+    }
+}
@@ -0,0 +1,300 @@
+use std::io::Write;
+
+use anyhow::Result;
+use log::error;
+use termion::{
+    color::{self, Fg},
+    cursor::Goto,
+    style,
+};
+
+use crate::{
+    io::{LogWriter, Logger},
+    model::{Line, WindowOptions, WindowPosition},
+};
+
+use super::{history::History, printable_chars::PrintableCharsIterator, wrap_line};
+
+const MIN_OUTPUT_HEIGHT: u16 = 3;
+const MIN_OUTPUT_WIDTH: u16 = 20;
+
+/// An area of the screen in terminal coordinates
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// A named output area with its own scrollback, created by scripts
+pub struct Window {
+    pub name: String,
+    pub options: WindowOptions,
+    /// Where the window is drawn, empty when it didn't fit on screen
+    pub rect: Rect,
+    /// The column between a side window and the main output
+    pub border: Option<u16>,
+    history: History,
+    /// Lines scrolled back from the newest one
+    scroll: usize,
+    logger: Option<Logger>,
+}
+
+impl Window {
+    pub fn new(name: &str, options: WindowOptions) -> Self {
+        let mut window = Self {
+            name: name.to_string(),
+            history: History::with_capacity(options.scrollback),
+            options: WindowOptions::default(),
+            rect: Rect::default(),
+            border: None,
+            scroll: 0,
+            logger: None,
+        };
+        window.set_options(options);
+        window
+    }
+
+    pub fn set_options(&mut self, options: WindowOptions) {
+        if options.log && self.logger.is_none() {
+            let mut logger = Logger::default();
+            let dir = crate::DATA_DIR
+                .join("logs")
+                .join("windows")
+                .join(&self.name);
+            match logger.start_logging(&self.name, Some(dir)) {
+                Ok(()) => self.logger = Some(logger),
+                Err(err) => error!("Failed to start log for window {}: {}", self.name, err),
+            }
+        } else if !options.log {
+            if let Some(mut logger) = self.logger.take() {
+                logger.stop_logging().ok();
+            }
+        }
+        self.history = {
+            let mut history = History::with_capacity(options.scrollback);
+            for line in self.history.inner.drain(..) {
+                history.append(&line);
+            }
+            history
+        };
+        self.options = options;
+    }
+
+    pub fn is_side(&self) -> bool {
+        matches!(
+            self.options.position,
+            WindowPosition::Left | WindowPosition::Right
+        )
+    }
+
+    pub fn append(&mut self, line: &Line) {
+        if let Some(logger) = &mut self.logger {
+            logger.log_line("", line).ok();
+        }
+        if let Some(print_line) = line.print_line() {
+            let len = self.history.len();
+            self.history.append(print_line);
+            if self.scroll > 0 {
+                // Keep the lines in view while scrolled back
+                self.scroll += self.history.len().saturating_sub(len);
+                self.scroll = self.scroll.min(self.history.len().saturating_sub(1));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.history.inner.clear();
+        self.scroll = 0;
+    }
+
+    /// Scrolls back by `lines`, forward if negative, or to the newest line
+    pub fn scroll(&mut self, lines: Option<i32>) {
+        self.scroll = match lines {
+            Some(lines) => (self.scroll as i64 + lines as i64)
+                .clamp(0, self.history.len().saturating_sub(1) as i64)
+                as usize,
+            None => 0,
+        };
+    }
+
+    /// The rows shown below the title bar, oldest first
+    fn visible_rows(&self) -> Vec<&str> {
+        let height = self.rect.height.saturating_sub(1) as usize;
+        let end = self.history.len().saturating_sub(self.scroll);
+        let mut rows: Vec<&str> = vec![];
+        for line in self.history.inner[..end].iter().rev() {
+            if rows.len() >= height {
+                break;
+            }
+            let mut wrapped = wrap_line(line, self.rect.width as usize);
+            if wrapped.is_empty() {
+                wrapped.push("");
+            }
+            rows.splice(0..0, wrapped);
+        }
+        let skip = rows.len().saturating_sub(height);
+        rows.split_off(skip)
+    }
+
+    pub fn draw(&self, screen: &mut impl Write) -> Result<()> {
+        let rect = self.rect;
+        if rect.height == 0 || rect.width == 0 {
+            return Ok(());
+        }
+        let title = if self.scroll > 0 {
+            format!("━ {} (more) ", self.name)
+        } else {
+            format!("━ {} ", self.name)
+        };
+        let title: String = title.chars().take(rect.width as usize).collect();
+        write!(
+            screen,
+            "{}{}{:━<3$}{}",
+            Goto(rect.x, rect.y),
+            Fg(color::Green),
+            title,
+            rect.width as usize,
+        )?;
+        if let Some(border) = self.border {
+            for y in rect.y..rect.y + rect.height {
+                write!(screen, "{}┃", Goto(border, y))?;
+            }
+        }
+        write!(screen, "{}", Fg(color::Reset))?;
+
+        let rows = self.visible_rows();
+        for i in 0..rect.height - 1 {
+            let row = rows.get(i as usize).copied().unwrap_or("");
+            let padding = (rect.width as usize).saturating_sub(row.printable_chars().count());
+            write!(
+                screen,
+                "{}{}{}{:padding$}",
+                Goto(rect.x, rect.y + 1 + i),
+                row,
+                style::Reset,
+                "",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Places the windows in the output area, top and bottom ones first so they
+/// span its full width. Windows that don't fit are hidden. Returns the area
+/// left for the main output.
+pub fn layout_windows(windows: &mut [Window], area: Rect) -> Rect {
+    let mut area = area;
+    for window in windows.iter_mut().filter(|w| !w.is_side()) {
+        let height = window.options.size.resolve(area.height).max(2);
+        window.border = None;
+        if area.height < height + MIN_OUTPUT_HEIGHT {
+            window.rect = Rect::default();
+            continue;
+        }
+        area.height -= height;
+        window.rect = if window.options.position == WindowPosition::Top {
+            area.y += height;
+            Rect::new(area.x, area.y - height, area.width, height)
+        } else {
+            Rect::new(area.x, area.y + area.height, area.width, height)
+        };
+    }
+    for window in windows.iter_mut().filter(|w| w.is_side()) {
+        let width = window.options.size.resolve(area.width).max(1);
+        if area.width < width + 1 + MIN_OUTPUT_WIDTH || area.height < 2 {
+            window.rect = Rect::default();
+            window.border = None;
+            continue;
+        }
+        area.width -= width + 1;
+        if window.options.position == WindowPosition::Left {
+            window.rect = Rect::new(area.x, area.y, width, area.height);
+            window.border = Some(area.x + width);
+            area.x += width + 1;
+        } else {
+            window.rect = Rect::new(area.x + area.width + 1, area.y, width, area.height);
+            window.border = Some(area.x + area.width);
+        }
+    }
+    area
+}
+
+pub fn find_window<'a>(windows: &'a mut [Window], name: &str) -> Option<&'a mut Window> {
+    windows.iter_mut().find(|window| window.name == name)
+}
+
+#[cfg(test)]
+mod test_window {
+    use super::*;
+    use crate::model::WindowSize;
+
+    fn window(name: &str, position: WindowPosition, size: WindowSize) -> Window {
+        Window::new(
+            name,
+            WindowOptions {
+                position,
+                size,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut windows = vec![
+            window("map", WindowPosition::Right, WindowSize::Percent(25)),
+            window("chat", WindowPosition::Top, WindowSize::Fixed(6)),
+            window("group", WindowPosition::Bottom, WindowSize::Percent(50)),
+            window("huge", WindowPosition::Left, WindowSize::Fixed(60)),
+        ];
+        let main = layout_windows(&mut windows, Rect::new(1, 2, 80, 30));
+        assert_eq!(windows[1].rect, Rect::new(1, 2, 80, 6));
+        assert_eq!(windows[2].rect, Rect::new(1, 20, 80, 12));
+        assert_eq!(windows[0].rect, Rect::new(61, 8, 20, 12));
+        assert_eq!(windows[0].border, Some(60));
+        assert_eq!(windows[3].rect, Rect::default());
+        assert_eq!(main, Rect::new(1, 8, 59, 12));
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut chat = window("chat", WindowPosition::Top, WindowSize::Fixed(4));
+        chat.rect = Rect::new(1, 1, 10, 4);
+        for line in ["one", "two", "three four five", "six"] {
+            chat.append(&Line::from(line));
+        }
+        let mut gagged = Line::from("gagged");
+        gagged.flags.gag = true;
+        chat.append(&gagged);
+        assert_eq!(chat.visible_rows(), vec!["three", "four five", "six"]);
+
+        chat.scroll(Some(1));
+        assert_eq!(chat.visible_rows(), vec!["two", "three", "four five"]);
+        chat.append(&Line::from("seven"));
+        assert_eq!(chat.visible_rows(), vec!["two", "three", "four five"]);
+        chat.scroll(Some(-10));
+        assert_eq!(chat.visible_rows(), vec!["four five", "six", "seven"]);
+
+        chat.set_options(WindowOptions {
+            scrollback: 2,
+            ..Default::default()
+        });
+        chat.scroll(None);
+        assert_eq!(chat.visible_rows(), vec!["six", "seven"]);
+        chat.clear();
+        assert!(chat.visible_rows().is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+require "tests.common"
+
+local attempts = 0
+local connections = 0
+
+mud.auto_reconnect({ attempts = 3, delay = 0.1, jitter = 0 })
+
+mud.on_reconnect_attempt(function (attempt, delay)
+    attempts = attempt
+    assert_eq(delay, 0.1)
+end)
+
+mud.on_connect(function ()
+    connections = connections + 1
+    if connections == 2 then
+        assert_eq(attempts, 1)
+        mud.auto_reconnect(false)
+        mud.disconnect()
+    end
+end)
+
+mud.on_disconnect(function ()
+    if connections == 2 then
+        blight.quit()
+    end
+end)
@@ -1,7 +1,8 @@
-use std::env;
+use std::{env, io::Read};
 
 use blightmud::{PROJECT_NAME, VERSION};
 use common::{join_blightmud, setup};
+use flate2::read::ZlibDecoder;
 use libtelnet_rs::telnet::{op_command::*, op_option::*};
 
 mod common;
@@ -36,7 +37,42 @@ fn test_ttype_negotiation() -> std::io::Result<()> {
     connection.send(&[IAC, SB, TTYPE, SEND, IAC, SE]);
     assert_eq!(
         connection.recv(),
-        vec![&[IAC, SB, TTYPE, IS][..], b"MTTS 271", &[IAC, SE][..]].concat()
+        vec![&[IAC, SB, TTYPE, IS][..], b"MTTS 783", &[IAC, SE][..]].concat()
+    );
+
+    connection.close();
+    join_blightmud(handle);
+    Ok(())
+}
+
+#[test]
+fn test_new_environ_negotiation() -> std::io::Result<()> {
+    let (mut connection, handle) = setup(None);
+
+    connection.send(&[IAC, DO, NEWENVIRON]);
+    assert_eq!(connection.read(3), &[IAC, WILL, NEWENVIRON]);
+    connection.send(
+        &[
+            &[IAC, SB, NEWENVIRON, SEND, 0][..],
+            b"CLIENT_NAME",
+            &[0][..],
+            b"IPADDRESS",
+            &[IAC, SE][..],
+        ]
+        .concat(),
+    );
+    assert_eq!(
+        connection.recv(),
+        [
+            &[IAC, SB, NEWENVIRON, IS, 0][..],
+            b"CLIENT_NAME",
+            &[1][..],
+            b"BLIGHTMUD",
+            &[0][..],
+            b"IPADDRESS",
+            &[IAC, SE][..],
+        ]
+        .concat()
     );
 
     connection.close();
@@ -78,3 +114,46 @@ fn test_gmcp_negotiation() -> std::io::Result<()> {
     join_blightmud(handle);
     Ok(())
 }
+
+#[test]
+fn test_gmcp_dispatch() -> std::io::Result<()> {
+    let (mut connection, handle) = setup(Some("tests/gmcp_echo.lua".to_string()));
+
+    let gmcp = |msg: &str| [&[IAC, SB, GMCP][..], msg.as_bytes(), &[IAC, SE][..]].concat();
+    connection.send(&[IAC, WILL, GMCP]);
+    assert_eq!(connection.read(3), &[IAC, DO, GMCP]);
+    let hello = format!(
+        "Core.Hello {{\"client\":\"{}\",\"version\":\"{}\"}}",
+        PROJECT_NAME, VERSION
+    );
+    assert_eq!(connection.read(hello.len() + 5), gmcp(&hello));
+    let supports = gmcp("Core.Supports.Set [\"Char 1\"]");
+    assert_eq!(connection.read(supports.len()), supports);
+
+    connection.send(&gmcp("Char.Vitals {\"hp\": 5}"));
+    let echo = gmcp("Test.Echo {\"hp\":5,\"package\":\"Char.Vitals\"}");
+    assert_eq!(connection.read(echo.len()), echo);
+
+    connection.close();
+    join_blightmud(handle);
+    Ok(())
+}
+
+#[test]
+fn test_mccp3_negotiation() -> std::io::Result<()> {
+    let (mut connection, handle) = setup(Some("tests/mccp3_echo.lua".to_string()));
+
+    connection.send(&[IAC, WILL, MCCP3]);
+    assert_eq!(connection.read(3), &[IAC, DO, MCCP3]);
+    assert_eq!(connection.read(5), &[IAC, SB, MCCP3, IAC, SE]);
+
+    connection.send(b"ping\r\n");
+    let mut decoder = ZlibDecoder::new(connection.stream.as_ref().unwrap().try_clone()?);
+    let mut buffer = [0u8; 6];
+    decoder.read_exact(&mut buffer)?;
+    assert_eq!(&buffer, b"pong\r\n");
+
+    connection.close();
+    join_blightmud(handle);
+    Ok(())
+}
@@ -62,6 +62,26 @@ fn test_reconnect_world() {
     join_blightmud(common::start_blightmud(rt))
 }
 
+#[test]
+fn test_auto_reconnect() {
+    let mut server = Server::bind(0);
+
+    let mut rt = RuntimeConfig::default();
+    rt.headless_mode = true;
+    rt.integration_test = true;
+    rt.connect = Some(server.local_addr.to_string());
+    rt.script = Some("tests/auto_reconnect.lua".to_string());
+    let handle = common::start_blightmud(rt);
+
+    let connection = server.listen();
+    assert!(connection.is_ok());
+    connection.unwrap().close();
+
+    let connection = server.listen();
+    assert!(connection.is_ok());
+    join_blightmud(handle);
+}
+
 #[test]
 fn test_is_connected() {
     let server = Server::bind(0);
@@ -73,3 +93,56 @@ fn test_is_connected() {
     rt.script = Some("tests/is_connected.lua".to_string());
     join_blightmud(common::start_blightmud(rt))
 }
+
+#[test]
+fn test_timing_mark_latency() {
+    let mut server = Server::bind(0);
+
+    let mut rt = RuntimeConfig::default();
+    rt.headless_mode = true;
+    rt.integration_test = true;
+    rt.connect = Some(server.local_addr.to_string());
+    rt.eval = Some(
+        r#"
+mud.on_connect(function ()
+    mud.keepalive({ interval = 0.1 })
+end)
+local id
+id = timer.add(0.1, 0, function ()
+    if mud.latency() then
+        timer.remove(id)
+        mud.keepalive(false)
+        mud.send("latency measured")
+    end
+end)
+mud.on_disconnect(function ()
+    blight.quit()
+end)
+"#
+        .to_string(),
+    );
+    let handle = common::start_blightmud(rt);
+
+    let mut connection = server.listen().unwrap();
+    let timing_mark = [255, 253, 6];
+    let mut received = vec![];
+    while !received.ends_with(&timing_mark) {
+        received.extend(connection.recv());
+    }
+    connection.send(&[255, 251, 6]);
+
+    // Nothing but more requests may be sent before the result, in particular
+    // no DONT TIMING-MARK refusing our reply
+    let mut received = vec![];
+    while !received.ends_with(b"latency measured\r\n") {
+        received.extend(connection.recv());
+    }
+    let requests = received
+        .strip_suffix(b"latency measured\r\n")
+        .unwrap()
+        .chunks(3)
+        .all(|chunk| chunk == timing_mark);
+    assert!(requests);
+    connection.close();
+    join_blightmud(handle);
+}
@@ -0,0 +1,4 @@
+gmcp.register("Char")
+gmcp.listen("Char.*", function (data, package)
+    gmcp.send("Test.Echo", { package = package, hp = data.hp })
+end)
@@ -0,0 +1,84 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use blightmud::RuntimeConfig;
+
+mod common;
+
+/// Answers each request with its method, path and body as JSON
+fn serve(listener: TcpListener, requests: usize) {
+    for stream in listener.incoming().take(requests) {
+        let mut stream = stream.unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        let mut parts = request_line.split_whitespace();
+        let response = format!(
+            "{{\"method\":\"{}\",\"path\":\"{}\",\"body\":{}}}",
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+            serde_json::to_string(&String::from_utf8(body).unwrap()).unwrap()
+        );
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn test_http_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || serve(listener, 2));
+
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(format!(
+            r#"
+assert(not pcall(http.get, "{url}/"))
+
+http.get("{url}/status", {{ timeout = 5 }}, function (response)
+    assert(response.error == nil, response.error)
+    assert(response.status == 200)
+    assert(response.headers["content-type"] == "application/json")
+    local data = response:json()
+    assert(data.method == "GET")
+    assert(data.path == "/status")
+
+    tasks.spawn(function ()
+        local response = http.post("{url}/items", {{ json = {{ name = "sword" }} }})
+        local data = response:json()
+        assert(data.method == "POST")
+        assert(json.decode(data.body).name == "sword")
+        blight.quit()
+    end)
+end)
+"#
+        )),
+        ..Default::default()
+    };
+    common::join_blightmud(common::start_blightmud(rt));
+    server.join().unwrap();
+}
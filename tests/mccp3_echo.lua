@@ -0,0 +1,3 @@
+trigger.add("^ping$", {}, function ()
+    mud.send("pong")
+end)
@@ -0,0 +1,65 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use blightmud::RuntimeConfig;
+use libtelnet_rs::telnet::{op_command::*, op_option::*};
+
+mod common;
+
+#[test]
+fn test_mssp_query() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let closed_port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&[IAC, WILL, MSSP]).unwrap();
+        let mut buffer = [0; 3];
+        stream.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [IAC, DO, MSSP]);
+        let reply = [
+            &[IAC, SB, MSSP][..],
+            b"\x01NAME\x02Blight\x01CODEBASE\x02Diku\x02Merc",
+            &[IAC, SE][..],
+        ]
+        .concat();
+        stream.write_all(&reply).unwrap();
+    });
+
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(format!(
+            r#"
+mud.on_connect(function ()
+    assert(false, "The session should not connect")
+end)
+
+mssp.query("127.0.0.1", {port}, function (info, err)
+    assert(err == nil, err)
+    assert(info.NAME == "Blight")
+    assert(info.CODEBASE[1] == "Diku")
+    assert(info.CODEBASE[2] == "Merc")
+    assert(not mud.is_connected())
+
+    mssp.query("127.0.0.1", {closed_port}, function (info, err)
+        assert(info == nil)
+        assert(err ~= nil)
+        blight.quit()
+    end)
+end)
+"#
+        )),
+        ..Default::default()
+    };
+    let handle = common::start_blightmud(rt);
+    server.join().unwrap();
+    common::join_blightmud(handle);
+}
@@ -0,0 +1,157 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+use blightmud::RuntimeConfig;
+use common::{join_blightmud, Server};
+
+mod common;
+
+#[derive(Clone, Copy)]
+enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+/// A minimal stand-in proxy that accepts a single client, records the
+/// handshake and then pipes data to the requested target.
+fn spawn_proxy(kind: ProxyKind) -> (SocketAddr, Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = channel();
+    thread::spawn(move || -> io::Result<()> {
+        let (mut client, _) = listener.accept()?;
+        let (handshake, target) = match kind {
+            ProxyKind::Socks5 => socks5_handshake(&mut client)?,
+            ProxyKind::Http => http_handshake(&mut client)?,
+        };
+        let upstream = TcpStream::connect(target)?;
+        match kind {
+            ProxyKind::Socks5 => client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])?,
+            ProxyKind::Http => client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?,
+        }
+        tx.send(handshake).unwrap();
+        pipe(client.try_clone()?, upstream.try_clone()?);
+        pipe(upstream, client);
+        Ok(())
+    });
+    (addr, rx)
+}
+
+fn socks5_handshake(client: &mut TcpStream) -> io::Result<(String, String)> {
+    let mut greeting = [0u8; 4];
+    client.read_exact(&mut greeting)?;
+    assert_eq!(greeting, [5, 2, 0, 2]);
+    client.write_all(&[5, 2])?;
+
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header)?;
+    assert_eq!(header[0], 1);
+    let mut username = vec![0u8; header[1] as usize];
+    client.read_exact(&mut username)?;
+    let mut len = [0u8; 1];
+    client.read_exact(&mut len)?;
+    let mut password = vec![0u8; len[0] as usize];
+    client.read_exact(&mut password)?;
+    client.write_all(&[1, 0])?;
+
+    let mut request = [0u8; 5];
+    client.read_exact(&mut request)?;
+    assert_eq!(request[..4], [5, 1, 0, 3]);
+    let mut host = vec![0u8; request[4] as usize];
+    client.read_exact(&mut host)?;
+    let mut port = [0u8; 2];
+    client.read_exact(&mut port)?;
+    let target = format!(
+        "{}:{}",
+        String::from_utf8(host).unwrap(),
+        u16::from_be_bytes(port)
+    );
+    let credentials = format!(
+        "{}:{}",
+        String::from_utf8(username).unwrap(),
+        String::from_utf8(password).unwrap()
+    );
+    Ok((format!("{credentials} {target}"), target))
+}
+
+fn http_handshake(client: &mut TcpStream) -> io::Result<(String, String)> {
+    let mut request = vec![];
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        client.read_exact(&mut byte)?;
+        request.push(byte[0]);
+    }
+    let request = String::from_utf8(request).unwrap();
+    let target = request.split_whitespace().nth(1).unwrap().to_string();
+    Ok((request, target))
+}
+
+fn pipe(mut from: TcpStream, mut to: TcpStream) {
+    thread::spawn(move || {
+        io::copy(&mut from, &mut to).ok();
+        to.shutdown(std::net::Shutdown::Both).ok();
+    });
+}
+
+fn start_blightmud(server: &Server, proxy: &str) -> thread::JoinHandle<anyhow::Result<()>> {
+    let eval = format!(
+        r#"
+trigger.add("^ping$", {{}}, function ()
+    mud.send("pong")
+end)
+mud.on_disconnect(function ()
+    blight.quit()
+end)
+mud.connect("127.0.0.1", {}, false, false, {{ proxy = "{}" }})
+"#,
+        server.local_addr.port(),
+        proxy
+    );
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(eval),
+        ..Default::default()
+    };
+    common::start_blightmud(rt)
+}
+
+fn assert_ping_pong(server: &mut Server) {
+    let mut connection = server.listen().unwrap();
+    connection.send(b"ping\r\n");
+    assert_eq!(connection.recv_string(), "pong\r\n");
+    connection.close();
+}
+
+#[test]
+fn test_socks5_proxy() {
+    let mut server = Server::bind(0);
+    let (proxy_addr, handshake) = spawn_proxy(ProxyKind::Socks5);
+    let handle = start_blightmud(&server, &format!("socks5://user:secret@{proxy_addr}"));
+
+    assert_ping_pong(&mut server);
+    assert_eq!(
+        handshake.recv().unwrap(),
+        format!("user:secret 127.0.0.1:{}", server.local_addr.port())
+    );
+    join_blightmud(handle);
+}
+
+#[test]
+fn test_http_proxy() {
+    let mut server = Server::bind(0);
+    let (proxy_addr, handshake) = spawn_proxy(ProxyKind::Http);
+    let handle = start_blightmud(&server, &format!("http://{proxy_addr}"));
+
+    assert_ping_pong(&mut server);
+    let port = server.local_addr.port();
+    assert_eq!(
+        handshake.recv().unwrap(),
+        format!("CONNECT 127.0.0.1:{port} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\r\n")
+    );
+    join_blightmud(handle);
+}
@@ -0,0 +1,50 @@
+use blightmud::RuntimeConfig;
+
+mod common;
+
+#[test]
+fn test_record_and_replay() {
+    let path = std::env::temp_dir().join(format!("blightmud-{}.rec", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+
+    let mut server = common::Server::bind(0);
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(include_str!("quit_on_disconnect.lua").to_string()),
+        connect: Some(server.local_addr.to_string()),
+        record: Some(path.clone()),
+        ..Default::default()
+    };
+    let handle = common::start_blightmud(rt);
+    let mut connection = server.listen().unwrap();
+    connection.send(b"Welcome!\r\n");
+    connection.send(b"You have 42 gold.\r\n");
+    connection.send(b"The end\r\n");
+    connection.close();
+    common::join_blightmud(handle);
+
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(
+            r#"
+local gold = nil
+trigger.add("^You have (\\d+) gold\\.$", {}, function (m)
+    gold = m[2]
+end)
+trigger.add("^The end$", {}, function ()
+    assert(gold == "42", "Gold was not replayed")
+    assert(not mud.is_connected(), "Replay shouldn't connect")
+    blight.quit()
+end)
+"#
+            .to_string(),
+        ),
+        replay: Some(path.clone()),
+        replay_speed: Some(0.0),
+        ..Default::default()
+    };
+    common::join_blightmud(common::start_blightmud(rt));
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use blightmud::RuntimeConfig;
+use common::Server;
+
+mod common;
+
+fn read_until(stream: &mut TcpStream, end: &[u8]) -> Vec<u8> {
+    let mut received = vec![];
+    let mut buffer = [0; 1024];
+    while !received.ends_with(end) {
+        let n = stream.read(&mut buffer).unwrap();
+        assert!(n > 0, "Connection closed early: {:?}", received);
+        received.extend(&buffer[..n]);
+    }
+    received
+}
+
+#[test]
+fn test_attach_client() {
+    let mut server = Server::bind(0);
+    let share_port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(include_str!("quit_on_disconnect.lua").to_string()),
+        connect: Some(server.local_addr.to_string()),
+        share: Some(share_port),
+        share_password: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let handle = common::start_blightmud(rt);
+    let mut connection = server.listen().unwrap();
+
+    // WILL GMCP, answered before the client attaches
+    connection.send(&[255, 251, 201]);
+    let mut received = vec![];
+    while !received.windows(3).any(|w| w == [255, 253, 201]) {
+        received.extend(connection.recv());
+    }
+
+    let mut client = loop {
+        match TcpStream::connect(("127.0.0.1", share_port)) {
+            Ok(client) => break client,
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    // Echo is turned off while the password is typed
+    assert_eq!(
+        read_until(&mut client, b"Password: "),
+        b"\xff\xfb\x01Password: "
+    );
+    client.write_all(b"secret\r\n").unwrap();
+    assert_eq!(read_until(&mut client, &[255, 252, 1]), b"\r\n\xff\xfc\x01");
+    assert_eq!(read_until(&mut client, &[255, 251, 201]), [255, 251, 201]);
+
+    connection.send(b"Welcome back!\r\n");
+    assert_eq!(
+        read_until(&mut client, b"Welcome back!\r\n"),
+        b"Welcome back!\r\n"
+    );
+
+    // The client's own negotiation is not passed on
+    client.write_all(&[255, 253, 201]).unwrap();
+    client.write_all(b"look\r\n").unwrap();
+    let mut received = vec![];
+    while !received.ends_with(b"look\r\n") {
+        received.extend(connection.recv());
+    }
+    assert!(!received.windows(3).any(|w| w == [255, 253, 201]));
+
+    connection.close();
+    common::join_blightmud(handle);
+}
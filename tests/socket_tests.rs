@@ -0,0 +1,134 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    os::unix::net::UnixStream,
+    thread,
+};
+
+use blightmud::RuntimeConfig;
+
+mod common;
+
+#[test]
+fn test_async_socket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "ping\n");
+        stream.write_all(b"hello\r\nwor").unwrap();
+        stream.flush().unwrap();
+        thread::sleep(std::time::Duration::from_millis(50));
+        stream.write_all(b"ld\nbye").unwrap();
+    });
+
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(format!(
+            r#"
+local lines = {{}}
+local conn = socket.connect_async("127.0.0.1", {port}, {{ line_mode = true }})
+conn:on_connect(function ()
+    assert(conn:is_connected())
+    conn:send("ping\n")
+end)
+conn:on_data(function (line)
+    table.insert(lines, line)
+end)
+conn:on_close(function (reason)
+    assert(reason == nil, "Unexpected close reason: " .. tostring(reason))
+    assert(not conn:is_connected())
+    assert(#lines == 3, "Got " .. #lines .. " lines")
+    assert(lines[1] == "hello")
+    assert(lines[2] == "world")
+    assert(lines[3] == "bye")
+
+    local failed = socket.connect_async("127.0.0.1", 1, {{ timeout = 1 }})
+    failed:on_close(function (reason)
+        assert(reason ~= nil)
+        blight.quit()
+    end)
+end)
+"#
+        )),
+        ..Default::default()
+    };
+    common::join_blightmud(common::start_blightmud(rt));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_listen() {
+    let path = std::env::temp_dir().join(format!("blightmud-{}.sock", std::process::id()));
+    let client_path = path.clone();
+    let unix_client = thread::spawn(move || {
+        while !client_path.exists() {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mut stream = UnixStream::connect(&client_path).unwrap();
+        stream.write_all(b"hello\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "HELLO\n");
+    });
+
+    let rt = RuntimeConfig {
+        headless_mode: true,
+        integration_test: true,
+        eval: Some(format!(
+            r#"
+local closed = 0
+local tcp = nil
+local function done()
+    closed = closed + 1
+    if closed == 3 then
+        tcp:close()
+        assert(not tcp:is_listening())
+        blight.quit()
+    end
+end
+
+local function echo_server(client)
+    client:on_data(function (line)
+        client:send(line:upper() .. "\n")
+    end)
+end
+
+tcp = socket.listen("127.0.0.1", 0, echo_server, {{ line_mode = true }})
+assert(tcp:is_listening())
+assert(tcp:port() > 0)
+local unix = socket.listen_unix({path:?}, function (client)
+    echo_server(client)
+    client:on_close(done)
+end, {{ line_mode = true }})
+assert(unix:port() == nil)
+
+local function check(conn)
+    conn:on_data(function (line)
+        assert(line == "HELLO", line)
+        conn:close()
+    end)
+    conn:on_close(done)
+end
+
+local conn = socket.connect_async("127.0.0.1", tcp:port(), {{ line_mode = true }})
+conn:on_connect(function ()
+    conn:send("hello\n")
+end)
+check(conn)
+
+local sync_conn = socket.connect("127.0.0.1", tcp:port(), {{ line_mode = true }})
+sync_conn:send("hello\n")
+check(sync_conn)
+"#
+        )),
+        ..Default::default()
+    };
+    common::join_blightmud(common::start_blightmud(rt));
+    unix_client.join().unwrap();
+    std::fs::remove_file(path).ok();
+}